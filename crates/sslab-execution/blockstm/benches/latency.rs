@@ -19,7 +19,7 @@ fn _get_smallbank_handler() -> SmallBankTransactionHandler {
 }
 
 fn _get_blockstm_executor() -> BlockSTM {
-    BlockSTM::new(Arc::new(concurrent_evm_storage()))
+    BlockSTM::new(Arc::new(concurrent_evm_storage()), num_cpus::get())
 }
 
 fn _create_random_smallbank_workload(
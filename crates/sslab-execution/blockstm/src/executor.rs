@@ -7,7 +7,7 @@ use crate::{
     outcome_array::OutcomeArray,
     scheduler::{Scheduler, SchedulerTask, TaskGuard, TxnIndex, Version},
     task::{ExecutionStatus, ExecutorTask, Transaction, TransactionOutput},
-    txn_last_input_output::{ReadDescriptor, TxnLastInputOutput},
+    txn_last_input_output::{ReadDescriptor, TxnAccesses, TxnLastInputOutput},
 };
 use anyhow::{bail, Result as AResult};
 use ethers::types::H256;
@@ -63,6 +63,14 @@ impl MultiversionView for EtherMVHashMapView<'_> {
     }
 }
 
+impl EtherMVHashMapView<'_> {
+    /// Return the (key, version) pairs observed by the reads so far, see
+    /// 'MVHashMapView::observed_versions'.
+    pub fn observed_versions(&self) -> Vec<(H256, Option<Version>)> {
+        self.versioned_map.observed_versions()
+    }
+}
+
 pub struct MVHashMapView<'a, K, V> {
     versioned_map: &'a MVHashMap<K, V>,
     txn_idx: TxnIndex,
@@ -72,12 +80,36 @@ pub struct MVHashMapView<'a, K, V> {
 }
 
 impl<'a, K: PartialOrd + Send + Clone + Hash + Eq, V: Send + Sync> MVHashMapView<'a, K, V> {
+    pub(crate) fn new(
+        versioned_map: &'a MVHashMap<K, V>,
+        txn_idx: TxnIndex,
+        scheduler: &'a Scheduler,
+    ) -> Self {
+        Self {
+            versioned_map,
+            txn_idx,
+            scheduler,
+            read_dependency: AtomicBool::new(false),
+            captured_reads: Mutex::new(Vec::new()),
+        }
+    }
+
     /// Drains the captured reads.
     pub fn take_reads(&self) -> Vec<ReadDescriptor<K>> {
         let mut reads = self.captured_reads.lock();
         std::mem::take(&mut reads)
     }
 
+    /// Return the keys read so far (without draining them), each along with the version of the
+    /// write it observed, or None if it was read from storage.
+    pub fn observed_versions(&self) -> Vec<(K, Option<Version>)> {
+        self.captured_reads
+            .lock()
+            .iter()
+            .map(|read| (read.path().clone(), read.version()))
+            .collect()
+    }
+
     /// Captures a read from the VM execution.
     pub fn read(&self, key: &K) -> AResult<Option<Arc<V>>> {
         loop {
@@ -123,8 +155,23 @@ impl<'a, K: PartialOrd + Send + Clone + Hash + Eq, V: Send + Sync> MVHashMapView
     }
 }
 
+/// How much speculative work the scheduler discarded while executing a block in parallel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExecutionStats {
+    /// Number of incarnations aborted due to validation failures.
+    pub aborts: usize,
+    /// Number of re-executions, due to aborts or resolved read dependencies.
+    pub reexecutions: usize,
+}
+
 pub struct ParallelTransactionExecutor<T: Transaction, E: ExecutorTask> {
     num_cpus: usize,
+    max_reexecutions: Option<usize>,
+    /// Per-transaction accesses of the last executed block, only kept when enabled via
+    /// 'with_recorded_accesses'.
+    recorded_accesses: Option<Mutex<Vec<TxnAccesses<T::Key>>>>,
+    /// Aborts and re-executions of the last executed block.
+    last_stats: Mutex<ExecutionStats>,
     phantom: PhantomData<(T, E)>,
 }
 
@@ -133,13 +180,51 @@ where
     T: Transaction,
     E: ExecutorTask<T = T>,
 {
-    pub fn new() -> Self {
+    /// Executes each block on `concurrency_level` worker threads (e.g., `num_cpus::get()`).
+    pub fn new(concurrency_level: usize) -> Self {
+        assert!(
+            concurrency_level > 0,
+            "the concurrency level must be positive"
+        );
+
         Self {
-            num_cpus: num_cpus::get(),
+            num_cpus: concurrency_level,
+            max_reexecutions: None,
+            recorded_accesses: None,
+            last_stats: Mutex::new(ExecutionStats::default()),
             phantom: PhantomData,
         }
     }
 
+    /// Bound how many times a transaction is re-executed in parallel. A transaction that keeps
+    /// getting aborted past this cap is executed serially (together with the transactions after
+    /// it) once all of its predecessors are done, which bounds the work under heavy contention.
+    pub fn with_max_reexecutions(mut self, max_reexecutions: usize) -> Self {
+        self.max_reexecutions = Some(max_reexecutions);
+        self
+    }
+
+    /// Keep the read and write keys each transaction last recorded, for debugging why a
+    /// transaction kept getting aborted. See 'recorded_accesses'.
+    pub fn with_recorded_accesses(mut self) -> Self {
+        self.recorded_accesses = Some(Mutex::new(Vec::new()));
+        self
+    }
+
+    /// Return the per-transaction accesses recorded during the last parallel execution, indexed
+    /// by the transaction's position in the block. Empty unless enabled.
+    pub fn recorded_accesses(&self) -> Vec<TxnAccesses<T::Key>> {
+        self.recorded_accesses
+            .as_ref()
+            .map(|accesses| accesses.lock().clone())
+            .unwrap_or_default()
+    }
+
+    /// Return the aborts and re-executions of the last parallel execution.
+    pub fn last_execution_stats(&self) -> ExecutionStats {
+        *self.last_stats.lock()
+    }
+
     pub fn execute<'a>(
         &self,
         version_to_execute: Version,
@@ -173,13 +258,7 @@ where
             }
         }
 
-        let state_view = MVHashMapView {
-            versioned_map: versioned_data_cache,
-            txn_idx: idx_to_execute,
-            scheduler,
-            read_dependency: AtomicBool::new(false),
-            captured_reads: Mutex::new(Vec::new()),
-        };
+        let state_view = MVHashMapView::new(versioned_data_cache, idx_to_execute, scheduler);
 
         // VM execution.
         let execute_result = executor.execute_transaction(&state_view, txn);
@@ -231,6 +310,58 @@ where
         scheduler.finish_execution(idx_to_execute, incarnation, writes_outside, guard)
     }
 
+    /// Execute a transaction left over by the scheduler's serial fallback. All lower transactions
+    /// must already be final, so reads never hit a dependency. Returns false if the rest of the
+    /// block must be skipped.
+    fn execute_serially(
+        &self,
+        idx_to_execute: TxnIndex,
+        signature_verified_block: &[T],
+        last_input_output: &TxnLastInputOutput<
+            <T as Transaction>::Key,
+            <E as ExecutorTask>::Output,
+            <E as ExecutorTask>::Error,
+        >,
+        versioned_data_cache: &MVHashMap<<T as Transaction>::Key, <T as Transaction>::Value>,
+        scheduler: &Scheduler,
+        executor: &E,
+    ) -> bool {
+        let state_view = MVHashMapView::new(versioned_data_cache, idx_to_execute, scheduler);
+
+        let execute_result =
+            executor.execute_transaction(&state_view, &signature_verified_block[idx_to_execute]);
+        debug_assert!(!state_view.read_dependency());
+
+        let mut prev_write_set: HashSet<T::Key> = last_input_output.write_set(idx_to_execute);
+        let write_version = (idx_to_execute, scheduler.max_reexecutions());
+        let mut apply_writes = |output: &<E as ExecutorTask>::Output| {
+            for (k, v) in output.get_writes().into_iter() {
+                prev_write_set.remove(&k);
+                versioned_data_cache.write(&k, write_version, v);
+            }
+        };
+
+        let (result, proceed) = match execute_result {
+            ExecutionStatus::Success(output) => {
+                apply_writes(&output);
+                (ExecutionStatus::Success(output), true)
+            }
+            ExecutionStatus::SkipRest(output) => {
+                apply_writes(&output);
+                (ExecutionStatus::SkipRest(output), false)
+            }
+            ExecutionStatus::Abort(err) => (ExecutionStatus::Abort(Error::UserError(err)), false),
+        };
+
+        // Remove stale entries (possibly estimates) left by the last parallel incarnation.
+        for k in &prev_write_set {
+            versioned_data_cache.delete(k, idx_to_execute);
+        }
+
+        last_input_output.record(idx_to_execute, state_view.take_reads(), result);
+        proceed
+    }
+
     pub fn validate<'a>(
         &self,
         version_to_validate: Version,
@@ -276,6 +407,7 @@ where
         signature_verified_block: Vec<T>,
     ) -> Result<Vec<E::Output>, E::Error> {
         if signature_verified_block.is_empty() {
+            *self.last_stats.lock() = ExecutionStats::default();
             return Ok(vec![]);
         }
 
@@ -284,7 +416,12 @@ where
         let outcomes = OutcomeArray::new(num_txns);
         let compute_cpus = self.num_cpus;
         let last_input_output = TxnLastInputOutput::new(num_txns);
-        let scheduler = Scheduler::new(num_txns);
+        let scheduler = match self.max_reexecutions {
+            Some(max_reexecutions) => {
+                Scheduler::new(num_txns).with_max_reexecutions(max_reexecutions)
+            }
+            None => Scheduler::new(num_txns),
+        };
 
         scope(|s| {
             // println!(
@@ -328,18 +465,49 @@ where
             }
         });
 
+        // Transactions that hit the re-execution cap are executed one by one on top of the
+        // (now final) results of their predecessors.
+        let valid_results_size = match scheduler.serial_fallback() {
+            Some(fallback) => {
+                let executor = E::init(executor_initial_arguments);
+                let mut stop_idx = fallback.end;
+                for idx in fallback {
+                    if !self.execute_serially(
+                        idx,
+                        &signature_verified_block,
+                        &last_input_output,
+                        &versioned_data_cache,
+                        &scheduler,
+                        &executor,
+                    ) {
+                        stop_idx = idx + 1;
+                        break;
+                    }
+                }
+                stop_idx
+            }
+            None => scheduler.num_txn_to_execute(),
+        };
+
+        if let Some(accesses) = &self.recorded_accesses {
+            *accesses.lock() = last_input_output.dump_accesses();
+        }
+        *self.last_stats.lock() = ExecutionStats {
+            aborts: scheduler.num_aborts(),
+            reexecutions: scheduler.num_reexecutions(),
+        };
+
         // Extract outputs in parallel
-        let valid_results_size = scheduler.num_txn_to_execute();
         let chunk_size = (valid_results_size + 4 * compute_cpus - 1) / (4 * compute_cpus);
         (0..valid_results_size)
             .collect::<Vec<TxnIndex>>()
             .par_chunks(chunk_size)
             .map(|chunk| {
-                for idx in chunk.iter() {
-                    outcomes.set_result(*idx, last_input_output.take_output(*idx));
-                }
+                chunk.iter().try_for_each(|idx| {
+                    outcomes.set_result(*idx, last_input_output.take_output(*idx))
+                })
             })
-            .collect::<()>();
+            .collect::<Result<(), E::Error>>()?;
 
         spawn(move || {
             // Explicit async drops.
@@ -10,16 +10,19 @@ mod outcome_array;
 pub mod proptest_types;
 mod scheduler;
 pub mod task;
-mod txn_last_input_output;
+pub mod txn_last_input_output;
 #[cfg(test)]
 mod unit_tests;
 pub mod utils;
 
-use evm::{backend::Apply, executor::stack::RwSet};
+use evm::{
+    backend::{Apply, Backend},
+    executor::stack::RwSet,
+};
 use evm_utils::execute_tx;
 use executor::EtherMVHashMapView;
 use sslab_execution::{
-    evm_storage::backend::{CMemoryBackend, ExecutionBackend},
+    evm_storage::backend::{ApplyBackend, CMemoryBackend, ExecutionBackend},
     executor::Executable,
     types::EthereumTransaction,
 };
@@ -28,7 +31,10 @@ use sui_types::error::SuiError;
 use task::ExecutorTask;
 use tracing::{debug, warn};
 
-use crate::executor::ParallelTransactionExecutor;
+use crate::{
+    executor::{ExecutionStats, ParallelTransactionExecutor},
+    infallible::Mutex,
+};
 
 struct EtherTxn(EthereumTransaction);
 
@@ -60,15 +66,55 @@ impl task::TransactionOutput for EtherTxnOutput {
     }
 }
 
-struct EvmExecutorTask {
-    global_state: Arc<evm_utils::EvmStorage<CMemoryBackend>>,
+/// Returns the storage slots written by more than one output of a batch, each reported once.
+#[cfg(any(debug_assertions, test))]
+fn duplicate_writes(outputs: &[EtherTxnOutput]) -> Vec<(ethers::types::H160, ethers::types::H256)> {
+    let mut written = std::collections::HashSet::new();
+    let mut duplicates = std::collections::BTreeSet::new();
+    for output in outputs {
+        // A single output writes each slot at most once; collect its slots first so that only
+        // writes of different outputs are compared.
+        let slots: std::collections::HashSet<_> = output
+            .0
+            .iter()
+            .filter_map(|apply| match apply {
+                Apply::Modify {
+                    address, storage, ..
+                } => Some(storage.keys().map(move |key| (*address, *key))),
+                Apply::Delete { .. } => None,
+            })
+            .flatten()
+            .collect();
+        for slot in slots {
+            if !written.insert(slot) {
+                duplicates.insert(slot);
+            }
+        }
+    }
+    duplicates.into_iter().collect()
+}
+
+/// A backend to keep the global state of BlockSTM in, e.g. 'CMemoryBackend' (the default), or a
+/// persistent one.
+pub trait BlockSTMBackend:
+    Backend + ApplyBackend + Clone + Default + Send + Sync + 'static
+{
+}
+
+impl<B> BlockSTMBackend for B where
+    B: Backend + ApplyBackend + Clone + Default + Send + Sync + 'static
+{
 }
 
-impl ExecutorTask for EvmExecutorTask {
+struct EvmExecutorTask<B: BlockSTMBackend> {
+    global_state: Arc<evm_utils::EvmStorage<B>>,
+}
+
+impl<B: BlockSTMBackend> ExecutorTask for EvmExecutorTask<B> {
     type T = EtherTxn;
     type Output = EtherTxnOutput;
     type Error = SuiError;
-    type Argument = Arc<evm_utils::EvmStorage<CMemoryBackend>>;
+    type Argument = Arc<evm_utils::EvmStorage<B>>;
 
     fn init(args: Self::Argument) -> Self {
         //TODO: modify to get the referce of Arc<EvmStorage> ?, clone() is expensive.
@@ -99,24 +145,40 @@ impl ExecutorTask for EvmExecutorTask {
     }
 }
 
-pub struct BlockSTM {
-    global_state: Arc<evm_utils::EvmStorage<CMemoryBackend>>,
+pub struct BlockSTM<B: BlockSTMBackend = CMemoryBackend> {
+    global_state: Arc<evm_utils::EvmStorage<B>>,
+    concurrency_level: usize,
+    /// Aborts and re-executions of each batch of the last consensus output.
+    execution_stats: Mutex<Vec<ExecutionStats>>,
 }
 
-impl BlockSTM {
-    pub fn new(global_state: Arc<evm_utils::EvmStorage<CMemoryBackend>>) -> Self {
-        Self { global_state }
+impl<B: BlockSTMBackend> BlockSTM<B> {
+    /// Executes each batch on `concurrency_level` worker threads, see
+    /// 'ParallelTransactionExecutor::new'.
+    pub fn new(global_state: Arc<evm_utils::EvmStorage<B>>, concurrency_level: usize) -> Self {
+        Self {
+            global_state,
+            concurrency_level,
+            execution_stats: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Return the aborts and re-executions of each batch of the last executed consensus output,
+    /// e.g. to compare them against the aborted transactions of OptME.
+    pub fn execution_stats(&self) -> Vec<ExecutionStats> {
+        self.execution_stats.lock().clone()
     }
 }
 
 #[async_trait::async_trait]
-impl Executable for BlockSTM {
+impl<B: BlockSTMBackend> Executable for BlockSTM<B> {
     async fn execute(
         &self,
         consensus_output: Vec<sslab_execution::types::ExecutableEthereumBatch>,
     ) {
-        let executor: ParallelTransactionExecutor<EtherTxn, EvmExecutorTask> =
-            ParallelTransactionExecutor::new();
+        let executor: ParallelTransactionExecutor<EtherTxn, EvmExecutorTask<B>> =
+            ParallelTransactionExecutor::new(self.concurrency_level);
+        let mut execution_stats = Vec::with_capacity(consensus_output.len());
 
         for batch in consensus_output.into_iter() {
             let txn_to_execute = batch
@@ -126,9 +188,26 @@ impl Executable for BlockSTM {
                 .map(|txn| EtherTxn(txn))
                 .collect();
 
-            match executor.execute_transactions_parallel(self.global_state.clone(), txn_to_execute)
-            {
+            let result =
+                executor.execute_transactions_parallel(self.global_state.clone(), txn_to_execute);
+            execution_stats.push(executor.last_execution_stats());
+
+            match result {
                 Ok(effects) => {
+                    // The flattened effects are applied in order, so the last write to a slot
+                    // wins. This is the expected outcome of later transactions overwriting earlier
+                    // ones, so the overlaps are only traced in debug builds to help inspect validation.
+                    #[cfg(debug_assertions)]
+                    {
+                        let duplicates = duplicate_writes(&effects);
+                        if !duplicates.is_empty() {
+                            debug!(
+                                "{} storage slots are written by more than one transaction: {:?}",
+                                duplicates.len(),
+                                duplicates
+                            );
+                        }
+                    }
                     let _effects = effects.into_iter().flat_map(|output| output.0).collect();
                     self.global_state.apply_local_effect(_effects);
                 }
@@ -137,17 +216,19 @@ impl Executable for BlockSTM {
                 }
             }
         }
+
+        *self.execution_stats.lock() = execution_stats;
     }
 }
 
 #[cfg(feature = "latency")]
-impl BlockSTM {
+impl<B: BlockSTMBackend> BlockSTM<B> {
     pub async fn execute_and_return_commit_latency(
         &self,
         consensus_output: Vec<sslab_execution::types::ExecutableEthereumBatch>,
     ) -> u128 {
-        let executor: ParallelTransactionExecutor<EtherTxn, EvmExecutorTask> =
-            ParallelTransactionExecutor::new();
+        let executor: ParallelTransactionExecutor<EtherTxn, EvmExecutorTask<B>> =
+            ParallelTransactionExecutor::new(self.concurrency_level);
         let mut commit_latency = 0;
 
         for batch in consensus_output.into_iter() {
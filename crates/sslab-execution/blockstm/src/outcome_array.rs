@@ -6,6 +6,7 @@ use crate::{
     task::{ExecutionStatus, TransactionOutput},
 };
 use once_cell::sync::OnceCell;
+use tracing::error;
 
 pub(crate) struct OutcomeArray<T, E> {
     // Hold the execution results for each individual transactions. Each cell should be set exactly
@@ -20,12 +21,41 @@ impl<T: TransactionOutput, E: Send> OutcomeArray<T, E> {
         }
     }
 
-    pub fn set_result(&self, idx: usize, res: ExecutionStatus<T, Error<E>>) {
-        // We don't need to worry about double writes due to the unique assignment of txn ids within
-        // a block here. And each txn id will be scheduled to execute exactly once.
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    /// Returns how many cells have been set so far, together with the allocated length.
+    pub fn utilization(&self) -> (usize, usize) {
+        let filled = self
+            .results
+            .iter()
+            .filter(|cell| cell.get().is_some())
+            .count();
+        (filled, self.results.len())
+    }
 
-        let entry = &self.results[idx];
-        assert!(entry.set(res).is_ok());
+    pub fn set_result(&self, idx: usize, res: ExecutionStatus<T, Error<E>>) -> Result<(), E> {
+        // We don't need to worry about double writes due to the unique assignment of txn ids within
+        // a block here. And each txn id will be scheduled to execute exactly once. Both cases are
+        // still reported as an invariant violation rather than a panic, since they can only stem
+        // from a mis-sized array or a scheduler bug.
+        let entry = match self.results.get(idx) {
+            Some(entry) => entry,
+            None => {
+                error!(
+                    "outcome index {} is out of bounds (allocated for {} txns)",
+                    idx,
+                    self.results.len()
+                );
+                return Err(Error::InvariantViolation);
+            }
+        };
+        if entry.set(res).is_err() {
+            error!("outcome of txn {} has already been set", idx);
+            return Err(Error::InvariantViolation);
+        }
+        Ok(())
     }
 
     pub fn get_all_results(self, stop_at: usize) -> Result<Vec<T>, E> {
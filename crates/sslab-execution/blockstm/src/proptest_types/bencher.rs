@@ -108,8 +108,9 @@ where
     }
 
     pub(crate) fn run(self) {
-        let output = ParallelTransactionExecutor::<Transaction<K, V>, Task<K, V>>::new()
-            .execute_transactions_parallel((), self.transactions);
+        let output =
+            ParallelTransactionExecutor::<Transaction<K, V>, Task<K, V>>::new(num_cpus::get())
+                .execute_transactions_parallel((), self.transactions);
 
         if let Some(expected_output) = self.expected_output {
             assert!(expected_output.check_output(&output))
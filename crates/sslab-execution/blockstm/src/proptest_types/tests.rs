@@ -35,7 +35,7 @@ where
 
     let baseline = ExpectedOutput::generate_baseline(&transactions);
 
-    let output = ParallelTransactionExecutor::<Transaction<K, V>, Task<K, V>>::new()
+    let output = ParallelTransactionExecutor::<Transaction<K, V>, Task<K, V>>::new(num_cpus::get())
         .execute_transactions_parallel((), transactions);
 
     baseline.check_output(&output)
@@ -5,6 +5,7 @@ use crate::infallible::Mutex;
 use std::{
     cmp::min,
     hint,
+    ops::Range,
     sync::atomic::{AtomicBool, AtomicUsize, Ordering},
 };
 
@@ -110,6 +111,20 @@ pub struct Scheduler {
     /// reconfiguration leads to early stopping (at that transaction idx).
     stop_idx: AtomicUsize,
 
+    /// Maximum number of re-executions a transaction may go through before the scheduler gives
+    /// up on executing it in parallel. Unbounded unless set via 'with_max_reexecutions'.
+    max_reexecutions: Incarnation,
+    /// Smallest index of a transaction that hit 'max_reexecutions'. Transactions from this index
+    /// onwards are no longer scheduled, and are left to the caller to execute serially once the
+    /// preceding transactions are done.
+    fallback_idx: AtomicUsize,
+
+    /// Number of validation failures that aborted an incarnation, see 'try_abort'.
+    num_aborts: AtomicUsize,
+    /// Number of incarnations created beyond the first of each transaction, due to aborts or
+    /// resolved read dependencies.
+    num_reexecutions: AtomicUsize,
+
     /// An index i maps to indices of other transactions that depend on transaction i, i.e. they
     /// should be re-executed once transaction i's next incarnation finishes.
     txn_dependency: Vec<CachePadded<Mutex<Vec<TxnIndex>>>>,
@@ -127,6 +142,10 @@ impl Scheduler {
             num_active_tasks: AtomicUsize::new(0),
             done_marker: AtomicBool::new(false),
             stop_idx: AtomicUsize::new(num_txns),
+            max_reexecutions: Incarnation::MAX,
+            fallback_idx: AtomicUsize::new(num_txns),
+            num_aborts: AtomicUsize::new(0),
+            num_reexecutions: AtomicUsize::new(0),
             txn_dependency: (0..num_txns)
                 .map(|_| CachePadded::new(Mutex::new(Vec::new())))
                 .collect(),
@@ -136,15 +155,44 @@ impl Scheduler {
         }
     }
 
+    /// Bound the number of re-executions per transaction. Once a transaction would be incarnated
+    /// for the 'max_reexecutions'-th time, it (and every higher transaction) falls back to serial
+    /// execution, see 'serial_fallback'.
+    pub fn with_max_reexecutions(mut self, max_reexecutions: Incarnation) -> Self {
+        assert!(
+            max_reexecutions > 0,
+            "at least one re-execution must be allowed"
+        );
+        self.max_reexecutions = max_reexecutions;
+        self
+    }
+
+    /// Return the incarnation number that transactions executed by the serial fallback carry.
+    pub fn max_reexecutions(&self) -> Incarnation {
+        self.max_reexecutions
+    }
+
     /// Reset txn_idx to end the execution earlier. The executor will stop at the smallest
     /// `stop_idx` when there are multiple concurrent invocation.
     pub fn set_stop_idx(&self, stop_idx: TxnIndex) {
         self.stop_idx.fetch_min(stop_idx, Ordering::Relaxed);
     }
 
-    /// Return the number of transactions to be executed from the block.
+    /// Return the number of transactions to be executed in parallel from the block.
     pub fn num_txn_to_execute(&self) -> usize {
-        self.stop_idx.load(Ordering::Relaxed)
+        min(
+            self.stop_idx.load(Ordering::Relaxed),
+            self.fallback_idx.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Return the transactions that exceeded (or follow a transaction that exceeded) the
+    /// re-execution cap, if any. They must be executed serially, in order, after the parallel
+    /// execution of the preceding transactions is done.
+    pub fn serial_fallback(&self) -> Option<Range<TxnIndex>> {
+        let fallback_idx = self.fallback_idx.load(Ordering::Relaxed);
+        let stop_idx = self.stop_idx.load(Ordering::Relaxed);
+        (fallback_idx < stop_idx).then_some(fallback_idx..stop_idx)
     }
 
     /// Try to abort version = (txn_idx, incarnation), called upon validation failure.
@@ -158,12 +206,24 @@ impl Scheduler {
 
         if *status == TransactionStatus::Executed(incarnation) {
             *status = TransactionStatus::Aborting(incarnation);
+            self.num_aborts.fetch_add(1, Ordering::Relaxed);
             true
         } else {
             false
         }
     }
 
+    /// Return the number of incarnations aborted so far, due to validation failures.
+    pub fn num_aborts(&self) -> usize {
+        self.num_aborts.load(Ordering::Relaxed)
+    }
+
+    /// Return the number of re-executions (i.e. incarnations other than the first) started so
+    /// far, due to aborts or resolved read dependencies.
+    pub fn num_reexecutions(&self) -> usize {
+        self.num_reexecutions.load(Ordering::Relaxed)
+    }
+
     /// Return the next task for the thread.
     pub fn next_task(&self) -> SchedulerTask {
         loop {
@@ -329,10 +389,17 @@ impl Scheduler {
         if txn_idx >= self.txn_status.len() {
             return None;
         }
+        if txn_idx >= self.fallback_idx.load(Ordering::SeqCst) {
+            // Left to the serial fallback.
+            return None;
+        }
 
         let mut status = self.txn_status[txn_idx].lock();
         if let TransactionStatus::ReadyToExecute(incarnation) = *status {
             *status = TransactionStatus::Executing(incarnation);
+            if incarnation > 0 {
+                self.num_reexecutions.fetch_add(1, Ordering::Relaxed);
+            }
             Some(incarnation)
         } else {
             None
@@ -425,6 +492,7 @@ impl Scheduler {
         let mut status = self.txn_status[txn_idx].lock();
         if let TransactionStatus::Executing(incarnation) = *status {
             *status = TransactionStatus::ReadyToExecute(incarnation + 1);
+            self.check_reexecution_cap(txn_idx, incarnation + 1);
         } else {
             unreachable!();
         }
@@ -449,6 +517,16 @@ impl Scheduler {
         debug_assert!(*status == TransactionStatus::Aborting(incarnation));
 
         *status = TransactionStatus::ReadyToExecute(incarnation + 1);
+        self.check_reexecution_cap(txn_idx, incarnation + 1);
+    }
+
+    /// If the next incarnation of the transaction reaches the re-execution cap, stop scheduling
+    /// it (and all higher transactions) so that it is executed serially instead. This bounds the
+    /// work spent on a transaction that keeps getting aborted under heavy contention.
+    fn check_reexecution_cap(&self, txn_idx: TxnIndex, next_incarnation: Incarnation) {
+        if next_incarnation >= self.max_reexecutions {
+            self.fallback_idx.fetch_min(txn_idx, Ordering::SeqCst);
+        }
     }
 
     /// A lazy, check of whether the scheduler execution is completed.
@@ -47,6 +47,14 @@ impl<K> ReadDescriptor<K> {
         &self.access_path
     }
 
+    /// The version of the write observed by the read, or None if it was read from storage.
+    pub fn version(&self) -> Option<Version> {
+        match self.kind {
+            ReadKind::MVHashMap(txn_idx, incarnation) => Some((txn_idx, incarnation)),
+            ReadKind::Storage => None,
+        }
+    }
+
     // Does the read descriptor describe a read from MVHashMap w. a specified version.
     pub fn validate_version(&self, version: Version) -> bool {
         let (txn_idx, incarnation) = version;
@@ -59,6 +67,14 @@ impl<K> ReadDescriptor<K> {
     }
 }
 
+/// The keys a transaction read and wrote in its last recorded incarnation. Useful to find out
+/// why a transaction kept getting aborted.
+#[derive(Debug, Clone)]
+pub struct TxnAccesses<K> {
+    pub reads: Vec<K>,
+    pub writes: HashSet<K>,
+}
+
 pub struct TxnLastInputOutput<K, T, E> {
     inputs: Vec<CachePadded<ArcSwapOption<TxnInput<K>>>>, // txn_idx -> input.
 
@@ -107,6 +123,24 @@ impl<K, T: TransactionOutput, E: Send + Clone> TxnLastInputOutput<K, T, E> {
         }
     }
 
+    // Dumps the read and write keys last recorded for every transaction. Must be called before
+    // the outputs are taken, since the write set is extracted from the output.
+    pub fn dump_accesses(&self) -> Vec<TxnAccesses<K>>
+    where
+        K: Clone,
+        <T as TransactionOutput>::T: Transaction<Key = K>,
+    {
+        (0..self.inputs.len())
+            .map(|txn_idx| TxnAccesses {
+                reads: self
+                    .read_set(txn_idx)
+                    .map(|reads| reads.iter().map(|r| r.path().clone()).collect())
+                    .unwrap_or_default(),
+                writes: self.write_set(txn_idx),
+            })
+            .collect()
+    }
+
     // Must be executed after parallel execution is done, grabs outputs. Will panic if
     // other outstanding references to the recorded outputs exist.
     pub fn take_output(&self, txn_idx: TxnIndex) -> ExecutionStatus<T, Error<E>> {
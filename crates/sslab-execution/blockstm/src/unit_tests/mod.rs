@@ -2,12 +2,42 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    executor::ParallelTransactionExecutor,
-    proptest_types::types::{ExpectedOutput, Task, Transaction},
+    duplicate_writes,
+    errors::Error,
+    executor::{EtherMVHashMapView, ExecutionStats, MVHashMapView, ParallelTransactionExecutor},
+    mvhashmap::MVHashMap,
+    outcome_array::OutcomeArray,
+    proptest_types::types::{ExpectedOutput, Output, Task, Transaction},
     scheduler::{Scheduler, SchedulerTask, TaskGuard},
+    task::{ExecutionStatus, ExecutorTask, TransactionOutput},
+    BlockSTM, EtherTxnOutput,
+};
+use ethers::types::{H160, H256, U256};
+use ethers_providers::{MockProvider, Provider};
+use evm::{
+    backend::{Apply, Backend, Basic},
+    executor::stack::{MultiversionView, RwSet},
 };
 use rand::random;
-use std::{fmt::Debug, hash::Hash, sync::atomic::AtomicUsize};
+use sslab_execution::{
+    evm_storage::backend::{CAccount, CMemoryBackend, MemoryBackend},
+    executor::Executable,
+    types::ExecutableEthereumBatch,
+    utils::{
+        smallbank_contract_benchmark::DEFAULT_CONTRACT_ADDRESS,
+        test_utils::{SmallBankTransactionHandler, DEFAULT_CHAIN_ID},
+    },
+};
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    hash::Hash,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 
 fn run_and_assert<K, V>(transactions: Vec<Transaction<K, V>>)
 where
@@ -16,7 +46,7 @@ where
 {
     let baseline = ExpectedOutput::generate_baseline(&transactions);
 
-    let output = ParallelTransactionExecutor::<Transaction<K, V>, Task<K, V>>::new()
+    let output = ParallelTransactionExecutor::<Transaction<K, V>, Task<K, V>>::new(num_cpus::get())
         .execute_transactions_parallel((), transactions);
 
     assert!(baseline.check_output(&output))
@@ -43,6 +73,35 @@ fn cycle_transactions() {
     run_and_assert(transactions)
 }
 
+/// Wraps the naive `Task`, counting how many times each transaction gets executed.
+struct CountingTask {
+    inner: Task<[u8; 32], u64>,
+    executions: Arc<Vec<AtomicUsize>>,
+}
+
+impl ExecutorTask for CountingTask {
+    type T = Transaction<[u8; 32], u64>;
+    type Output = Output<[u8; 32], u64>;
+    type Error = usize;
+    type Argument = Arc<Vec<AtomicUsize>>;
+
+    fn init(executions: Self::Argument) -> Self {
+        Self {
+            inner: Task::new(),
+            executions,
+        }
+    }
+
+    fn execute_transaction(
+        &self,
+        view: &MVHashMapView<[u8; 32], u64>,
+        txn: &Self::T,
+    ) -> ExecutionStatus<Self::Output, Self::Error> {
+        self.executions[view.txn_idx()].fetch_add(1, Ordering::SeqCst);
+        self.inner.execute_transaction(view, txn)
+    }
+}
+
 const NUM_BLOCKS: u64 = 10;
 const TXN_PER_BLOCK: u64 = 100;
 
@@ -131,6 +190,136 @@ fn early_skips() {
     run_and_assert(transactions)
 }
 
+#[test]
+fn recorded_accesses() {
+    let key = random::<[u8; 32]>();
+    let transactions = vec![
+        Transaction::Write {
+            reads: vec![],
+            actual_writes: vec![(key, random::<u64>())],
+            skipped_writes: vec![],
+        },
+        Transaction::Write {
+            reads: vec![key],
+            actual_writes: vec![],
+            skipped_writes: vec![],
+        },
+    ];
+
+    let executor =
+        ParallelTransactionExecutor::<Transaction<[u8; 32], u64>, Task<[u8; 32], u64>>::new(
+            num_cpus::get(),
+        )
+        .with_recorded_accesses();
+    assert!(executor
+        .execute_transactions_parallel((), transactions)
+        .is_ok());
+
+    let accesses = executor.recorded_accesses();
+    assert_eq!(accesses.len(), 2);
+    assert!(accesses[0].writes.contains(&key));
+    assert!(accesses[1].reads.contains(&key));
+}
+
+#[test]
+fn reexecution_cap_hotspot() {
+    const MAX_REEXECUTIONS: usize = 2;
+
+    // Every transaction reads and writes the same key.
+    let key = random::<[u8; 32]>();
+    let transactions: Vec<_> = (0..NUM_BLOCKS * TXN_PER_BLOCK)
+        .map(|_| Transaction::Write {
+            reads: vec![key],
+            actual_writes: vec![(key, random::<u64>())],
+            skipped_writes: vec![],
+        })
+        .collect();
+    let baseline = ExpectedOutput::generate_baseline(&transactions);
+    let executions: Arc<Vec<AtomicUsize>> = Arc::new(
+        (0..transactions.len())
+            .map(|_| AtomicUsize::new(0))
+            .collect(),
+    );
+
+    let output = ParallelTransactionExecutor::<Transaction<[u8; 32], u64>, CountingTask>::new(
+        num_cpus::get(),
+    )
+    .with_max_reexecutions(MAX_REEXECUTIONS)
+    .execute_transactions_parallel(executions.clone(), transactions);
+
+    assert!(baseline.check_output(&output));
+    // At most MAX_REEXECUTIONS parallel incarnations, plus the serial fallback.
+    assert!(executions
+        .iter()
+        .all(|count| count.load(Ordering::SeqCst) <= MAX_REEXECUTIONS + 1));
+}
+
+#[test]
+fn observed_versions() {
+    let versioned_map = MVHashMap::new();
+    let scheduler = Scheduler::new(3);
+    let (written, unwritten) = (H256::from_low_u64_be(1), H256::from_low_u64_be(2));
+
+    // txn 1 wrote the key (in its 2nd incarnation), which txn 2 depends on.
+    versioned_map.write(&written, (1, 1), H256::from_low_u64_be(3));
+
+    let view = MVHashMapView::new(&versioned_map, 2, &scheduler);
+    let ether_view = EtherMVHashMapView {
+        versioned_map: &view,
+    };
+    assert!(ether_view.read(&H160::zero(), &written).unwrap().is_some());
+    assert!(ether_view
+        .read(&H160::zero(), &unwritten)
+        .unwrap()
+        .is_none());
+
+    assert_eq!(
+        ether_view.observed_versions(),
+        vec![(written, Some((1, 1))), (unwritten, None)]
+    );
+    // Observing does not drain the reads.
+    assert_eq!(ether_view.take_reads().len(), 2);
+}
+
+#[test]
+fn scheduler_abort_counters() {
+    let s = Scheduler::new(2);
+    let fake_counter = AtomicUsize::new(0);
+
+    // Both txns are executed speculatively at once.
+    assert!(matches!(
+        s.next_task(),
+        SchedulerTask::ExecutionTask((0, 0), _)
+    ));
+    assert!(matches!(
+        s.next_task(),
+        SchedulerTask::ExecutionTask((1, 0), _)
+    ));
+    assert!(matches!(
+        s.finish_execution(0, 0, false, TaskGuard::new(&fake_counter)),
+        SchedulerTask::ValidationTask((0, 0), _)
+    ));
+    assert!(matches!(
+        s.finish_execution(1, 0, false, TaskGuard::new(&fake_counter)),
+        SchedulerTask::NoTask
+    ));
+    assert_eq!(s.num_aborts(), 0);
+    assert_eq!(s.num_reexecutions(), 0);
+
+    // txn 1 read what txn 0 has overwritten, so its validation fails and it is re-executed.
+    assert!(s.try_abort(1, 0));
+    assert!(matches!(
+        s.finish_abort(1, 0, TaskGuard::new(&fake_counter)),
+        SchedulerTask::ExecutionTask((1, 1), _)
+    ));
+    assert_eq!(s.num_aborts(), 1);
+    assert_eq!(s.num_reexecutions(), 1);
+
+    // The same version may not be aborted twice.
+    assert!(!s.try_abort(1, 0));
+    assert_eq!(s.num_aborts(), 1);
+}
+
 #[test]
 fn scheduler_tasks() {
     let s = Scheduler::new(6);
@@ -367,3 +556,258 @@ fn scheduler_incarnation() {
 
     assert!(matches!(s.next_task(), SchedulerTask::Done));
 }
+
+#[test]
+fn outcome_array_bounds() {
+    let outcomes = OutcomeArray::<Output<[u8; 32], u64>, usize>::new(TXN_PER_BLOCK as usize);
+    assert_eq!(outcomes.len(), TXN_PER_BLOCK as usize);
+    assert_eq!(outcomes.utilization(), (0, TXN_PER_BLOCK as usize));
+
+    for idx in 0..TXN_PER_BLOCK as usize {
+        assert!(outcomes
+            .set_result(idx, ExecutionStatus::Success(Output::skip_output()))
+            .is_ok());
+    }
+    assert_eq!(
+        outcomes.utilization(),
+        (TXN_PER_BLOCK as usize, TXN_PER_BLOCK as usize)
+    );
+
+    // An index past the allocated length and a double write are both rejected.
+    assert!(matches!(
+        outcomes.set_result(
+            TXN_PER_BLOCK as usize,
+            ExecutionStatus::Success(Output::skip_output())
+        ),
+        Err(Error::InvariantViolation)
+    ));
+    assert!(matches!(
+        outcomes.set_result(0, ExecutionStatus::Success(Output::skip_output())),
+        Err(Error::InvariantViolation)
+    ));
+}
+
+#[test]
+fn scheduler_reexecution_cap() {
+    let s = Scheduler::new(3).with_max_reexecutions(2);
+    let fake_counter = AtomicUsize::new(0);
+
+    for i in 0..3 {
+        assert!(matches!(
+            s.next_task(),
+            SchedulerTask::ExecutionTask((j, 0), _) if i == j
+        ));
+    }
+
+    // First abort of txn 1 is re-executed in parallel.
+    assert!(matches!(
+        s.finish_execution(1, 0, false, TaskGuard::new(&fake_counter)),
+        SchedulerTask::NoTask
+    ));
+    assert!(s.try_abort(1, 0));
+    assert!(matches!(
+        s.finish_abort(1, 0, TaskGuard::new(&fake_counter)),
+        SchedulerTask::ExecutionTask((1, 1), _)
+    ));
+    assert!(s.serial_fallback().is_none());
+
+    // Second abort hits the cap: txn 1 and above are left to the serial fallback.
+    assert!(matches!(
+        s.finish_execution(1, 1, false, TaskGuard::new(&fake_counter)),
+        SchedulerTask::NoTask
+    ));
+    assert!(s.try_abort(1, 1));
+    assert!(matches!(
+        s.finish_abort(1, 1, TaskGuard::new(&fake_counter)),
+        SchedulerTask::NoTask
+    ));
+    assert_eq!(s.serial_fallback(), Some(1..3));
+    assert_eq!(s.num_txn_to_execute(), 1);
+
+    // Only txn 0 remains to be scheduled in parallel.
+    assert!(matches!(
+        s.finish_execution(0, 0, false, TaskGuard::new(&fake_counter)),
+        SchedulerTask::NoTask
+    ));
+    assert!(matches!(
+        s.next_task(),
+        SchedulerTask::ValidationTask((0, 0), _)
+    ));
+    assert!(matches!(s.next_task(), SchedulerTask::Done));
+}
+
+#[test]
+fn cmemory_backend_with_accounts() {
+    const NUM_ACCOUNTS: u64 = 1000;
+
+    let backend = CMemoryBackend::with_accounts((0..NUM_ACCOUNTS).map(|i| {
+        (
+            H160::from_low_u64_be(i),
+            CAccount {
+                nonce: U256::one(),
+                balance: U256::from(i),
+                ..Default::default()
+            },
+        )
+    }));
+
+    assert_eq!(backend.state().len(), NUM_ACCOUNTS as usize);
+    for i in [0, 1, NUM_ACCOUNTS / 2, NUM_ACCOUNTS - 1] {
+        let basic = backend.basic(H160::from_low_u64_be(i));
+        assert_eq!(basic.balance, U256::from(i));
+        assert_eq!(basic.nonce, U256::one());
+    }
+    assert!(!backend.exists(H160::from_low_u64_be(NUM_ACCOUNTS)));
+}
+
+#[test]
+fn duplicate_writes_are_detected() {
+    let address = H160::from_low_u64_be(1);
+    let (shared, other) = (H256::from_low_u64_be(1), H256::from_low_u64_be(2));
+    let output = |slots: &[H256]| {
+        let modify = Apply::Modify {
+            address,
+            basic: Basic::default(),
+            code: None,
+            storage: slots
+                .iter()
+                .map(|slot| (*slot, H256::from_low_u64_be(random::<u64>())))
+                .collect::<BTreeMap<_, _>>(),
+            reset_storage: false,
+        };
+        EtherTxnOutput(vec![modify], RwSet::default())
+    };
+
+    assert!(duplicate_writes(&[output(&[shared]), output(&[other])]).is_empty());
+    assert_eq!(
+        duplicate_writes(&[output(&[shared]), output(&[shared, other])]),
+        vec![(address, shared)]
+    );
+}
+
+fn smallbank_handler() -> SmallBankTransactionHandler {
+    SmallBankTransactionHandler::new(
+        Provider::<MockProvider>::new(MockProvider::default()),
+        DEFAULT_CHAIN_ID,
+    )
+}
+
+/* reads the smallbank contract storage, whichever storage wraps the backend */
+fn smallbank_storage(backend: &CMemoryBackend) -> BTreeMap<H256, H256> {
+    let state = backend.state().pin();
+    let contract = state
+        .get(&H160::from_str(DEFAULT_CONTRACT_ADDRESS).unwrap())
+        .unwrap();
+    let storage = contract.storage.pin();
+    storage.iter().map(|(key, value)| (*key, *value)).collect()
+}
+
+#[tokio::test]
+async fn test_blockstm_with_two_threads() {
+    let handler = smallbank_handler();
+    let blockstm = |concurrency_level| {
+        let state = Arc::new(crate::utils::smallbank_contract_benchmark::concurrent_evm_storage());
+        (BlockSTM::new(state.clone(), concurrency_level), state)
+    };
+
+    //given
+    let batches = vec![
+        ExecutableEthereumBatch::new(
+            (0..4)
+                .map(|acc| handler.create_account_transaction(acc))
+                .collect(),
+            Default::default(),
+        ),
+        ExecutableEthereumBatch::new(
+            (0..8)
+                .map(|i| handler.send_payment_transaction(i % 4, (i + 1) % 4, U256::one()))
+                .collect(),
+            Default::default(),
+        ),
+    ];
+    let (two_threads, two_threads_state) = blockstm(2);
+    let (all_threads, all_threads_state) = blockstm(num_cpus::get());
+
+    //when
+    two_threads.execute(batches.clone()).await;
+    all_threads.execute(batches).await;
+
+    //then
+    let expected = smallbank_storage(all_threads_state.get_storage());
+    assert!(!expected.is_empty());
+    assert_eq!(smallbank_storage(two_threads_state.get_storage()), expected);
+}
+
+#[tokio::test]
+async fn test_blockstm_over_memory_backend() {
+    let handler = smallbank_handler();
+    let contract = H160::from_str(DEFAULT_CONTRACT_ADDRESS).unwrap();
+
+    //given
+    let batches = vec![
+        ExecutableEthereumBatch::new(
+            (0..4)
+                .map(|acc| handler.create_account_transaction(acc))
+                .collect(),
+            Default::default(),
+        ),
+        ExecutableEthereumBatch::new(
+            (0..8)
+                .map(|i| handler.send_payment_transaction(i % 4, (i + 1) % 4, U256::one()))
+                .collect(),
+            Default::default(),
+        ),
+    ];
+    let concurrent_state =
+        Arc::new(crate::utils::smallbank_contract_benchmark::concurrent_evm_storage());
+    let memory_state = Arc::new(crate::utils::smallbank_contract_benchmark::memory_evm_storage());
+    let over_concurrent: BlockSTM<CMemoryBackend> = BlockSTM::new(concurrent_state.clone(), 2);
+    let over_memory: BlockSTM<MemoryBackend> = BlockSTM::new(memory_state.clone(), 2);
+
+    //when
+    over_concurrent.execute(batches.clone()).await;
+    over_memory.execute(batches).await;
+
+    //then
+    let expected = smallbank_storage(concurrent_state.get_storage());
+    assert!(!expected.is_empty());
+    expected.iter().for_each(|(key, value)| {
+        assert_eq!(memory_state.get_storage().storage(contract, *key), *value)
+    });
+}
+
+#[tokio::test]
+async fn test_blockstm_execution_stats_per_batch() {
+    let handler = smallbank_handler();
+    let state = Arc::new(crate::utils::smallbank_contract_benchmark::concurrent_evm_storage());
+    let blockstm = BlockSTM::new(state, 2);
+
+    //given
+    let batches = vec![
+        ExecutableEthereumBatch::new(
+            vec![handler.create_account_transaction(0)],
+            Default::default(),
+        ),
+        ExecutableEthereumBatch::new(
+            (0..8)
+                .map(|i| handler.send_payment_transaction(i % 4, (i + 1) % 4, U256::one()))
+                .collect(),
+            Default::default(),
+        ),
+    ];
+
+    //when
+    blockstm.execute(batches.clone()).await;
+
+    //then (one entry per batch, and a single transaction never conflicts)
+    let stats = blockstm.execution_stats();
+    assert_eq!(stats.len(), 2);
+    assert_eq!(stats[0], ExecutionStats::default());
+    assert!(stats[1].reexecutions >= stats[1].aborts);
+
+    //when (the next consensus output replaces the stats)
+    blockstm.execute(batches[..1].to_vec()).await;
+
+    //then
+    assert_eq!(blockstm.execution_stats(), vec![ExecutionStats::default()]);
+}
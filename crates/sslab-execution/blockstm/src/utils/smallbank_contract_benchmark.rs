@@ -5,7 +5,7 @@ use sslab_execution::{utils::smallbank_contract_benchmark::{
     DEFAULT_CONTRACT_ADDRESS, 
     CONTRACT_BYTECODE, 
     ADMIN_ADDRESS
-}, evm_storage::backend::CMemoryBackend};
+}, evm_storage::backend::{CMemoryBackend, MemoryBackend}};
 
 use crate::evm_utils::EvmStorage;
 
@@ -19,4 +19,15 @@ pub fn concurrent_evm_storage() -> EvmStorage<CMemoryBackend> {
         memory_backend,
         BTreeMap::new(),
     )
-}
\ No newline at end of file
+}
+/// The same initial state as 'concurrent_evm_storage', but kept in a 'MemoryBackend'.
+pub fn memory_evm_storage() -> EvmStorage<MemoryBackend> {
+    let memory_backend = sslab_execution::evm_storage::memory_storage(
+        DEFAULT_CONTRACT_ADDRESS,
+        CONTRACT_BYTECODE,
+        ADMIN_ADDRESS,
+    )
+    .get_storage()
+    .clone();
+    EvmStorage::new(U64::from(9), memory_backend, BTreeMap::new())
+}
@@ -1,8 +1,14 @@
 use std::fmt::Debug;
+use std::sync::Arc;
 use ethers_core::types::{U256, H256, H160};
 use evm::backend::{MemoryVicinity, Backend, Basic, Apply};
+use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::sync::atomic::{AtomicU64, Ordering};
 use super::{ApplyBackend, ConcurrentHashMap};
 
+/// The number of lock stripes of [`CMemoryBackend::new`].
+pub const DEFAULT_LOCK_STRIPES: usize = 256;
+
 
 #[derive(Debug, Default, Clone)]
 pub struct CAccount {
@@ -16,26 +22,151 @@ pub struct CAccount {
 	pub code: Vec<u8>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct CMemoryBackend {
     vicinity: MemoryVicinity,
-    state: ConcurrentHashMap<H160, CAccount>
+    state: ConcurrentHashMap<H160, CAccount>,
+    stripes: Arc<[RwLock<()>]>,
+    /// See [`CMemoryBackend::contended_locks`].
+    contended_locks: AtomicU64,
+    /// The number of reads and writes of each storage slot, since the backend was created (see [`CMemoryBackend::hot_keys`]).
+    #[cfg(feature = "storage-metrics")]
+    access_counts: ConcurrentHashMap<(H160, H256), AtomicU64>,
 }
 
 impl CMemoryBackend {
 
-	/// Create a new memory backend.
+	/// Create a new memory backend with [`DEFAULT_LOCK_STRIPES`] lock stripes.
 	pub fn new(vicinity: MemoryVicinity, state: ConcurrentHashMap<H160, CAccount>) -> Self {
+		Self::new_striped(vicinity, state, DEFAULT_LOCK_STRIPES)
+	}
+
+	/// Create a new memory backend whose accounts are guarded by `num_stripes` locks, keyed by the address.
+	/// An effect is applied to an account under the lock of its stripe: the storage-only effects, applied in place,
+	/// share it, and the others, which write a copy of the account back, hold it exclusively. So an account is never
+	/// replaced by a copy missing a concurrent write to its storage, while the effects on the accounts of different
+	/// stripes are applied fully in parallel.
+	pub fn new_striped(
+		vicinity: MemoryVicinity,
+		state: ConcurrentHashMap<H160, CAccount>,
+		num_stripes: usize,
+	) -> Self {
+		assert!(num_stripes > 0, "the number of lock stripes must be positive");
+
 		Self {
 			vicinity,
 			state,
+			stripes: (0..num_stripes).map(|_| RwLock::new(())).collect(),
+			contended_locks: AtomicU64::new(0),
+			#[cfg(feature = "storage-metrics")]
+			access_counts: ConcurrentHashMap::default(),
 		}
 	}
 
+	/// Create a memory backend with the default vicinity, pre-populated with the given accounts.
+	/// Much cheaper than replaying account-creation effects when setting up a large state.
+	pub fn with_accounts(accounts: impl IntoIterator<Item = (H160, CAccount)>) -> Self {
+		let backend = Self::default();
+		{
+			let state = backend.state.pin();
+			for (address, account) in accounts {
+				state.insert(address, account);
+			}
+		}
+		backend
+	}
+
 	/// Get the underlying `BTreeMap` storing the state.
 	pub fn state(&self) -> &ConcurrentHashMap<H160, CAccount> {
 		&self.state
 	}
+
+	/// Re-stripe the locks of the backend, see [`CMemoryBackend::new_striped`].
+	pub fn with_stripes(self, num_stripes: usize) -> Self {
+		Self::new_striped(self.vicinity, self.state, num_stripes)
+	}
+
+	pub fn num_stripes(&self) -> usize {
+		self.stripes.len()
+	}
+
+	/// Lock the stripe of the given account exclusively, e.g., to read the account consistently.
+	/// Note that it blocks the effects on every account of the stripe until the guard is dropped.
+	pub fn lock_account(&self, address: &H160) -> RwLockWriteGuard<'_, ()> {
+		let stripe = self.stripe(address);
+		stripe.try_write().unwrap_or_else(|| {
+			self.contended_locks.fetch_add(1, Ordering::Relaxed);
+			stripe.write()
+		})
+	}
+
+	/// The number of times an effect (or [`CMemoryBackend::lock_account`]) waited for the lock of its stripe,
+	/// since the backend was created, e.g., to tell how much the stripes contend.
+	pub fn contended_locks(&self) -> u64 {
+		self.contended_locks.load(Ordering::Relaxed)
+	}
+
+	// locks the stripe of the account shared, i.e., for a write in place, counting the wait for it as `lock_account` does.
+	fn lock_account_shared(&self, address: &H160) -> RwLockReadGuard<'_, ()> {
+		let stripe = self.stripe(address);
+		stripe.try_read().unwrap_or_else(|| {
+			self.contended_locks.fetch_add(1, Ordering::Relaxed);
+			stripe.read()
+		})
+	}
+
+	fn stripe(&self, address: &H160) -> &RwLock<()> {
+		&self.stripes[(address.to_low_u64_be() % self.stripes.len() as u64) as usize]
+	}
+}
+
+#[cfg(feature = "storage-metrics")]
+impl CMemoryBackend {
+	/// The number of accounts in the state.
+	pub fn num_accounts(&self) -> usize {
+		self.state.len()
+	}
+
+	/// The number of (non-zero) storage slots over all the accounts.
+	pub fn num_storage_slots(&self) -> usize {
+		self.state.pin().values().map(|account| account.storage.len()).sum()
+	}
+
+	/// The `n` storage slots read or written the most, along with their access counts, hottest first.
+	/// The slots accessed as many times are ordered by address and index.
+	pub fn hot_keys(&self, n: usize) -> Vec<((H160, H256), u64)> {
+		let mut counts = self.access_counts
+			.pin()
+			.iter()
+			.map(|(key, count)| (*key, count.load(Ordering::Relaxed)))
+			.collect::<Vec<_>>();
+
+		counts.sort_unstable_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+		counts.truncate(n);
+		counts
+	}
+
+	fn record_access(&self, address: H160, index: H256) {
+		let counts = self.access_counts.pin();
+		match counts.get(&(address, index)) {
+			Some(count) => {
+				count.fetch_add(1, Ordering::Relaxed);
+			}
+			None => {
+				// another thread may have inserted the counter in the meantime.
+				if let Err(e) = counts.try_insert((address, index), AtomicU64::new(1)) {
+					e.current.fetch_add(1, Ordering::Relaxed);
+				}
+			}
+		}
+	}
+}
+
+impl Clone for CMemoryBackend {
+	// the copied state is independent of the original one, so it gets locks of its own.
+	fn clone(&self) -> Self {
+		Self::new_striped(self.vicinity.clone(), self.state.clone(), self.num_stripes())
+	}
 }
 
 impl Default for CMemoryBackend {
@@ -124,6 +255,9 @@ impl Backend for CMemoryBackend {
     }
 
     fn storage(&self, address: H160, index: H256) -> H256 {
+        #[cfg(feature = "storage-metrics")]
+        self.record_access(address, index);
+
         match self.state.pin().get(&address) {
             Some(v) => {
                 match v.storage.pin().get(&index) {
@@ -151,52 +285,238 @@ impl ApplyBackend for CMemoryBackend {
 					storage,
 					reset_storage,
 				} => {
-                    let state = self.state.pin();
-					let is_empty = {
-                        let mut account = state.get(&address).cloned().unwrap_or_default();
-
-						account.balance = basic.balance;
-						account.nonce = basic.nonce;
-						if let Some(code) = code {
-							account.code = code;
-						}
+					#[cfg(feature = "storage-metrics")]
+					for index in storage.keys() {
+						self.record_access(address, *index);
+					}
 
-						if reset_storage {
-							account.storage = ConcurrentHashMap::default();
+					// storage-only changes are applied in place to the live account, so that
+					// the transactions committed in parallel (i.e., in the same generation) do not
+					// overwrite each other's writes to the same contract.
+					let shared = self.lock_account_shared(&address);
+					let state = self.state.pin();
+					match state.get(&address) {
+						Some(account) if account.balance == basic.balance
+							&& account.nonce == basic.nonce
+							&& code.is_none()
+							&& !reset_storage =>
+						{
+							let account_storage = account.storage.pin();
+							for (index, value) in storage {
+								if value == H256::default() {
+									account_storage.remove(&index);
+								} else {
+									account_storage.insert(index, value);
+								}
+							}
 						}
+						_ => {
+							// the account is written back as a whole, so no write in place may land in the meantime.
+							drop(shared);
+							let _exclusive = self.lock_account(&address);
+							let mut account = state.get(&address).cloned().unwrap_or_default();
 
-						let zeros = account
-							.storage.pin()
-							.iter()
-							.filter(|(_, value)| *value == &H256::default())
-							.map(|(key, _)| key.to_owned())
-							.collect::<Vec<H256>>();
+							account.balance = basic.balance;
+							account.nonce = basic.nonce;
+							if let Some(code) = code {
+								account.code = code;
+							}
 
-						for zero in zeros.iter() {
-							account.storage.pin().remove(zero);
-						}
+							if reset_storage {
+								account.storage = ConcurrentHashMap::default();
+							}
 
-						for (index, value) in storage {
-							if value == H256::default() {
-								account.storage.pin().remove(&index);
-							} else {
-								account.storage.pin().insert(index, value);
+							let zeros = account
+								.storage.pin()
+								.iter()
+								.filter(|(_, value)| *value == &H256::default())
+								.map(|(key, _)| key.to_owned())
+								.collect::<Vec<H256>>();
+
+							for zero in zeros.iter() {
+								account.storage.pin().remove(zero);
 							}
-						}
 
-						account.balance == U256::zero()
-							&& account.nonce == U256::zero()
-							&& account.code.is_empty()
-					};
+							for (index, value) in storage {
+								if value == H256::default() {
+									account.storage.pin().remove(&index);
+								} else {
+									account.storage.pin().insert(index, value);
+								}
+							}
+
+							let is_empty = account.balance == U256::zero()
+								&& account.nonce == U256::zero()
+								&& account.code.is_empty();
 
-					if is_empty && delete_empty {
-						state.remove(&address);
+							if is_empty && delete_empty {
+								state.remove(&address);
+							} else {
+								// `account` is a deep copy of the stored one, so it must be written back.
+								state.insert(address, account);
+							}
+						}
 					}
 				}
 				Apply::Delete { address } => {
+					let _exclusive = self.lock_account(&address);
 					self.state.pin().remove(&address);
 				}
 			}
 		}
 	}
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod lock_stripes_tests {
+    use std::{
+        collections::BTreeMap,
+        sync::{Arc, Barrier},
+    };
+
+    use ethers_core::types::{H160, H256, U256};
+    use evm::backend::{Apply, Backend, Basic};
+
+    use super::{ApplyBackend, CMemoryBackend, DEFAULT_LOCK_STRIPES};
+
+    #[test]
+    fn test_lock_stripes_do_not_block_disjoint_accounts() {
+        let (a, b) = (H160::from_low_u64_be(1), H160::from_low_u64_be(2));
+        let fund = |address: H160| Apply::Modify {
+            address,
+            basic: Basic {
+                balance: U256::one(),
+                nonce: U256::zero(),
+            },
+            code: None,
+            storage: BTreeMap::new(),
+            reset_storage: false,
+        };
+        // applies the effect on another thread, and returns once the thread waits for the lock of its stripe.
+        let apply_blocked = |backend: &Arc<CMemoryBackend>, address: H160| {
+            let contended = backend.contended_locks();
+            let (applier, effect) = (backend.clone(), fund(address));
+            let handle = std::thread::spawn(move || applier.apply(vec![effect], false));
+            while backend.contended_locks() == contended {
+                std::thread::yield_now();
+            }
+            handle
+        };
+
+        //given (a and b fall into different stripes)
+        let backend = Arc::new(CMemoryBackend::default());
+        assert_eq!(backend.num_stripes(), DEFAULT_LOCK_STRIPES);
+        let guard = backend.lock_account(&a);
+
+        //then (the account of another stripe is applied right away while a is locked, but a itself waits for the lock)
+        backend.apply(vec![fund(b)], false);
+        assert_eq!(backend.basic(b).balance, U256::one());
+        assert_eq!(backend.contended_locks(), 0);
+        let blocked = apply_blocked(&backend, a);
+        assert_eq!(backend.basic(a).balance, U256::zero());
+        drop(guard);
+        blocked.join().unwrap();
+        assert_eq!(backend.basic(a).balance, U256::one());
+
+        //given (a single stripe)
+        let backend = Arc::new(CMemoryBackend::default().with_stripes(1));
+        let guard = backend.lock_account(&a);
+
+        //then (every account waits for the lock)
+        let blocked = apply_blocked(&backend, b);
+        assert_eq!(backend.basic(b).balance, U256::zero());
+        drop(guard);
+        blocked.join().unwrap();
+        assert_eq!(backend.basic(b).balance, U256::one());
+    }
+
+    #[test]
+    fn test_rewriting_an_account_keeps_concurrent_writes_to_its_storage() {
+        const WRITERS: u64 = 8;
+        const SLOTS: u64 = 200;
+        let contract = H160::from_low_u64_be(0xc0de);
+        let write = |balance: u64, slot: u64| Apply::Modify {
+            address: contract,
+            basic: Basic {
+                balance: U256::from(balance),
+                nonce: U256::one(),
+            },
+            code: None,
+            storage: BTreeMap::from([(H256::from_low_u64_be(slot), H256::from_low_u64_be(1))]),
+            reset_storage: false,
+        };
+
+        //given (a contract written in place by some, and rewritten with a new balance by another)
+        let backend = CMemoryBackend::default();
+        backend.apply(vec![write(0, 0)], false);
+        let start = Barrier::new(WRITERS as usize + 1);
+
+        //when
+        std::thread::scope(|scope| {
+            for writer in 1..=WRITERS {
+                let (backend, start) = (&backend, &start);
+                scope.spawn(move || {
+                    start.wait();
+                    for slot in (1..=SLOTS).map(|i| writer * SLOTS + i) {
+                        backend.apply(vec![write(0, slot)], false);
+                    }
+                });
+            }
+            start.wait();
+            for balance in 1..=SLOTS {
+                backend.apply(vec![write(balance, balance)], false);
+            }
+        });
+
+        //then (no write is lost, whether it was applied in place or with the rewritten account)
+        let account = backend.state().pin().get(&contract).cloned().unwrap();
+        assert_eq!(account.storage.len() as u64, (WRITERS + 1) * SLOTS + 1);
+    }
+}
+
+#[cfg(all(test, feature = "storage-metrics"))]
+mod storage_metrics_tests {
+    use std::collections::BTreeMap;
+
+    use ethers_core::types::{H160, H256, U256};
+    use evm::backend::{Apply, Backend, Basic};
+
+    use super::{ApplyBackend, CMemoryBackend};
+
+    #[test]
+    fn test_storage_metrics_rank_hot_keys() {
+        let (a, b) = (H160::from_low_u64_be(1), H160::from_low_u64_be(2));
+        let write = |address: H160, slots: &[u64]| Apply::Modify {
+            address,
+            basic: Basic {
+                balance: U256::zero(),
+                nonce: U256::one(),
+            },
+            code: None,
+            storage: slots
+                .iter()
+                .map(|slot| (H256::from_low_u64_be(*slot), H256::from_low_u64_be(1)))
+                .collect::<BTreeMap<_, _>>(),
+            reset_storage: false,
+        };
+
+        //given
+        let backend = CMemoryBackend::default();
+        backend.apply(vec![write(a, &[1, 2, 3]), write(b, &[1])], false);
+        backend.apply(vec![write(a, &[1, 2])], false);
+        backend.storage(a, H256::from_low_u64_be(1));
+
+        //then
+        assert_eq!(backend.num_accounts(), 2);
+        assert_eq!(backend.num_storage_slots(), 4);
+        assert_eq!(
+            backend.hot_keys(3),
+            vec![
+                ((a, H256::from_low_u64_be(1)), 3),
+                ((a, H256::from_low_u64_be(2)), 2),
+                ((a, H256::from_low_u64_be(3)), 1),
+            ]
+        );
+        assert_eq!(backend.hot_keys(10).len(), 4);
+    }
+}
@@ -5,7 +5,7 @@ use evm::{backend::{Log, Apply}, Config, executor::stack::PrecompileFn};
 mod concurrent_memory_backend;
 mod memory_backend;
 
-pub use concurrent_memory_backend::{CMemoryBackend, CAccount};
+pub use concurrent_memory_backend::{CMemoryBackend, CAccount, DEFAULT_LOCK_STRIPES};
 pub use memory_backend::MemoryBackend;
 
 pub type ConcurrentHashMap<K, V> = flurry::HashMap<K, V>;
@@ -1,8 +1,8 @@
 use std::collections::BTreeMap;
 
-use ethers_core::types::{H160, U64};
+use ethers_core::types::{Bytes, H160, U64};
 use evm::{
-    backend::Backend, 
+    backend::{Backend, Basic}, 
     executor::stack::{
         PrecompileFn, StackExecutor, MemoryStackState, StackSubstateMetadata
     }
@@ -41,9 +41,20 @@ impl<B: Backend+ApplyBackend+Clone+Default> EvmStorage<B> {
         simulation: bool
     ) -> StackExecutor<MemoryStackState<B>, BTreeMap<H160, PrecompileFn>> {
 
+        self.executor_with_config(gas_limit, simulation, self.config())
+    }
+
+    /// Like [`Self::executor`], but with the given config instead of the one of the storage, e.g., to execute under another fork.
+    pub fn executor_with_config<'a>(
+        &'a self, 
+        gas_limit: u64, 
+        simulation: bool,
+        config: &'a evm::Config
+    ) -> StackExecutor<'a, 'a, MemoryStackState<'a, 'a, B>, BTreeMap<H160, PrecompileFn>> {
+
         StackExecutor::new_with_precompiles(
-            MemoryStackState::new(StackSubstateMetadata::new(gas_limit, self.config()), &self.backend),
-            self.config(),
+            MemoryStackState::new(StackSubstateMetadata::new(gas_limit, config), &self.backend),
+            config,
             self.precompiles(),
             simulation
         )
@@ -62,6 +73,22 @@ impl<B: Backend+ApplyBackend+Clone+Default> EvmStorage<B> {
         &self.backend
     }
 
+    /// The nonce and balance of the account (zeroed if it does not exist).
+    pub fn get_account(&self, address: H160) -> Basic {
+        self.backend.basic(address)
+    }
+
+    /// Whether the account has code, e.g., for a contract creation to detect a collision with it.
+    pub fn has_code(&self, address: H160) -> bool {
+        !self.backend.code(address).is_empty()
+    }
+
+    /// The code of the account, or `None` if it has none (e.g., an EOA or an account which does not exist).
+    pub fn code(&self, address: H160) -> Option<Bytes> {
+        let code = self.backend.code(address);
+        (!code.is_empty()).then(|| code.into())
+    }
+
     pub fn as_ref(&self) -> &Self {
         self
     }
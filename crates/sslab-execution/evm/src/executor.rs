@@ -1,9 +1,15 @@
+use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+
+use ethers_core::types::H160;
 use evm::ExitReason;
 use sui_types::error::SuiError;
 use tokio::sync::mpsc::Receiver;
 use tracing::{debug, warn};
 
-use crate::types::{ExecutableEthereumBatch, ExecutableConsensusOutput}; 
+use crate::{
+    evm_storage::{backend::CAccount, ConcurrentEVMStorage},
+    types::{ExecutableEthereumBatch, ExecutableConsensusOutput},
+}; 
 
 #[async_trait::async_trait]
 pub trait Executable {
@@ -72,6 +78,82 @@ impl<ExecutionModel: Executable + Send + Sync> ParallelExecutor<ExecutionModel>
 }
 
 
+/// Runs every consensus output through a canonical executor and a shadow executor.
+/// The shadow executor must run against its own copy of the state (e.g., `EvmStorage::snapshot()`),
+/// so that it never affects the canonical path. After each execution, the two states are compared
+/// and any divergence is reported.
+pub struct TeeExecutor<A: Executable + Send + Sync, B: Executable + Send + Sync> {
+    canonical: A,
+    canonical_state: Arc<ConcurrentEVMStorage>,
+    shadow: B,
+    shadow_state: Arc<ConcurrentEVMStorage>,
+    divergences: AtomicUsize,
+}
+
+#[async_trait::async_trait]
+impl<A: Executable + Send + Sync, B: Executable + Send + Sync> Executable for TeeExecutor<A, B> {
+    async fn execute(&self, consensus_output: Vec<ExecutableEthereumBatch>) {
+        self.canonical.execute(consensus_output.clone()).await;
+        self.shadow.execute(consensus_output).await;
+
+        let diverged = self.diverged_accounts();
+        if !diverged.is_empty() {
+            self.divergences.fetch_add(1, Ordering::Relaxed);
+            warn!("shadow executor diverged from the canonical one at {} accounts: {:?}", diverged.len(), diverged);
+        }
+    }
+}
+
+impl<A: Executable + Send + Sync, B: Executable + Send + Sync> TeeExecutor<A, B> {
+    pub fn new(
+        canonical: A, 
+        canonical_state: Arc<ConcurrentEVMStorage>, 
+        shadow: B, 
+        shadow_state: Arc<ConcurrentEVMStorage>
+    ) -> Self {
+        Self {
+            canonical,
+            canonical_state,
+            shadow,
+            shadow_state,
+            divergences: AtomicUsize::new(0),
+        }
+    }
+
+    /// The number of executions after which the shadow state differed from the canonical state.
+    pub fn divergences(&self) -> usize {
+        self.divergences.load(Ordering::Relaxed)
+    }
+
+    fn diverged_accounts(&self) -> Vec<H160> {
+        let canonical = self.canonical_state.get_storage().state().pin();
+        let shadow = self.shadow_state.get_storage().state().pin();
+
+        let mut diverged = canonical
+            .iter()
+            .filter(|(address, account)| match shadow.get(*address) {
+                Some(other) => !Self::_same_account(account, other),
+                None => true,
+            })
+            .map(|(address, _)| *address)
+            .collect::<Vec<H160>>();
+
+        diverged.extend(shadow.keys().filter(|address| !canonical.contains_key(*address)));
+
+        diverged
+    }
+
+    fn _same_account(left: &CAccount, right: &CAccount) -> bool {
+        if left.nonce != right.nonce || left.balance != right.balance || left.code != right.code {
+            return false;
+        }
+
+        let (left, right) = (left.storage.pin(), right.storage.pin());
+        left.len() == right.len() && left.iter().all(|(key, value)| right.get(key) == Some(value))
+    }
+}
+
+
 pub struct EvmExecutionUtils;
 
 impl EvmExecutionUtils {
@@ -5,6 +5,8 @@ use narwhal_worker::TransactionValidator;
 use sui_protocol_config::ProtocolConfig;
 use thiserror::Error;
 
+use crate::types::EthereumTransaction;
+
 #[derive(Debug, Error)]
 pub enum TxValidationError {
     #[error(transparent)]
@@ -13,10 +15,32 @@ pub enum TxValidationError {
     DecoderError(#[from] TypedTransactionError),
     #[error(transparent)]
     SerdeError(#[from] serde_json::Error),
+    #[error("the transaction is signed for chain {actual:?}, not for chain {expected}")]
+    ChainIdMismatch { expected: u64, actual: Option<u64> },
+    #[error("the transaction has no gas limit, or a zero one")]
+    ZeroGasLimit,
 }
 
 #[derive(Clone, Debug, Default)]
-pub struct EthereumTxValidator;
+pub struct EthereumTxValidator {
+    chain_id: Option<u64>,
+}
+
+impl EthereumTxValidator {
+    /// Rejects the transactions signed for another chain than `chain_id` (e.g., `DEFAULT_CHAIN_ID`), so that
+    /// they cannot be replayed here. Without it, the chain id is not checked.
+    pub fn with_chain_id(mut self, chain_id: u64) -> Self {
+        self.chain_id = Some(chain_id);
+        self
+    }
+
+    fn _validate_chain_id(&self, tx: &TypedTransaction) -> Result<(), TxValidationError> {
+        match self.chain_id {
+            Some(expected) => EthereumTransaction::from_typed(tx.clone()).validate_chain_id(expected),
+            None => Ok(()),
+        }
+    }
+}
 
 #[async_trait]
 impl TransactionValidator for EthereumTxValidator {
@@ -27,7 +51,7 @@ impl TransactionValidator for EthereumTxValidator {
     fn validate(&self, t: &[u8]) -> Result<(), Self::Error> { 
 
         match serde_json::from_slice::<TypedTransaction>(t) {
-            Ok(_) => Ok(()),
+            Ok(tx) => self._validate_chain_id(&tx),
             Err(_) => {
                 let rlp = Rlp::new(t);
         
@@ -37,7 +61,7 @@ impl TransactionValidator for EthereumTxValidator {
                             return Err(TxValidationError::SignatureError(e));
                         }
                         // debug!("validated tx: {:?}, sig: {:?}", tx, sig);
-                        Ok(())
+                        self._validate_chain_id(&tx)
                     },
                     Err(e) => Err(TxValidationError::DecoderError(e))
                 }
@@ -57,4 +81,58 @@ impl TransactionValidator for EthereumTxValidator {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers_providers::{MockProvider, Provider};
+    use narwhal_types::BatchDigest;
+    use narwhal_worker::TransactionValidator as _;
+
+    use super::{EthereumTxValidator, TxValidationError};
+    use crate::{
+        types::ExecutableEthereumBatch,
+        utils::test_utils::{
+            mock_smallbank_handler, SmallBankTransactionHandler, DEFAULT_CHAIN_ID,
+        },
+    };
+
+    #[test]
+    fn test_wrong_chain_id_is_rejected() {
+        //given
+        let native = mock_smallbank_handler();
+        let foreign = SmallBankTransactionHandler::new(
+            Provider::<MockProvider>::new(MockProvider::default()),
+            1,
+        );
+        let wrong = foreign.random_operation(0.0, 10);
+        assert_eq!(wrong.chain_id(), Some(1));
+
+        //when
+        let batch = ExecutableEthereumBatch::new(
+            vec![native.random_operation(0.0, 10), wrong],
+            BatchDigest::default(),
+        );
+        let validator = EthereumTxValidator::default().with_chain_id(DEFAULT_CHAIN_ID);
+
+        //then
+        assert!(matches!(
+            batch.validate(DEFAULT_CHAIN_ID),
+            Err(TxValidationError::ChainIdMismatch {
+                expected: DEFAULT_CHAIN_ID,
+                actual: Some(1)
+            })
+        ));
+        assert!(validator
+            .validate(&native.random_operation_raw(0.0, 10))
+            .is_ok());
+        assert!(matches!(
+            validator.validate(&foreign.random_operation_raw(0.0, 10)),
+            Err(TxValidationError::ChainIdMismatch { .. })
+        ));
+        // the chain id is not checked unless it is configured.
+        assert!(EthereumTxValidator::default()
+            .validate(&foreign.random_operation_raw(0.0, 10))
+            .is_ok());
+    }
+}
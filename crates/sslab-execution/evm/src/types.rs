@@ -2,10 +2,14 @@ use std::rc::Rc;
 use enumn;
 use ethers_core::types::{H256, U256, Bytes};
 use ethers_core::types::{Address, transaction::eip2718::TypedTransaction};
-use ethers_core::utils::rlp::Rlp;
+use ethers_core::utils::{get_contract_address, keccak256, rlp::Rlp};
 use evm::{Runtime, Config, Context};
 use fastcrypto::hash::Hash;
-use narwhal_types::{BatchDigest, ConsensusOutput, ConsensusOutputDigest};
+use narwhal_types::{Batch, BatchAPI, BatchDigest, ConsensusOutput, ConsensusOutputDigest, TxWireFormat, TX_WIRE_FORMAT};
+#[cfg(feature = "verify-digests")]
+use narwhal_types::{BatchV1, Transaction};
+use thiserror::Error;
+use tracing::warn;
 use serde::{Serialize, Deserialize};
 
 use crate::transaction_validator::TxValidationError;
@@ -18,6 +22,18 @@ pub struct EthereumTransaction(pub TypedTransaction);
 
 impl EthereumTransaction {
 
+    pub fn from_typed(tx: TypedTransaction) -> EthereumTransaction {
+        EthereumTransaction(tx)
+    }
+
+    pub fn as_typed(&self) -> &TypedTransaction {
+        &self.0
+    }
+
+    pub fn into_typed(self) -> TypedTransaction {
+        self.0
+    }
+
     pub fn digest_u64(&self) -> u64 {
         u64::from_be_bytes(self.0.sighash()[2..10].try_into().ok().unwrap())
     }
@@ -31,7 +47,7 @@ impl EthereumTransaction {
     }
 
     pub fn from_json(bytes: &[u8]) -> Result<EthereumTransaction, TxValidationError> { 
-        let tx: TypedTransaction = serde_json::from_slice(bytes).unwrap();
+        let tx: TypedTransaction = serde_json::from_slice(bytes)?;
 
         Ok(EthereumTransaction(tx))
     }
@@ -44,17 +60,48 @@ impl EthereumTransaction {
         Ok(EthereumTransaction(tx))
     }
 
+    /// Decodes a transaction of a sealed batch, which the batch maker encodes in `TX_WIRE_FORMAT`.
+    /// The other encoding accepted by `EthereumTxValidator` is tried as well, for the batches built by hand.
+    pub fn decode(bytes: &[u8]) -> Result<EthereumTransaction, TxValidationError> {
+        match TX_WIRE_FORMAT {
+            TxWireFormat::Json => Self::from_json(bytes).or_else(|_| Self::from_rlp(bytes)),
+            TxWireFormat::Rlp => Self::from_rlp(bytes).or_else(|_| Self::from_json(bytes)),
+        }
+    }
+
+    /// The chain which the transaction is signed for, if any (i.e., not for a pre-EIP-155 legacy transaction).
+    pub fn chain_id(&self) -> Option<u64> {
+        self.0.chain_id().map(|chain_id| chain_id.as_u64())
+    }
+
+    /// Checks that the transaction is signed for the given chain, so that a transaction of another chain is not replayed.
+    /// A transaction without chain id is rejected as well.
+    pub fn validate_chain_id(&self, chain_id: u64) -> Result<(), TxValidationError> {
+        match self.chain_id() {
+            Some(actual) if actual == chain_id => Ok(()),
+            actual => Err(TxValidationError::ChainIdMismatch { expected: chain_id, actual }),
+        }
+    }
+
+    /// Checks that the transaction has a (non-zero) gas limit, since it would run out of gas right away otherwise.
+    pub fn validate_gas_limit(&self) -> Result<(), TxValidationError> {
+        match self.gas_limit() {
+            0 => Err(TxValidationError::ZeroGasLimit),
+            _ => Ok(()),
+        }
+    }
+
     pub fn execution_part(&self, code :Vec<u8>) -> Runtime {
         
         let context = Context {
             caller: *self.0.from().unwrap(),
-            address: *self.0.to_addr().unwrap(), //TODO: check this
+            address: self.to_addr().copied().or_else(|| self.created_address()).unwrap(), //TODO: check this
             apparent_value: *self.0.value().unwrap(), //TODO: only for delegate call?
         };
 
         Runtime::new(
             Rc::new(code), 
-            Rc::new(self.0.data().unwrap().to_vec().clone()),
+            Rc::new(self.0.data().map_or_else(Vec::new, |data| data.to_vec())),
             context,
             DEFAULT_EVM_STACK_LIMIT,
             DEFAULT_EVM_MEMORY_LIMIT
@@ -65,6 +112,18 @@ impl EthereumTransaction {
         self.0.to_addr()
     }
 
+    /// A transaction without a recipient deploys a contract, running its data as the init code.
+    pub fn is_contract_creation(&self) -> bool {
+        self.0.to().is_none()
+    }
+
+    /// The address of the contract deployed by this transaction, i.e., keccak256(rlp([caller, nonce]))[12..] as `CREATE` does.
+    /// Note that the executor derives it from the nonce in the state, so both should match for a valid transaction.
+    pub fn created_address(&self) -> Option<Address> {
+        self.is_contract_creation()
+            .then(|| get_contract_address(self.caller(), self.nonce()))
+    }
+
     pub fn caller(&self) -> Address {
         self.0.from().unwrap().clone()
     }
@@ -77,8 +136,9 @@ impl EthereumTransaction {
         self.0.data() 
     }
 
+    /// The gas limit of the transaction, or 0 if it has none.
     pub fn gas_limit(&self) -> u64 {
-        self.0.gas().unwrap().clone().as_u64()
+        self.0.gas().map_or(0, |gas| gas.as_u64())
     }
 
     pub fn access_list(&self) -> Vec<(Address, Vec<H256>)> {
@@ -101,12 +161,22 @@ impl std::hash::Hash for EthereumTransaction {
 #[derive(Clone, Debug, Default, Deserialize, Serialize, Eq, PartialEq)]
 pub struct IndexedEthereumTransaction {
     pub tx: EthereumTransaction,
+    /// sequential position of the transaction in the consensus output, by which the executors key it.
+    /// Unlike `digest_u64`, it is unique even for identical transactions (e.g., a replay of the same signed one).
     pub id: u64,
+    /// position of the originating batch in the consensus output.
+    #[serde(default)]
+    pub batch_id: usize,
 }
 
 impl IndexedEthereumTransaction {
     pub fn new(tx: EthereumTransaction, id: u64) -> Self {
-        Self { tx, id }
+        Self { tx, id, batch_id: 0 }
+    }
+
+    pub fn with_batch_id(mut self, batch_id: usize) -> Self {
+        self.batch_id = batch_id;
+        self
     }
 
     pub fn data(&self) -> &EthereumTransaction {
@@ -123,20 +193,77 @@ impl IndexedEthereumTransaction {
 }
 
 
+#[derive(Debug, Error)]
+#[error("batch digest mismatch (expected: {expected}, computed: {computed})")]
+pub struct DigestMismatch {
+    pub expected: BatchDigest,
+    pub computed: BatchDigest,
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct ExecutableEthereumBatch{
     digest: BatchDigest,
     data: Vec<EthereumTransaction>, 
+    #[cfg(feature = "verify-digests")]
+    raw: Option<Vec<Transaction>>,
 }
 
 impl ExecutableEthereumBatch {
     pub fn new(batch: Vec<EthereumTransaction>, digest: BatchDigest) -> ExecutableEthereumBatch {
         Self {
             data: batch,
-            digest
+            digest,
+            #[cfg(feature = "verify-digests")]
+            raw: None,
         }
     }
 
+    pub fn try_from_batch(batch: &Batch) -> Result<ExecutableEthereumBatch, TxValidationError> {
+        let data = batch.transactions()
+            .iter()
+            .map(|tx| EthereumTransaction::decode(tx))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let executable = Self::new(data, batch.digest());
+
+        #[cfg(feature = "verify-digests")]
+        let executable = executable.with_raw_transactions(batch.transactions().clone());
+
+        Ok(executable)
+    }
+
+    /// Keeps the raw transactions which the batch maker hashed, so that the digest can be verified later.
+    #[cfg(feature = "verify-digests")]
+    pub fn with_raw_transactions(mut self, raw: Vec<Transaction>) -> Self {
+        self.raw = Some(raw);
+        self
+    }
+
+    /// Recomputes the digest from the raw transactions as the batch maker does, and checks that it matches
+    /// the carried digest and that the raw transactions decode to the carried ones.
+    /// A batch without the raw transactions (i.e., not built from a narwhal batch) cannot be verified, and passes.
+    #[cfg(feature = "verify-digests")]
+    pub fn verify_digest(&self) -> Result<(), DigestMismatch> {
+        let Some(raw) = &self.raw else {
+            return Ok(());
+        };
+
+        let computed = Batch::V1(BatchV1::new(raw.clone())).digest();
+        let decoded = raw.iter()
+            .map(|tx| EthereumTransaction::decode(tx))
+            .collect::<Result<Vec<_>, _>>();
+
+        match decoded {
+            Ok(data) if computed == self.digest && data == self.data => Ok(()),
+            _ => Err(DigestMismatch { expected: self.digest, computed }),
+        }
+    }
+
+    /// Checks that every transaction of the batch is signed for the given chain (see [`EthereumTransaction::validate_chain_id`]).
+    pub fn validate(&self, chain_id: u64) -> Result<(), TxValidationError> {
+        self.data.iter().try_for_each(|tx| tx.validate_chain_id(chain_id))
+    }
+
     pub fn digest(&self) -> &BatchDigest {
         &self.digest
     }
@@ -166,6 +293,24 @@ impl ExecutableConsensusOutput {
         }
     }
 
+    /// Hands the consensus output over to the executor: decodes its batches in the order of the certificates
+    /// (see [`ExecutableEthereumBatch::try_from_batch`]). A batch which does not decode is skipped, as every node does.
+    pub fn from_consensus_output(consensus_output: &ConsensusOutput) -> ExecutableConsensusOutput {
+        let data = consensus_output.batches
+            .iter()
+            .flatten()
+            .filter_map(|batch| match ExecutableEthereumBatch::try_from_batch(batch) {
+                Ok(executable) => Some(executable),
+                Err(e) => {
+                    warn!("skip batch {}: {e}", batch.digest());
+                    None
+                }
+            })
+            .collect();
+
+        Self::new(data, consensus_output)
+    }
+
     pub fn digest(&self) -> &ConsensusOutputDigest {
         &self.digest
     }
@@ -191,6 +336,9 @@ impl ExecutableConsensusOutput {
     }
 }
 
+/// The digests of the batches executed, in the order of execution.
+/// Results are equal if they executed the same batches in the same order.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub struct ExecutionResult {
     pub digests: Vec<BatchDigest>,
 }
@@ -205,6 +353,15 @@ impl ExecutionResult {
     pub fn iter(&self) -> impl Iterator<Item = &BatchDigest> {
         self.digests.iter()
     }
+
+    /// The keccak256 of the digests concatenated in the order of execution, e.g., for validators to agree on the executed batches.
+    pub fn result_hash(&self) -> H256 {
+        let bytes = self.digests
+            .iter()
+            .flat_map(|digest| digest.0)
+            .collect::<Vec<u8>>();
+        H256::from(keccak256(bytes))
+    }
 }
 
 /// SpecId and their activation block
@@ -285,3 +442,125 @@ impl ChainConfig {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use fastcrypto::hash::Hash;
+    use narwhal_types::{
+        Batch, BatchDigest, BatchV1, Certificate, CommittedSubDag, ConsensusOutput, ReputationScores,
+        TxWireFormat, TX_WIRE_FORMAT,
+    };
+    use narwhal_worker::encode_for_wire;
+
+    use super::{EthereumTransaction, ExecutableConsensusOutput, ExecutableEthereumBatch, ExecutionResult};
+    use crate::utils::test_utils::mock_smallbank_handler;
+
+    #[tokio::test]
+    async fn test_executable_batch_from_narwhal_batch() {
+        let handler = mock_smallbank_handler();
+
+        //given
+        let raw_txs = (0..2)
+            .map(|_| handler.random_operation_raw(0.0, 1_000).to_vec())
+            .collect::<Vec<_>>();
+        let batch = Batch::V1(BatchV1::new(raw_txs.clone()));
+
+        //when
+        let executable = ExecutableEthereumBatch::try_from_batch(&batch).unwrap();
+
+        //then
+        assert_eq!(executable.data().len(), 2);
+        executable
+            .data()
+            .iter()
+            .zip(raw_txs.iter())
+            .for_each(|(tx, raw)| assert_eq!(tx, &EthereumTransaction::from_rlp(raw).unwrap()));
+
+        let malformed = Batch::V1(BatchV1::new(vec![vec![0xde, 0xad]]));
+        assert!(ExecutableEthereumBatch::try_from_batch(&malformed).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_transaction_sealed_by_worker_decodes() {
+        let handler = mock_smallbank_handler();
+
+        //given (transactions encoded in the wire format as the batch maker seals them)
+        let raw_txs = (0..2)
+            .map(|_| handler.random_operation_raw(0.0, 1_000).to_vec())
+            .collect::<Vec<_>>();
+        let sealed_txs = raw_txs
+            .iter()
+            .map(|raw| encode_for_wire(raw))
+            .collect::<Vec<_>>();
+        let batch = Batch::V1(BatchV1::new(sealed_txs.clone()));
+
+        //when
+        let executable = ExecutableEthereumBatch::try_from_batch(&batch).unwrap();
+
+        //then
+        executable
+            .data()
+            .iter()
+            .zip(raw_txs.iter().zip(sealed_txs.iter()))
+            .for_each(|(tx, (raw, sealed))| {
+                assert_eq!(tx, &EthereumTransaction::from_rlp(raw).unwrap());
+                match TX_WIRE_FORMAT {
+                    TxWireFormat::Json => {
+                        assert_eq!(tx, &EthereumTransaction::from_json(sealed).unwrap())
+                    }
+                    TxWireFormat::Rlp => {
+                        assert_eq!(tx, &EthereumTransaction::from_rlp(sealed).unwrap())
+                    }
+                }
+            });
+    }
+
+    #[tokio::test]
+    async fn test_consensus_output_handoff_skips_malformed_batches() {
+        let handler = mock_smallbank_handler();
+        let batch = |size: usize| Batch::V1(BatchV1::new(
+            (0..size).map(|_| handler.random_operation_raw(0.0, 1_000).to_vec()).collect(),
+        ));
+
+        //given (a malformed batch between two well-formed ones, of different certificates)
+        let (first, second) = (batch(1), batch(2));
+        let malformed = Batch::V1(BatchV1::new(vec![vec![0xde, 0xad]]));
+        let consensus_output = ConsensusOutput {
+            sub_dag: Arc::new(CommittedSubDag::new(
+                vec![], Certificate::default(), 0, ReputationScores::default(), None,
+            )),
+            batches: vec![vec![first.clone(), malformed], vec![second.clone()]],
+        };
+
+        //when
+        let executable = ExecutableConsensusOutput::from_consensus_output(&consensus_output);
+
+        //then
+        assert_eq!(
+            executable.data().iter().map(|batch| *batch.digest()).collect::<Vec<_>>(),
+            vec![first.digest(), second.digest()]
+        );
+        assert_eq!(executable.data()[1].data().len(), 2);
+    }
+
+    #[test]
+    fn test_execution_result_hash() {
+        let digest = |byte: u8| BatchDigest([byte; 32]);
+
+        //given
+        let result = ExecutionResult::new(vec![digest(1), digest(2), digest(3)]);
+        let replayed = ExecutionResult::new(vec![digest(1), digest(2), digest(3)]);
+        let reordered = ExecutionResult::new(vec![digest(2), digest(1), digest(3)]);
+        let other = ExecutionResult::new(vec![digest(1), digest(2)]);
+
+        //then
+        assert_eq!(result, replayed);
+        assert_eq!(result.result_hash(), replayed.result_hash());
+        [reordered, other].iter().for_each(|differing| {
+            assert_ne!(&result, differing);
+            assert_ne!(result.result_hash(), differing.result_hash());
+        });
+    }
+}
@@ -8,7 +8,7 @@ use ethers_core::{
 use ethers_providers::{MockProvider, Provider};
 use ethers_signers::{LocalWallet, Signer};
 use narwhal_types::BatchDigest;
-use rand::Rng as _;
+use rand::Rng;
 use rand_distr::{Distribution, Uniform, Zipf};
 use rayon::prelude::*;
 use std::{str::FromStr, sync::Arc};
@@ -20,6 +20,17 @@ pub const ADMIN_SECRET_KEY: &[u8] = &[
 // pub const ADMIN_ADDRESS: &str = "0xe14de1592b52481b94b99df4e9653654e14fffb6";
 pub const DEFAULT_CONTRACT_ADDRESS: &str = "0x1000000000000000000000000000000000000000";
 pub const DEFAULT_CHAIN_ID: u64 = 9; // ISTANBUL
+pub const DEFAULT_INITIAL_BALANCE: u64 = 1_000_000;
+
+/// The shape of the conflicts among the transactions of a batch (see [`SmallBankTransactionHandler::create_contention_pattern`]).
+/// Every transaction of both shapes reads and writes the checking balances of two accounts, out of `0..=n`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContentionPattern {
+    /// the i-th transaction pays from account i to account i + 1, so it conflicts only with its neighbors.
+    Chain,
+    /// every transaction pays from account 0 (the hub) to an account of its own, so any two of them conflict.
+    Star,
+}
 
 pub struct SmallBankTransactionHandler {
     admin_wallet: LocalWallet,
@@ -27,6 +38,7 @@ pub struct SmallBankTransactionHandler {
     contract: Option<SmallBank<Provider<MockProvider>>>,
     random_op_gen: Uniform<u8>,
     val_gen: Uniform<u32>,
+    initial_balance: U256,
 }
 
 #[allow(dead_code)]
@@ -43,9 +55,61 @@ impl SmallBankTransactionHandler {
             )),
             random_op_gen: Uniform::new(1, 7),
             val_gen: Uniform::new(1, 1000),
+            initial_balance: U256::from(DEFAULT_INITIAL_BALANCE),
         }
     }
 
+    /// Sets the checking and saving balances which accounts are created with, see [`Self::create_account_batch`].
+    /// Note that the account count (`account_num`) is a separate parameter of the workload generators.
+    pub fn with_initial_balance(mut self, initial_balance: U256) -> Self {
+        self.initial_balance = initial_balance;
+        self
+    }
+
+    /// Creates the account `acc` with the initial balance, both for checking and saving.
+    pub fn create_account_transaction(&self, acc: u64) -> EthereumTransaction {
+        let mut tx =
+            self.create_account(acc.to_string(), self.initial_balance, self.initial_balance);
+        self.get_signed(&mut tx)
+    }
+
+    /// Creates the accounts `1..=account_num`, which the generated workloads operate on, with the initial balance.
+    /// Execute it before the workload so that its payments draw on the configured balances.
+    pub fn create_account_batch(&self, account_num: u64) -> ExecutableEthereumBatch {
+        let txs = (1..=account_num)
+            .map(|acc| self.create_account_transaction(acc))
+            .collect();
+
+        ExecutableEthereumBatch::new(txs, BatchDigest::default())
+    }
+
+    pub fn send_payment_transaction(
+        &self,
+        from: u64,
+        to: u64,
+        amount: U256,
+    ) -> EthereumTransaction {
+        let mut tx = self.send_payment(from.to_string(), to.to_string(), amount);
+        self.get_signed(&mut tx)
+    }
+
+    /// Creates a batch of `n` payments whose conflicts have the given shape, e.g., to test the quality of a schedule
+    /// against the known ground truth. The accounts `0..=n` must have been created beforehand.
+    pub fn create_contention_pattern(
+        &self,
+        pattern: ContentionPattern,
+        n: usize,
+    ) -> ExecutableEthereumBatch {
+        let txs = (0..n as u64)
+            .map(|i| match pattern {
+                ContentionPattern::Chain => self.send_payment_transaction(i, i + 1, U256::one()),
+                ContentionPattern::Star => self.send_payment_transaction(0, i + 1, U256::one()),
+            })
+            .collect();
+
+        ExecutableEthereumBatch::new(txs, BatchDigest::default())
+    }
+
     pub fn create_batches(
         &self,
         batch_size: usize,
@@ -99,35 +163,41 @@ impl SmallBankTransactionHandler {
     }
 
     pub fn random_operation(&self, zipfian_coef: f32, account_num: u64) -> EthereumTransaction {
-        let acc_gen = Zipf::new(account_num, zipfian_coef).unwrap();
-        let acc1 = rand::thread_rng().sample(acc_gen).to_string();
-        let acc2 = rand::thread_rng().sample(acc_gen).to_string();
-
-        let rng = &mut rand::thread_rng();
-        let op = self.random_op_gen.sample(rng);
-        let mut tx = match op {
-            0 => self.create_account(acc1, U256::from(1_000_000), U256::from(1_000_000)),
-            1 => self.amalgamate(acc1, acc2),
-            2 => self.get_balance(acc1),
-            3 => self.send_payment(acc1, acc2, self.random_value(rng)),
-            4 => self.update_balance(acc1, self.random_value(rng)),
-            5 => self.update_saving(acc1, self.random_value(rng)),
-            6 => self.write_check(acc1, self.random_value(rng)),
-            _ => panic!("invalid operation"),
-        };
+        let mut tx = self.random_call(&mut rand::thread_rng(), zipfian_coef, account_num);
+        self.get_signed(&mut tx)
+    }
 
+    /// Generates a random operation as [`Self::random_operation`] does, but draws everything from `rng`
+    /// (including the nonce, instead of the current time), so that a seeded `rng` generates the same transactions.
+    pub fn random_operation_with(
+        &self,
+        rng: &mut impl Rng,
+        zipfian_coef: f32,
+        account_num: u64,
+    ) -> EthereumTransaction {
+        let mut tx = self.random_call(rng, zipfian_coef, account_num);
+        tx.set_nonce(U256::from(rng.gen::<u64>()));
         self.get_signed(&mut tx)
     }
 
     pub fn random_operation_raw(&self, zipfian_coef: f32, account_num: u64) -> bytes::Bytes {
+        let tx = self.random_call(&mut rand::thread_rng(), zipfian_coef, account_num);
+        self.get_raw_signed(tx)
+    }
+
+    fn random_call(
+        &self,
+        rng: &mut impl Rng,
+        zipfian_coef: f32,
+        account_num: u64,
+    ) -> TypedTransaction {
         let acc_gen = Zipf::new(account_num, zipfian_coef).unwrap();
-        let acc1 = rand::thread_rng().sample(acc_gen).to_string();
-        let acc2 = rand::thread_rng().sample(acc_gen).to_string();
+        let acc1 = rng.sample(acc_gen).to_string();
+        let acc2 = rng.sample(acc_gen).to_string();
 
-        let rng = &mut rand::thread_rng();
         let op = self.random_op_gen.sample(rng);
-        let tx = match op {
-            0 => self.create_account(acc1, U256::from(1_000_000), U256::from(1_000_000)),
+        match op {
+            0 => self.create_account(acc1, self.initial_balance, self.initial_balance),
             1 => self.amalgamate(acc1, acc2),
             2 => self.get_balance(acc1),
             3 => self.send_payment(acc1, acc2, self.random_value(rng)),
@@ -135,13 +205,11 @@ impl SmallBankTransactionHandler {
             5 => self.update_saving(acc1, self.random_value(rng)),
             6 => self.write_check(acc1, self.random_value(rng)),
             _ => panic!("invalid operation"),
-        };
-
-        self.get_raw_signed(tx)
+        }
     }
 
     #[inline]
-    fn random_value(&self, rng: &mut rand::rngs::ThreadRng) -> U256 {
+    fn random_value(&self, rng: &mut impl Rng) -> U256 {
         U256::from(self.val_gen.sample(rng))
     }
 
@@ -240,6 +308,15 @@ impl SmallBankTransactionHandler {
     }
 }
 
+/// A handler over a mock provider, for the unit tests of the crate.
+#[cfg(test)]
+pub(crate) fn mock_smallbank_handler() -> SmallBankTransactionHandler {
+    SmallBankTransactionHandler::new(
+        Provider::<MockProvider>::new(MockProvider::default()),
+        DEFAULT_CHAIN_ID,
+    )
+}
+
 /// This module was auto-generated with ethers-rs Abigen.
 /// More information at: <https://github.com/gakonst/ethers-rs>
 #[allow(
@@ -605,3 +682,32 @@ pub mod small_bank {
         pub balance: ::ethers::core::types::U256,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ethers_core::rand::{rngs::StdRng, SeedableRng};
+
+    use super::mock_smallbank_handler;
+    use crate::types::EthereumTransaction;
+
+    #[test]
+    fn test_seeded_random_operation_is_reproducible() {
+        let handler = mock_smallbank_handler();
+        let generate = |seed: u64| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..10)
+                .map(|_| handler.random_operation_with(&mut rng, 0.5, 100))
+                .collect::<Vec<_>>()
+        };
+
+        //when
+        let (txs, replayed, other) = (generate(7), generate(7), generate(8));
+
+        //then
+        let rlp = |txs: &[EthereumTransaction]| {
+            txs.iter().map(|tx| tx.0.rlp().to_vec()).collect::<Vec<_>>()
+        };
+        assert_eq!(rlp(&txs), rlp(&replayed));
+        assert_ne!(rlp(&txs), rlp(&other));
+    }
+}
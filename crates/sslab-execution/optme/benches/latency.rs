@@ -68,7 +68,10 @@ fn optme_latency_inspection(c: &mut Criterion) {
                                 },
                                 |(optme, consensus_output)| async move {
                                     latency_metrics.write().push(
-                                        optme._execute_and_return_latency(consensus_output).await,
+                                        optme
+                                            ._execute_and_return_latency(consensus_output)
+                                            .await
+                                            .unwrap(),
                                     );
                                 },
                                 BatchSize::SmallInput,
@@ -87,19 +90,21 @@ fn optme_latency_inspection(c: &mut Criterion) {
                     mut v_exec,
                     mut v_val,
                     mut commit,
+                    mut serial,
                     mut tx_latency,
                 ) = (
-                    0 as f64, 0 as f64, 0 as f64, 0 as f64, 0 as f64, 0 as f64, 0f64,
+                    0 as f64, 0 as f64, 0 as f64, 0 as f64, 0 as f64, 0 as f64, 0 as f64, 0f64,
                 );
 
-                for (a1, a2, a3, a4, a5, a6, a7) in latency_metrics.read().iter() {
+                for (a1, a2, a3, a4, a5, a6, a7, a8) in latency_metrics.read().iter() {
                     total += *a1 as f64;
                     simulation += *a2 as f64;
                     scheduling += *a3 as f64;
                     v_exec += *a4 as f64;
                     v_val += *a5 as f64;
                     commit += *a6 as f64;
-                    tx_latency += *a7 as f64;
+                    serial += *a7 as f64;
+                    tx_latency += *a8 as f64;
                 }
                 total /= len;
                 simulation /= len;
@@ -107,12 +112,13 @@ fn optme_latency_inspection(c: &mut Criterion) {
                 v_exec /= len;
                 v_val /= len;
                 commit /= len;
+                serial /= len;
                 tx_latency /= len;
-                let other = total - (simulation + scheduling + v_exec + v_val + commit);
+                let other = total - (simulation + scheduling + v_exec + v_val + commit + serial);
 
                 println!(
-                    "Total: {:.4}, Simulation: {:.4}, Scheduling: {:.4}, V_exec: {:.4}, V_val: {:.4}, Commit: {:.4}, Other: {:.4}",
-                    total /1000.0, simulation /1000.0, scheduling/1000.0, v_exec/1000.0, v_val/1000.0, commit/1000.0, other/1000.0
+                    "Total: {:.4}, Simulation: {:.4}, Scheduling: {:.4}, V_exec: {:.4}, V_val: {:.4}, Commit: {:.4}, Serial: {:.4}, Other: {:.4}",
+                    total /1000.0, simulation /1000.0, scheduling/1000.0, v_exec/1000.0, v_val/1000.0, commit/1000.0, serial/1000.0, other/1000.0
                 );
                 println!("TX latency: {:.4}", tx_latency / 1000.0);
                 println!(
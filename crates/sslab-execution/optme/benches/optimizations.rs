@@ -1,14 +1,23 @@
+use std::collections::BTreeMap;
+
 use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use ethers_core::types::{H160, H256, U256};
 use ethers_providers::{MockProvider, Provider};
+use evm::{
+    backend::{Apply, Basic},
+    executor::stack::RwSet,
+};
 use parking_lot::RwLock;
 use sslab_execution::{
-    types::ExecutableEthereumBatch,
+    types::{EthereumTransaction, ExecutableEthereumBatch, IndexedEthereumTransaction},
     utils::smallbank_contract_benchmark::concurrent_evm_storage,
     utils::test_utils::{SmallBankTransactionHandler, DEFAULT_CHAIN_ID},
 };
 
 use sslab_execution_optme::{
-    address_based_conflict_graph::Benchmark as _, optme_core::ScheduledInfo,
+    address_based_conflict_graph::Benchmark as _,
+    optme_core::ScheduledInfo,
+    types::{FinalizedTransaction, ReExecutedTransaction},
     AddressBasedConflictGraph, ConcurrencyLevelManager, SimulatedTransaction, SimulationResult,
 };
 const DEFAULT_BATCH_SIZE: usize = 200;
@@ -40,7 +49,7 @@ fn _get_rw_sets(
 ) -> Vec<SimulatedTransaction> {
     let (tx, rx) = std::sync::mpsc::channel();
     let _ = tokio::runtime::Handle::current().spawn(async move {
-        let SimulationResult { rw_sets, .. } = optme.simulate(consensus_output).await;
+        let SimulationResult { rw_sets, .. } = optme.simulate(consensus_output).await.unwrap();
         tx.send(rw_sets).unwrap();
     });
     rx.recv().unwrap()
@@ -322,5 +331,64 @@ fn parallel_early_detection(c: &mut Criterion) {
     }
 }
 
-criterion_group!(benches, baseline, early_detection, parallel_early_detection,);
+fn chunked_commit(c: &mut Criterion) {
+    let width = 4_096;
+    let counter = H160::from_low_u64_be(0x189);
+    let mut group = c.benchmark_group("Commit");
+
+    // a single wide generation of tiny transactions, each of which writes a slot of its own.
+    let generation = move || {
+        (0..width)
+            .map(|slot| {
+                let effect = Apply::Modify {
+                    address: counter,
+                    basic: Basic {
+                        balance: U256::zero(),
+                        nonce: U256::one(),
+                    },
+                    code: None,
+                    storage: BTreeMap::from([(
+                        H256::from_low_u64_be(slot),
+                        H256::from_low_u64_be(1),
+                    )]),
+                    reset_storage: false,
+                };
+                FinalizedTransaction::from(ReExecutedTransaction::build_from(
+                    IndexedEthereumTransaction::new(EthereumTransaction::default(), slot),
+                    vec![effect],
+                    vec![],
+                    RwSet::new(),
+                ))
+            })
+            .collect::<Vec<_>>()
+    };
+
+    for chunk_len in [1, 16, 64, 256] {
+        let optme = _get_optme_executor(1).with_commit_chunk_len(chunk_len);
+
+        group.bench_with_input(
+            criterion::BenchmarkId::new(
+                "chunked",
+                format!("(width: {}, chunk: {})", width, chunk_len),
+            ),
+            &optme,
+            |b, optme| {
+                b.to_async(tokio::runtime::Runtime::new().unwrap())
+                    .iter_batched(
+                        generation,
+                        |txs| async move { optme._concurrent_commit(vec![txs]).await.unwrap() },
+                        BatchSize::SmallInput,
+                    );
+            },
+        );
+    }
+}
+
+criterion_group!(
+    benches,
+    baseline,
+    early_detection,
+    parallel_early_detection,
+    chunked_commit,
+);
 criterion_main!(benches);
@@ -1,13 +1,20 @@
 use criterion::Throughput;
 use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use ethers_core::types::{H160, H256, U256};
 use ethers_providers::{MockProvider, Provider};
+use evm::{
+    backend::{Apply, Basic},
+    executor::stack::RwSet,
+};
 use sslab_execution::{
-    types::ExecutableEthereumBatch,
+    types::{EthereumTransaction, ExecutableEthereumBatch, IndexedEthereumTransaction},
     utils::smallbank_contract_benchmark::concurrent_evm_storage,
     utils::test_utils::{SmallBankTransactionHandler, DEFAULT_CHAIN_ID},
 };
 
-use sslab_execution_optme::{ConcurrencyLevelManager, SimulatedTransaction, SimulationResult};
+use sslab_execution_optme::{
+    types::ReExecutedTransaction, ConcurrencyLevelManager, SimulatedTransaction, SimulationResult,
+};
 
 const DEFAULT_BATCH_SIZE: usize = 200;
 
@@ -36,7 +43,7 @@ fn _get_rw_sets(
 ) -> Vec<SimulatedTransaction> {
     let (tx, rx) = std::sync::mpsc::channel();
     let _ = tokio::runtime::Handle::current().spawn(async move {
-        let SimulationResult { rw_sets, .. } = optme.simulate(consensus_output).await;
+        let SimulationResult { rw_sets, .. } = optme.simulate(consensus_output).await.unwrap();
         tx.send(rw_sets).unwrap();
     });
     rx.recv().unwrap()
@@ -69,7 +76,7 @@ fn optme(c: &mut Criterion) {
                                 (optme, consensus_output)
                             },
                             |(optme, consensus_output)| async move {
-                                optme._execute(consensus_output).await
+                                optme._execute(consensus_output).await.unwrap()
                             },
                             BatchSize::SmallInput,
                         );
@@ -106,7 +113,7 @@ fn optme_skewness(c: &mut Criterion) {
                                 (optme, consensus_output)
                             },
                             |(optme, consensus_output)| async move {
-                                optme._execute(consensus_output).await
+                                optme._execute(consensus_output).await.unwrap()
                             },
                             BatchSize::SmallInput,
                         );
@@ -116,5 +123,188 @@ fn optme_skewness(c: &mut Criterion) {
     }
 }
 
-criterion_group!(benches, optme, optme_skewness);
+fn optme_commit_shards(c: &mut Criterion) {
+    let shards = [1, 2, 4, 8];
+    let i = 80;
+    let mut group = c.benchmark_group("OptME");
+
+    for num_shards in shards {
+        group.throughput(Throughput::Elements((DEFAULT_BATCH_SIZE * i) as u64));
+        group.bench_with_input(
+            criterion::BenchmarkId::new(
+                "commit_shards",
+                format!("(shards: {}, block_concurrency: {})", num_shards, i),
+            ),
+            &num_shards,
+            |b, num_shards| {
+                b.to_async(tokio::runtime::Runtime::new().unwrap())
+                    .iter_batched(
+                        || {
+                            let consensus_output =
+                                _create_random_smallbank_workload(0.0, DEFAULT_BATCH_SIZE, i);
+                            let optme = _get_optme_executor(i).with_commit_shards(*num_shards);
+                            (optme, consensus_output)
+                        },
+                        |(optme, consensus_output)| async move {
+                            optme._execute(consensus_output).await.unwrap()
+                        },
+                        BatchSize::SmallInput,
+                    );
+            },
+        );
+    }
+}
+
+/* the commit throughput of a generation writing distinct accounts, whose effects are applied under the locks of their stripes */
+fn optme_lock_stripes(c: &mut Criterion) {
+    use ethers_core::types::U64;
+    use sslab_execution::evm_storage::EvmStorage;
+
+    let stripes = [1, 16, 256];
+    let num_txs = (DEFAULT_BATCH_SIZE * 80) as u64;
+    let mut group = c.benchmark_group("OptME");
+    group.throughput(Throughput::Elements(num_txs));
+
+    for num_stripes in stripes {
+        group.bench_with_input(
+            criterion::BenchmarkId::new(
+                "lock_stripes",
+                format!("(stripes: {}, txs: {})", num_stripes, num_txs),
+            ),
+            &num_stripes,
+            |b, num_stripes| {
+                b.to_async(tokio::runtime::Runtime::new().unwrap())
+                    .iter_batched(
+                        || {
+                            let backend = concurrent_evm_storage()
+                                .get_storage()
+                                .clone()
+                                .with_stripes(*num_stripes);
+                            let storage =
+                                EvmStorage::new(U64::from(9), backend, Default::default());
+                            let txs = _re_executed_groups(num_txs)
+                                .into_iter()
+                                .flatten()
+                                .collect::<Vec<_>>();
+                            (ConcurrencyLevelManager::new(storage, 1), txs)
+                        },
+                        |(optme, txs)| async move { optme._concurrent_commit_2(txs).await.unwrap() },
+                        BatchSize::SmallInput,
+                    );
+            },
+        );
+    }
+}
+
+/* single-transaction groups of re-executed transactions, each writing a slot of its own account */
+fn _re_executed_groups(num_groups: u64) -> Vec<Vec<ReExecutedTransaction>> {
+    (0..num_groups)
+        .map(|id| {
+            let effect = Apply::Modify {
+                address: H160::from_low_u64_be(id + 1),
+                basic: Basic {
+                    balance: U256::zero(),
+                    nonce: U256::one(),
+                },
+                code: None,
+                storage: [(H256::zero(), H256::from_low_u64_be(id + 1))].into(),
+                reset_storage: false,
+            };
+            vec![ReExecutedTransaction::build_from(
+                IndexedEthereumTransaction::new(EthereumTransaction::default(), id),
+                vec![effect],
+                vec![],
+                RwSet::new(),
+            )]
+        })
+        .collect()
+}
+
+fn optme_validation_commit(c: &mut Criterion) {
+    let num_groups = 200;
+    let mut group = c.benchmark_group("OptME");
+    group.throughput(Throughput::Elements(num_groups));
+
+    for inline in [true, false] {
+        group.bench_with_input(
+            criterion::BenchmarkId::new(
+                "validation_commit",
+                format!("(inline: {}, groups: {})", inline, num_groups),
+            ),
+            &inline,
+            |b, &inline| {
+                b.to_async(tokio::runtime::Runtime::new().unwrap())
+                    .iter_batched(
+                        || (_get_optme_executor(1), _re_executed_groups(num_groups)),
+                        |(optme, groups)| async move {
+                            for txs in groups {
+                                if inline {
+                                    optme._validate_optimistic_assumption(txs).await.unwrap();
+                                } else {
+                                    optme
+                                        ._validate_optimistic_assumption_two_phase(txs)
+                                        .await
+                                        .unwrap();
+                                }
+                            }
+                        },
+                        BatchSize::SmallInput,
+                    );
+            },
+        );
+    }
+}
+
+/* a sustained load of several blocks on the same manager, so that the pooled vectors are recycled across the blocks */
+fn optme_buffer_pooling(c: &mut Criterion) {
+    let num_blocks = 10;
+    let i = 80;
+    let mut group = c.benchmark_group("OptME");
+    group.throughput(Throughput::Elements(
+        (DEFAULT_BATCH_SIZE * i * num_blocks) as u64,
+    ));
+
+    for pooled in [true, false] {
+        group.bench_with_input(
+            criterion::BenchmarkId::new(
+                "buffer_pooling",
+                format!(
+                    "(pooled: {}, blocks: {}, block_concurrency: {})",
+                    pooled, num_blocks, i
+                ),
+            ),
+            &pooled,
+            |b, &pooled| {
+                b.to_async(tokio::runtime::Runtime::new().unwrap())
+                    .iter_batched(
+                        || {
+                            let blocks = (0..num_blocks)
+                                .map(|_| {
+                                    _create_random_smallbank_workload(0.5, DEFAULT_BATCH_SIZE, i)
+                                })
+                                .collect::<Vec<_>>();
+                            let optme = _get_optme_executor(i).with_buffer_pooling(pooled);
+                            (optme, blocks)
+                        },
+                        |(optme, blocks)| async move {
+                            for consensus_output in blocks {
+                                optme._execute(consensus_output).await.unwrap();
+                            }
+                        },
+                        BatchSize::SmallInput,
+                    );
+            },
+        );
+    }
+}
+
+criterion_group!(
+    benches,
+    optme,
+    optme_skewness,
+    optme_commit_shards,
+    optme_validation_commit,
+    optme_buffer_pooling,
+    optme_lock_stripes
+);
 criterion_main!(benches);
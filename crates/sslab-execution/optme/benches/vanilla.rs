@@ -40,12 +40,22 @@ fn _get_rw_sets(
 ) -> Vec<SimulatedTransaction> {
     let (tx, rx) = std::sync::mpsc::channel();
     let _ = tokio::runtime::Handle::current().spawn(async move {
-        let SimulationResult { rw_sets, .. } = optme.simulate(consensus_output).await;
+        let SimulationResult { rw_sets, .. } = optme.simulate(consensus_output).await.unwrap();
         tx.send(rw_sets).unwrap();
     });
     rx.recv().unwrap()
 }
 
+/* nothing committed (e.g., every transaction aborted) counts as no throughput, instead of NaN. */
+fn _ktps(commit_len: f64, latency: f64, block_concurrency: usize) -> f64 {
+    if commit_len == 0.0 {
+        return 0.0;
+    }
+
+    let expected_num_of_trials = DEFAULT_BATCH_SIZE as f64 * block_concurrency as f64 / commit_len;
+    commit_len / (latency * expected_num_of_trials)
+}
+
 fn vanilla_tps_blocksize(c: &mut Criterion) {
     let s = [0.0];
     let param = 1..81;
@@ -76,7 +86,7 @@ fn vanilla_tps_blocksize(c: &mut Criterion) {
                             },
                             |(optme, consensus_output)| async move {
                                 let now = tokio::time::Instant::now();
-                                let result = optme.simulate(consensus_output).await;
+                                let result = optme.simulate(consensus_output).await.unwrap();
                                 let ScheduledInfo {
                                     scheduled_txs,
                                     aborted_txs: _,
@@ -89,13 +99,11 @@ fn vanilla_tps_blocksize(c: &mut Criterion) {
                                 .await;
                                 let commit_len =
                                     scheduled_txs.iter().map(|txs| txs.len()).sum::<usize>() as f64;
-                                optme._concurrent_commit(scheduled_txs).await;
+                                optme._concurrent_commit(scheduled_txs).await.unwrap();
 
                                 let latency = now.elapsed().as_micros() as f64;
 
-                                let expected_num_of_trials =
-                                    DEFAULT_BATCH_SIZE as f64 * *i as f64 / commit_len;
-                                let ktps = commit_len / (latency * expected_num_of_trials);
+                                let ktps = _ktps(commit_len, latency, *i);
                                 metrics.write().push(ktps);
                             },
                             BatchSize::SmallInput,
@@ -148,7 +156,7 @@ fn vanilla_tps_skewness(c: &mut Criterion) {
                             },
                             |(optme, consensus_output)| async move {
                                 let now = tokio::time::Instant::now();
-                                let result = optme.simulate(consensus_output).await;
+                                let result = optme.simulate(consensus_output).await.unwrap();
                                 let ScheduledInfo {
                                     scheduled_txs,
                                     aborted_txs: _,
@@ -161,13 +169,11 @@ fn vanilla_tps_skewness(c: &mut Criterion) {
                                 .await;
                                 let commit_len =
                                     scheduled_txs.iter().map(|txs| txs.len()).sum::<usize>() as f64;
-                                optme._concurrent_commit(scheduled_txs).await;
+                                optme._concurrent_commit(scheduled_txs).await.unwrap();
 
                                 let latency = now.elapsed().as_micros() as f64;
 
-                                let expected_num_of_trials =
-                                    DEFAULT_BATCH_SIZE as f64 * *i as f64 / commit_len;
-                                let ktps = commit_len / (latency * expected_num_of_trials);
+                                let ktps = _ktps(commit_len, latency, *i);
                                 metrics.write().push(ktps);
                             },
                             BatchSize::SmallInput,
@@ -287,7 +293,7 @@ fn tps_of_last_committer_wins_rule(c: &mut Criterion) {
                             },
                             |(optme, consensus_output)| async move {
                                 let now = tokio::time::Instant::now();
-                                let result = optme.simulate(consensus_output).await;
+                                let result = optme.simulate(consensus_output).await.unwrap();
                                 let ScheduledInfo {
                                     scheduled_txs,
                                     aborted_txs: _,
@@ -301,13 +307,11 @@ fn tps_of_last_committer_wins_rule(c: &mut Criterion) {
                                 let commit_len =
                                     scheduled_txs.iter().map(|txs| txs.len()).sum::<usize>() as f64;
                                 let c_latency = tokio::time::Instant::now();
-                                optme._concurrent_commit(scheduled_txs).await;
+                                optme._concurrent_commit(scheduled_txs).await.unwrap();
                                 let c_latency = c_latency.elapsed().as_micros() as f64;
                                 let latency = now.elapsed().as_micros() as f64;
 
-                                let expected_num_of_trials =
-                                    DEFAULT_BATCH_SIZE as f64 * *i as f64 / commit_len;
-                                let ktps = commit_len / (latency * expected_num_of_trials);
+                                let ktps = _ktps(commit_len, latency, *i);
                                 metrics.write().push((ktps, c_latency));
                             },
                             BatchSize::SmallInput,
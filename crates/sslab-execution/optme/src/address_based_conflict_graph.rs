@@ -13,7 +13,11 @@ use parking_lot::{RwLock, RwLockReadGuard};
 use rayon::prelude::*;
 use sslab_execution::types::IndexedEthereumTransaction;
 
-use super::{optme_core::ScheduledInfo, types::SimulatedTransaction};
+use super::{
+    optme_core::ScheduledInfo,
+    types::{AbortClearing, AbortReason, ReExecutedTransaction, SimulatedTransaction},
+    utils::run_on_rayon,
+};
 
 pub(crate) type FastHashMap<K, V> = hashbrown::HashMap<K, V, nohash_hasher::BuildNoHashHasher<K>>;
 pub(crate) type FastHashSet<K> = hashbrown::HashSet<K, nohash_hasher::BuildNoHashHasher<K>>;
@@ -22,6 +26,7 @@ pub struct AddressBasedConflictGraph {
     addresses: hashbrown::HashMap<H256, Address>,
     tx_list: FastHashMap<u64, Arc<Transaction>>, // tx_id -> transaction
     aborted_txs: Vec<Arc<Transaction>>, // transactions that are aborted due to read-write conflict (used for reordering).
+    generations: FastHashMap<u64, u32>, // tx_id -> generation, indexed when the schedule is extracted.
 }
 
 impl AddressBasedConflictGraph {
@@ -30,6 +35,7 @@ impl AddressBasedConflictGraph {
             addresses: hashbrown::HashMap::new(),
             tx_list: FastHashMap::default(),
             aborted_txs: Vec::new(),
+            generations: FastHashMap::default(),
         }
     }
 
@@ -37,46 +43,69 @@ impl AddressBasedConflictGraph {
         let mut acg = Self::new();
 
         for tx in simulation_result {
-            let (_tx, rw_set) = Transaction::from(tx);
-            let tx = Arc::new(_tx);
+            acg._insert_tx(tx);
+        }
 
-            let (read_set, write_set) = rw_set.destruct();
-            let mut write_units =
-                Self::_convert_to_units(&tx, UnitType::Write, write_set, Some(&read_set));
+        acg
+    }
 
-            if acg._check_updater_already_exist_in_same_address(&write_units) {
-                tx.abort();
-                acg.aborted_txs.push(tx);
-                continue;
-            }
+    /// Integrates the rw-sets of re-executed transactions into the graph, instead of rebuilding it from scratch.
+    /// The units of a transaction already in the graph are replaced by the ones of its new rw-set,
+    /// and the others are inserted as [`Self::construct`] would do.
+    /// It must be called before [`Self::hierarchcial_sort`], since the sequences are not recomputed.
+    pub fn update(&mut self, new_rw_sets: Vec<ReExecutedTransaction>) -> &mut Self {
+        let tx_ids = new_rw_sets
+            .iter()
+            .map(|tx| tx.id())
+            .collect::<FastHashSet<u64>>();
+        let mut touched = self._remove_txs(&tx_ids);
+
+        for tx in new_rw_sets {
+            let tx = SimulatedTransaction::from(tx);
+            touched.extend(tx.read_set().iter().chain(tx.write_set().iter()).cloned());
+            self._insert_tx(tx);
+        }
 
-            let mut read_units = Self::_convert_to_units(&tx, UnitType::Read, read_set, None);
+        // keep the units in the order of tx ids, as the full reconstruction does.
+        touched.iter().for_each(|addr| {
+            if let Some(address) = self.addresses.get_mut(addr) {
+                address.sort_units_by_tx_id();
+            }
+        });
 
-            // before inserting the units, wr-dependencies must be created b/w RW units.
-            Self::_set_wr_dependencies(&mut read_units, &mut write_units);
-            tx.set_write_units(write_units.clone());
+        self
+    }
 
-            acg.tx_list.insert(tx.id(), tx);
-            acg._add_units_to_address(read_units);
-            acg._add_units_to_address(write_units);
-        }
+    /// Removes every transaction but the given ones from the graph, e.g., the ones executed serially instead.
+    /// As [`Self::update`], it must be called before [`Self::hierarchcial_sort`].
+    pub fn retain(&mut self, tx_ids: &FastHashSet<u64>) -> &mut Self {
+        let removed = self
+            .tx_list
+            .keys()
+            .copied()
+            .chain(self.aborted_txs.iter().map(|tx| tx.id()))
+            .filter(|tx_id| !tx_ids.contains(tx_id))
+            .collect::<FastHashSet<u64>>();
+        self._remove_txs(&removed);
 
-        acg
+        self
     }
 
-    async fn _par_construct<F, B>(simulation_result: Vec<B>, constructor: F) -> Self
+    /// Returns the emptied `simulation_result` along with the graph, keeping its capacity (e.g., to recycle it).
+    async fn _par_construct<F, B>(mut simulation_result: Vec<B>, constructor: F) -> (Self, Vec<B>)
     where
-        B: Sync + Send + Clone + 'static,
+        B: Sync + Send + 'static,
         F: Fn(Vec<B>) -> Self + Sync + Send + 'static,
     {
         let num_of_txn = simulation_result.len();
         let ncpu = num_cpus::get();
 
-        let (send, recv) = tokio::sync::oneshot::channel();
-        rayon::spawn(move || {
+        run_on_rayon(move || {
+            // the chunks are moved into the sub-graphs, rather than copied out of the slice.
             let mut sub_graphs = simulation_result
-                .par_chunks(std::cmp::max(num_of_txn / ncpu, 1))
-                .map(|chunk| constructor(chunk.to_vec()))
+                .par_drain(..)
+                .chunks(std::cmp::max(num_of_txn / ncpu, 1))
+                .map(&constructor)
                 .collect::<Vec<Self>>();
 
             while sub_graphs.len() > 1 {
@@ -95,14 +124,77 @@ impl AddressBasedConflictGraph {
                     .collect::<Vec<Self>>();
             }
 
-            let result = sub_graphs.into_iter().next().unwrap();
-            let _ = send.send(result);
-        });
+            let graph = sub_graphs.into_iter().next().unwrap_or_else(Self::new);
+            (graph, simulation_result)
+        })
+        .await
+        .unwrap()
+    }
 
-        recv.await.unwrap()
+    /// Returns the transaction of the given id, e.g., to inspect the sequence it was ordered with.
+    pub fn transaction(&self, tx_id: u64) -> Option<&Arc<Transaction>> {
+        self.tx_list.get(&tx_id)
+    }
+
+    /// The conflicts between the transactions in the graph as weighted edges `(tx_a, tx_b, shared_key_count)`,
+    /// with `tx_a < tx_b`, in ascending order. Two transactions conflict on a key if both access it and either writes it,
+    /// including the transactions aborted while inserted (which have no units in the graph).
+    /// The graph is emptied by the extraction of the schedule, so call it before [`Self::extract_schedule`].
+    pub fn to_adjacency(&self) -> Vec<(u64, u64, u32)> {
+        let mut accesses: hashbrown::HashMap<H256, (Vec<u64>, Vec<u64>)> =
+            hashbrown::HashMap::new(); // key -> (readers, writers)
+        self.tx_list
+            .values()
+            .chain(self.aborted_txs.iter())
+            .for_each(|tx| {
+                let (read_keys, write_keys) = tx.rw_set();
+                read_keys
+                    .difference(&write_keys)
+                    .for_each(|key| accesses.entry(*key).or_default().0.push(tx.id()));
+                write_keys
+                    .iter()
+                    .for_each(|key| accesses.entry(*key).or_default().1.push(tx.id()));
+            });
+
+        let mut weights: BTreeMap<(u64, u64), u32> = BTreeMap::new();
+        for (readers, writers) in accesses.values() {
+            let pairs = writers
+                .iter()
+                .flat_map(|writer| {
+                    readers
+                        .iter()
+                        .chain(writers.iter())
+                        .filter(move |tx_id| *tx_id != writer)
+                        .map(move |tx_id| {
+                            (
+                                std::cmp::min(*writer, *tx_id),
+                                std::cmp::max(*writer, *tx_id),
+                            )
+                        })
+                })
+                .collect::<hashbrown::HashSet<_>>();
+            pairs
+                .into_iter()
+                .for_each(|pair| *weights.entry(pair).or_default() += 1);
+        }
+
+        weights
+            .into_iter()
+            .map(|((tx_a, tx_b), weight)| (tx_a, tx_b, weight))
+            .collect()
     }
 
     pub async fn par_construct(simulation_result: Vec<SimulatedTransaction>) -> Self {
+        Self::_par_construct(simulation_result, Self::construct)
+            .await
+            .0
+    }
+
+    /// Constructs the graph as [`Self::par_construct`] does, and gives the emptied vector of the simulation result back,
+    /// so that its capacity can be reused for the next block (see [`crate::utils::VecPool`]).
+    pub async fn par_construct_recycling(
+        simulation_result: Vec<SimulatedTransaction>,
+    ) -> (Self, Vec<SimulatedTransaction>) {
         Self::_par_construct(simulation_result, Self::construct).await
     }
 
@@ -120,6 +212,25 @@ impl AddressBasedConflictGraph {
     }
 
     pub fn reorder(&mut self) -> &mut Self {
+        self._reorder(|address, _| {
+            address
+                .write_units
+                .max_seq()
+                .max(address.read_units.max_seq())
+        })
+    }
+
+    /// Revives the same transactions as [`Self::reorder`], but places each of them at the earliest sequence it can take,
+    /// i.e., right after the readers of its addresses and the writers of lower ids, instead of after every writer.
+    /// So the schedule stays as close to the index order as possible (e.g., for fairness), at the cost of parallelism.
+    pub fn reorder_preserve_order(&mut self) -> &mut Self {
+        self._reorder(|address, tx| address.min_seq_preserving_order(tx.id()))
+    }
+
+    fn _reorder<F>(&mut self, seq_at: F) -> &mut Self
+    where
+        F: Fn(&Address, &Transaction) -> u32,
+    {
         let (reorder_targets, aborted) = self
             ._extract_aborted_txs()
             .into_iter()
@@ -133,17 +244,9 @@ impl AddressBasedConflictGraph {
                 .iter()
                 .map(|unit| unit.address())
                 .unique()
-                .map(|addr| {
-                    let address = self.addresses.get(addr).unwrap();
-
-                    address
-                        .write_units
-                        .max_seq()
-                        .max(address.read_units.max_seq())
-                })
+                .map(|addr| seq_at(self.addresses.get(addr).unwrap(), tx))
                 .max()
-                .unwrap()
-                .clone();
+                .unwrap();
 
             tx.set_sequence(seq);
 
@@ -153,8 +256,33 @@ impl AddressBasedConflictGraph {
         self
     }
 
+    /// The generation (i.e., the position in [`ScheduledInfo::scheduled_txs`]) the transaction was scheduled into
+    /// by the last extracted schedule, or `None` if it was aborted or is not in the graph.
+    pub fn generation_of(&self, tx_id: u64) -> Option<u32> {
+        self.generations.get(&tx_id).copied()
+    }
+
+    // generations are the distinct sequences in ascending order, as `ScheduledInfo` groups the transactions.
+    fn _index_generations(tx_list: &FastHashMap<u64, Arc<Transaction>>) -> FastHashMap<u64, u32> {
+        let seqs = tx_list
+            .values()
+            .map(|tx| tx.sequence())
+            .sorted_unstable()
+            .dedup()
+            .collect_vec();
+
+        tx_list
+            .iter()
+            .map(|(tx_id, tx)| {
+                let generation = seqs.binary_search(&tx.sequence()).unwrap();
+                (*tx_id, generation as u32)
+            })
+            .collect()
+    }
+
     #[must_use]
     pub fn extract_schedule(&mut self) -> ScheduledInfo {
+        self.generations = Self::_index_generations(&self.tx_list);
         let tx_list = std::mem::replace(&mut self.tx_list, hashbrown::HashMap::default());
         let aborted_txs = std::mem::take(&mut self.aborted_txs);
 
@@ -165,17 +293,22 @@ impl AddressBasedConflictGraph {
     }
 
     pub async fn par_extract_schedule(&mut self) -> ScheduledInfo {
+        self.par_extract_schedule_with(AbortClearing::default())
+            .await
+    }
+
+    /// Extracts the schedule as [`Self::par_extract_schedule`] does, clearing the aborted transactions as `clearing` decides.
+    pub async fn par_extract_schedule_with(&mut self, clearing: AbortClearing) -> ScheduledInfo {
+        self.generations = Self::_index_generations(&self.tx_list);
         let tx_list = std::mem::take(&mut self.tx_list);
         let aborted_txs = std::mem::take(&mut self.aborted_txs);
 
         self.addresses.clear();
         self.addresses.shrink_to_fit();
 
-        let (send, recv) = tokio::sync::oneshot::channel();
-        rayon::spawn(move || {
-            let _ = send.send(ScheduledInfo::par_from(tx_list, aborted_txs));
-        });
-        recv.await.unwrap()
+        run_on_rayon(move || ScheduledInfo::par_from_with(tx_list, aborted_txs, clearing))
+            .await
+            .unwrap()
     }
 
     /* (Algorithm1) */
@@ -204,6 +337,66 @@ impl AddressBasedConflictGraph {
             .collect_vec()
     }
 
+    fn _insert_tx(&mut self, tx: SimulatedTransaction) {
+        let (_tx, rw_set) = Transaction::from(tx);
+        let tx = Arc::new(_tx);
+
+        let (read_set, write_set) = rw_set.destruct();
+
+        // a read-only transaction never conflicts, as it can be ordered before every writer of the keys it read
+        // (i.e., it read the snapshot). So it is kept out of the graph, and left unordered in the first generation.
+        if write_set.values().all(HashMap::is_empty) {
+            self.tx_list.insert(tx.id(), tx);
+            return;
+        }
+
+        let mut write_units =
+            Self::_convert_to_units(&tx, UnitType::Write, write_set, Some(&read_set));
+
+        if self._check_updater_already_exist_in_same_address(&write_units) {
+            tx.abort(AbortReason::WriteWrite);
+            self.aborted_txs.push(tx);
+            return;
+        }
+
+        let mut read_units = Self::_convert_to_units(&tx, UnitType::Read, read_set, None);
+
+        // before inserting the units, wr-dependencies must be created b/w RW units.
+        Self::_set_wr_dependencies(&mut read_units, &mut write_units);
+        tx.set_write_units(write_units.clone());
+
+        self.tx_list.insert(tx.id(), tx);
+        self._add_units_to_address(read_units);
+        self._add_units_to_address(write_units);
+    }
+
+    // returns the addresses from which the units of the given transactions are removed.
+    fn _remove_txs(&mut self, tx_ids: &FastHashSet<u64>) -> hashbrown::HashSet<H256> {
+        let mut touched = hashbrown::HashSet::new();
+
+        // early-detected aborted transactions have no units in the graph.
+        self.aborted_txs.retain(|tx| !tx_ids.contains(&tx.id()));
+
+        tx_ids.iter().for_each(|tx_id| {
+            if let Some(tx) = self.tx_list.remove(tx_id) {
+                let (read_keys, write_keys) = tx.rw_set();
+                read_keys.union(&write_keys).for_each(|addr| {
+                    if let Some(address) = self.addresses.get_mut(addr) {
+                        address.remove_units_of(*tx_id);
+                        if address.is_empty() {
+                            self.addresses.remove(addr);
+                        } else {
+                            touched.insert(*addr);
+                        }
+                    }
+                });
+                tx.clear_write_units();
+            }
+        });
+
+        touched
+    }
+
     fn _add_units_to_address(&mut self, units: Vec<Arc<Unit>>) {
         units.into_iter().for_each(|unit| {
             let raw_address = unit.address();
@@ -334,6 +527,7 @@ where
             Self::construct_without_early_detection,
         )
         .await
+        .0
     }
 }
 
@@ -376,6 +570,7 @@ impl Benchmark for AddressBasedConflictGraph {
 #[derive(Debug)]
 pub struct AbortInfo {
     aborted: bool,
+    reason: Option<AbortReason>,
     prev_write_keys: BTreeMap<H160, HashMap<H256, H256>>,
     prev_read_keys: BTreeMap<H160, HashMap<H256, H256>>,
 }
@@ -384,6 +579,7 @@ impl AbortInfo {
     fn new(rw_set: RwSet) -> Self {
         Self {
             aborted: false,
+            reason: None,
             prev_write_keys: rw_set.writes().to_owned(),
             prev_read_keys: rw_set.reads().to_owned(),
         }
@@ -421,6 +617,13 @@ impl AbortInfo {
     pub fn aborted(&self) -> bool {
         self.aborted
     }
+
+    /// Why the transaction was last aborted, or `None` if it never was.
+    /// It is kept by [`Transaction::init`], so that it carries over to the [`crate::types::AbortedTransaction`].
+    #[inline]
+    pub fn reason(&self) -> Option<AbortReason> {
+        self.reason
+    }
 }
 
 #[derive(Debug)]
@@ -452,9 +655,11 @@ impl Transaction {
         (tx, rw_set)
     }
 
+    /// Resets the transaction for the next round, i.e., not ordered and not aborted, keeping its rw-set.
+    /// It is idempotent, so it is safe to call it again on a transaction already reset (e.g., across re-execution rounds).
     #[inline]
     pub fn init(&self) {
-        *self.sequence.write() = 0;
+        self.reset_sequence();
         let mut abort_info = self.abort_info.write();
         abort_info.aborted = false;
     }
@@ -469,6 +674,13 @@ impl Transaction {
         self.sequence.read().to_owned()
     }
 
+    /// Marks the transaction as not ordered yet, so that a sequence of the previous round (or block)
+    /// does not leak into the next one, where it would be taken as already sorted.
+    #[inline]
+    pub fn reset_sequence(&self) {
+        *self.sequence.write() = 0;
+    }
+
     #[inline]
     pub fn simulation_result(&self) -> (Vec<Apply>, Vec<Log>) {
         (self.effects.clone(), self.logs.clone())
@@ -480,6 +692,8 @@ impl Transaction {
         write_units.into_iter().for_each(|u| my_units.push(u));
     }
 
+    // drops the write units (and their references back to the transaction itself, which would otherwise keep it alive).
+    // It is idempotent, since clearing the units again has nothing left to drop.
     #[inline]
     pub(crate) fn clear_write_units(&self) {
         let mut my_units = self.write_units.write();
@@ -488,9 +702,10 @@ impl Transaction {
     }
 
     #[inline]
-    fn abort(&self) {
+    fn abort(&self, reason: AbortReason) {
         let mut info = self.abort_info.write();
         info.aborted = true;
+        info.reason = Some(reason);
     }
 
     #[inline]
@@ -617,9 +832,9 @@ impl Unit {
     }
 
     #[inline]
-    // abort a transaction that makes the anti-rw conflict, resulting in a cycle at ACG.
-    fn abort_tx(&self) {
-        self.tx.abort();
+    // abort a transaction that makes the anti-rw conflict, resulting in a cycle at ACG (or loses the first updater wins).
+    fn abort_tx(&self, reason: AbortReason) {
+        self.tx.abort(reason);
     }
 
     #[inline]
@@ -744,7 +959,7 @@ impl WriteUnits {
                                 self.first_updater_flag = true;
                             }
                             true => {
-                                unit.abort_tx();
+                                unit.abort_tx(AbortReason::WriteWrite);
                             }
                         }
                     }
@@ -757,7 +972,7 @@ impl WriteUnits {
             .filter(|unit| !unit.tx.aborted())
             .for_each(|unit| {
                 if unit.sequence() < read_units.max_seq() {
-                    unit.abort_tx();
+                    unit.abort_tx(AbortReason::Rescheduled);
                 }
             });
 
@@ -851,6 +1066,58 @@ impl Address {
         self.write_units.sort(&mut self.read_units);
     }
 
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.read_units.units.is_empty() && self.write_units.units.is_empty()
+    }
+
+    /// The earliest sequence a blind writer of the given id can take at this address: after every reader
+    /// (which must not see its write), and not before any writer of a lower id.
+    #[inline]
+    fn min_seq_preserving_order(&self, tx_id: u64) -> u32 {
+        let after_readers = self
+            .read_units
+            .units
+            .iter()
+            .filter(|unit| !unit.tx.aborted())
+            .map(|unit| unit.sequence() + 1);
+        let after_writers = self
+            .write_units
+            .units
+            .iter()
+            .filter(|unit| !unit.tx.aborted() && unit.tx.id() < tx_id)
+            .map(|unit| unit.sequence());
+
+        after_readers.chain(after_writers).max().unwrap_or(1)
+    }
+
+    #[inline]
+    fn remove_units_of(&mut self, tx_id: u64) {
+        let (in_degree, out_degree) = (&mut self.in_degree, &mut self.out_degree);
+
+        self.read_units.units.retain(|unit| {
+            let keep = unit.tx.id() != tx_id;
+            if !keep {
+                *in_degree -= unit.degree();
+            }
+            keep
+        });
+        self.write_units.units.retain(|unit| {
+            let keep = unit.tx.id() != tx_id;
+            if !keep {
+                *out_degree -= unit.degree();
+            }
+            keep
+        });
+        self.first_updater_flag = self.write_units.units.iter().any(|unit| unit.co_located());
+    }
+
+    #[inline]
+    fn sort_units_by_tx_id(&mut self) {
+        self.read_units.units.sort_by_key(|unit| unit.tx.id());
+        self.write_units.units.sort_by_key(|unit| unit.tx.id());
+    }
+
     #[inline]
     fn merge(&mut self, other: Self) {
         // unwind
@@ -858,7 +1125,7 @@ impl Address {
             // abort last updater
             for u in other.read_units.units {
                 if u.co_located {
-                    u.abort_tx();
+                    u.abort_tx(AbortReason::WriteWrite);
                     self.in_degree += other.in_degree;
                     self.in_degree -= u.degree(); // decrease degree accordingly
                     break;
@@ -866,7 +1133,7 @@ impl Address {
             }
             for u in other.write_units.units {
                 if u.co_located {
-                    u.abort_tx();
+                    u.abort_tx(AbortReason::WriteWrite);
                     self.in_degree += other.in_degree;
                     self.in_degree -= u.degree(); // decrease degree accordingly
                     break;
@@ -0,0 +1,331 @@
+//! The serializable forms of the evm types, which implement no serde traits themselves,
+//! for the commit WAL and the cache of simulation results.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use ethers_core::types::{Bytes, H160, H256, U256};
+use evm::{
+    backend::{Apply, Basic, Log},
+    executor::stack::RwSet,
+    ExitError, Opcode,
+};
+use narwhal_types::BatchDigest;
+use serde::{Deserialize, Serialize};
+use sslab_execution::types::IndexedEthereumTransaction;
+
+use crate::types::{SimulatedTransaction, SimulationOutcome, SimulationResult};
+
+/// The serializable form of [`Apply`].
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SerdeApply {
+    Modify {
+        address: H160,
+        balance: U256,
+        nonce: U256,
+        code: Option<Vec<u8>>,
+        storage: Vec<(H256, H256)>,
+        reset_storage: bool,
+    },
+    Delete {
+        address: H160,
+    },
+}
+
+impl From<&Apply> for SerdeApply {
+    fn from(apply: &Apply) -> Self {
+        match apply {
+            Apply::Modify {
+                address,
+                basic,
+                code,
+                storage,
+                reset_storage,
+            } => SerdeApply::Modify {
+                address: *address,
+                balance: basic.balance,
+                nonce: basic.nonce,
+                code: code.clone(),
+                storage: storage.iter().map(|(k, v)| (*k, *v)).collect(),
+                reset_storage: *reset_storage,
+            },
+            Apply::Delete { address } => SerdeApply::Delete { address: *address },
+        }
+    }
+}
+
+impl From<SerdeApply> for Apply {
+    fn from(effect: SerdeApply) -> Self {
+        match effect {
+            SerdeApply::Modify {
+                address,
+                balance,
+                nonce,
+                code,
+                storage,
+                reset_storage,
+            } => Apply::Modify {
+                address,
+                basic: Basic { balance, nonce },
+                code,
+                storage: storage.into_iter().collect::<BTreeMap<_, _>>(),
+                reset_storage,
+            },
+            SerdeApply::Delete { address } => Apply::Delete { address },
+        }
+    }
+}
+
+/// The serializable form of [`Log`].
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SerdeLog {
+    pub address: H160,
+    pub topics: Vec<H256>,
+    pub data: Vec<u8>,
+}
+
+impl From<&Log> for SerdeLog {
+    fn from(log: &Log) -> Self {
+        Self {
+            address: log.address,
+            topics: log.topics.clone(),
+            data: log.data.clone(),
+        }
+    }
+}
+
+impl From<SerdeLog> for Log {
+    fn from(log: SerdeLog) -> Self {
+        Log {
+            address: log.address,
+            topics: log.topics,
+            data: log.data,
+        }
+    }
+}
+
+/// The serializable form of [`RwSet`]: the (contract, slot, value) reads and writes.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SerdeRwSet {
+    pub reads: Vec<(H160, H256, H256)>,
+    pub writes: Vec<(H160, H256, H256)>,
+}
+
+impl From<&RwSet> for SerdeRwSet {
+    fn from(rw_set: &RwSet) -> Self {
+        Self {
+            reads: rw_set
+                .reads()
+                .iter()
+                .flat_map(|(address, state)| {
+                    state
+                        .iter()
+                        .map(move |(key, value)| (*address, *key, *value))
+                })
+                .collect(),
+            writes: rw_set
+                .writes()
+                .iter()
+                .flat_map(|(address, state)| {
+                    state
+                        .iter()
+                        .map(move |(key, value)| (*address, *key, *value))
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<SerdeRwSet> for RwSet {
+    fn from(rw_set: SerdeRwSet) -> Self {
+        let mut set = RwSet::new();
+        rw_set
+            .reads
+            .into_iter()
+            .for_each(|(address, key, value)| set.record_read_key(address, key, value));
+        rw_set
+            .writes
+            .into_iter()
+            .for_each(|(address, key, value)| set.record_write_key(address, key, value));
+        set
+    }
+}
+
+/// The serializable form of [`SimulationOutcome`].
+/// The EVM errors are kept in their debug form, and restored as [`ExitError::Other`].
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SerdeOutcome {
+    Success,
+    Reverted(Bytes),
+    Failed(SerdeExitError),
+    Slow(Duration),
+}
+
+impl From<&SimulationOutcome> for SerdeOutcome {
+    fn from(outcome: &SimulationOutcome) -> Self {
+        match outcome {
+            SimulationOutcome::Success => SerdeOutcome::Success,
+            SimulationOutcome::Reverted(output) => SerdeOutcome::Reverted(output.clone()),
+            SimulationOutcome::Failed(e) => SerdeOutcome::Failed(SerdeExitError::from(e)),
+            SimulationOutcome::Slow(elapsed) => SerdeOutcome::Slow(*elapsed),
+        }
+    }
+}
+
+impl From<SerdeOutcome> for SimulationOutcome {
+    fn from(outcome: SerdeOutcome) -> Self {
+        match outcome {
+            SerdeOutcome::Success => SimulationOutcome::Success,
+            SerdeOutcome::Reverted(output) => SimulationOutcome::Reverted(output),
+            SerdeOutcome::Failed(e) => SimulationOutcome::Failed(e.into()),
+            SerdeOutcome::Slow(elapsed) => SimulationOutcome::Slow(elapsed),
+        }
+    }
+}
+
+/// The serializable form of [`ExitError`].
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SerdeExitError {
+    StackUnderflow,
+    StackOverflow,
+    InvalidJump,
+    InvalidRange,
+    DesignatedInvalid,
+    CallTooDeep,
+    CreateCollision,
+    CreateContractLimit,
+    InvalidCode(u8),
+    OutOfOffset,
+    OutOfGas,
+    OutOfFund,
+    PCUnderflow,
+    CreateEmpty,
+    MaxNonce,
+    Other(String),
+}
+
+impl From<&ExitError> for SerdeExitError {
+    fn from(e: &ExitError) -> Self {
+        match e {
+            ExitError::StackUnderflow => SerdeExitError::StackUnderflow,
+            ExitError::StackOverflow => SerdeExitError::StackOverflow,
+            ExitError::InvalidJump => SerdeExitError::InvalidJump,
+            ExitError::InvalidRange => SerdeExitError::InvalidRange,
+            ExitError::DesignatedInvalid => SerdeExitError::DesignatedInvalid,
+            ExitError::CallTooDeep => SerdeExitError::CallTooDeep,
+            ExitError::CreateCollision => SerdeExitError::CreateCollision,
+            ExitError::CreateContractLimit => SerdeExitError::CreateContractLimit,
+            ExitError::InvalidCode(opcode) => SerdeExitError::InvalidCode(opcode.0),
+            ExitError::OutOfOffset => SerdeExitError::OutOfOffset,
+            ExitError::OutOfGas => SerdeExitError::OutOfGas,
+            ExitError::OutOfFund => SerdeExitError::OutOfFund,
+            ExitError::PCUnderflow => SerdeExitError::PCUnderflow,
+            ExitError::CreateEmpty => SerdeExitError::CreateEmpty,
+            ExitError::MaxNonce => SerdeExitError::MaxNonce,
+            ExitError::Other(reason) => SerdeExitError::Other(reason.to_string()),
+            // (the errors the fork adds for the multi-version backend never come out of a simulation)
+            #[allow(unreachable_patterns)]
+            e => SerdeExitError::Other(format!("{e:?}")),
+        }
+    }
+}
+
+impl From<SerdeExitError> for ExitError {
+    fn from(e: SerdeExitError) -> Self {
+        match e {
+            SerdeExitError::StackUnderflow => ExitError::StackUnderflow,
+            SerdeExitError::StackOverflow => ExitError::StackOverflow,
+            SerdeExitError::InvalidJump => ExitError::InvalidJump,
+            SerdeExitError::InvalidRange => ExitError::InvalidRange,
+            SerdeExitError::DesignatedInvalid => ExitError::DesignatedInvalid,
+            SerdeExitError::CallTooDeep => ExitError::CallTooDeep,
+            SerdeExitError::CreateCollision => ExitError::CreateCollision,
+            SerdeExitError::CreateContractLimit => ExitError::CreateContractLimit,
+            SerdeExitError::InvalidCode(opcode) => ExitError::InvalidCode(Opcode(opcode)),
+            SerdeExitError::OutOfOffset => ExitError::OutOfOffset,
+            SerdeExitError::OutOfGas => ExitError::OutOfGas,
+            SerdeExitError::OutOfFund => ExitError::OutOfFund,
+            SerdeExitError::PCUnderflow => ExitError::PCUnderflow,
+            SerdeExitError::CreateEmpty => ExitError::CreateEmpty,
+            SerdeExitError::MaxNonce => ExitError::MaxNonce,
+            SerdeExitError::Other(reason) => ExitError::Other(reason.into()),
+        }
+    }
+}
+
+/// The serializable form of [`SimulatedTransaction`]. Its read and write sets are derived from the rw-set again when loaded.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SerdeSimulatedTransaction {
+    pub raw_tx: IndexedEthereumTransaction,
+    pub rw_set: SerdeRwSet,
+    pub effects: Vec<SerdeApply>,
+    pub logs: Vec<SerdeLog>,
+    pub outcome: SerdeOutcome,
+    pub gas_used: u64,
+}
+
+impl From<&SimulatedTransaction> for SerdeSimulatedTransaction {
+    fn from(tx: &SimulatedTransaction) -> Self {
+        Self {
+            raw_tx: tx.raw_tx().clone(),
+            rw_set: SerdeRwSet::from(tx.rw_set()),
+            effects: tx.effects().iter().map(SerdeApply::from).collect(),
+            logs: tx.logs().iter().map(SerdeLog::from).collect(),
+            outcome: SerdeOutcome::from(tx.outcome()),
+            gas_used: tx.gas_used(),
+        }
+    }
+}
+
+impl From<SerdeSimulatedTransaction> for SimulatedTransaction {
+    fn from(tx: SerdeSimulatedTransaction) -> Self {
+        SimulatedTransaction::new(
+            RwSet::from(tx.rw_set),
+            tx.effects.into_iter().map(Apply::from).collect(),
+            tx.logs.into_iter().map(Log::from).collect(),
+            tx.raw_tx,
+        )
+        .with_outcome(tx.outcome.into())
+        .with_gas_used(tx.gas_used)
+    }
+}
+
+/// The serializable form of [`SimulationResult`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SerdeSimulationResult {
+    pub digests: Vec<BatchDigest>,
+    pub rw_sets: Vec<SerdeSimulatedTransaction>,
+}
+
+#[cfg(feature = "sim-cache")]
+impl SimulationResult {
+    /// Caches the simulation result to a JSON file, e.g., to run scheduling experiments on the same workload
+    /// without simulating it again (see [`Self::load`]).
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        let cached = SerdeSimulationResult {
+            digests: self.digests.clone(),
+            rw_sets: self
+                .rw_sets
+                .iter()
+                .map(SerdeSimulatedTransaction::from)
+                .collect(),
+        };
+
+        let writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+        serde_json::to_writer(writer, &cached).map_err(std::io::Error::from)
+    }
+
+    /// Loads the simulation result cached by [`Self::save`].
+    pub fn load(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+        let cached: SerdeSimulationResult = serde_json::from_reader(reader)?;
+
+        Ok(Self {
+            digests: cached.digests,
+            rw_sets: cached
+                .rw_sets
+                .into_iter()
+                .map(SimulatedTransaction::from)
+                .collect(),
+        })
+    }
+}
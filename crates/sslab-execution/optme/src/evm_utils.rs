@@ -1,8 +1,8 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, panic::{self, AssertUnwindSafe}, time::Instant};
 use sui_types::error::SuiError;
 use evm::{
     backend::{Apply, Log, Backend}, 
-    executor::stack::RwSet
+    executor::stack::RwSet, Config, ExitError, ExitReason
 };
 use sslab_execution::{
     types::EthereumTransaction, 
@@ -11,35 +11,46 @@ use sslab_execution::{
 };
 use tracing::debug;
 
+use crate::types::SimulationOutcome;
 
+
+/// Simulates the transaction over the snapshot, under the given config (i.e., the gas rules of a fork).
+/// Along with the effects, returns the outcome (i.e., whether it succeeded, reverted or failed) and the gas it used.
+/// A reverted or failed transaction has no effects, and its revert output or EVM error is kept in the outcome,
+/// except a contract creation colliding with an existing contract, which still bumps the nonce of its caller (see EIP-684).
 pub fn simulate_tx<B>(
     tx: &EthereumTransaction, 
-    snapshot: &EvmStorage<B>
-) -> Result<Option<(Vec<Apply>, Vec<Log>, RwSet)>, SuiError> 
+    snapshot: &EvmStorage<B>,
+    config: &Config
+) -> Result<(SimulationOutcome, Vec<Apply>, Vec<Log>, RwSet, u64), SuiError> 
 where
     B: Backend + ApplyBackend + Default + Clone
 {
-    let mut executor = snapshot.executor(tx.gas_limit(), true);
+    let mut executor = snapshot.executor_with_config(tx.gas_limit(), true, config);
 
     let mut effect: Vec<Apply> = vec![];
     let mut log: Vec<Log> = vec![];
 
-    if let Some(to_addr) = tx.to_addr() {
+    if !tx.is_contract_creation() {
+        let to_addr = tx.to_addr().ok_or_else(|| {
+            SuiError::ExecutionError(String::from("the recipient of a transaction must be an address"))
+        })?;
 
-        let (reason, _) = & executor.transact_call(
-            tx.caller(), *to_addr, tx.value(), tx.data().unwrap().to_owned().to_vec(), 
+        let (reason, output) = executor.transact_call(
+            tx.caller(), *to_addr, tx.value(), tx.data().map_or_else(Vec::new, |data| data.to_vec()), 
             tx.gas_limit(), tx.access_list()
         );
 
-        match EvmExecutionUtils::process_transact_call_result(reason) {
+        match EvmExecutionUtils::process_transact_call_result(&reason) {
             Ok(fail) => {
                 if fail {
-                    return Ok(None);
+                    return Ok((_failure_outcome(reason, output), effect, log, RwSet::new(), executor.used_gas()));
                 } else {
                     // debug!("success to execute a transaction {}", tx.id());
                     let rw_set = executor.rw_set().unwrap().clone();
+                    let gas_used = executor.used_gas();
                     (effect, log) = executor.into_state().deconstruct();
-                    return Ok(Some((effect, log, rw_set)));
+                    return Ok((SimulationOutcome::Success, effect, log, rw_set, gas_used));
                 }
             },
             Err(e) => return Err(e)
@@ -48,17 +59,24 @@ where
         if let Some(data) = tx.data() {
              // create EOA
             let init_code = data.to_vec();
-            let (reason, _) = &executor.transact_create(tx.caller(), tx.value(), init_code.clone(), tx.gas_limit(), tx.access_list());
+            let (reason, output) = executor.transact_create(tx.caller(), tx.value(), init_code.clone(), tx.gas_limit(), tx.access_list());
 
-            match EvmExecutionUtils::process_transact_create_result(reason) {
+            match EvmExecutionUtils::process_transact_create_result(&reason) {
                 Ok(fail) => {
-                    if fail {
-                        return Ok(None);
+                    if fail && reason == ExitReason::Error(ExitError::CreateCollision) {
+                        debug!("fail to deploy a contract at {:?}, which already has code", tx.created_address());
+                        let rw_set = executor.rw_set().unwrap().clone();
+                        let gas_used = executor.used_gas();
+                        (effect, log) = executor.into_state().deconstruct();
+                        return Ok((_failure_outcome(reason, output), effect, log, rw_set, gas_used));
+                    } else if fail {
+                        return Ok((_failure_outcome(reason, output), effect, log, RwSet::new(), executor.used_gas()));
                     } else {
-                        debug!("success to deploy a contract!");
+                        debug!("success to deploy a contract at {:?}", tx.created_address());
                         let rw_set = executor.rw_set().unwrap().clone();
+                        let gas_used = executor.used_gas();
                         (effect, log) = executor.into_state().deconstruct();
-                        return Ok(Some((effect, log, rw_set)));
+                        return Ok((SimulationOutcome::Success, effect, log, rw_set, gas_used));
                     }
                 },
                 Err(e) => return Err(e)
@@ -80,7 +98,59 @@ where
                 data: vec![],
             });
             // Self::_process_local_effect(store, effect, log, &mut effects, &mut logs);
-            return Ok(Some((effect, log, RwSet::new())));
+            return Ok((SimulationOutcome::Success, effect, log, RwSet::new(), 0));
+        }
+    }
+}
+
+/// Simulates the transaction as [`simulate_tx`] does, but interrupts it once the deadline has passed,
+/// in which case `None` is returned. The deadline is checked as the EVM charges gas (i.e., every few steps).
+pub fn simulate_tx_until<B>(
+    tx: &EthereumTransaction, 
+    snapshot: &EvmStorage<B>,
+    config: &Config,
+    deadline: Instant
+) -> Result<Option<(SimulationOutcome, Vec<Apply>, Vec<Log>, RwSet, u64)>, SuiError> 
+where
+    B: Backend + ApplyBackend + Default + Clone
+{
+    let mut watchdog = Watchdog { deadline, steps: 0 };
+
+    // the watchdog unwinds out of the EVM, which has only borrowed the snapshot to read from.
+    match panic::catch_unwind(AssertUnwindSafe(|| {
+        evm::gasometer::tracing::using(&mut watchdog, || simulate_tx(tx, snapshot, config))
+    })) {
+        Ok(result) => result.map(Some),
+        Err(payload) if payload.is::<DeadlineExceeded>() => Ok(None),
+        Err(payload) => panic::resume_unwind(payload),
+    }
+}
+
+// the payload the watchdog unwinds with.
+struct DeadlineExceeded;
+
+// interrupts the simulation once the deadline has passed, looking at the clock only every `WATCHDOG_INTERVAL` gas events.
+struct Watchdog {
+    deadline: Instant,
+    steps: u64,
+}
+
+const WATCHDOG_INTERVAL: u64 = 1024;
+
+impl evm::gasometer::tracing::EventListener for Watchdog {
+    fn event(&mut self, _event: evm::gasometer::tracing::Event) {
+        self.steps += 1;
+        if self.steps % WATCHDOG_INTERVAL == 0 && Instant::now() > self.deadline {
+            panic::panic_any(DeadlineExceeded);
         }
     }
+}
+
+// fatal errors never reach here, since `EvmExecutionUtils` has already converted them into `SuiError`.
+fn _failure_outcome(reason: ExitReason, output: Vec<u8>) -> SimulationOutcome {
+    match reason {
+        ExitReason::Revert(_) => SimulationOutcome::Reverted(output.into()),
+        ExitReason::Error(e) => SimulationOutcome::Failed(e),
+        ExitReason::Succeed(_) | ExitReason::Fatal(_) => unreachable!("{:?} is not a failure of the transaction", reason),
+    }
 }
\ No newline at end of file
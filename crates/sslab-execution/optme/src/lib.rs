@@ -1,11 +1,23 @@
 pub mod address_based_conflict_graph;
+#[cfg(any(feature = "wal", feature = "sim-cache"))]
+pub mod codec;
 mod evm_utils;
 pub mod optme_core;
+pub mod scheduling;
+pub mod service;
 pub mod types;
+pub mod utils;
+#[cfg(feature = "wal")]
+pub mod wal;
 pub use {
     address_based_conflict_graph::AddressBasedConflictGraph,
     optme_core::{ConcurrencyLevelManager, OptME},
-    types::{SimulatedTransaction, SimulationResult},
+    service::ExecutionService,
+    types::{
+        AbortClearing, AbortReason, AccessKeys, AutoTuner, BatchRoute, Committable,
+        ConflictGranularity, FirstPassMode, GasMetrics, OptmeError, PhaseTimings, SimSummary,
+        SimulatedTransaction, SimulationOutcome, SimulationResult,
+    },
 };
 
 pub mod tests;
@@ -1,30 +1,67 @@
-use ethers_core::types::H256;
+use ethers_core::types::{TransactionReceipt, H160, H256, U256};
+use evm::{
+    backend::{Apply, Backend as _, Basic},
+    executor::stack::RwSet,
+};
+use futures::{stream, FutureExt as _, Stream, StreamExt as _, TryStreamExt as _};
 use itertools::Itertools;
 use narwhal_types::BatchDigest;
+use parking_lot::Mutex;
 use rayon::prelude::*;
 use sslab_execution::{
     evm_storage::{backend::ExecutionBackend, ConcurrentEVMStorage},
     executor::Executable,
-    types::{ExecutableEthereumBatch, ExecutionResult, IndexedEthereumTransaction},
+    types::{
+        ChainConfig, DigestMismatch, ExecutableEthereumBatch, ExecutionResult,
+        IndexedEthereumTransaction, SpecId,
+    },
+};
+use std::{
+    collections::{BTreeMap, HashSet},
+    future::Future,
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
-use std::sync::Arc;
-use tracing::warn;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
 
 use crate::{
-    address_based_conflict_graph::FastHashMap,
+    address_based_conflict_graph::{FastHashMap, FastHashSet},
+    scheduling::EpochMap,
+    service::state_root,
     types::{
-        is_disjoint, AbortedTransaction, FinalizedTransaction, ReExecutedTransaction,
-        ScheduledTransaction,
+        collapse_effects, conflict_density, is_disjoint, modified_accounts, record_value_transfer,
+        AbortClearing, AbortReason, AbortedTransaction, AccessKeys, AutoTuner, BatchRoute,
+        Committable, CommittedWrites, ConflictGranularity, FinalizedTransaction, FirstPassMode,
+        GasMetrics, OptmeError, PhaseTimings, PreImages, ReExecutedTransaction, ReceiptCollector,
+        ReceiptRecord, ScheduledTransaction, WindowMetrics,
     },
-    AddressBasedConflictGraph, SimulationResult,
+    utils::{panic_message, run_on_rayon, VecPool},
+    AddressBasedConflictGraph, SimulationOutcome, SimulationResult,
 };
 
 use super::{address_based_conflict_graph::Transaction, types::SimulatedTransaction};
+#[cfg(feature = "disable-early-detection")]
+use crate::address_based_conflict_graph::Benchmark as _;
+#[cfg(feature = "wal")]
+use crate::wal::CommitWal;
+
+/// The number of rounds [`ConcurrencyLevelManager::execute_vanilla`] re-simulates the aborted transactions,
+/// before executing the rest of them serially.
+#[cfg(feature = "disable-early-detection")]
+pub const MAX_VANILLA_ROUNDS: usize = 64;
 
 #[async_trait::async_trait]
 impl Executable for OptME {
     async fn execute(&self, consensus_output: Vec<ExecutableEthereumBatch>) {
-        let _ = self.inner.prepare_execution(consensus_output).await;
+        // (a failed output is already rolled back, so the node goes on with the next one)
+        if let Err(e) = self.inner.prepare_execution(consensus_output).await {
+            error!("skip the consensus output which fails to execute: {e}");
+        }
     }
 }
 
@@ -38,91 +75,1462 @@ impl OptME {
             inner: ConcurrencyLevelManager::new(global_state, concurrency_level),
         }
     }
+
+    pub fn new_with_state(
+        global_state: Arc<ConcurrentEVMStorage>,
+        concurrency_level: usize,
+    ) -> Self {
+        Self {
+            inner: ConcurrencyLevelManager::new_with_state(global_state, concurrency_level),
+        }
+    }
+
+    pub fn with_conflict_granularity(self, granularity: ConflictGranularity) -> Self {
+        Self {
+            inner: self.inner.with_conflict_granularity(granularity),
+        }
+    }
+
+    pub fn with_slow_simulation_threshold(self, threshold: Duration) -> Self {
+        Self {
+            inner: self.inner.with_slow_simulation_threshold(threshold),
+        }
+    }
+
+    pub fn with_conflict_threshold(self, threshold: f64) -> Self {
+        Self {
+            inner: self.inner.with_conflict_threshold(threshold),
+        }
+    }
+
+    pub fn with_spec_id(self, spec_id: SpecId) -> Self {
+        Self {
+            inner: self.inner.with_spec_id(spec_id),
+        }
+    }
+
+    pub fn with_construction_deadline(self, deadline: Duration) -> Self {
+        Self {
+            inner: self.inner.with_construction_deadline(deadline),
+        }
+    }
+
+    pub fn with_commit_shards(self, num_shards: usize) -> Self {
+        Self {
+            inner: self.inner.with_commit_shards(num_shards),
+        }
+    }
+
+    pub fn with_commit_chunk_len(self, chunk_len: usize) -> Self {
+        Self {
+            inner: self.inner.with_commit_chunk_len(chunk_len),
+        }
+    }
+
+    pub fn with_value_transfer_conflicts(self, enabled: bool) -> Self {
+        Self {
+            inner: self.inner.with_value_transfer_conflicts(enabled),
+        }
+    }
+
+    pub fn with_sender_chains(self, enabled: bool) -> Self {
+        Self {
+            inner: self.inner.with_sender_chains(enabled),
+        }
+    }
+
+    pub fn with_trace_tx_id(self, tx_id: u64) -> Self {
+        Self {
+            inner: self.inner.with_trace_tx_id(tx_id),
+        }
+    }
+
+    pub fn with_pipelined_windows(self, enabled: bool) -> Self {
+        Self {
+            inner: self.inner.with_pipelined_windows(enabled),
+        }
+    }
+
+    pub fn with_independent_windows(self, enabled: bool) -> Self {
+        Self {
+            inner: self.inner.with_independent_windows(enabled),
+        }
+    }
+
+    pub fn with_min_parallel_txs(self, min_txs: usize) -> Self {
+        Self {
+            inner: self.inner.with_min_parallel_txs(min_txs),
+        }
+    }
+
+    pub fn with_buffer_pooling(self, enabled: bool) -> Self {
+        Self {
+            inner: self.inner.with_buffer_pooling(enabled),
+        }
+    }
+
+    pub fn with_abort_clearing(self, clearing: AbortClearing) -> Self {
+        Self {
+            inner: self.inner.with_abort_clearing(clearing),
+        }
+    }
+
+    pub fn with_first_pass_mode(self, mode: FirstPassMode) -> Self {
+        Self {
+            inner: self.inner.with_first_pass_mode(mode),
+        }
+    }
+
+    pub fn with_tx_dedup(self, enabled: bool) -> Self {
+        Self {
+            inner: self.inner.with_tx_dedup(enabled),
+        }
+    }
+
+    pub fn with_chain_id(self, chain_id: u64) -> Self {
+        Self {
+            inner: self.inner.with_chain_id(chain_id),
+        }
+    }
+
+    pub fn enable_autotune(self, min: usize, max: usize) -> Self {
+        Self {
+            inner: self.inner.enable_autotune(min, max),
+        }
+    }
+
+    #[cfg(feature = "wal")]
+    pub fn with_commit_wal(self, wal: CommitWal) -> Result<Self, OptmeError> {
+        Ok(Self {
+            inner: self.inner.with_commit_wal(wal)?,
+        })
+    }
+
+    pub fn global_state(&self) -> Arc<ConcurrentEVMStorage> {
+        self.inner.global_state()
+    }
 }
 
 pub struct ConcurrencyLevelManager {
-    concurrency_level: usize,
+    concurrency_level: AtomicUsize,
     global_state: Arc<ConcurrentEVMStorage>,
+    config: Arc<evm::Config>,
+    granularity: ConflictGranularity,
+    slow_simulation_threshold: Option<Duration>,
+    conflict_threshold: Option<f64>,
+    construction_deadline: Option<Duration>,
+    value_transfer_conflicts: bool,
+    sender_chains: bool,
+    trace_tx_id: Option<u64>,
+    pipelined_windows: bool,
+    independent_windows: bool,
+    min_parallel_txs: usize,
+    simulated_buffers: Option<Arc<VecPool<SimulatedTransaction>>>,
+    re_executed_buffers: Option<Arc<VecPool<ReExecutedTransaction>>>,
+    abort_clearing: AbortClearing,
+    first_pass_mode: FirstPassMode,
+    tx_dedup: bool,
+    chain_id: Option<u64>,
+    commit_shards: Option<Arc<[rayon::ThreadPool]>>,
+    commit_chunk_len: usize,
+    #[cfg(feature = "wal")]
+    commit_wal: Option<Arc<CommitWal>>,
+    re_execution_histogram: Mutex<BTreeMap<usize, usize>>, // #re-execution rounds -> #blocks
+    construction_deadline_hits: AtomicUsize,
+    concurrent_windows: AtomicUsize,
+    removed_duplicates: AtomicUsize,
+    autotune: Option<Mutex<AutoTuner>>,
+    gas_metrics: Mutex<GasMetrics>, // of the last block
+    pre_images: Arc<PreImages>,     // of the consensus output being executed, to roll it back
+    executing: tokio::sync::Mutex<()>,
+    #[cfg(test)]
+    fault: Option<Arc<FaultInjection>>,
 }
 
 impl ConcurrencyLevelManager {
     pub fn new(global_state: ConcurrentEVMStorage, concurrency_level: usize) -> Self {
+        Self::new_with_state(Arc::new(global_state), concurrency_level)
+    }
+
+    /// Creates a manager over a state which may be shared with others (e.g., a shadow executor),
+    /// so that the commits of either are visible to all of them.
+    pub fn new_with_state(
+        global_state: Arc<ConcurrentEVMStorage>,
+        concurrency_level: usize,
+    ) -> Self {
+        #[cfg(feature = "deadlock-detection")]
+        spawn_deadlock_detector();
+
         Self {
-            global_state: Arc::new(global_state),
-            concurrency_level,
+            config: Arc::new(global_state.config().clone()),
+            global_state,
+            concurrency_level: AtomicUsize::new(concurrency_level),
+            granularity: ConflictGranularity::default(),
+            slow_simulation_threshold: None,
+            conflict_threshold: None,
+            construction_deadline: None,
+            value_transfer_conflicts: true,
+            sender_chains: false,
+            trace_tx_id: None,
+            pipelined_windows: false,
+            independent_windows: false,
+            min_parallel_txs: 0,
+            simulated_buffers: None,
+            re_executed_buffers: None,
+            abort_clearing: AbortClearing::default(),
+            first_pass_mode: FirstPassMode::default(),
+            tx_dedup: false,
+            chain_id: None,
+            commit_shards: None,
+            commit_chunk_len: 1,
+            #[cfg(feature = "wal")]
+            commit_wal: None,
+            re_execution_histogram: Mutex::new(BTreeMap::new()),
+            construction_deadline_hits: AtomicUsize::new(0),
+            concurrent_windows: AtomicUsize::new(0),
+            removed_duplicates: AtomicUsize::new(0),
+            autotune: None,
+            gas_metrics: Mutex::new(GasMetrics::default()),
+            pre_images: Arc::new(PreImages::default()),
+            executing: tokio::sync::Mutex::new(()),
+            #[cfg(test)]
+            fault: None,
         }
     }
 
-    async fn prepare_execution(
+    pub fn with_conflict_granularity(mut self, granularity: ConflictGranularity) -> Self {
+        self.granularity = granularity;
+        self
+    }
+
+    pub fn global_state(&self) -> Arc<ConcurrentEVMStorage> {
+        self.global_state.clone()
+    }
+
+    /// The number of batches of the consensus output executed as a window.
+    pub fn concurrency_level(&self) -> usize {
+        self.concurrency_level.load(Ordering::Relaxed)
+    }
+
+    /// Changes the number of batches of a window, from the next window on.
+    pub fn set_concurrency_level(&self, concurrency_level: usize) {
+        assert!(
+            concurrency_level > 0,
+            "the concurrency level must be positive"
+        );
+        self.concurrency_level
+            .store(concurrency_level, Ordering::Relaxed);
+    }
+
+    /// How many blocks needed each number of re-execution rounds, since this manager was created.
+    pub fn re_execution_histogram(&self) -> BTreeMap<usize, usize> {
+        self.re_execution_histogram.lock().clone()
+    }
+
+    /// How many blocks exceeded the construction deadline (see [`Self::with_construction_deadline`]), since this manager was created.
+    pub fn construction_deadline_hits(&self) -> usize {
+        self.construction_deadline_hits.load(Ordering::Relaxed)
+    }
+
+    /// How many windows of the consensus output were executed concurrently with an earlier one
+    /// (see [`Self::with_independent_windows`]), since this manager was created.
+    pub fn concurrent_windows(&self) -> usize {
+        self.concurrent_windows.load(Ordering::Relaxed)
+    }
+
+    /// How many duplicate transactions were dropped from the executed consensus outputs (see [`Self::with_tx_dedup`]),
+    /// since this manager was created. Simulating an output (e.g., [`Self::simulate`]) drops them without counting.
+    pub fn removed_duplicates(&self) -> usize {
+        self.removed_duplicates.load(Ordering::Relaxed)
+    }
+
+    /// The gas used by the simulation of the last block (i.e., window of the consensus output, or the group of windows
+    /// executed concurrently, see [`Self::with_independent_windows`]), and the part of it wasted on the aborted
+    /// transactions (see [`GasMetrics::wasted_gas_ratio`]).
+    pub fn gas_metrics(&self) -> GasMetrics {
+        *self.gas_metrics.lock()
+    }
+
+    /// Transactions whose simulation takes longer than `threshold` are interrupted, left out of the schedule,
+    /// and executed serially after the others (which is then their only complete execution).
+    pub fn with_slow_simulation_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_simulation_threshold = Some(threshold);
+        self
+    }
+
+    /// Routes each batch by the conflict density of its simulated transactions (see [`conflict_density`]):
+    /// the aborted transactions of a batch whose density is at most `threshold` are executed serially in a single pass,
+    /// and only the ones of the more conflicting batches go through the re-execution rounds.
+    pub fn with_conflict_threshold(mut self, threshold: f64) -> Self {
+        self.conflict_threshold = Some(threshold);
+        self
+    }
+
+    /// Executes the transactions under the gas rules of the given fork, instead of the ones of the global state.
+    /// Panics if the fork is not supported (see [`ChainConfig::new`]).
+    pub fn with_spec_id(mut self, spec_id: SpecId) -> Self {
+        self.config = Arc::new(ChainConfig::new(spec_id).config().clone());
+        self
+    }
+
+    /// Bounds the time to construct the conflict graph of a block and extract its schedule.
+    /// A block exceeding `deadline` skips the parallel scheduling, and its transactions are executed serially in the total order.
+    /// Note that the construction is not preempted, so its (discarded) work still occupies the rayon workers until it is done.
+    pub fn with_construction_deadline(mut self, deadline: Duration) -> Self {
+        self.construction_deadline = Some(deadline);
+        self
+    }
+
+    /// Whether transactions transferring value conflict on the balances of the caller and the recipient
+    /// (see [`crate::types::balance_key`]). Enabled by default; disabling it is only safe if no two concurrent transfers share an account.
+    pub fn with_value_transfer_conflicts(mut self, enabled: bool) -> Self {
+        self.value_transfer_conflicts = enabled;
+        self
+    }
+
+    /// Chains the transactions of each sender in a window: only the first one (in the order of ids) enters the conflict graph,
+    /// and the others are executed serially after it, in the order of ids. Otherwise, the transactions of a sender which
+    /// conflict on nothing else are committed concurrently, each bumping the nonce of the sender from the same snapshot.
+    /// Disabled by default, since a workload signed by a single account (e.g., SmallBank) would be executed serially.
+    pub fn with_sender_chains(mut self, enabled: bool) -> Self {
+        self.sender_chains = enabled;
+        self
+    }
+
+    /// Logs (at `info`) each phase the transaction of the given id (i.e., its index in the consensus output)
+    /// goes through: simulation, scheduling or abort, commit, re-execution, validation and serial execution.
+    pub fn with_trace_tx_id(mut self, tx_id: u64) -> Self {
+        self.trace_tx_id = Some(tx_id);
+        self
+    }
+
+    /// Whether the next window of the consensus output (of `concurrency_level` batches) is simulated
+    /// while the current one is scheduled and committed. Disabled by default.
+    pub fn with_pipelined_windows(mut self, enabled: bool) -> Self {
+        self.pipelined_windows = enabled;
+        self
+    }
+
+    /// Whether the windows of the consensus output (of `concurrency_level` batches) which are independent of each other
+    /// are executed concurrently. Consecutive windows are independent if none of them writes a key another one reads or writes,
+    /// so the order in which their effects reach the global state does not change the final state.
+    /// Disabled by default, and ignored with [`Self::with_pipelined_windows`].
+    pub fn with_independent_windows(mut self, enabled: bool) -> Self {
+        self.independent_windows = enabled;
+        self
+    }
+
+    /// Executes a block (i.e., window of the consensus output) of fewer than `min_txs` transactions serially
+    /// in the total order, skipping the simulation and the scheduling, whose overheads outweigh the parallelism
+    /// of a handful of transactions. Disabled (i.e., 0) by default.
+    pub fn with_min_parallel_txs(mut self, min_txs: usize) -> Self {
+        self.min_parallel_txs = min_txs;
+        self
+    }
+
+    /// Recycles the vectors of the simulated and the re-executed transactions across blocks (see [`VecPool`]),
+    /// instead of allocating them afresh for each block. Disabled by default.
+    pub fn with_buffer_pooling(mut self, enabled: bool) -> Self {
+        self.simulated_buffers = enabled.then(Default::default);
+        self.re_executed_buffers = enabled.then(Default::default);
+        self
+    }
+
+    /// How the aborted transactions are cleared while their epochs are assigned (see [`AbortClearing`]).
+    /// `Streaming` lowers the peak memory of scheduling a block with many aborted transactions. `Batched` by default.
+    pub fn with_abort_clearing(mut self, clearing: AbortClearing) -> Self {
+        self.abort_clearing = clearing;
+        self
+    }
+
+    /// How the simulated transactions of a window are first committed (see [`FirstPassMode`]). `ScheduleThenCommit` by default.
+    /// Experimental: `OptimisticCommitThenValidate` ignores the batch routing.
+    pub fn with_first_pass_mode(mut self, mode: FirstPassMode) -> Self {
+        self.first_pass_mode = mode;
+        self
+    }
+
+    /// Drops the duplicates of a transaction from the consensus output before simulation (e.g., the same transaction
+    /// submitted through different workers), keeping its first occurrence. The whole output is deduplicated before it is
+    /// split into windows, so duplicates in different windows are dropped as well. Duplicates are recognized by the sender and
+    /// the full sighash (see [`sslab_execution::types::EthereumTransaction::digest`]), which does not cover the sender
+    /// of an unsigned transaction. Disabled by default.
+    pub fn with_tx_dedup(mut self, enabled: bool) -> Self {
+        self.tx_dedup = enabled;
+        self
+    }
+
+    /// Drops the transactions of the consensus output signed for another chain than `chain_id` (see
+    /// [`sslab_execution::types::EthereumTransaction::validate_chain_id`]), as the workers validating with
+    /// [`sslab_execution::transaction_validator::EthereumTxValidator::with_chain_id`] do. Without it, the chain id is not checked.
+    pub fn with_chain_id(mut self, chain_id: u64) -> Self {
+        self.chain_id = Some(chain_id);
+        self
+    }
+
+    /// Tunes the concurrency level within `[min, max]` after each window (see [`AutoTuner`]), toward the one maximizing
+    /// the effective throughput, i.e., the transactions per second discounted by the ratio of the gas wasted on aborts
+    /// (see [`GasMetrics::wasted_gas_ratio`]). The level starts from the one given at construction, clamped into the bounds.
+    /// Ignored with [`Self::with_pipelined_windows`] and [`Self::with_independent_windows`], which split the windows upfront.
+    pub fn enable_autotune(mut self, min: usize, max: usize) -> Self {
+        self.autotune = Some(Mutex::new(AutoTuner::new(min, max)));
+        self.set_concurrency_level(self.concurrency_level().clamp(min, max));
+        self
+    }
+
+    /// Partitions the key space by address into `num_shards` shards, and commits the effects of each shard
+    /// on its own rayon sub-pool (named `commit-shard-{i}`), so that the writes to an account always land on the same threads.
+    /// Note that the global state itself is not sharded yet, so the sub-pools are not pinned to NUMA nodes either.
+    pub fn with_commit_shards(mut self, num_shards: usize) -> Self {
+        assert!(
+            num_shards > 0,
+            "the number of commit shards must be positive"
+        );
+
+        let threads_per_shard = std::cmp::max(num_cpus::get() / num_shards, 1);
+        let pools = (0..num_shards)
+            .map(|shard| {
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads_per_shard)
+                    .thread_name(move |i| format!("commit-shard-{shard}-{i}"))
+                    .build()
+                    .expect("fail to build a thread pool for commit shards")
+            })
+            .collect::<Vec<_>>();
+
+        self.commit_shards = Some(pools.into());
+        self
+    }
+
+    /// Applies the effects of at least `chunk_len` transactions of a generation in each rayon task
+    /// (see [`rayon::iter::IndexedParallelIterator::with_min_len`]), so that the task overhead does not dominate
+    /// the commit of wide generations of tiny transactions. 1 (i.e., a task per transaction) by default.
+    /// Ignored with [`Self::with_commit_shards`], which already applies the effects of an account in a single task.
+    pub fn with_commit_chunk_len(mut self, chunk_len: usize) -> Self {
+        assert!(chunk_len > 0, "the commit chunk length must be positive");
+        self.commit_chunk_len = chunk_len;
+        self
+    }
+
+    /// Logs each generation to the write-ahead log before committing it. The global state (i.e., the genesis state
+    /// on restart) first recovers what the previous runs have committed, including the generations left in the log.
+    /// Fails if the log cannot be read.
+    #[cfg(feature = "wal")]
+    pub fn with_commit_wal(mut self, wal: CommitWal) -> Result<Self, OptmeError> {
+        let replayed = wal.recover(&self.global_state)?;
+        if replayed > 0 {
+            warn!("replayed {} generations from the commit WAL", replayed);
+        }
+
+        self.commit_wal = Some(Arc::new(wal));
+        Ok(self)
+    }
+
+    // injects the fault once `after` generations are committed (see `FaultInjection`).
+    #[cfg(test)]
+    pub(crate) fn with_fault(mut self, fault: Fault, after: usize) -> Self {
+        self.fault = Some(Arc::new(FaultInjection::new(fault, after)));
+        self
+    }
+
+    /// Pre-loads the chain config and touches the given accounts of the global state, so that
+    /// the first block does not pay the cold-start costs. Returns the number of touched accounts
+    /// which exist in the global state.
+    pub async fn warm_up(&self, addresses: Option<Vec<H160>>) -> Result<usize, OptmeError> {
+        let snapshot = self.global_state.clone();
+
+        let touched = run_on_rayon(move || {
+            let _ = snapshot.config();
+            let _ = snapshot.executor(0, true);
+
+            addresses
+                .unwrap_or_default()
+                .into_par_iter()
+                .filter(|address| {
+                    let backend = snapshot.get_storage();
+                    let _ = backend.basic(*address);
+                    let _ = backend.code(*address);
+                    backend.exists(*address)
+                })
+                .count()
+        })
+        .await?;
+
+        Ok(touched)
+    }
+
+    /// Executes the consensus output, which commits fully or not at all: on failure (e.g., a corrupted batch,
+    /// a worker which panicked, or the WAL which cannot be accessed), everything it has committed is rolled back
+    /// (through the WAL, if any) before the failure is returned, and the windows after the failed one are not executed.
+    pub(crate) async fn prepare_execution(
         &self,
         consensus_output: Vec<ExecutableEthereumBatch>,
-    ) -> ExecutionResult {
-        let mut result = vec![];
-        let mut target = consensus_output;
+    ) -> Result<ExecutionResult, OptmeError> {
+        self._atomically(async {
+            // (duplicates are dropped across the whole output before it is split into windows)
+            let consensus_output = self._dedup(consensus_output).await?;
+
+            if self.pipelined_windows {
+                return self._prepare_pipelined_execution(consensus_output).await;
+            }
+            if self.independent_windows {
+                return self._prepare_out_of_order_execution(consensus_output).await;
+            }
+
+            let windows = self
+                ._execute_streaming(consensus_output, false)
+                .try_collect::<Vec<_>>()
+                .await?;
+
+            Ok(ExecutionResult::new(
+                windows
+                    .into_iter()
+                    .flat_map(|window| window.digests)
+                    .collect(),
+            ))
+        })
+        .await
+    }
+
+    /// Executes the consensus output window by window (as [`Self::prepare_execution`] does without the pipelining),
+    /// and yields the result of each window as soon as it is committed, e.g., so that a downstream confirmer
+    /// acts on the early windows of a large block. The next window is executed only when the stream is polled.
+    /// Each window commits fully or not at all, and the stream ends with the first one which fails.
+    pub fn prepare_execution_streaming(
+        &self,
+        consensus_output: Vec<ExecutableEthereumBatch>,
+    ) -> impl Stream<Item = Result<ExecutionResult, OptmeError>> + '_ {
+        stream::once(self._dedup(consensus_output)).flat_map(move |consensus_output| {
+            let (consensus_output, failed) = match consensus_output {
+                Ok(consensus_output) => (consensus_output, None),
+                Err(e) => (vec![], Some(Err(e))),
+            };
+
+            stream::iter(failed).chain(self._execute_streaming(consensus_output, true))
+        })
+    }
 
-        while !target.is_empty() {
-            let split_idx = std::cmp::min(self.concurrency_level, target.len());
+    // executes the consensus output, already deduplicated, window by window (see `prepare_execution_streaming`),
+    // and ends with the first window which fails. With `atomic_windows`, each window is rolled back on its own.
+    fn _execute_streaming(
+        &self,
+        consensus_output: Vec<ExecutableEthereumBatch>,
+        atomic_windows: bool,
+    ) -> impl Stream<Item = Result<ExecutionResult, OptmeError>> + '_ {
+        stream::unfold(Some(consensus_output), move |target| async move {
+            let mut target = target.filter(|target| !target.is_empty())?;
+
+            let split_idx = std::cmp::min(self.concurrency_level(), target.len());
             let remains: Vec<ExecutableEthereumBatch> = target.split_off(split_idx);
 
-            result.extend(self._execute(target).await);
+            let window = async move {
+                let num_txs = target.iter().map(|batch| batch.data().len()).sum::<usize>();
+                let clock = Instant::now();
+                let (digests, gas_metrics) = self._unpack_and_execute(target, None, None).await?;
+                self._autotune(num_txs, clock.elapsed(), gas_metrics);
+
+                Ok::<_, OptmeError>(ExecutionResult::new(digests))
+            };
+            let executed = if atomic_windows {
+                self._atomically(window).await
+            } else {
+                window.await
+            };
+
+            // (the windows after a failed one are not executed)
+            let remains = executed.is_ok().then_some(remains);
+            Some((executed, remains))
+        })
+    }
+
+    /// Executes the consensus output window by window (as [`Self::prepare_execution`] does without the pipelining),
+    /// and fails on the first window which cannot be executed. As with [`Self::prepare_execution`], the output commits
+    /// fully or not at all: on failure, everything the windows before have committed is rolled back as well.
+    pub async fn try_execute(
+        &self,
+        consensus_output: Vec<ExecutableEthereumBatch>,
+    ) -> Result<ExecutionResult, OptmeError> {
+        self._atomically(async {
+            let mut result = vec![];
+            let mut target = self._dedup(consensus_output).await?;
+
+            while !target.is_empty() {
+                let split_idx = std::cmp::min(self.concurrency_level(), target.len());
+                let remains: Vec<ExecutableEthereumBatch> = target.split_off(split_idx);
+
+                let (digests, _) = self._unpack_and_execute(target, None, None).await?;
+                result.extend(digests);
+
+                target = remains;
+            }
+
+            Ok(ExecutionResult::new(result))
+        })
+        .await
+    }
+
+    // runs the execution of a consensus output, recording the pre-images of what it commits (see `PreImages`),
+    // so that if it fails or panics, everything it has committed is rolled back before the failure is returned.
+    // The executions are serialized, since the pre-images of concurrent ones would be mixed up.
+    async fn _atomically<T>(
+        &self,
+        execution: impl Future<Output = Result<T, OptmeError>>,
+    ) -> Result<T, OptmeError> {
+        let _executing = self.executing.lock().await;
 
-            target = remains;
+        self.pre_images.start();
+        let executed = AssertUnwindSafe(execution)
+            .catch_unwind()
+            .await
+            .map_err(|payload| OptmeError::Panicked(panic_message(payload)))
+            .and_then(|executed| executed);
+        match executed {
+            Ok(executed) => {
+                self.pre_images.commit();
+                Ok(executed)
+            }
+            Err(e) => {
+                error!("fail to execute the consensus output, roll it back: {e}");
+                self._roll_back(self.pre_images.revert()).await?;
+                Err(e)
+            }
         }
+    }
 
-        ExecutionResult::new(result)
+    /// Executes the consensus output window by window (as [`Self::prepare_execution`] does without the pipelining),
+    /// and returns the receipts of its transactions in their original order, however they were scheduled.
+    /// The gas used is cumulated over the whole output. A transaction which fails to execute has no receipt.
+    /// The output commits fully or not at all, as with [`Self::prepare_execution`].
+    pub async fn execute_to_receipts(
+        &self,
+        consensus_output: Vec<ExecutableEthereumBatch>,
+    ) -> Result<Vec<TransactionReceipt>, OptmeError> {
+        self._atomically(async {
+            let mut receipts = vec![];
+            let mut cumulative_gas_used = U256::zero();
+            let mut target = self._dedup(consensus_output).await?;
+
+            while !target.is_empty() {
+                let split_idx = std::cmp::min(self.concurrency_level(), target.len());
+                let remains: Vec<ExecutableEthereumBatch> = target.split_off(split_idx);
+
+                let records = ReceiptCollector::default();
+                self._unpack_and_execute(target, None, Some(&records))
+                    .await?;
+
+                // (the records are keyed by the ids of the window, i.e., the positions of the transactions in it)
+                for record in records.into_records() {
+                    cumulative_gas_used += U256::from(record.gas_used);
+                    receipts.push(record.into_receipt(receipts.len(), cumulative_gas_used));
+                }
+
+                target = remains;
+            }
+
+            Ok(receipts)
+        })
+        .await
     }
 
-    async fn _unpack_batches(
+    // feeds the effective throughput of the last window back to the auto-tuner, if enabled (see `enable_autotune`).
+    fn _autotune(&self, num_txs: usize, elapsed: Duration, gas_metrics: GasMetrics) {
+        let Some(tuner) = &self.autotune else {
+            return;
+        };
+
+        let throughput = num_txs as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+        let score = throughput * (1.0 - gas_metrics.wasted_gas_ratio());
+        let level = tuner.lock().next_level(self.concurrency_level(), score);
+        self.set_concurrency_level(level);
+    }
+
+    // Simulates the next window while the current one is scheduled and committed. Since the next window is simulated
+    // over a state the current one is still being committed to, its transactions touching any account the current window
+    // has committed to (after re-execution and the serial pass as well) are simulated again once the current window is done.
+    // The conflict graph of the next window is constructed along with its simulation, and only the transactions simulated
+    // again are integrated into it (see [`AddressBasedConflictGraph::update`]).
+    async fn _prepare_pipelined_execution(
+        &self,
+        consensus_output: Vec<ExecutableEthereumBatch>,
+    ) -> Result<ExecutionResult, OptmeError> {
+        let mut result = vec![];
+        let mut windows = consensus_output
+            .into_iter()
+            .chunks(self.concurrency_level())
+            .into_iter()
+            .map(Itertools::collect_vec)
+            .collect_vec()
+            .into_iter();
+
+        let Some(first) = windows.next() else {
+            return Ok(ExecutionResult::new(result));
+        };
+        let mut current = self._simulate_batches(first).await?;
+        let mut graph = None;
+
+        loop {
+            let next = windows.next();
+
+            let (executed, next) =
+                tokio::join!(self._execute_simulated(current, None, graph), async {
+                    match next {
+                        Some(window) => Some(self._simulate_and_construct(window).await),
+                        None => None,
+                    }
+                });
+            let (digests, committed) = executed?;
+            result.extend(digests);
+
+            let Some((next, mut next_graph)) = next.transpose()? else {
+                break;
+            };
+            let (mut fresh, stale) = next.split_stale(&committed);
+            let re_simulated = self._simulate(stale).await?;
+            // only the stale transactions are integrated into the graph of the next window, instead of rebuilding it.
+            if let Some(graph) = next_graph.as_mut() {
+                graph.update(
+                    re_simulated
+                        .iter()
+                        .cloned()
+                        .map(ReExecutedTransaction::from)
+                        .collect(),
+                );
+            }
+            fresh.rw_sets.extend(re_simulated);
+            fresh.rw_sets.sort_unstable_by_key(SimulatedTransaction::id);
+            current = fresh;
+            graph = next_graph;
+        }
+
+        Ok(ExecutionResult::new(result))
+    }
+
+    // simulates the window, and constructs its conflict graph if the first pass schedules it (see `FirstPassMode`).
+    async fn _simulate_and_construct(
+        &self,
+        window: Vec<ExecutableEthereumBatch>,
+    ) -> Result<(SimulationResult, Option<AddressBasedConflictGraph>), OptmeError> {
+        let simulation = self._simulate_batches(window).await?;
+        let graph = match self.first_pass_mode {
+            FirstPassMode::ScheduleThenCommit => {
+                Some(AddressBasedConflictGraph::par_construct(simulation.rw_sets.clone()).await)
+            }
+            FirstPassMode::OptimisticCommitThenValidate => None,
+        };
+        Ok((simulation, graph))
+    }
+
+    // Simulates the windows one after another over the same state, and groups the consecutive ones independent of each other
+    // (see [`AccessKeys::is_independent_of`]). Since none of the earlier windows of a group touches the keys of a later one,
+    // all of them are simulated as if the earlier ones were already committed, and the group is executed concurrently.
+    // A window of a group commits only what stays within its own keys; its transactions executed serially, or re-executed
+    // into other keys, are executed serially once the whole group has committed, in the order of the windows.
+    // The window which ends a group is simulated again over the state the group has committed to, but only its transactions
+    // touching any account the group has committed to.
+    async fn _prepare_out_of_order_execution(
+        &self,
+        consensus_output: Vec<ExecutableEthereumBatch>,
+    ) -> Result<ExecutionResult, OptmeError> {
+        let mut result = vec![];
+        let mut windows = consensus_output
+            .into_iter()
+            .chunks(self.concurrency_level())
+            .into_iter()
+            .map(Itertools::collect_vec)
+            .collect_vec()
+            .into_iter();
+
+        let Some(first) = windows.next() else {
+            return Ok(ExecutionResult::new(result));
+        };
+        let mut pending = Some(self._simulate_batches(first).await?);
+
+        while let Some(first) = pending.take() {
+            let mut keys = first.access_keys(&self.global_state);
+            let mut group = vec![(first, keys.clone())];
+
+            for window in windows.by_ref() {
+                let simulation = self._simulate_batches(window).await?;
+                let window_keys = simulation.access_keys(&self.global_state);
+                match (keys.as_mut(), window_keys) {
+                    (Some(group_keys), Some(window_keys))
+                        if window_keys.is_independent_of(group_keys) =>
+                    {
+                        group_keys.extend(window_keys.clone());
+                        group.push((simulation, Some(window_keys)));
+                    }
+                    _ => {
+                        pending = Some(simulation);
+                        break;
+                    }
+                }
+            }
+
+            let concurrent = group.len() > 1;
+            self.concurrent_windows
+                .fetch_add(group.len() - 1, Ordering::Relaxed);
+
+            // (join_all yields the digests in the order of the windows, whichever finishes first)
+            let executed = futures::future::join_all(group.into_iter().map(
+                |(simulation, footprint)| async move {
+                    let footprint = footprint.as_ref().filter(|_| concurrent);
+                    let mut metrics = WindowMetrics::default();
+                    let executed = self
+                        ._execute_simulated_within(
+                            simulation,
+                            None,
+                            footprint,
+                            None,
+                            None,
+                            &mut metrics,
+                        )
+                        .await;
+                    (executed, metrics)
+                },
+            ))
+            .await;
+            // (the metrics of each window are merged in the order of the windows, as a single block)
+            let mut group_metrics = WindowMetrics::default();
+            let mut committed = HashSet::new();
+            let mut deferred = vec![];
+            for (executed, metrics) in executed {
+                group_metrics = group_metrics.merge(metrics);
+                let (digests, accounts, mut txs) = executed?;
+                result.extend(digests);
+                committed.extend(accounts);
+                txs.sort_unstable_by_key(|tx| tx.id);
+                deferred.extend(txs);
+            }
+            self._record_block(group_metrics);
+            committed.extend(self._serial_execute(deferred, None).await?);
+
+            if let Some(next) = pending.take() {
+                let (mut fresh, stale) = next.split_stale(&committed);
+                fresh.rw_sets.extend(self._simulate(stale).await?);
+                fresh.rw_sets.sort_unstable_by_key(SimulatedTransaction::id);
+                pending = Some(fresh);
+            }
+        }
+
+        Ok(ExecutionResult::new(result))
+    }
+
+    /// Executes the batches over a throwaway copy of the global state with the same configuration (but without the WAL),
+    /// leaving the global state untouched, e.g., for fee estimation or fraud proofs.
+    /// Returns the net effects on the copy in the order of addresses, and the state root of the copy (see [`state_root`]),
+    /// or the failure of the batches on the copy.
+    pub async fn execute_batches_isolated(
+        &self,
+        batches: Vec<ExecutableEthereumBatch>,
+    ) -> Result<(Vec<Apply>, H256), OptmeError> {
+        let live = self.global_state.clone();
+        let sandbox = Self {
+            concurrency_level: AtomicUsize::new(self.concurrency_level()),
+            global_state: Arc::new(live.as_ref().clone()),
+            config: self.config.clone(),
+            granularity: self.granularity,
+            slow_simulation_threshold: self.slow_simulation_threshold,
+            conflict_threshold: self.conflict_threshold,
+            construction_deadline: self.construction_deadline,
+            value_transfer_conflicts: self.value_transfer_conflicts,
+            sender_chains: self.sender_chains,
+            trace_tx_id: self.trace_tx_id,
+            pipelined_windows: self.pipelined_windows,
+            independent_windows: self.independent_windows,
+            min_parallel_txs: self.min_parallel_txs,
+            simulated_buffers: self.simulated_buffers.clone(),
+            re_executed_buffers: self.re_executed_buffers.clone(),
+            abort_clearing: self.abort_clearing,
+            first_pass_mode: self.first_pass_mode,
+            tx_dedup: self.tx_dedup,
+            chain_id: self.chain_id,
+            commit_shards: self.commit_shards.clone(),
+            commit_chunk_len: self.commit_chunk_len,
+            #[cfg(feature = "wal")]
+            commit_wal: None,
+            re_execution_histogram: Mutex::new(BTreeMap::new()),
+            construction_deadline_hits: AtomicUsize::new(0),
+            concurrent_windows: AtomicUsize::new(0),
+            removed_duplicates: AtomicUsize::new(0),
+            autotune: None,
+            gas_metrics: Mutex::new(GasMetrics::default()),
+            pre_images: Arc::new(PreImages::default()),
+            executing: tokio::sync::Mutex::new(()),
+            #[cfg(test)]
+            fault: None,
+        };
+
+        sandbox.prepare_execution(batches).await?;
+
+        let isolated = sandbox.global_state();
+        let isolated =
+            run_on_rayon(move || (Self::_state_diff(&live, &isolated), state_root(&isolated)))
+                .await?;
+
+        Ok(isolated)
+    }
+
+    // The effects which turn `before` into `after`, in the order of addresses.
+    fn _state_diff(before: &ConcurrentEVMStorage, after: &ConcurrentEVMStorage) -> Vec<Apply> {
+        let before = before.get_storage().state().pin();
+        let after = after.get_storage().state().pin();
+
+        let modified = after.iter().filter_map(|(address, account)| {
+            let prev = before.get(address);
+            let prev_storage = prev.map(|prev| prev.storage.pin());
+            let curr_storage = account.storage.pin();
+
+            let mut storage = curr_storage
+                .iter()
+                .filter(|(key, value)| {
+                    prev_storage
+                        .as_ref()
+                        .and_then(|prev| prev.get(*key))
+                        .map_or(true, |prev| prev != *value)
+                })
+                .map(|(key, value)| (*key, *value))
+                .collect::<BTreeMap<_, _>>();
+            if let Some(prev) = &prev_storage {
+                prev.keys()
+                    .filter(|key| !curr_storage.contains_key(*key))
+                    .for_each(|key| {
+                        storage.insert(*key, H256::zero());
+                    });
+            }
+
+            let code_changed = prev.map_or(true, |prev| prev.code != account.code);
+            let basic_changed = prev.map_or(true, |prev| {
+                prev.nonce != account.nonce || prev.balance != account.balance
+            });
+            if !code_changed && !basic_changed && storage.is_empty() {
+                return None;
+            }
+
+            Some(Apply::Modify {
+                address: *address,
+                basic: Basic {
+                    balance: account.balance,
+                    nonce: account.nonce,
+                },
+                code: code_changed.then(|| account.code.clone()),
+                storage,
+                reset_storage: false,
+            })
+        });
+        let deleted = before
+            .keys()
+            .filter(|address| !after.contains_key(*address))
+            .map(|address| Apply::Delete { address: *address });
+
+        modified
+            .chain(deleted)
+            .sorted_by_key(|apply| match apply {
+                Apply::Modify { address, .. } | Apply::Delete { address } => *address,
+            })
+            .collect()
+    }
+
+    // Emits the lifecycle event of the traced transaction, if any and `event` finds it.
+    #[inline]
+    fn _trace(&self, event: impl FnOnce(u64) -> Option<String>) {
+        let Some(tx_id) = self.trace_tx_id else {
+            return;
+        };
+        if let Some(event) = event(tx_id) {
+            info!("traced transaction {}: {}", tx_id, event);
+        }
+    }
+
+    // drops the duplicates from the consensus output (see `_drop_duplicates`), and counts them. Only the execution counts them,
+    // so that simulating the same output beforehand does not count them twice.
+    async fn _dedup(
+        &self,
         consensus_output: Vec<ExecutableEthereumBatch>,
-    ) -> (Vec<BatchDigest>, Vec<IndexedEthereumTransaction>) {
-        let (send, recv) = tokio::sync::oneshot::channel();
+    ) -> Result<Vec<ExecutableEthereumBatch>, OptmeError> {
+        let (consensus_output, removed) = self._drop_duplicates(consensus_output).await?;
+
+        if removed > 0 {
+            debug!("drop {removed} duplicate transactions from the consensus output");
+            self.removed_duplicates
+                .fetch_add(removed, Ordering::Relaxed);
+        }
+        Ok(consensus_output)
+    }
+
+    /// With `tx_dedup`, drops the duplicates of a transaction across the whole consensus output, keeping its first
+    /// occurrence, so that a transaction is executed once even if its duplicates fall into different windows.
+    /// Returns the output along with the number of dropped transactions.
+    ///
+    /// With `verify-digests`, this is the one place the digest of each batch is recomputed from its raw transactions
+    /// (see [`ExecutableEthereumBatch::verify_digest`]), whether `tx_dedup` is enabled or not, and the first mismatch
+    /// is returned as an error. Every entry point goes through it before the output is unpacked, and the digests are
+    /// verified before any batch is rebuilt without its raw transactions (which would pass any later verification).
+    async fn _drop_duplicates(
+        &self,
+        consensus_output: Vec<ExecutableEthereumBatch>,
+    ) -> Result<(Vec<ExecutableEthereumBatch>, usize), OptmeError> {
+        if !self.tx_dedup && !cfg!(feature = "verify-digests") {
+            return Ok((consensus_output, 0));
+        }
 
-        rayon::spawn(move || {
+        let tx_dedup = self.tx_dedup;
+        let deduped = run_on_rayon(move || {
+            #[cfg(feature = "verify-digests")]
+            consensus_output
+                .par_iter()
+                .try_for_each(ExecutableEthereumBatch::verify_digest)?;
+
+            if !tx_dedup {
+                return Ok((consensus_output, 0));
+            }
+
+            let mut seen = hashbrown::HashSet::new();
+            let mut removed = 0;
+            let consensus_output = consensus_output
+                .into_iter()
+                .map(|batch| {
+                    let unique = batch
+                        .data()
+                        .iter()
+                        .map(|tx| seen.insert((tx.caller(), tx.digest())))
+                        .collect::<Vec<_>>();
+                    let duplicates = unique.iter().filter(|unique| !**unique).count();
+                    if duplicates == 0 {
+                        return batch;
+                    }
+
+                    // (only the batches which lost a transaction are rebuilt)
+                    removed += duplicates;
+                    let data = batch
+                        .data()
+                        .iter()
+                        .zip(unique)
+                        .filter_map(|(tx, unique)| unique.then(|| tx.clone()))
+                        .collect();
+                    ExecutableEthereumBatch::new(data, batch.digest().to_owned())
+                })
+                .collect::<Vec<_>>();
+
+            Ok::<_, DigestMismatch>((consensus_output, removed))
+        })
+        .await??;
+
+        Ok(deduped)
+    }
+
+    // drops the duplicates from the consensus output without counting them (see `_dedup`), and unpacks it.
+    async fn _unpack_unique_batches(
+        &self,
+        consensus_output: Vec<ExecutableEthereumBatch>,
+    ) -> Result<(Vec<BatchDigest>, Vec<IndexedEthereumTransaction>), OptmeError> {
+        let (consensus_output, _) = self._drop_duplicates(consensus_output).await?;
+        self._unpack_batches(consensus_output).await
+    }
+
+    /// Unpacks the transactions of the batches, whose digests are already verified (see `_drop_duplicates`).
+    async fn _unpack_batches(
+        &self,
+        consensus_output: Vec<ExecutableEthereumBatch>,
+    ) -> Result<(Vec<BatchDigest>, Vec<IndexedEthereumTransaction>), OptmeError> {
+        let chain_id = self.chain_id;
+        let unpacked = run_on_rayon(move || {
             let (digests, batches): (Vec<_>, Vec<_>) = consensus_output
                 .par_iter()
                 .map(|batch| (batch.digest().to_owned(), batch.data().to_owned()))
                 .unzip();
 
+            // (invalid transactions are dropped before the ids are assigned, so that the ids stay contiguous)
             let tx_list = batches
                 .into_iter()
-                .flatten()
                 .enumerate()
-                .map(|(id, tx)| IndexedEthereumTransaction::new(tx, id as u64))
+                .flat_map(|(batch_id, txs)| txs.into_iter().map(move |tx| (batch_id, tx)))
+                .filter(|(_, tx)| {
+                    let valid = tx.validate_gas_limit().and_then(|()| {
+                        chain_id.map_or(Ok(()), |chain_id| tx.validate_chain_id(chain_id))
+                    });
+                    match valid {
+                        Ok(()) => true,
+                        Err(e) => {
+                            warn!("skip transaction {}: {e}", tx.digest_u64());
+                            false
+                        }
+                    }
+                })
+                .enumerate()
+                .map(|(id, (batch_id, tx))| {
+                    IndexedEthereumTransaction::new(tx, id as u64).with_batch_id(batch_id)
+                })
                 .collect::<Vec<_>>();
 
-            let _ = send.send((digests, tx_list)).unwrap();
-        });
+            (digests, tx_list)
+        })
+        .await?;
 
-        recv.await.unwrap()
+        Ok(unpacked)
     }
 
     pub async fn _execute(
         &self,
         consensus_output: Vec<ExecutableEthereumBatch>,
-    ) -> Vec<BatchDigest> {
-        let (digests, tx_list) = Self::_unpack_batches(consensus_output).await;
+    ) -> Result<Vec<BatchDigest>, OptmeError> {
+        self._execute_with(consensus_output, None).await
+    }
+
+    /// Executes as [`Self::_execute`] does, and records the time each phase takes into `timings`, if given.
+    /// The consensus output is executed as a single window, which commits fully or not at all.
+    pub async fn _execute_with(
+        &self,
+        consensus_output: Vec<ExecutableEthereumBatch>,
+        mut timings: Option<&mut PhaseTimings>,
+    ) -> Result<Vec<BatchDigest>, OptmeError> {
+        if let Some(timings) = timings.as_deref_mut() {
+            timings.start();
+        }
+
+        let (digests, _) = self
+            ._atomically(async {
+                let consensus_output = self._dedup(consensus_output).await?;
+                self._unpack_and_execute(consensus_output, timings.as_deref_mut(), None)
+                    .await
+            })
+            .await?;
+
+        if let Some(timings) = timings {
+            timings.finish();
+        }
+        Ok(digests)
+    }
+
+    // executes a window of the consensus output, which the entry points have already deduplicated (see `_dedup`),
+    // and returns the gas metrics of the window itself, which another window may have overwritten in the manager.
+    async fn _unpack_and_execute(
+        &self,
+        consensus_output: Vec<ExecutableEthereumBatch>,
+        mut timings: Option<&mut PhaseTimings>,
+        receipts: Option<&ReceiptCollector>,
+    ) -> Result<(Vec<BatchDigest>, GasMetrics), OptmeError> {
+        if consensus_output.is_empty() {
+            return Ok((vec![], GasMetrics::default()));
+        }
+
+        let (digests, tx_list) = self._unpack_batches(consensus_output).await?;
+
+        if tx_list.len() < self.min_parallel_txs {
+            let traced = self
+                .trace_tx_id
+                .map_or(false, |id| tx_list.iter().any(|tx| tx.id == id));
+            self._serial_execute(tx_list, receipts).await?;
+            self._trace(|_| traced.then(|| "committed serially (small block)".to_string()));
+            return Ok((digests, GasMetrics::default()));
+        }
+
+        let clock = Instant::now();
+        let rw_sets = self._simulate(tx_list).await?;
+        Self::_record(&mut timings, clock, |t| &mut t.simulation);
+
+        let mut metrics = WindowMetrics::default();
+        let executed = self
+            ._execute_simulated_within(
+                SimulationResult { digests, rw_sets },
+                timings,
+                None,
+                receipts,
+                None,
+                &mut metrics,
+            )
+            .await;
+        self._record_block(metrics);
+        let (digests, _, _) = executed?;
+        Ok((digests, metrics.gas_metrics))
+    }
+
+    // records the metrics of a block, i.e., a window or a group of windows executed concurrently.
+    fn _record_block(&self, metrics: WindowMetrics) {
+        if let Some(rounds) = metrics.re_execution_rounds {
+            *self
+                .re_execution_histogram
+                .lock()
+                .entry(rounds)
+                .or_default() += 1;
+        }
+        *self.gas_metrics.lock() = metrics.gas_metrics;
+    }
+
+    // adds the time elapsed since `clock` to the phase picked by `phase`, if the timings are recorded.
+    fn _record(
+        timings: &mut Option<&mut PhaseTimings>,
+        clock: Instant,
+        phase: fn(&mut PhaseTimings) -> &mut u128,
+    ) {
+        if let Some(timings) = timings.as_deref_mut() {
+            *phase(timings) += clock.elapsed().as_micros();
+        }
+    }
+
+    // commits the effects writing the pre-images of a failed output back (see `PreImages::revert`) through the WAL,
+    // if any, so that the output is not replayed partially on recovery either.
+    pub(crate) async fn _roll_back(&self, reverting: Vec<Apply>) -> Result<(), OptmeError> {
+        if reverting.is_empty() {
+            return Ok(());
+        }
+
+        warn!(
+            "roll back {} accounts modified by the failed output",
+            reverting.len()
+        );
+        self._concurrent_commit(vec![vec![FinalizedTransaction::new(0, reverting)]])
+            .await
+    }
+
+    /// Schedules, commits and re-executes the transactions of an already simulated window.
+    /// Returns the digests along with the accounts the window has committed to, whichever path the effects went through.
+    async fn _execute_simulated(
+        &self,
+        simulation: SimulationResult,
+        timings: Option<&mut PhaseTimings>,
+        graph: Option<AddressBasedConflictGraph>,
+    ) -> Result<(Vec<BatchDigest>, HashSet<H160>), OptmeError> {
+        let mut metrics = WindowMetrics::default();
+        let executed = self
+            ._execute_simulated_within(simulation, timings, None, None, graph, &mut metrics)
+            .await;
+        self._record_block(metrics);
+        let (digests, committed, _) = executed?;
+        Ok((digests, committed))
+    }
+
+    // executes as `_execute_simulated` does, and with the footprint of the window (i.e., while other windows execute
+    // concurrently), commits only what stays within it: the transactions executed serially, and the re-executed ones
+    // accessing any other key, are returned instead, to be executed serially once the other windows have committed.
+    // If the conflict graph of the window is already constructed (see `_prepare_pipelined_execution`), it is scheduled
+    // instead of a new one, with only the transactions the window schedules.
+    async fn _execute_simulated_within(
+        &self,
+        simulation: SimulationResult,
+        mut timings: Option<&mut PhaseTimings>,
+        footprint: Option<&AccessKeys>,
+        receipts: Option<&ReceiptCollector>,
+        graph: Option<AddressBasedConflictGraph>,
+        metrics: &mut WindowMetrics,
+    ) -> Result<
+        (
+            Vec<BatchDigest>,
+            HashSet<H160>,
+            Vec<IndexedEthereumTransaction>,
+        ),
+        OptmeError,
+    > {
+        let SimulationResult { digests, rw_sets } = simulation;
+
+        // e.g., the batches are empty, or every transaction failed to execute: nothing to schedule nor commit.
+        if rw_sets.is_empty() {
+            *metrics = WindowMetrics {
+                gas_metrics: GasMetrics::default(),
+                re_execution_rounds: Some(0),
+            };
+            return Ok((digests, HashSet::new(), vec![]));
+        }
+
+        if self.first_pass_mode == FirstPassMode::OptimisticCommitThenValidate {
+            let (committed, deferred) = self
+                ._execute_optimistically(rw_sets, timings, footprint, receipts, metrics)
+                .await?;
+            return Ok((digests, committed, deferred));
+        }
+
+        let mut committed = HashSet::new();
+        let mut deferred = vec![];
 
         let scheduled_aborted_txs: Vec<Vec<AbortedTransaction>>;
+        let single_pass_txs: Vec<AbortedTransaction>;
+        let slow_txs: Vec<SimulatedTransaction>;
+        let chained_txs: Vec<SimulatedTransaction>;
 
         // 1st execution
         {
-            let rw_sets = self._simulate(tx_list).await;
+            let is_slow =
+                |tx: &SimulatedTransaction| matches!(tx.outcome(), SimulationOutcome::Slow(_));
+            // (slow transactions are rare, so the vector of the simulation result is kept as is, to be recycled)
+            let (slow, rw_sets): (Vec<_>, Vec<_>) = if rw_sets.iter().any(is_slow) {
+                rw_sets.into_iter().partition(is_slow)
+            } else {
+                (vec![], rw_sets)
+            };
+            let (chained, rw_sets) = self._chain_senders(rw_sets);
+            self._trace(|id| {
+                rw_sets
+                    .iter()
+                    .chain(slow.iter())
+                    .chain(chained.iter())
+                    .find(|tx| tx.id() == id)
+                    .map(|tx| format!("simulated ({:?})", tx.outcome()))
+            });
+            self._trace(|id| {
+                chained
+                    .iter()
+                    .any(|tx| tx.id() == id)
+                    .then(|| "chained after the first transaction of its sender".to_string())
+            });
+            slow_txs = slow;
+            chained_txs = chained;
+
+            // (staged until the schedule is committed, since the aborted transactions are executed again)
+            let staged = receipts.map(|_| Self::_stage_receipts(&rw_sets));
+
+            let gas_by_id = rw_sets
+                .iter()
+                .map(|tx| (tx.id(), tx.gas_used()))
+                .collect::<hashbrown::HashMap<_, _>>();
+            let serial_gas = slow_txs
+                .iter()
+                .chain(chained_txs.iter())
+                .map(|tx| tx.gas_used())
+                .sum::<u64>();
+            let simulated_gas = gas_by_id.values().sum::<u64>() + serial_gas;
+
+            let routes = self
+                .conflict_threshold
+                .map(|threshold| Self::_route_batches(&rw_sets, threshold))
+                .unwrap_or_default();
+
+            // kept aside only to fall back on, in case the construction deadline is exceeded.
+            let fallback_txs = self.construction_deadline.map(|_| {
+                rw_sets
+                    .iter()
+                    .map(|tx| tx.raw_tx().to_owned())
+                    .collect_vec()
+            });
 
-            let ScheduledInfo {
+            let graph = graph.map(|mut graph| {
+                graph.retain(
+                    &rw_sets
+                        .iter()
+                        .map(|tx| tx.id())
+                        .collect::<FastHashSet<u64>>(),
+                );
+                graph
+            });
+
+            let clock = Instant::now();
+            let schedule = self._schedule(rw_sets, graph).await;
+            Self::_record(&mut timings, clock, |t| &mut t.scheduling);
+
+            let Some(ScheduledInfo {
                 scheduled_txs,
                 aborted_txs,
-            } = AddressBasedConflictGraph::par_construct(rw_sets)
-                .await
-                .hierarchcial_sort()
-                .reorder()
-                .par_extract_schedule()
-                .await;
+            }) = schedule
+            else {
+                self.construction_deadline_hits
+                    .fetch_add(1, Ordering::Relaxed);
+                warn!("conflict graph construction exceeded the deadline, fall back to serial execution");
+                metrics.gas_metrics = GasMetrics {
+                    simulated_gas,
+                    aborted_gas: simulated_gas,
+                };
 
-            self._concurrent_commit(scheduled_txs).await;
+                let serial_txs = fallback_txs
+                    .unwrap_or_default()
+                    .into_iter()
+                    .chain(
+                        slow_txs
+                            .into_iter()
+                            .chain(chained_txs)
+                            .map(|tx| tx.raw_tx().to_owned()),
+                    )
+                    .sorted_by_key(|tx| tx.id)
+                    .collect_vec();
+                let traced = self
+                    .trace_tx_id
+                    .map_or(false, |id| serial_txs.iter().any(|tx| tx.id == id));
+                let num_serial_txs = serial_txs.len();
+                let clock = Instant::now();
+                let committed = self
+                    ._serial_execute_or_defer(serial_txs, footprint, &mut deferred, receipts)
+                    .await?;
+                Self::_record(&mut timings, clock, |t| &mut t.serial);
+                if let Some(timings) = timings.as_deref_mut() {
+                    timings.record_commit(num_serial_txs);
+                }
+                self._trace(|_| {
+                    traced.then(|| "committed serially (construction deadline exceeded)".to_string())
+                });
+
+                return Ok((digests, committed, deferred));
+            };
+
+            let traced_generation = self.trace_tx_id.and_then(|id| {
+                scheduled_txs
+                    .iter()
+                    .position(|txs| txs.iter().any(|tx| tx.id() == id))
+            });
+            self._trace(|_| traced_generation.map(|g| format!("scheduled into generation {g}")));
+            metrics.gas_metrics = GasMetrics {
+                simulated_gas,
+                aborted_gas: aborted_txs
+                    .iter()
+                    .flatten()
+                    .map(|tx| gas_by_id.get(&tx.id()).copied().unwrap_or_default())
+                    .sum::<u64>()
+                    + serial_gas,
+            };
+            self._trace(|id| {
+                aborted_txs.iter().enumerate().find_map(|(e, txs)| {
+                    txs.iter()
+                        .find(|tx| tx.id() == id)
+                        .map(|tx| format!("aborted into epoch {e} ({:?})", tx.abort_reason()))
+                })
+            });
+
+            let num_scheduled_txs = scheduled_txs.iter().map(Vec::len).sum::<usize>();
+            committed.extend(modified_accounts(
+                scheduled_txs.iter().flatten().flat_map(|tx| tx.effects()),
+            ));
+            let scheduled_ids = staged.as_ref().map(|_| {
+                scheduled_txs
+                    .iter()
+                    .flatten()
+                    .map(|tx| tx.id())
+                    .collect::<FastHashSet<u64>>()
+            });
+            let clock = Instant::now();
+            self._concurrent_commit(scheduled_txs).await?;
+            Self::_record(&mut timings, clock, |t| &mut t.commit);
+            if let (Some(receipts), Some(staged), Some(scheduled_ids)) =
+                (receipts, staged, scheduled_ids)
+            {
+                receipts.commit(staged, |id| scheduled_ids.contains(&id));
+            }
+            if let Some(timings) = timings.as_deref_mut() {
+                timings.record_commit(num_scheduled_txs);
+            }
+            self._trace(|_| traced_generation.map(|_| "committed".to_string()));
 
-            scheduled_aborted_txs = aborted_txs;
+            let mut single_pass = vec![];
+            scheduled_aborted_txs = aborted_txs
+                .into_iter()
+                .map(|txs| {
+                    let (single, re_execute): (Vec<_>, Vec<_>) = txs.into_iter().partition(|tx| {
+                        routes.get(&tx.batch_id()) == Some(&BatchRoute::SinglePass)
+                    });
+                    single_pass.extend(
+                        single
+                            .into_iter()
+                            .map(|tx| tx.with_abort_reason(AbortReason::SerialFallback)),
+                    );
+                    re_execute
+                })
+                .filter(|txs| !txs.is_empty())
+                .collect();
+            self._trace(|id| {
+                single_pass
+                    .iter()
+                    .any(|tx| tx.id() == id)
+                    .then(|| "routed to serial execution".to_string())
+            });
+            single_pass_txs = single_pass;
         }
 
+        let mut re_execution_rounds = 0;
         for tx_list_to_re_execute in scheduled_aborted_txs.into_iter() {
             // 2nd execution
             //  (1) re-simulation  ----------------> (rw-sets are changed ??)  -------yes-------> (2') invalidate (or, fallback)
@@ -131,18 +1539,65 @@ impl ConcurrencyLevelManager {
             //                                                 |
             //                                          (2) commit
 
-            let rw_sets = self
+            let reasons = tx_list_to_re_execute
+                .iter()
+                .map(|tx| (tx.id(), tx.abort_reason()))
+                .collect::<FastHashMap<_, _>>();
+            let staged = receipts.map(|_| ReceiptCollector::default());
+            let clock = Instant::now();
+            let mut rw_sets = self
                 ._re_execute(
                     tx_list_to_re_execute
                         .into_iter()
                         .map(|tx| tx.into_raw_tx())
                         .collect(),
+                    staged.as_ref(),
                 )
-                .await;
+                .await?;
+            Self::_record(&mut timings, clock, |t| &mut t.re_execution);
+            rw_sets
+                .iter_mut()
+                .for_each(|tx| tx.set_abort_reason(reasons.get(&tx.id()).copied().flatten()));
+            let rw_sets = self._defer_escaped(rw_sets, footprint, &mut deferred);
+
+            if !rw_sets.is_empty() {
+                re_execution_rounds += 1;
+            }
+            let traced = self
+                .trace_tx_id
+                .map_or(false, |id| rw_sets.iter().any(|tx| tx.id() == id));
+            self._trace(|_| traced.then(|| format!("re-executed in round {re_execution_rounds}")));
+
+            let num_re_executed_txs = rw_sets.len();
+            // (the invalidated ones are included as well, which only makes the next window re-simulate more)
+            committed.extend(modified_accounts(
+                rw_sets.iter().flat_map(|tx| tx.effects()),
+            ));
+            let validated_ids = staged.as_ref().map(|_| Self::_ids(&rw_sets));
+            let clock = Instant::now();
+            let invalid_txs = self
+                ._validate_optimistic_assumption_with(rw_sets, false)
+                .await?;
+            Self::_record(&mut timings, clock, |t| &mut t.validation);
+            Self::_commit_receipts(receipts, staged, validated_ids, invalid_txs.as_deref());
+            if let Some(timings) = timings.as_deref_mut() {
+                timings
+                    .record_commit(num_re_executed_txs - invalid_txs.as_ref().map_or(0, Vec::len));
+            }
 
-            match self._validate_optimistic_assumption(rw_sets).await {
-                None => {}
+            match invalid_txs {
+                None => self._trace(|_| traced.then(|| "validated and committed".to_string())),
                 Some(invalid_txs) => {
+                    self._trace(|id| {
+                        traced.then(|| {
+                            if invalid_txs.iter().any(|tx| tx.id() == id) {
+                                "invalidated".to_string()
+                            } else {
+                                "validated and committed".to_string()
+                            }
+                        })
+                    });
+
                     //* invalidate */
                     tracing::debug!("invalidated txs: {:?}", invalid_txs);
 
@@ -174,362 +1629,1015 @@ impl ConcurrencyLevelManager {
             }
         }
 
-        digests
+        metrics.re_execution_rounds = Some(re_execution_rounds);
+
+        // 3rd execution (serial) for the aborted transactions of the low-conflict batches,
+        // the transactions classified as slow during simulation, and the ones chained after the first of their sender
+        let serial_txs = single_pass_txs
+            .into_iter()
+            .map(|tx| tx.into_raw_tx())
+            .sorted_by_key(|tx| tx.id)
+            .chain(
+                slow_txs
+                    .into_iter()
+                    .chain(chained_txs)
+                    .sorted_by_key(SimulatedTransaction::id)
+                    .map(|tx| tx.raw_tx().to_owned()),
+            )
+            .collect_vec();
+        let traced = self
+            .trace_tx_id
+            .map_or(false, |id| serial_txs.iter().any(|tx| tx.id == id));
+        let num_serial_txs = serial_txs.len();
+        let clock = Instant::now();
+        committed.extend(
+            self._serial_execute_or_defer(serial_txs, footprint, &mut deferred, receipts)
+                .await?,
+        );
+        Self::_record(&mut timings, clock, |t| &mut t.serial);
+        if let Some(timings) = timings.as_deref_mut() {
+            timings.record_commit(num_serial_txs);
+        }
+        self._trace(|_| traced.then(|| "committed serially".to_string()));
+
+        Ok((digests, committed, deferred))
+    }
+
+    // with the footprint of a window, sets the re-executed transactions accessing any key outside of it aside
+    // into `deferred`, since the windows executing concurrently may access that key.
+    fn _defer_escaped(
+        &self,
+        rw_sets: Vec<ReExecutedTransaction>,
+        footprint: Option<&AccessKeys>,
+        deferred: &mut Vec<IndexedEthereumTransaction>,
+    ) -> Vec<ReExecutedTransaction> {
+        let Some(footprint) = footprint else {
+            return rw_sets;
+        };
+        let (within, escaped): (Vec<_>, Vec<_>) = rw_sets.into_iter().partition(|tx| {
+            tx.access_keys(&self.global_state)
+                .map_or(false, |keys| keys.is_within(footprint))
+        });
+        deferred.extend(escaped.into_iter().map(ReExecutedTransaction::into_raw_tx));
+        within
+    }
+
+    // executes the transactions serially, or with the footprint of a window, sets them aside into `deferred`,
+    // since they may access any key.
+    async fn _serial_execute_or_defer(
+        &self,
+        tx_list: Vec<IndexedEthereumTransaction>,
+        footprint: Option<&AccessKeys>,
+        deferred: &mut Vec<IndexedEthereumTransaction>,
+        receipts: Option<&ReceiptCollector>,
+    ) -> Result<HashSet<H160>, OptmeError> {
+        if footprint.is_some() {
+            deferred.extend(tx_list);
+            return Ok(HashSet::new());
+        }
+        self._serial_execute(tx_list, receipts).await
+    }
+
+    // with sender chains, splits off the transactions of each sender after its first one (in the order of ids),
+    // which are executed serially instead of entering the conflict graph (see `with_sender_chains`).
+    fn _chain_senders(
+        &self,
+        rw_sets: Vec<SimulatedTransaction>,
+    ) -> (Vec<SimulatedTransaction>, Vec<SimulatedTransaction>) {
+        if !self.sender_chains {
+            return (vec![], rw_sets);
+        }
+
+        let mut firsts = hashbrown::HashMap::<H160, u64>::new();
+        for tx in &rw_sets {
+            let first = firsts.entry(tx.raw_tx().data().caller()).or_insert(tx.id());
+            *first = (*first).min(tx.id());
+        }
+        rw_sets
+            .into_iter()
+            .partition(|tx| firsts[&tx.raw_tx().data().caller()] != tx.id())
+    }
+
+    // the receipt records of the first pass, staged until the transactions are committed.
+    fn _stage_receipts(rw_sets: &[SimulatedTransaction]) -> ReceiptCollector {
+        let staged = ReceiptCollector::default();
+        staged.extend(rw_sets.iter().map(|tx| {
+            ReceiptRecord::new(
+                tx.raw_tx(),
+                tx.outcome().is_success(),
+                tx.gas_used(),
+                tx.logs(),
+            )
+        }));
+        staged
+    }
+
+    pub(crate) fn _ids(rw_sets: &[ReExecutedTransaction]) -> FastHashSet<u64> {
+        rw_sets.iter().map(|tx| tx.id()).collect()
+    }
+
+    // commits the staged records of the validated transactions, but the invalidated ones (whose effects are discarded),
+    // and the ones deferred out of the window before the validation (i.e., not in `validated_ids`).
+    pub(crate) fn _commit_receipts(
+        receipts: Option<&ReceiptCollector>,
+        staged: Option<ReceiptCollector>,
+        validated_ids: Option<FastHashSet<u64>>,
+        invalid_txs: Option<&[ReExecutedTransaction]>,
+    ) {
+        let (Some(receipts), Some(staged), Some(mut validated_ids)) =
+            (receipts, staged, validated_ids)
+        else {
+            return;
+        };
+        for tx in invalid_txs.unwrap_or_default() {
+            validated_ids.remove(&tx.id());
+        }
+        receipts.commit(staged, |id| validated_ids.contains(&id));
+    }
+
+    // commits the simulated transactions which do not conflict with the earlier committed ones, and re-executes
+    // the others over the updated state, round by round (see `FirstPassMode::OptimisticCommitThenValidate`).
+    // The first pending transaction of a round never conflicts, so every round commits at least one.
+    async fn _execute_optimistically(
+        &self,
+        rw_sets: Vec<SimulatedTransaction>,
+        mut timings: Option<&mut PhaseTimings>,
+        footprint: Option<&AccessKeys>,
+        receipts: Option<&ReceiptCollector>,
+        metrics: &mut WindowMetrics,
+    ) -> Result<(HashSet<H160>, Vec<IndexedEthereumTransaction>), OptmeError> {
+        let (slow_txs, rw_sets): (Vec<_>, Vec<_>) = rw_sets
+            .into_iter()
+            .partition(|tx| matches!(tx.outcome(), SimulationOutcome::Slow(_)));
+        let (chained_txs, rw_sets) = self._chain_senders(rw_sets);
+
+        // (the records of each round are staged until it is validated, see `_commit_receipts`)
+        let mut staged = receipts.map(|_| Self::_stage_receipts(&rw_sets));
+
+        let gas_by_id = rw_sets
+            .iter()
+            .map(|tx| (tx.id(), tx.gas_used()))
+            .collect::<hashbrown::HashMap<_, _>>();
+        let serial_gas = slow_txs
+            .iter()
+            .chain(chained_txs.iter())
+            .map(|tx| tx.gas_used())
+            .sum::<u64>();
+        let simulated_gas = gas_by_id.values().sum::<u64>() + serial_gas;
+
+        let mut pending = rw_sets
+            .into_iter()
+            .map(ReExecutedTransaction::from)
+            .collect_vec();
+        let mut re_execution_rounds = 0;
+        let mut aborted_gas = serial_gas;
+        let mut committed = HashSet::new();
+        let mut deferred = vec![];
+        loop {
+            let num_pending_txs = pending.len();
+            committed.extend(modified_accounts(
+                pending.iter().flat_map(|tx| tx.effects()),
+            ));
+            let validated_ids = staged.as_ref().map(|_| Self::_ids(&pending));
+            let clock = Instant::now();
+            let invalid_txs = self
+                ._validate_optimistic_assumption_with(pending, true)
+                .await?;
+            Self::_record(&mut timings, clock, |t| &mut t.validation);
+            Self::_commit_receipts(
+                receipts,
+                staged.take(),
+                validated_ids,
+                invalid_txs.as_deref(),
+            );
+            if let Some(timings) = timings.as_deref_mut() {
+                timings.record_commit(num_pending_txs - invalid_txs.as_ref().map_or(0, Vec::len));
+            }
+
+            let Some(invalid_txs) = invalid_txs else {
+                break;
+            };
+            // (as in `ScheduleThenCommit`, only the gas of the invalidated first pass is wasted)
+            if re_execution_rounds == 0 {
+                aborted_gas += invalid_txs
+                    .iter()
+                    .map(|tx| gas_by_id.get(&tx.id()).copied().unwrap_or_default())
+                    .sum::<u64>();
+            }
+            re_execution_rounds += 1;
+            debug!(
+                "re-execute {} transactions in round {re_execution_rounds}",
+                invalid_txs.len()
+            );
+
+            staged = receipts.map(|_| ReceiptCollector::default());
+            let clock = Instant::now();
+            pending = self
+                ._re_execute(
+                    invalid_txs
+                        .into_iter()
+                        .map(ReExecutedTransaction::into_raw_tx)
+                        .collect(),
+                    staged.as_ref(),
+                )
+                .await?;
+            Self::_record(&mut timings, clock, |t| &mut t.re_execution);
+            pending = self._defer_escaped(pending, footprint, &mut deferred);
+        }
+
+        *metrics = WindowMetrics {
+            gas_metrics: GasMetrics {
+                simulated_gas,
+                aborted_gas,
+            },
+            re_execution_rounds: Some(re_execution_rounds),
+        };
+
+        let serial_txs = slow_txs
+            .into_iter()
+            .chain(chained_txs)
+            .sorted_by_key(SimulatedTransaction::id)
+            .map(SimulatedTransaction::into_raw_tx)
+            .collect_vec();
+        let num_serial_txs = serial_txs.len();
+        let clock = Instant::now();
+        committed.extend(
+            self._serial_execute_or_defer(serial_txs, footprint, &mut deferred, receipts)
+                .await?,
+        );
+        Self::_record(&mut timings, clock, |t| &mut t.serial);
+        if let Some(timings) = timings.as_deref_mut() {
+            timings.record_commit(num_serial_txs);
+        }
+
+        Ok((committed, deferred))
+    }
+
+    /// Executes the consensus output under the vanilla first-committer-wins rule (i.e., without the early detection):
+    /// each round commits the schedule of the conflict graph, and re-simulates the aborted transactions over
+    /// the updated state for the next round, until every transaction is committed.
+    /// The transactions still aborted after [`MAX_VANILLA_ROUNDS`] rounds, and the ones classified as slow during simulation,
+    /// are executed serially at the end. The output commits fully or not at all, as with [`Self::prepare_execution`].
+    #[cfg(feature = "disable-early-detection")]
+    pub async fn execute_vanilla(
+        &self,
+        consensus_output: Vec<ExecutableEthereumBatch>,
+    ) -> Result<Vec<BatchDigest>, OptmeError> {
+        self._atomically(async {
+            let consensus_output = self._dedup(consensus_output).await?;
+            let (digests, mut tx_list) = self._unpack_batches(consensus_output).await?;
+
+            let mut slow_txs = vec![];
+            let mut rounds = 0;
+            while !tx_list.is_empty() && rounds < MAX_VANILLA_ROUNDS {
+                rounds += 1;
+
+                let (slow, rw_sets): (Vec<_>, Vec<_>) = self
+                    ._simulate(tx_list)
+                    .await?
+                    .into_iter()
+                    .partition(|tx| matches!(tx.outcome(), SimulationOutcome::Slow(_)));
+                slow_txs.extend(slow.into_iter().map(|tx| tx.raw_tx().to_owned()));
+
+                let ScheduledInfo {
+                    scheduled_txs,
+                    aborted_txs,
+                } = AddressBasedConflictGraph::par_construct_without_early_detection(rw_sets)
+                    .await
+                    .hierarchcial_sort()
+                    .reorder()
+                    .par_extract_schedule()
+                    .await;
+
+                self._concurrent_commit(scheduled_txs).await?;
+
+                tx_list = aborted_txs
+                    .into_iter()
+                    .flatten()
+                    .map(|tx| tx.into_raw_tx())
+                    .sorted_by_key(|tx| tx.id)
+                    .collect_vec();
+            }
+            debug!("vanilla execution took {rounds} rounds");
+
+            if !tx_list.is_empty() {
+                warn!(
+                    "{} transactions are still aborted after {MAX_VANILLA_ROUNDS} rounds, execute them serially",
+                    tx_list.len()
+                );
+            }
+            let serial_txs = tx_list
+                .into_iter()
+                .chain(slow_txs)
+                .sorted_by_key(|tx| tx.id)
+                .collect_vec();
+            self._serial_execute(serial_txs, None).await?;
+
+            Ok(digests)
+        })
+        .await
+    }
+
+    pub async fn simulate(
+        &self,
+        consensus_output: Vec<ExecutableEthereumBatch>,
+    ) -> Result<SimulationResult, OptmeError> {
+        let (consensus_output, _) = self._drop_duplicates(consensus_output).await?;
+        self._simulate_batches(consensus_output).await
+    }
+
+    // simulates as `simulate` does, the consensus output already deduplicated (e.g., a window of it).
+    async fn _simulate_batches(
+        &self,
+        consensus_output: Vec<ExecutableEthereumBatch>,
+    ) -> Result<SimulationResult, OptmeError> {
+        let (digests, tx_list) = self._unpack_batches(consensus_output).await?;
+        let rw_sets = self._simulate(tx_list).await?;
+
+        Ok(SimulationResult { digests, rw_sets })
+    }
+
+    /// Simulates like [`Self::simulate`], but stops simulating the transactions not started yet once `cancel` is cancelled
+    /// (e.g., on shutdown), and returns the ones simulated so far. The in-flight simulations are not preempted.
+    /// Check `cancel` to tell a partial result, which misses some transactions of the consensus output.
+    pub async fn simulate_cancellable(
+        &self,
+        consensus_output: Vec<ExecutableEthereumBatch>,
+        cancel: CancellationToken,
+    ) -> Result<SimulationResult, OptmeError> {
+        let (digests, tx_list) = self._unpack_unique_batches(consensus_output).await?;
+        let num_txs = tx_list.len();
+        let rw_sets = self._simulate_with(tx_list, Some(cancel)).await?;
+        if rw_sets.len() < num_txs {
+            debug!("simulated {} of {} transactions", rw_sets.len(), num_txs);
+        }
+
+        Ok(SimulationResult { digests, rw_sets })
+    }
+
+    /// Simulates like [`Self::simulate`], and also seeds the read set of each transaction with the storage slots
+    /// declared in its EIP-2930 access list, so that the conflicts it declares are taken into account.
+    /// (the EVM already uses the access list for the warm/cold gas accounting.)
+    pub async fn simulate_with_access_list(
+        &self,
+        consensus_output: Vec<ExecutableEthereumBatch>,
+    ) -> Result<SimulationResult, OptmeError> {
+        let (digests, tx_list) = self._unpack_unique_batches(consensus_output).await?;
+        let rw_sets = self._simulate(tx_list).await?;
+
+        let snapshot = self.global_state.clone();
+        let granularity = self.granularity;
+
+        let rw_sets = run_on_rayon(move || {
+            let backend = snapshot.get_storage();
+            rw_sets
+                .into_par_iter()
+                .map(|tx| {
+                    let reads = tx
+                        .raw_tx()
+                        .data()
+                        .access_list()
+                        .into_iter()
+                        .flat_map(|(contract, keys)| {
+                            keys.into_iter()
+                                .map(move |key| (contract, key, backend.storage(contract, key)))
+                        })
+                        .collect_vec();
+                    tx.seed_reads(reads, granularity)
+                })
+                .collect()
+        })
+        .await?;
+
+        Ok(SimulationResult { digests, rw_sets })
     }
 
-    pub async fn simulate(
+    /// Simulates the given transactions in parallel over the current global state.
+    ///
+    /// The reverted and failed (e.g., out-of-gas) transactions are kept with no effects, along with their
+    /// [`SimulationOutcome`] telling why. The ones whose simulation takes longer than the slow-simulation
+    /// threshold (if any) are interrupted, and kept with no rw-sets nor effects, marked [`SimulationOutcome::Slow`]
+    /// to be executed serially instead of scheduled. The returned rw-sets keep the relative order of `tx_list` (rayon's indexed `par_extend`
+    /// is order-preserving), but the transactions the EVM cannot execute at all (e.g., a fatal error) are dropped,
+    /// so the position of a `SimulatedTransaction` in the output is NOT its identity. Downstream consumers
+    /// (e.g., the conflict graph) must key transactions by `SimulatedTransaction::id()`, which is
+    /// the index assigned in `_unpack_batches` and survives the gaps.
+    async fn _simulate(
         &self,
-        consensus_output: Vec<ExecutableEthereumBatch>,
-    ) -> SimulationResult {
-        let (digests, tx_list) = Self::_unpack_batches(consensus_output).await;
-        let rw_sets = self._simulate(tx_list).await;
-
-        SimulationResult { digests, rw_sets }
+        tx_list: Vec<IndexedEthereumTransaction>,
+    ) -> Result<Vec<SimulatedTransaction>, OptmeError> {
+        self._simulate_with(tx_list, None).await
     }
 
-    async fn _simulate(
+    // skips the transactions not started yet once `cancel` (if any) is cancelled.
+    async fn _simulate_with(
         &self,
         tx_list: Vec<IndexedEthereumTransaction>,
-    ) -> Vec<SimulatedTransaction> {
+        cancel: Option<CancellationToken>,
+    ) -> Result<Vec<SimulatedTransaction>, OptmeError> {
         let snapshot = self.global_state.clone();
+        let config = self.config.clone();
+        let granularity = self.granularity;
+        let slow_threshold = self.slow_simulation_threshold;
+        let value_transfer_conflicts = self.value_transfer_conflicts;
+        let mut buffer = Self::_take_buffer(&self.simulated_buffers);
+
+        run_on_rayon(move || {
+            buffer.par_extend(tx_list.into_par_iter().filter_map(|tx| {
+                if cancel
+                    .as_ref()
+                    .map_or(false, CancellationToken::is_cancelled)
+                {
+                    return None;
+                }
+
+                let result = match slow_threshold {
+                    Some(threshold) => {
+                        let clock = Instant::now();
+                        crate::evm_utils::simulate_tx_until(
+                            tx.data(),
+                            snapshot.as_ref(),
+                            &config,
+                            clock + threshold,
+                        )
+                        .map(|result| result.ok_or_else(|| clock.elapsed()))
+                    }
+                    None => {
+                        crate::evm_utils::simulate_tx(tx.data(), snapshot.as_ref(), &config).map(Ok)
+                    }
+                };
+
+                match result {
+                    Ok(Ok((outcome, effect, log, rw_set, gas_used))) => {
+                        let mut rw_set = granularity.coarsen(rw_set);
+                        if !outcome.is_success() {
+                            debug!("transaction {} did not succeed: {:?}", tx.id, outcome);
+                        } else if value_transfer_conflicts {
+                            rw_set = record_value_transfer(rw_set, tx.data());
+                        }
+                        Some(
+                            SimulatedTransaction::new(rw_set, effect, log, tx)
+                                .with_outcome(outcome)
+                                .with_gas_used(gas_used),
+                        )
+                    }
+                    Ok(Err(elapsed)) => {
+                        warn!(
+                            "simulation of transaction {} is interrupted after {:?}",
+                            tx.id, elapsed
+                        );
+                        Some(
+                            SimulatedTransaction::new(RwSet::new(), vec![], vec![], tx)
+                                .with_outcome(SimulationOutcome::Slow(elapsed)),
+                        )
+                    }
+                    Err(_) => {
+                        warn!("fail to execute a transaction {}", tx.digest_u64());
+                        None
+                    }
+                }
+            }));
+            buffer
+        })
+        .await
+        .map_err(OptmeError::from)
+    }
 
-        // Parallel simulation requires heavy cpu usages.
-        // CPU-bound jobs would make the I/O-bound tokio threads starve.
-        // To this end, a separated thread pool need to be used for cpu-bound jobs.
-        // a new thread is created, and a new thread pool is created on the thread. (specifically, rayon's thread pool is created)
-        let (send, recv) = tokio::sync::oneshot::channel();
-        rayon::spawn(move || {
-            let result = tx_list
+    pub(crate) async fn _re_execute(
+        &self,
+        tx_list: Vec<IndexedEthereumTransaction>,
+        receipts: Option<&ReceiptCollector>,
+    ) -> Result<Vec<ReExecutedTransaction>, OptmeError> {
+        let snapshot = self.global_state.clone();
+        let config = self.config.clone();
+        let granularity = self.granularity;
+        let value_transfer_conflicts = self.value_transfer_conflicts;
+        let mut buffer = Self::_take_buffer(&self.re_executed_buffers);
+        let collect_receipts = receipts.is_some();
+
+        let (buffer, records) = run_on_rayon(move || {
+            let executed = tx_list
                 .into_par_iter()
                 .filter_map(|tx| {
-                    match crate::evm_utils::simulate_tx(tx.data(), snapshot.as_ref()) {
-                        Ok(Some((effect, log, rw_set))) => {
-                            Some(SimulatedTransaction::new(rw_set, effect, log, tx))
+                    match crate::evm_utils::simulate_tx(tx.data(), snapshot.as_ref(), &config) {
+                        Ok((outcome, effect, log, rw_set, gas_used)) => {
+                            let record = collect_receipts.then(|| {
+                                ReceiptRecord::new(&tx, outcome.is_success(), gas_used, &log)
+                            });
+                            let mut rw_set = granularity.coarsen(rw_set);
+                            if outcome.is_success() && value_transfer_conflicts {
+                                rw_set = record_value_transfer(rw_set, tx.data());
+                            }
+                            Some((
+                                ReExecutedTransaction::build_from(tx, effect, log, rw_set)
+                                    .with_gas_used(gas_used),
+                                record,
+                            ))
                         }
-                        _ => {
+                        Err(_) => {
                             warn!("fail to execute a transaction {}", tx.digest_u64());
                             None
                         }
                     }
                 })
-                .collect();
+                .collect::<Vec<_>>();
 
-            let _ = send.send(result).unwrap();
-        });
+            let mut records = vec![];
+            buffer.extend(executed.into_iter().map(|(tx, record)| {
+                records.extend(record);
+                tx
+            }));
+            (buffer, records)
+        })
+        .await?;
+
+        if let Some(receipts) = receipts {
+            receipts.extend(records);
+        }
+        Ok(buffer)
+    }
 
-        match recv.await {
-            Ok(rw_sets) => rw_sets,
-            Err(e) => {
-                panic!(
-                    "fail to receive simulation result from the worker thread. {:?}",
-                    e
-                );
-            }
+    /// Constructs the conflict graph of the simulated transactions (unless it is given, already holding them)
+    /// and extracts the schedule from it, or returns `None` if it takes longer than the construction deadline (if any).
+    async fn _schedule(
+        &self,
+        mut rw_sets: Vec<SimulatedTransaction>,
+        graph: Option<AddressBasedConflictGraph>,
+    ) -> Option<ScheduledInfo> {
+        let schedule = async move {
+            let mut graph = match graph {
+                Some(graph) => {
+                    rw_sets.clear();
+                    Self::_recycle_buffer(&self.simulated_buffers, rw_sets);
+                    graph
+                }
+                None => {
+                    let (graph, buffer) =
+                        AddressBasedConflictGraph::par_construct_recycling(rw_sets).await;
+                    Self::_recycle_buffer(&self.simulated_buffers, buffer);
+                    graph
+                }
+            };
+
+            graph
+                .hierarchcial_sort()
+                .reorder()
+                .par_extract_schedule_with(self.abort_clearing)
+                .await
+        };
+
+        match self.construction_deadline {
+            Some(deadline) => tokio::time::timeout(deadline, schedule).await.ok(),
+            None => Some(schedule.await),
+        }
+    }
+
+    fn _take_buffer<T>(pool: &Option<Arc<VecPool<T>>>) -> Vec<T> {
+        pool.as_ref().map(|pool| pool.take()).unwrap_or_default()
+    }
+
+    fn _recycle_buffer<T>(pool: &Option<Arc<VecPool<T>>>, buffer: Vec<T>) {
+        if let Some(pool) = pool {
+            pool.recycle(buffer);
         }
     }
 
-    async fn _re_execute(
+    // executes the transactions one after another over the global state, and returns the accounts they committed to.
+    async fn _serial_execute(
         &self,
         tx_list: Vec<IndexedEthereumTransaction>,
-    ) -> Vec<ReExecutedTransaction> {
-        let snapshot = self.global_state.clone();
+        receipts: Option<&ReceiptCollector>,
+    ) -> Result<HashSet<H160>, OptmeError> {
+        if tx_list.is_empty() {
+            return Ok(HashSet::new());
+        }
 
-        // Parallel simulation requires heavy cpu usages.
-        // CPU-bound jobs would make the I/O-bound tokio threads starve.
-        // To this end, a separated thread pool need to be used for cpu-bound jobs.
-        // a new thread is created, and a new thread pool is created on the thread. (specifically, rayon's thread pool is created)
-        let (send, recv) = tokio::sync::oneshot::channel();
-        rayon::spawn(move || {
-            let result = tx_list
-                .into_par_iter()
-                .filter_map(|tx| {
-                    match crate::evm_utils::simulate_tx(tx.data(), snapshot.as_ref()) {
-                        Ok(Some((effect, log, rw_set))) => {
-                            Some(ReExecutedTransaction::build_from(tx, effect, log, rw_set))
-                        }
-                        _ => {
-                            warn!("fail to execute a transaction {}", tx.digest_u64());
-                            None
+        let storage = self.global_state.clone();
+        let config = self.config.clone();
+        let pre_images = self.pre_images.clone();
+        let collect_receipts = receipts.is_some();
+
+        let (committed, records) = run_on_rayon(move || {
+            let mut committed = HashSet::new();
+            let mut records = vec![];
+            tx_list.into_iter().for_each(|tx| {
+                match crate::evm_utils::simulate_tx(tx.data(), storage.as_ref(), &config) {
+                    Ok((outcome, effect, log, _, gas_used)) => {
+                        if collect_receipts {
+                            records.push(ReceiptRecord::new(
+                                &tx,
+                                outcome.is_success(),
+                                gas_used,
+                                &log,
+                            ));
                         }
+                        committed.extend(modified_accounts(&effect));
+                        pre_images.record(&storage, &effect);
+                        storage.apply_local_effect(effect)
                     }
-                })
-                .collect();
+                    Err(_) => warn!("fail to execute a transaction {}", tx.digest_u64()),
+                }
+            });
+            (committed, records)
+        })
+        .await?;
 
-            let _ = send.send(result).unwrap();
-        });
+        if let Some(receipts) = receipts {
+            receipts.extend(records);
+        }
+        Ok(committed)
+    }
 
-        match recv.await {
-            Ok(rw_sets) => rw_sets,
-            Err(e) => {
-                panic!(
-                    "fail to receive simulation result from the worker thread. {:?}",
-                    e
-                );
+    /// Decides the route of each batch (keyed by its position in the block) from the conflict density
+    /// of its simulated transactions.
+    pub(crate) fn _route_batches(
+        rw_sets: &[SimulatedTransaction],
+        threshold: f64,
+    ) -> BTreeMap<usize, BatchRoute> {
+        rw_sets
+            .iter()
+            .into_group_map_by(|tx| tx.batch_id())
+            .into_iter()
+            .map(|(batch_id, txs)| {
+                let route = if conflict_density(txs) <= threshold {
+                    BatchRoute::SinglePass
+                } else {
+                    BatchRoute::ReExecution
+                };
+                (batch_id, route)
+            })
+            .collect()
+    }
+
+    /// Merges adjacent generations whose writes are disjoint, so that they are committed in a single phase.
+    /// Since the effects are already computed, only write-write conflicts constrain the commit order.
+    /// The merged writers of an account may then rewrite it while others write its storage in place, which relies on
+    /// the effects on an account being applied atomically (see `CMemoryBackend::new_striped`).
+    pub(crate) fn _merge_disjoint_generations(
+        scheduled_txs: Vec<Vec<FinalizedTransaction>>,
+    ) -> Vec<Vec<FinalizedTransaction>> {
+        let mut merged: Vec<Vec<FinalizedTransaction>> = vec![];
+        let mut merged_writes: Option<CommittedWrites> = None;
+
+        for txs in scheduled_txs {
+            let writes = txs
+                .iter()
+                .try_fold(CommittedWrites::default(), |mut writes, tx| {
+                    let tx_writes = tx.writes()?;
+                    if !writes.commutes_with(&tx_writes) {
+                        return None;
+                    }
+                    writes.extend(tx_writes);
+                    Some(writes)
+                });
+
+            match (merged_writes.as_mut(), writes) {
+                (Some(prev_writes), Some(writes)) if prev_writes.commutes_with(&writes) => {
+                    prev_writes.extend(writes);
+                    merged.last_mut().unwrap().extend(txs);
+                }
+                (_, writes) => {
+                    merged_writes = writes;
+                    merged.push(txs);
+                }
             }
         }
+
+        merged
     }
 
-    //TODO: (optimization) commit the last write of each key
-    #[cfg(not(feature = "latency"))]
-    pub async fn _concurrent_commit(&self, scheduled_txs: Vec<Vec<FinalizedTransaction>>) {
-        let storage = self.global_state.clone();
+    // Transactions in a generation never write the same key. With `deterministic-commit`, they are
+    // applied one by one in the order of tx ids anyway, so that the final state is reproducible even if they do.
+    #[inline]
+    fn _commit_generation(
+        storage: &ConcurrentEVMStorage,
+        shards: Option<&[rayon::ThreadPool]>,
+        chunk_len: usize,
+        mut txs: Vec<FinalizedTransaction>,
+    ) {
+        if let Some(shards) = shards {
+            Self::_commit_generation_by_shard(storage, shards, txs);
+        } else if cfg!(feature = "deterministic-commit") {
+            txs.sort_unstable_by_key(|tx| tx.id());
+            txs.into_iter()
+                .for_each(|tx| storage.apply_local_effect(tx.extract()));
+        } else {
+            txs.into_par_iter()
+                .with_min_len(chunk_len)
+                .for_each(|tx| storage.apply_local_effect(tx.extract()));
+        }
+    }
 
-        // Parallel simulation requires heavy cpu usages.
-        // CPU-bound jobs would make the I/O-bound tokio threads starve.
-        // To this end, a separated thread pool need to be used for cpu-bound jobs.
-        // a new thread is created, and a new thread pool is created on the thread. (specifically, rayon's thread pool is created)
-        let (send, recv) = tokio::sync::oneshot::channel();
-        rayon::spawn(move || {
-            let _storage = &storage;
-            for txs_to_commit in scheduled_txs {
-                txs_to_commit.into_par_iter().for_each(|tx| {
-                    let effect = tx.extract();
-                    _storage.apply_local_effect(effect)
-                })
+    // Effects are grouped by the shard of their address, and each shard is committed on its own sub-pool.
+    // Within a shard, the effects on an account are collapsed in the order of tx ids (see `collapse_effects`),
+    // and only the last write of the account is applied.
+    fn _commit_generation_by_shard(
+        storage: &ConcurrentEVMStorage,
+        shards: &[rayon::ThreadPool],
+        mut txs: Vec<FinalizedTransaction>,
+    ) {
+        txs.sort_unstable_by_key(|tx| tx.id());
+
+        let mut effects_by_shard = (0..shards.len()).map(|_| vec![]).collect_vec();
+        for apply in txs.into_iter().flat_map(FinalizedTransaction::extract) {
+            let address = match &apply {
+                Apply::Modify { address, .. } | Apply::Delete { address } => *address,
+            };
+            effects_by_shard[shard_of(&address, shards.len())].push((address, apply));
+        }
+
+        rayon::scope(|s| {
+            for (pool, effects) in shards.iter().zip(effects_by_shard) {
+                if effects.is_empty() {
+                    continue;
+                }
+                s.spawn(move |_| {
+                    pool.install(|| {
+                        effects
+                            .into_iter()
+                            .into_group_map()
+                            .into_par_iter()
+                            .for_each(|(_, effect)| {
+                                storage.apply_local_effect(collapse_effects(effect))
+                            });
+                    })
+                });
             }
-            let _ = send.send(());
         });
-
-        let _ = recv.await;
     }
 
-    #[cfg(feature = "latency")]
-    pub async fn _concurrent_commit(&self, scheduled_txs: Vec<Vec<FinalizedTransaction>>) -> u128 {
+    //TODO: (optimization) commit the last write of each key
+    // commits the generations in order, and returns the failure of a worker or of the WAL.
+    pub async fn _concurrent_commit<T: Committable + Send>(
+        &self,
+        scheduled_txs: Vec<Vec<T>>,
+    ) -> Result<(), OptmeError> {
         let storage = self.global_state.clone();
+        let shards = self.commit_shards.clone();
+        let chunk_len = self.commit_chunk_len;
+        #[cfg(feature = "wal")]
+        let wal = self.commit_wal.clone();
+        let pre_images = self.pre_images.clone();
+        #[cfg(test)]
+        let fault = self.fault.clone();
+        #[cfg(test)]
+        if let Some(fault) = &fault {
+            fault.inject(Fault::Panic)?;
+        }
+        let scheduled_txs = Self::_merge_disjoint_generations(
+            scheduled_txs
+                .into_iter()
+                .map(|txs| {
+                    txs.into_iter()
+                        .map(FinalizedTransaction::from_committable)
+                        .collect_vec()
+                })
+                .collect(),
+        );
 
-        // Parallel simulation requires heavy cpu usages.
-        // CPU-bound jobs would make the I/O-bound tokio threads starve.
-        // To this end, a separated thread pool need to be used for cpu-bound jobs.
-        // a new thread is created, and a new thread pool is created on the thread. (specifically, rayon's thread pool is created)
-        let (send, recv) = tokio::sync::oneshot::channel();
-        rayon::spawn(move || {
-            let _storage = &storage;
-
-            let mut latency = 0u128;
-            let clock = std::time::Instant::now();
-            for txs_to_commit in scheduled_txs {
-                let tx_len = txs_to_commit.len() as u128;
-                txs_to_commit.into_par_iter().for_each(|tx| {
-                    let effect = tx.extract();
-                    _storage.apply_local_effect(effect)
-                });
-                latency += tx_len * clock.elapsed().as_micros();
+        run_on_rayon(move || {
+            Self::_commit_generations(
+                &storage,
+                shards.as_deref(),
+                chunk_len,
+                #[cfg(feature = "wal")]
+                wal.as_deref(),
+                &pre_images,
+                #[cfg(test)]
+                fault.as_deref(),
+                scheduled_txs,
+            )
+        })
+        .await?
+    }
+
+    // commits the generations one after another on the current (rayon) thread, logging each of them to the WAL if any.
+    fn _commit_generations(
+        storage: &ConcurrentEVMStorage,
+        shards: Option<&[rayon::ThreadPool]>,
+        chunk_len: usize,
+        #[cfg(feature = "wal")] wal: Option<&CommitWal>,
+        pre_images: &PreImages,
+        #[cfg(test)] fault: Option<&FaultInjection>,
+        scheduled_txs: Vec<Vec<FinalizedTransaction>>,
+    ) -> Result<(), OptmeError> {
+        for txs_to_commit in scheduled_txs {
+            #[cfg(test)]
+            if let Some(fault) = fault {
+                fault.inject(Fault::WorkerPanic)?;
+                #[cfg(feature = "wal")]
+                if wal.is_some() {
+                    fault.inject(Fault::Wal)?;
+                }
+                fault.count_generation();
             }
-            let _ = send.send(latency);
-        });
+            pre_images.record(
+                storage,
+                txs_to_commit.iter().flat_map(FinalizedTransaction::effects),
+            );
+
+            #[cfg(feature = "wal")]
+            if let Some(wal) = wal {
+                wal.commit(txs_to_commit, |txs| {
+                    Self::_commit_generation(storage, shards, chunk_len, txs)
+                })?;
+                continue;
+            }
+
+            Self::_commit_generation(storage, shards, chunk_len, txs_to_commit);
+        }
+        Ok(())
+    }
+
+    /// Commits the re-executed transactions whose writes are disjoint with the ones before them (in the order given),
+    /// and returns the others, if any. The commit runs inline in the same rayon job as the validation,
+    /// instead of being handed over to [`Self::_concurrent_commit`] as a job of its own.
+    #[doc(hidden)]
+    pub async fn _validate_optimistic_assumption(
+        &self,
+        rw_set: Vec<ReExecutedTransaction>,
+    ) -> Result<Option<Vec<ReExecutedTransaction>>, OptmeError> {
+        self._validate_optimistic_assumption_with(rw_set, false)
+            .await
+    }
+
+    // validates as `_validate_optimistic_assumption` does, and with `check_reads`, also invalidates the transactions
+    // reading a key written before them (i.e., which may have read a stale value).
+    async fn _validate_optimistic_assumption_with(
+        &self,
+        rw_set: Vec<ReExecutedTransaction>,
+        check_reads: bool,
+    ) -> Result<Option<Vec<ReExecutedTransaction>>, OptmeError> {
+        if rw_set.is_empty() {
+            return Ok(None);
+        }
 
-        recv.await.unwrap()
+        let storage = self.global_state.clone();
+        let shards = self.commit_shards.clone();
+        let chunk_len = self.commit_chunk_len;
+        #[cfg(feature = "wal")]
+        let wal = self.commit_wal.clone();
+        let pre_images = self.pre_images.clone();
+        #[cfg(test)]
+        let fault = self.fault.clone();
+        let buffers = self.re_executed_buffers.clone();
+
+        run_on_rayon(move || {
+            let mut rw_set = rw_set;
+            let (valid_txs, invalid_txs) =
+                Self::_partition_by_write_conflicts(&mut rw_set, check_reads);
+            Self::_recycle_buffer(&buffers, rw_set);
+
+            let valid_txs = valid_txs
+                .into_iter()
+                .map(FinalizedTransaction::from)
+                .collect_vec();
+            Self::_commit_generations(
+                &storage,
+                shards.as_deref(),
+                chunk_len,
+                #[cfg(feature = "wal")]
+                wal.as_deref(),
+                &pre_images,
+                #[cfg(test)]
+                fault.as_deref(),
+                vec![valid_txs],
+            )?;
+
+            Ok(invalid_txs)
+        })
+        .await?
     }
 
-    async fn _validate_optimistic_assumption(
+    /// Validates as [`Self::_validate_optimistic_assumption`] does, but commits the valid transactions
+    /// in a separate phase (i.e., another rayon job). Kept to compare against the inline commit.
+    #[doc(hidden)]
+    pub async fn _validate_optimistic_assumption_two_phase(
         &self,
         rw_set: Vec<ReExecutedTransaction>,
-    ) -> Option<Vec<ReExecutedTransaction>> {
+    ) -> Result<Option<Vec<ReExecutedTransaction>>, OptmeError> {
         if rw_set.len() == 1 {
-            self._concurrent_commit_2(rw_set).await;
-            return None;
+            self._concurrent_commit_2(rw_set).await?;
+            return Ok(None);
         }
 
-        let (send, recv) = tokio::sync::oneshot::channel();
-        rayon::spawn(move || {
-            let mut valid_txs = vec![];
-            let mut invalid_txs = vec![];
+        let (valid_txs, invalid_txs) = run_on_rayon(move || {
+            let mut rw_set = rw_set;
+            Self::_partition_by_write_conflicts(&mut rw_set, false)
+        })
+        .await?;
 
-            let mut write_set = hashbrown::HashSet::<H256>::new();
-            for tx in rw_set.into_iter() {
-                let set = tx.write_set();
+        self._concurrent_commit_2(valid_txs).await?;
 
-                if is_disjoint(&set, &write_set) {
-                    write_set.extend(set);
-                    valid_txs.push(tx);
-                } else {
-                    invalid_txs.push(tx);
-                }
-            }
+        Ok(invalid_txs)
+    }
 
-            if invalid_txs.is_empty() {
-                let _ = send.send((valid_txs, None));
+    /// Drains the re-executed transactions, leaving the vector empty (with its capacity).
+    /// With `check_reads`, a transaction also conflicts with the earlier ones writing a key it reads.
+    fn _partition_by_write_conflicts(
+        rw_set: &mut Vec<ReExecutedTransaction>,
+        check_reads: bool,
+    ) -> (
+        Vec<ReExecutedTransaction>,
+        Option<Vec<ReExecutedTransaction>>,
+    ) {
+        let mut valid_txs = vec![];
+        let mut invalid_txs = vec![];
+
+        let mut write_set = hashbrown::HashSet::<H256>::new();
+        for mut tx in rw_set.drain(..) {
+            let set = tx.write_set();
+
+            if is_disjoint(&set, &write_set)
+                && (!check_reads || is_disjoint(&tx.read_set(), &write_set))
+            {
+                write_set.extend(set);
+                valid_txs.push(tx);
             } else {
-                let _ = send.send((valid_txs, Some(invalid_txs)));
+                tx.set_abort_reason(Some(AbortReason::Invalidated));
+                invalid_txs.push(tx);
             }
-        });
-
-        let (valid_txs, invalid_txs) = recv.await.unwrap();
-
-        self._concurrent_commit_2(valid_txs).await;
+        }
 
-        invalid_txs
+        if invalid_txs.is_empty() {
+            (valid_txs, None)
+        } else {
+            (valid_txs, Some(invalid_txs))
+        }
     }
 
-    pub async fn _concurrent_commit_2(&self, scheduled_txs: Vec<ReExecutedTransaction>) {
+    pub async fn _concurrent_commit_2(
+        &self,
+        scheduled_txs: Vec<ReExecutedTransaction>,
+    ) -> Result<(), OptmeError> {
         let scheduled_txs = vec![scheduled_txs //TODO: compare to into_par_iter()
             .into_iter()
             .map(FinalizedTransaction::from)
             .collect_vec()];
 
-        self._concurrent_commit(scheduled_txs).await;
+        self._concurrent_commit(scheduled_txs).await
     }
 }
-// #[cfg(feature = "latency")]
-use tokio::time::Instant;
-
-// #[cfg(feature = "latency")]
 #[async_trait::async_trait]
 pub trait LatencyBenchmark {
     async fn _execute_and_return_latency(
         &self,
         consensus_output: Vec<ExecutableEthereumBatch>,
-    ) -> (u128, u128, u128, u128, u128, u128, f64);
+    ) -> Result<(u128, u128, u128, u128, u128, u128, u128, f64), OptmeError>;
 
     async fn _validate_optimistic_assumption_and_return_latency(
         &self,
         rw_set: Vec<ReExecutedTransaction>,
-    ) -> (Option<Vec<ReExecutedTransaction>>, u128, u128);
+    ) -> Result<(Option<Vec<ReExecutedTransaction>>, u128, u128), OptmeError>;
 }
 
-// #[cfg(feature = "latency")]
+// delegates to the execution with an injected `PhaseTimings`, so that the measured path is the one executed in production.
 #[async_trait::async_trait]
 impl LatencyBenchmark for ConcurrencyLevelManager {
+    /// Returns the (total, simulation, scheduling, re-execution, validation, commit, serial) latencies in microseconds,
+    /// and the average latency of a transaction (see [`PhaseTimings`]).
     async fn _execute_and_return_latency(
         &self,
         consensus_output: Vec<ExecutableEthereumBatch>,
-    ) -> (u128, u128, u128, u128, u128, u128, f64) {
-        let (_, tx_list) = Self::_unpack_batches(consensus_output).await;
-        let total_tx_len = tx_list.len();
-
-        let scheduled_aborted_txs: Vec<Vec<AbortedTransaction>>;
-
-        let mut simulation_latency = 0;
-        let mut scheduling_latency = 0;
-        let mut v_val_latency = 0;
-        let mut v_exec_latency = 0;
-        let mut commit_latency = 0;
-
-        let total_latency = Instant::now();
-        let mut tx_latency = 0u128;
-        // 1st execution
-        {
-            let latency = Instant::now();
-            let rw_sets = self._simulate(tx_list).await;
-            simulation_latency += latency.elapsed().as_micros();
-
-            let latency = Instant::now();
-            let ScheduledInfo {
-                scheduled_txs,
-                aborted_txs,
-            } = AddressBasedConflictGraph::par_construct(rw_sets)
-                .await
-                .hierarchcial_sort()
-                .reorder()
-                .par_extract_schedule()
-                .await;
-            scheduling_latency += latency.elapsed().as_micros();
-
-            let tx_len = scheduled_txs.len() as u128;
-            let latency = Instant::now();
-            tx_latency += total_latency.elapsed().as_micros() * tx_len
-                + self._concurrent_commit(scheduled_txs).await;
-            commit_latency += latency.elapsed().as_micros();
-
-            scheduled_aborted_txs = aborted_txs;
-        }
-
-        for tx_list_to_re_execute in scheduled_aborted_txs.into_iter() {
-            // 2nd execution
-            //  (1) re-simulation  ----------------> (rw-sets are changed ??)  -------yes-------> (2') invalidate (or, fallback)
-            //                                                 |
-            //                                                no
-            //                                                 |
-            //                                          (2) commit
-            let txss: Vec<IndexedEthereumTransaction> = tx_list_to_re_execute
-                .into_par_iter()
-                .map(|tx| tx.into_raw_tx())
-                .collect();
-            let tx_len = txss.len() as u128;
-
-            let latency = Instant::now();
-            let rw_sets = self._re_execute(txss).await;
-            v_exec_latency += latency.elapsed().as_micros();
-
-            match self
-                ._validate_optimistic_assumption_and_return_latency(rw_sets)
-                .await
-            {
-                (None, v, c) => {
-                    commit_latency += c;
-                    v_val_latency += v;
-                }
-                (Some(invalid_txs), v, c) => {
-                    commit_latency += c;
-                    v_val_latency += v;
-
-                    //* invalidate */
-                    tracing::debug!("invalidated txs: {:?}", invalid_txs);
-                }
-            }
-
-            tx_latency += total_latency.elapsed().as_micros() * tx_len;
-        }
-
-        (
-            total_latency.elapsed().as_micros(),
-            simulation_latency,
-            scheduling_latency,
-            v_exec_latency,
-            v_val_latency,
-            commit_latency,
-            tx_latency as f64 / total_tx_len as f64,
-        )
+    ) -> Result<(u128, u128, u128, u128, u128, u128, u128, f64), OptmeError> {
+        let mut timings = PhaseTimings::default();
+        self._execute_with(consensus_output, Some(&mut timings))
+            .await?;
+
+        Ok((
+            timings.total,
+            timings.simulation,
+            timings.scheduling,
+            timings.re_execution,
+            timings.validation,
+            timings.commit,
+            timings.serial,
+            timings.avg_tx_latency(),
+        ))
     }
 
+    /// Returns the invalid transactions, and the latencies of the validation and the commit in microseconds.
+    /// The two are measured apart, so the valid transactions are committed after the validation (as a job of its own).
     async fn _validate_optimistic_assumption_and_return_latency(
         &self,
         rw_set: Vec<ReExecutedTransaction>,
-    ) -> (Option<Vec<ReExecutedTransaction>>, u128, u128) {
-        if rw_set.len() == 1 {
-            let latency = Instant::now();
-            self._concurrent_commit_2(rw_set).await;
-
-            return (None, 0, latency.elapsed().as_micros());
+    ) -> Result<(Option<Vec<ReExecutedTransaction>>, u128, u128), OptmeError> {
+        if rw_set.is_empty() {
+            return Ok((None, 0, 0));
         }
 
-        let (send, recv) = tokio::sync::oneshot::channel();
-
-        let latency = Instant::now();
-        rayon::spawn(move || {
-            let mut valid_txs = vec![];
-            let mut invalid_txs = vec![];
-
-            let mut write_set = hashbrown::HashSet::<H256>::new();
-            for tx in rw_set.into_iter() {
-                let set = tx.write_set();
-
-                if is_disjoint(&set, &write_set) {
-                    write_set.extend(set);
-                    valid_txs.push(tx);
-                } else {
-                    invalid_txs.push(tx);
-                }
-            }
-
-            if invalid_txs.is_empty() {
-                let _ = send.send((valid_txs, None));
-            } else {
-                let _ = send.send((valid_txs, Some(invalid_txs)));
-            }
-        });
-
-        let (valid_txs, invalid_txs) = recv.await.unwrap();
-        let validation_latency = latency.elapsed().as_micros();
+        let clock = Instant::now();
+        let (valid_txs, invalid_txs) = run_on_rayon(move || {
+            let mut rw_set = rw_set;
+            Self::_partition_by_write_conflicts(&mut rw_set, false)
+        })
+        .await?;
+        let validation_latency = clock.elapsed().as_micros();
 
-        let commit_latency = Instant::now();
-        self._concurrent_commit_2(valid_txs).await;
+        let clock = Instant::now();
+        self._concurrent_commit_2(valid_txs).await?;
 
-        (
-            invalid_txs,
-            validation_latency,
-            commit_latency.elapsed().as_micros(),
-        )
+        Ok((invalid_txs, validation_latency, clock.elapsed().as_micros()))
     }
 }
 
@@ -549,8 +2657,6 @@ pub trait Benchmark {
     ) -> (f64, f64, f64, f64, f64, u32);
 }
 #[cfg(all(feature = "parallelism-analysis", feature = "disable-early-detection"))]
-use crate::address_based_conflict_graph::Benchmark as _;
-#[cfg(all(feature = "parallelism-analysis", feature = "disable-early-detection"))]
 use incr_stats::incr::Stats;
 
 #[cfg(all(feature = "parallelism-analysis", feature = "disable-early-detection"))]
@@ -560,8 +2666,8 @@ impl Benchmark for ConcurrencyLevelManager {
         &self,
         consensus_output: Vec<ExecutableEthereumBatch>,
     ) -> (f64, f64, f64, f64, f64, u32) {
-        let (_, tx_list) = Self::_unpack_batches(consensus_output).await;
-        let rw_sets = self._simulate(tx_list).await;
+        let (_, tx_list) = self._unpack_batches(consensus_output).await.unwrap();
+        let rw_sets = self._simulate(tx_list).await.unwrap();
 
         let ScheduledInfo {
             scheduled_txs,
@@ -593,8 +2699,8 @@ impl Benchmark for ConcurrencyLevelManager {
         &self,
         consensus_output: Vec<ExecutableEthereumBatch>,
     ) -> (f64, f64, f64, f64, f64, u32) {
-        let (_, tx_list) = Self::_unpack_batches(consensus_output).await;
-        let rw_sets = self._simulate(tx_list).await;
+        let (_, tx_list) = self._unpack_batches(consensus_output).await.unwrap();
+        let rw_sets = self._simulate(tx_list).await.unwrap();
 
         let ScheduledInfo {
             scheduled_txs,
@@ -628,6 +2734,111 @@ impl Benchmark for ConcurrencyLevelManager {
     }
 }
 
+/// The commit shard which the given address belongs to.
+#[inline]
+pub(crate) fn shard_of(address: &H160, num_shards: usize) -> usize {
+    (address.to_low_u64_be() % num_shards as u64) as usize
+}
+
+#[cfg(feature = "deadlock-detection")]
+pub(crate) static DEADLOCK_DETECTOR: std::sync::Once = std::sync::Once::new();
+
+/// A failure injected into the execution (see `ConcurrencyLevelManager::with_fault`), to exercise its failure paths.
+#[cfg(test)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Fault {
+    /// a rayon worker panics before committing a generation.
+    WorkerPanic,
+    /// the commit WAL fails to log a generation.
+    #[cfg(feature = "wal")]
+    Wal,
+    /// the execution panics outside of the rayon workers, before committing the generations of a schedule.
+    Panic,
+}
+
+// injects the fault once, after the given number of generations are committed
+// (so that the commit which rolls the failed output back is left alone).
+#[cfg(test)]
+pub(crate) struct FaultInjection {
+    fault: Fault,
+    after: usize,
+    committed: AtomicUsize,
+    injected: std::sync::atomic::AtomicBool,
+}
+
+#[cfg(test)]
+impl FaultInjection {
+    fn new(fault: Fault, after: usize) -> Self {
+        Self {
+            fault,
+            after,
+            committed: AtomicUsize::new(0),
+            injected: Default::default(),
+        }
+    }
+
+    fn inject(&self, at: Fault) -> Result<(), OptmeError> {
+        if self.fault != at
+            || self.committed.load(Ordering::SeqCst) < self.after
+            || self.injected.swap(true, Ordering::SeqCst)
+        {
+            return Ok(());
+        }
+
+        match at {
+            Fault::WorkerPanic | Fault::Panic => panic!("injected {at:?}"),
+            #[cfg(feature = "wal")]
+            Fault::Wal => Err(typed_store::rocks::TypedStoreError::RocksDBError(
+                "injected".to_string(),
+            )
+            .into()),
+        }
+    }
+
+    fn count_generation(&self) {
+        self.committed.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Spawns (once per process) a background thread which periodically reports the threads
+/// deadlocked on `parking_lot` locks, e.g., the `abort_info` of conflicting transactions.
+/// Note that the feature makes every lock acquisition noticeably more expensive, so it is for debugging only.
+#[cfg(feature = "deadlock-detection")]
+fn spawn_deadlock_detector() {
+    DEADLOCK_DETECTOR.call_once(|| {
+        std::thread::Builder::new()
+            .name("deadlock-detector".into())
+            .spawn(|| loop {
+                std::thread::sleep(Duration::from_secs(10));
+                let deadlocks = parking_lot::deadlock::check_deadlock();
+                if deadlocks.is_empty() {
+                    continue;
+                }
+
+                warn!("{} deadlocks detected", deadlocks.len());
+                for (i, threads) in deadlocks.iter().enumerate() {
+                    for t in threads {
+                        warn!(
+                            "deadlock #{}: thread {:?}\n{:?}",
+                            i,
+                            t.thread_id(),
+                            t.backtrace()
+                        );
+                    }
+                }
+            })
+            .expect("fail to spawn the deadlock detector");
+    });
+}
+
+/// The tx ids of a [`ScheduledInfo`], as written by [`ScheduledInfo::write_json`].
+#[cfg(feature = "schedule-dump")]
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ScheduleDump {
+    pub scheduled_txs: Vec<Vec<u64>>,
+    pub aborted_txs: Vec<Vec<u64>>,
+}
+
 pub struct ScheduledInfo {
     pub scheduled_txs: Vec<Vec<FinalizedTransaction>>,
     pub aborted_txs: Vec<Vec<AbortedTransaction>>,
@@ -651,7 +2862,20 @@ impl ScheduledInfo {
         tx_list: FastHashMap<u64, Arc<Transaction>>,
         aborted_txs: Vec<Arc<Transaction>>,
     ) -> Self {
-        let aborted_txs = Self::_schedule_aborted_txs(aborted_txs, true);
+        Self::par_from_with(tx_list, aborted_txs, AbortClearing::default())
+    }
+
+    /// Schedules as [`Self::par_from`] does, clearing the aborted transactions as `clearing` decides.
+    /// The epochs assigned to the aborted transactions are the same either way.
+    pub fn par_from_with(
+        tx_list: FastHashMap<u64, Arc<Transaction>>,
+        aborted_txs: Vec<Arc<Transaction>>,
+        clearing: AbortClearing,
+    ) -> Self {
+        let aborted_txs = match clearing {
+            AbortClearing::Batched => Self::_schedule_aborted_txs(aborted_txs, true),
+            AbortClearing::Streaming => Self::_stream_aborted_txs(aborted_txs),
+        };
         let scheduled_txs = Self::_schedule_sorted_txs(tx_list, true);
 
         Self {
@@ -660,20 +2884,6 @@ impl ScheduledInfo {
         }
     }
 
-    fn _unwrap(tx: Arc<Transaction>) -> Transaction {
-        match Arc::try_unwrap(tx) {
-            Ok(tx) => tx,
-            Err(tx) => {
-                panic!(
-                    "fail to unwrap transaction. (strong:{}, weak:{}): {:?}",
-                    Arc::strong_count(&tx),
-                    Arc::weak_count(&tx),
-                    tx
-                );
-            }
-        }
-    }
-
     fn _schedule_sorted_txs(
         tx_list: FastHashMap<u64, Arc<Transaction>>,
         rayon: bool,
@@ -696,13 +2906,14 @@ impl ScheduledInfo {
                 .collect::<Vec<ScheduledTransaction>>()
         };
 
-        // sort groups by sequence.
+        // sort groups by sequence, and renumber the sequences by generation (i.e., without gaps).
         list.sort_unstable_by_key(|tx| tx.seq());
         let mut scheduled_txs = Vec::<Vec<FinalizedTransaction>>::new();
         for (_key, txns) in &list.into_iter().group_by(|tx| tx.seq()) {
+            let seq = scheduled_txs.len() as u32;
             scheduled_txs.push(
                 txns.into_iter()
-                    .map(FinalizedTransaction::from)
+                    .map(|tx| FinalizedTransaction::from(ScheduledTransaction { seq, ..tx }))
                     .collect_vec(),
             );
         }
@@ -710,6 +2921,15 @@ impl ScheduledInfo {
         scheduled_txs
     }
 
+    // all the references to the aborted transactions should have been dropped by `clear_write_units`
+    // and clearing the addresses, but fall back to copying the transaction rather than panicking.
+    fn _unwrap_aborted_tx(tx: Arc<Transaction>) -> AbortedTransaction {
+        AbortedTransaction::try_from(tx).unwrap_or_else(|e| {
+            warn!("{}", e);
+            AbortedTransaction::from_shared(&e.0)
+        })
+    }
+
     fn _schedule_aborted_txs(
         txs: Vec<Arc<Transaction>>,
         rayon: bool,
@@ -722,7 +2942,7 @@ impl ScheduledInfo {
             });
             aborted_txs = txs
                 .into_par_iter()
-                .map(AbortedTransaction::from)
+                .map(Self::_unwrap_aborted_tx)
                 .collect::<Vec<_>>();
         } else {
             txs.iter().for_each(|tx| {
@@ -731,12 +2951,12 @@ impl ScheduledInfo {
             });
             aborted_txs = txs
                 .into_iter()
-                .map(AbortedTransaction::from)
+                .map(Self::_unwrap_aborted_tx)
                 .collect::<Vec<_>>();
         };
 
-        // determine minimum #epoch in which tx have no conflicts with others --> by binary-search over a map (#epoch, writeset)
-        let mut epoch_map: Vec<hashbrown::HashSet<H256>> = vec![]; // (epoch, write set)
+        // determine minimum #epoch in which tx have no conflicts with others
+        let mut epoch_map = EpochMap::default();
 
         // store final schedule information
         let mut schedule: Vec<Vec<AbortedTransaction>> = vec![];
@@ -745,25 +2965,11 @@ impl ScheduledInfo {
             aborted_txs.sort_unstable_by_key(|tx| tx.id());
 
             for tx in aborted_txs.iter() {
-                let read_keys = tx.read_keys();
-                let write_keys = tx.write_keys();
-
-                let epoch = Self::_find_minimun_epoch_with_no_conflicts(
-                    &read_keys,
-                    &write_keys,
-                    &epoch_map,
-                );
+                let epoch = epoch_map.assign(tx.read_keys(), tx.write_keys().clone());
 
-                // update epoch_map & schedule
-                match epoch_map.get_mut(epoch) {
-                    Some(w_map) => {
-                        w_map.extend(write_keys);
-                        schedule[epoch].push(tx.clone());
-                    }
-                    None => {
-                        epoch_map.push(write_keys.clone());
-                        schedule.push(vec![tx.clone()]);
-                    }
+                match schedule.get_mut(epoch) {
+                    Some(txs) => txs.push(tx.clone()),
+                    None => schedule.push(vec![tx.clone()]),
                 };
             }
         }
@@ -771,26 +2977,45 @@ impl ScheduledInfo {
         schedule
     }
 
-    fn _find_minimun_epoch_with_no_conflicts(
-        read_keys_of_tx: &hashbrown::HashSet<H256>,
-        write_keys_of_tx: &hashbrown::HashSet<H256>,
-        epoch_map: &Vec<hashbrown::HashSet<H256>>,
-    ) -> usize {
-        // 1) ww dependencies are occured when the keys which are both read and written by latter tx are overlapped with the rw keys of the previous txs in the same epoch.
-        //   for simplicity, only single write is allowed for each key in the same epoch.
+    // assigns the epochs as `_schedule_aborted_txs` does, but clears each transaction right before its epoch is assigned
+    // and drops its keys right after, instead of holding them for every aborted transaction until the end.
+    fn _stream_aborted_txs(mut txs: Vec<Arc<Transaction>>) -> Vec<Vec<AbortedTransaction>> {
+        let mut epoch_map = EpochMap::default();
+        let mut schedule: Vec<Vec<AbortedTransaction>> = vec![];
+
+        if cfg!(not(feature = "disable-rescheduling")) {
+            txs.sort_unstable_by_key(|tx| tx.id());
 
-        // 2) anti-rw dependencies are occured when the read keys of latter tx are overlapped with the write keys of the previous txs in the same epoch.
-        let keys_of_tx = read_keys_of_tx
-            .union(write_keys_of_tx)
-            .cloned()
-            .collect::<hashbrown::HashSet<_>>();
+            for tx in txs {
+                tx.clear_write_units();
+                tx.init();
+                let mut tx = Self::_unwrap_aborted_tx(tx);
+                let (read_keys, write_keys) = tx.take_keys();
+                let epoch = epoch_map.assign(&read_keys, write_keys);
 
-        let mut epoch = 0;
-        while epoch_map.len() > epoch && !keys_of_tx.is_disjoint(&epoch_map[epoch]) {
-            epoch += 1;
+                match schedule.get_mut(epoch) {
+                    Some(txs) => txs.push(tx),
+                    None => schedule.push(vec![tx]),
+                };
+            }
         }
 
-        epoch
+        schedule
+    }
+
+    /// Flattens the schedule into the order in which transactions are committed:
+    /// scheduled generations first and then the aborted ones (to be re-executed), each sorted by tx id.
+    pub fn total_order(&self) -> Vec<u64> {
+        let scheduled = self
+            .scheduled_txs
+            .iter()
+            .flat_map(|txs| txs.iter().map(|tx| tx.id()).sorted_unstable());
+        let aborted = self
+            .aborted_txs
+            .iter()
+            .flat_map(|txs| txs.iter().map(|tx| tx.id()).sorted_unstable());
+
+        scheduled.chain(aborted).collect()
     }
 
     pub fn scheduled_txs_len(&self) -> usize {
@@ -801,8 +3026,51 @@ impl ScheduledInfo {
         self.aborted_txs.iter().map(|vec| vec.len()).sum()
     }
 
+    /// Appends the schedule of the next window (see `prepare_execution`), for a whole-block view.
+    /// The generations (and the epochs of aborted transactions) of `other` follow the ones of `self`,
+    /// i.e., its sequences are offset by the number of generations of `self`.
+    pub fn append(&mut self, other: ScheduledInfo) {
+        let ScheduledInfo {
+            mut scheduled_txs,
+            aborted_txs,
+        } = other;
+
+        let offset = self.scheduled_txs.len() as u32;
+        scheduled_txs
+            .iter_mut()
+            .flatten()
+            .for_each(|tx| tx.offset_seq(offset));
+        self.scheduled_txs.extend(scheduled_txs);
+        self.aborted_txs.extend(aborted_txs);
+    }
+
+    /// Dumps the schedule, i.e., the tx ids of each generation and of each epoch of aborted transactions, to a JSON file.
+    #[cfg(feature = "schedule-dump")]
+    pub fn write_json(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        let dump = ScheduleDump {
+            scheduled_txs: self
+                .scheduled_txs
+                .iter()
+                .map(|txs| txs.iter().map(|tx| tx.id()).sorted_unstable().collect())
+                .collect(),
+            aborted_txs: self
+                .aborted_txs
+                .iter()
+                .map(|txs| txs.iter().map(|tx| tx.id()).sorted_unstable().collect())
+                .collect(),
+        };
+
+        let writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+        serde_json::to_writer_pretty(writer, &dump).map_err(std::io::Error::from)
+    }
+
     pub fn parallism_metric(&self) -> (usize, f64, f64, usize, usize) {
         let total_tx = self.scheduled_txs_len() + self.aborted_txs_len();
+        if self.scheduled_txs.is_empty() {
+            // every transaction aborted; zeroed widths instead of NaN, which would poison the aggregations.
+            return (total_tx, 0.0, 0.0, 0, 0);
+        }
+
         let max_width = self
             .scheduled_txs
             .iter()
@@ -0,0 +1,70 @@
+//! The set primitives of the scheduling over the keys of transactions: the disjointness check, and the epoch
+//! assignment of the aborted transactions. They only depend on `core` and `hashbrown` (i.e., neither on the async
+//! runtime, rayon nor the EVM). The conflict graph itself (see [`crate::AddressBasedConflictGraph`]) is not here,
+//! since it is built over the simulated transactions and in parallel, and the crate has no feature to build
+//! this module alone. Keys are generic, and are the (hashed) storage keys of the rw-sets in the executor.
+
+use core::hash::Hash;
+
+use hashbrown::HashSet;
+
+/// Whether the two sets share no key, iterating over the smaller one.
+#[inline]
+pub fn is_disjoint<K>(left: &HashSet<K>, right: &HashSet<K>) -> bool
+where
+    K: Eq + Hash,
+{
+    (left.len() <= right.len() && left.is_disjoint(right))
+        || (left.len() > right.len() && right.is_disjoint(left))
+}
+
+/// The write keys of each epoch the aborted transactions are assigned to, in the order of epochs.
+#[derive(Clone, Debug)]
+pub struct EpochMap<K> {
+    epochs: Vec<HashSet<K>>,
+}
+
+impl<K> Default for EpochMap<K> {
+    fn default() -> Self {
+        Self { epochs: Vec::new() }
+    }
+}
+
+impl<K: Eq + Hash> EpochMap<K> {
+    /// The first epoch none of whose write keys the transaction reads or writes, or a new epoch if there is none.
+    ///
+    /// 1) ww dependencies occur when the keys read and written by a latter transaction overlap with the write keys
+    ///    of the previous transactions in the same epoch. For simplicity, only a single write is allowed for each key in an epoch.
+    /// 2) anti-rw dependencies occur when the read keys of a latter transaction overlap with the write keys
+    ///    of the previous transactions in the same epoch.
+    pub fn min_epoch_without_conflicts(
+        &self,
+        read_keys: &HashSet<K>,
+        write_keys: &HashSet<K>,
+    ) -> usize {
+        self.epochs
+            .iter()
+            .position(|epoch| is_disjoint(read_keys, epoch) && is_disjoint(write_keys, epoch))
+            .unwrap_or(self.epochs.len())
+    }
+
+    /// Assigns the transaction to its minimum epoch (see [`Self::min_epoch_without_conflicts`]),
+    /// whose write keys are extended with the ones of the transaction, and returns the epoch.
+    pub fn assign(&mut self, read_keys: &HashSet<K>, write_keys: HashSet<K>) -> usize {
+        let epoch = self.min_epoch_without_conflicts(read_keys, &write_keys);
+        match self.epochs.get_mut(epoch) {
+            Some(epoch_keys) => epoch_keys.extend(write_keys),
+            None => self.epochs.push(write_keys),
+        }
+        epoch
+    }
+
+    /// The number of epochs.
+    pub fn len(&self) -> usize {
+        self.epochs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.epochs.is_empty()
+    }
+}
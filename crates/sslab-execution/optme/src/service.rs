@@ -0,0 +1,93 @@
+use ethers_core::{
+    types::{H160, H256},
+    utils::keccak256,
+};
+use evm::backend::Backend as _;
+use itertools::Itertools;
+use rayon::prelude::*;
+use sslab_execution::{
+    evm_storage::ConcurrentEVMStorage,
+    types::{ExecutableEthereumBatch, ExecutionResult},
+};
+
+use crate::{utils::run_on_rayon, ConcurrencyLevelManager, OptmeError, SimulationResult};
+
+/// The single integration point for external services (e.g., an RPC server) to drive and query the execution,
+/// without depending on the internal types of the manager. It is transport-agnostic on purpose.
+pub struct ExecutionService {
+    manager: ConcurrencyLevelManager,
+}
+
+impl ExecutionService {
+    pub fn new(manager: ConcurrencyLevelManager) -> Self {
+        Self { manager }
+    }
+
+    /// Executes and commits the given batches, in order. They commit fully or not at all.
+    pub async fn submit(
+        &self,
+        batches: Vec<ExecutableEthereumBatch>,
+    ) -> Result<ExecutionResult, OptmeError> {
+        self.manager.prepare_execution(batches).await
+    }
+
+    /// Simulates the given batches over the committed state, without committing them.
+    pub async fn simulate(
+        &self,
+        batches: Vec<ExecutableEthereumBatch>,
+    ) -> Result<SimulationResult, OptmeError> {
+        self.manager.simulate(batches).await
+    }
+
+    /// Reads a storage slot of the committed state.
+    pub async fn get_state(&self, address: H160, key: H256) -> H256 {
+        self.manager
+            .global_state()
+            .get_storage()
+            .storage(address, key)
+    }
+
+    /// A digest of the committed state (see [`state_root`]).
+    pub async fn state_root(&self) -> H256 {
+        let snapshot = self.manager.global_state();
+
+        run_on_rayon(move || state_root(&snapshot)).await.unwrap()
+    }
+}
+
+/// keccak256 over the hashes of every account (address, nonce, balance, code hash and storage) in the order of addresses.
+/// Note that it is NOT the Merkle-Patricia root of Ethereum.
+pub fn state_root(storage: &ConcurrentEVMStorage) -> H256 {
+    let state = storage.get_storage().state().pin();
+    let accounts = state
+        .iter()
+        .sorted_unstable_by_key(|(address, _)| **address)
+        .collect_vec();
+
+    let leaves = accounts
+        .into_par_iter()
+        .map(|(address, account)| {
+            let mut buf = address.as_bytes().to_vec();
+            let mut word = [0u8; 32];
+            account.nonce.to_big_endian(&mut word);
+            buf.extend_from_slice(&word);
+            account.balance.to_big_endian(&mut word);
+            buf.extend_from_slice(&word);
+            buf.extend_from_slice(&keccak256(&account.code));
+
+            let storage = account.storage.pin();
+            storage
+                .iter()
+                .sorted_unstable_by_key(|(key, _)| **key)
+                .for_each(|(key, value)| {
+                    buf.extend_from_slice(key.as_bytes());
+                    buf.extend_from_slice(value.as_bytes());
+                });
+
+            keccak256(buf)
+        })
+        .collect::<Vec<_>>()
+        .concat();
+
+    H256::from(keccak256(leaves))
+}
@@ -0,0 +1,86 @@
+use std::borrow::Cow;
+
+use evm::{ExitError, Opcode};
+use itertools::Itertools;
+use sslab_execution::utils::smallbank_contract_benchmark::concurrent_evm_storage;
+
+use super::test_utils::get_smallbank_handler;
+use crate::{
+    codec::SerdeOutcome,
+    optme_core::{ConcurrencyLevelManager, ScheduledInfo},
+    AddressBasedConflictGraph, SimulationOutcome, SimulationResult,
+};
+
+#[tokio::test]
+async fn test_cached_simulation_schedules_identically() {
+    let handler = get_smallbank_handler();
+    let optme = ConcurrencyLevelManager::new(concurrent_evm_storage(), 1);
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("simulation.json");
+    let schedule = |result: SimulationResult| {
+        let ScheduledInfo {
+            scheduled_txs,
+            aborted_txs,
+        } = AddressBasedConflictGraph::construct(result.rw_sets)
+            .hierarchcial_sort()
+            .reorder()
+            .extract_schedule();
+        let ids = |txs: Vec<Vec<u64>>| {
+            txs.into_iter()
+                .map(|txs| txs.into_iter().sorted_unstable().collect_vec())
+                .collect_vec()
+        };
+        (
+            ids(scheduled_txs
+                .iter()
+                .map(|txs| txs.iter().map(|tx| tx.id()).collect())
+                .collect()),
+            ids(aborted_txs
+                .iter()
+                .map(|txs| txs.iter().map(|tx| tx.id()).collect())
+                .collect()),
+        )
+    };
+
+    //given
+    let simulated = optme
+        .simulate(handler.create_batches(100, 1, 0.7, 1_000))
+        .await
+        .unwrap();
+
+    //when
+    simulated.save(&path).unwrap();
+    let loaded = SimulationResult::load(&path).unwrap();
+
+    //then
+    assert_eq!(loaded.digests, simulated.digests);
+    assert_eq!(loaded.rw_sets.len(), simulated.rw_sets.len());
+    loaded
+        .rw_sets
+        .iter()
+        .zip(simulated.rw_sets.iter())
+        .for_each(|(loaded, simulated)| {
+            assert_eq!(loaded.id(), simulated.id());
+            assert_eq!(loaded.read_set(), simulated.read_set());
+            assert_eq!(loaded.write_set(), simulated.write_set());
+            assert_eq!(loaded.gas_used(), simulated.gas_used());
+            assert_eq!(loaded.outcome(), simulated.outcome());
+        });
+    assert_eq!(schedule(loaded), schedule(simulated));
+}
+
+#[test]
+fn test_failed_outcome_keeps_its_exit_error() {
+    for outcome in [
+        SimulationOutcome::Failed(ExitError::OutOfGas),
+        SimulationOutcome::Failed(ExitError::InvalidCode(Opcode(0xfe))),
+        SimulationOutcome::Failed(ExitError::Other(Cow::from("custom"))),
+    ] {
+        //when
+        let encoded = serde_json::to_string(&SerdeOutcome::from(&outcome)).unwrap();
+        let decoded: SerdeOutcome = serde_json::from_str(&encoded).unwrap();
+
+        //then
+        assert_eq!(SimulationOutcome::from(decoded), outcome);
+    }
+}
@@ -1,24 +1,58 @@
+use std::{collections::BTreeMap, str::FromStr, sync::Arc, time::Duration};
+
+use ethers_core::{
+    abi::{self, Token},
+    types::{
+        transaction::{
+            eip2718::TypedTransaction,
+            eip2930::{AccessList, AccessListItem, Eip2930TransactionRequest},
+        },
+        TransactionRequest, H160, H256, U256, U64,
+    },
+};
 use ethers_providers::{MockProvider, Provider};
-use narwhal_types::BatchDigest;
+use evm::{
+    backend::{Apply, Backend, Basic},
+    executor::stack::RwSet,
+};
+use itertools::Itertools;
+use narwhal_types::{Batch, BatchDigest, BatchV1};
 use sslab_execution::{
-    types::ExecutableEthereumBatch,
+    evm_storage::{
+        backend::{CAccount, ConcurrentHashMap, ExecutionBackend},
+        ConcurrentEVMStorage,
+    },
+    executor::{Executable, TeeExecutor},
+    types::{EthereumTransaction, ExecutableEthereumBatch, IndexedEthereumTransaction, SpecId},
     utils::{
-        smallbank_contract_benchmark::concurrent_evm_storage,
-        test_utils::{SmallBankTransactionHandler, DEFAULT_CHAIN_ID},
+        smallbank_contract_benchmark::{
+            concurrent_evm_storage, ADMIN_ADDRESS, DEFAULT_CONTRACT_ADDRESS,
+        },
+        test_utils::{small_bank::SmallBank, ContentionPattern},
     },
 };
+use sslab_execution_blockstm::BlockSTM;
+use sslab_execution_serial::SerialExecutor;
 use tokio::time::Instant;
 
-use crate::{optme_core::ConcurrencyLevelManager, AddressBasedConflictGraph, SimulationResult};
-
-fn get_smallbank_handler() -> SmallBankTransactionHandler {
-    let provider = Provider::<MockProvider>::new(MockProvider::default());
-    SmallBankTransactionHandler::new(provider, DEFAULT_CHAIN_ID)
-}
-
-fn get_optme_executor() -> ConcurrencyLevelManager {
-    ConcurrencyLevelManager::new(concurrent_evm_storage(), 10)
-}
+use super::test_utils::{
+    assert_serial_equivalence, assert_serializable, counter_address, counter_storage,
+    deploy_copier_contract, deploy_counter_contract, deploy_setter_contract, finalized_write,
+    get_optme_executor, get_smallbank_handler, increment_transaction, reverting_transaction,
+    set_transaction, signed_increment_transaction, skewed_counter_workload, smallbank_storage,
+    two_windows_of_increments,
+};
+use crate::{
+    optme_core::{ConcurrencyLevelManager, Fault, ScheduledInfo},
+    service::state_root,
+    types::{
+        balance_key, BatchRoute, Committable, FinalizedTransaction, OptmeError, PreImages,
+        ReExecutedTransaction, ReceiptCollector, ScheduledTransaction,
+    },
+    utils::RayonError,
+    AddressBasedConflictGraph, ConflictGranularity, ExecutionService, FirstPassMode, OptME,
+    PhaseTimings, SimSummary, SimulatedTransaction, SimulationOutcome, SimulationResult,
+};
 
 /* this test is for debuging optme algorithm under a smallbank workload */
 #[tokio::test]
@@ -42,7 +76,7 @@ async fn test_smallbank() {
     //when
     let total = Instant::now();
     let mut now = Instant::now();
-    let SimulationResult { rw_sets, .. } = optme.simulate(consensus_output).await;
+    let SimulationResult { rw_sets, .. } = optme.simulate(consensus_output).await.unwrap();
     let mut time = now.elapsed().as_millis();
     println!(
         "Simulation took {} ms for {} transactions.",
@@ -62,7 +96,10 @@ async fn test_smallbank() {
     let aborted_tx_len = scheduled_info.aborted_txs_len();
 
     now = Instant::now();
-    optme._concurrent_commit(scheduled_info.scheduled_txs).await;
+    optme
+        ._concurrent_commit(scheduled_info.scheduled_txs)
+        .await
+        .unwrap();
     time = now.elapsed().as_millis();
 
     println!(
@@ -100,7 +137,7 @@ async fn test_par_smallbank() {
     //when
     let total = Instant::now();
     let mut now = Instant::now();
-    let SimulationResult { rw_sets, .. } = optme.simulate(consensus_output).await;
+    let SimulationResult { rw_sets, .. } = optme.simulate(consensus_output).await.unwrap();
     let mut time = now.elapsed().as_millis();
     println!(
         "Simulation took {} ms for {} transactions.",
@@ -122,7 +159,10 @@ async fn test_par_smallbank() {
     let aborted_tx_len = scheduled_info.aborted_txs_len();
 
     now = Instant::now();
-    optme._concurrent_commit(scheduled_info.scheduled_txs).await;
+    optme
+        ._concurrent_commit(scheduled_info.scheduled_txs)
+        .await
+        .unwrap();
     time = now.elapsed().as_millis();
 
     println!(
@@ -159,7 +199,2948 @@ async fn test_par_smallbank_for_advanced_optme() {
 
     //when
     let now = Instant::now();
-    let _ = optme._execute(consensus_output).await;
+    optme._execute(consensus_output).await.unwrap();
     let time = now.elapsed().as_millis();
     println!("execution took {} ms", time);
 }
+
+#[tokio::test]
+async fn test_zero_gas_transactions_are_skipped() {
+    let optme = get_optme_executor();
+    let handler = get_smallbank_handler();
+    let without_gas = |gas: Option<U256>| {
+        let mut tx = reverting_transaction();
+        if let TypedTransaction::Legacy(request) = &mut tx.0 {
+            request.gas = gas;
+        }
+        tx
+    };
+
+    //given (a transaction with a zero gas limit, and one without any)
+    let txs = vec![
+        handler.random_operation(0.0, 1_000),
+        without_gas(Some(U256::zero())),
+        without_gas(None),
+        handler.random_operation(0.0, 1_000),
+    ];
+    let consensus_output = vec![ExecutableEthereumBatch::new(txs, BatchDigest::default())];
+
+    //when
+    let SimulationResult { rw_sets, .. } = optme.simulate(consensus_output.clone()).await.unwrap();
+
+    //then (they are skipped before simulation, and the rest keep contiguous ids)
+    assert_eq!(rw_sets.iter().map(|tx| tx.id()).collect_vec(), vec![0, 1]);
+    assert_eq!(
+        optme._execute(consensus_output).await.unwrap(),
+        vec![BatchDigest::default()]
+    );
+}
+
+#[tokio::test]
+async fn test_transactions_of_other_chains_are_skipped() {
+    let counter = counter_address();
+    let optme = ConcurrencyLevelManager::new(counter_storage(), 10).with_chain_id(9);
+    let signed_for = |slot: u64, chain_id: Option<u64>| {
+        let mut tx = increment_transaction(1, counter, slot);
+        if let TypedTransaction::Legacy(request) = &mut tx.0 {
+            request.chain_id = chain_id.map(U64::from);
+        }
+        tx
+    };
+
+    //given (a transaction of another chain, and one without chain id)
+    let txs = vec![
+        signed_for(0, Some(9)),
+        signed_for(1, Some(1)),
+        signed_for(2, None),
+        signed_for(3, Some(9)),
+    ];
+    let consensus_output = vec![ExecutableEthereumBatch::new(txs, BatchDigest::default())];
+
+    //when
+    let SimulationResult { rw_sets, .. } = optme.simulate(consensus_output).await.unwrap();
+
+    //then (they are skipped before simulation, and the rest keep contiguous ids)
+    assert_eq!(rw_sets.iter().map(|tx| tx.id()).collect_vec(), vec![0, 1]);
+    assert_eq!(
+        rw_sets
+            .iter()
+            .map(|tx| tx.raw_tx().data().chain_id())
+            .collect_vec(),
+        vec![Some(9), Some(9)]
+    );
+}
+
+#[tokio::test]
+async fn test_simulation_preserves_tx_ids_with_failed_transactions() {
+    let optme = get_optme_executor();
+    let handler = get_smallbank_handler();
+
+    //given (every third transaction reverts during simulation)
+    let batch_size = 30;
+    let block_concurrency = 3;
+    let mut consensus_output = Vec::new();
+    let mut reverted_ids = hashbrown::HashSet::new();
+    for batch_idx in 0..block_concurrency {
+        let mut tmp = Vec::new();
+        for i in 0..batch_size {
+            if i % 3 == 0 {
+                reverted_ids.insert((batch_idx * batch_size + i) as u64);
+                tmp.push(reverting_transaction());
+            } else {
+                tmp.push(handler.random_operation(0.0, 1_000));
+            }
+        }
+        consensus_output.push(ExecutableEthereumBatch::new(tmp, BatchDigest::default()));
+    }
+
+    //when
+    let SimulationResult { rw_sets, .. } = optme.simulate(consensus_output).await.unwrap();
+
+    //then (reverted transactions are kept without effects, and every surviving transaction
+    //      keeps its original id, in the original order)
+    assert!(!rw_sets.is_empty());
+    assert!(rw_sets.len() <= batch_size * block_concurrency);
+    assert_eq!(
+        rw_sets
+            .iter()
+            .filter(|tx| reverted_ids.contains(&tx.id()))
+            .filter(|tx| matches!(tx.outcome(), SimulationOutcome::Reverted(_)))
+            .count(),
+        reverted_ids.len()
+    );
+    assert!(rw_sets.iter().all(|tx| tx.id() == tx.raw_tx().id));
+    assert!(rw_sets.windows(2).all(|w| w[0].id() < w[1].id()));
+
+    //then (scheduling is keyed by id, so the gaps do not affect it)
+    let simulated_ids = rw_sets
+        .iter()
+        .map(|tx| tx.id())
+        .collect::<hashbrown::HashSet<_>>();
+    let ScheduledInfo {
+        scheduled_txs,
+        aborted_txs,
+    } = AddressBasedConflictGraph::construct(rw_sets)
+        .hierarchcial_sort()
+        .reorder()
+        .extract_schedule();
+
+    let mut scheduled_ids = scheduled_txs
+        .iter()
+        .flatten()
+        .map(|tx| tx.id())
+        .chain(aborted_txs.iter().flatten().map(|tx| tx.id()))
+        .collect::<Vec<_>>();
+    let total = scheduled_ids.len();
+    scheduled_ids.sort_unstable();
+    scheduled_ids.dedup();
+
+    assert_eq!(total, scheduled_ids.len());
+    assert_eq!(
+        simulated_ids,
+        scheduled_ids.into_iter().collect::<hashbrown::HashSet<_>>()
+    );
+}
+
+#[tokio::test]
+async fn test_tee_serial_against_optme() {
+    let handler = get_smallbank_handler();
+
+    //given (OptME shadows the serial executor on its own copy of the state)
+    let canonical_state = Arc::new(concurrent_evm_storage());
+    let serial = SerialExecutor::new(canonical_state.clone());
+    let optme = OptME::new(canonical_state.snapshot(), 10);
+    let shadow_state = optme.global_state();
+    let tee = TeeExecutor::new(serial, canonical_state, optme, shadow_state);
+
+    let consensus_output = handler.create_batches(10, 2, 0.0, 100_000);
+
+    //when
+    tee.execute(consensus_output).await;
+
+    //then
+    assert_eq!(tee.divergences(), 0);
+}
+
+#[tokio::test]
+async fn test_tee_serial_against_optme_on_counters() {
+    //given (every transaction has its own sender, so the storage conflicts are the only ones)
+    let counter = counter_address();
+    let storage = counter_storage();
+
+    let canonical_state = Arc::new(storage);
+    let serial = SerialExecutor::new(canonical_state.clone());
+    let optme = OptME::new(canonical_state.snapshot(), 10);
+    let shadow_state = optme.global_state();
+    let tee = TeeExecutor::new(serial, canonical_state, optme, shadow_state);
+
+    let consensus_output = (0..2)
+        .map(|batch| {
+            ExecutableEthereumBatch::new(
+                (0..10)
+                    .map(|i| increment_transaction(batch * 10 + i + 1, counter, i % 4))
+                    .collect(),
+                BatchDigest::default(),
+            )
+        })
+        .collect();
+
+    //when
+    tee.execute(consensus_output).await;
+
+    //then
+    assert_eq!(tee.divergences(), 0);
+}
+
+#[tokio::test]
+async fn test_warm_up() {
+    let optme = get_optme_executor();
+    let handler = get_smallbank_handler();
+
+    //when
+    let touched = optme
+        .warm_up(Some(vec![
+            H160::from_str(DEFAULT_CONTRACT_ADDRESS).unwrap(),
+            H160::from_str(ADMIN_ADDRESS).unwrap(),
+            H160::from_low_u64_be(0xdead),
+        ]))
+        .await
+        .unwrap();
+    optme.warm_up(None).await.unwrap();
+
+    //then (only the pre-deployed accounts exist)
+    assert_eq!(touched, 2);
+
+    let consensus_output = handler.create_batches(10, 1, 0.0, 1_000);
+    let SimulationResult { rw_sets, .. } = optme.simulate(consensus_output).await.unwrap();
+    assert!(!rw_sets.is_empty());
+}
+
+#[tokio::test]
+async fn test_simulation_captures_revert_reason() {
+    //given (a contract which always reverts with `Error("not enough balance")`)
+    let reason = [0x08, 0xc3, 0x79, 0xa0] // selector of Error(string)
+        .into_iter()
+        .chain(abi::encode(&[Token::String("not enough balance".into())]))
+        .collect::<Vec<u8>>();
+    let len = reason.len() as u8;
+    let mut code = vec![
+        0x60, len, 0x60, 0x0c, 0x60, 0x00, 0x39, // CODECOPY(0, 12, len)
+        0x60, len, 0x60, 0x00, 0xfd, // REVERT(0, len)
+    ];
+    code.extend(reason.clone());
+
+    let reverting_contract = H160::from_low_u64_be(0xbad);
+    let storage = concurrent_evm_storage();
+    storage.get_storage().state().pin().insert(
+        reverting_contract,
+        CAccount {
+            nonce: U256::one(),
+            balance: U256::zero(),
+            storage: ConcurrentHashMap::default(),
+            code,
+        },
+    );
+    let optme = ConcurrencyLevelManager::new(storage, 10);
+
+    let tx = TransactionRequest::new()
+        .from(H160::from_str(ADMIN_ADDRESS).unwrap())
+        .to(reverting_contract)
+        .data(vec![])
+        .value(U256::zero())
+        .nonce(U256::zero())
+        .gas(u64::MAX)
+        .gas_price(U256::zero());
+    let consensus_output = vec![ExecutableEthereumBatch::new(
+        vec![EthereumTransaction(TypedTransaction::Legacy(tx))],
+        BatchDigest::default(),
+    )];
+
+    //when
+    let SimulationResult { rw_sets, .. } = optme.simulate(consensus_output).await.unwrap();
+
+    //then
+    assert_eq!(rw_sets.len(), 1);
+    assert_eq!(
+        rw_sets[0].outcome(),
+        &SimulationOutcome::Reverted(reason.into())
+    );
+    assert!(rw_sets[0].write_set().is_empty());
+}
+
+#[tokio::test]
+async fn test_simulation_groups_logs_by_address() {
+    //given (a contract which emits an empty LOG0 on every call)
+    let logging_contract = H160::from_low_u64_be(0x106);
+    let storage = concurrent_evm_storage();
+    storage.get_storage().state().pin().insert(
+        logging_contract,
+        CAccount {
+            nonce: U256::one(),
+            balance: U256::zero(),
+            storage: ConcurrentHashMap::default(),
+            code: vec![0x60, 0x00, 0x60, 0x00, 0xa0, 0x00], // LOG0(0, 0); STOP
+        },
+    );
+    let optme = ConcurrencyLevelManager::new(storage, 10);
+
+    let tx = TransactionRequest::new()
+        .from(H160::from_str(ADMIN_ADDRESS).unwrap())
+        .to(logging_contract)
+        .data(vec![])
+        .value(U256::zero())
+        .nonce(U256::zero())
+        .gas(u64::MAX)
+        .gas_price(U256::zero());
+    let consensus_output = vec![ExecutableEthereumBatch::new(
+        vec![
+            EthereumTransaction(TypedTransaction::Legacy(tx.clone())),
+            EthereumTransaction(TypedTransaction::Legacy(tx)),
+        ],
+        BatchDigest::default(),
+    )];
+
+    //when
+    let result = optme.simulate(consensus_output).await.unwrap();
+
+    //then
+    assert!(result.rw_sets.iter().all(|tx| tx.logs().len() == 1));
+
+    let logs = result.logs_by_address();
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[&logging_contract].len(), 2);
+    assert!(logs[&logging_contract]
+        .iter()
+        .all(|log| log.topics.is_empty() && log.data.is_empty()));
+}
+
+#[tokio::test]
+async fn test_storage_read_your_writes_across_generations() {
+    //given (slots 1~3 are written once, while slot 0 is incremented by three transactions,
+    //       two of which are re-executed in later generations on top of the committed writes)
+    let counter = counter_address();
+    let storage = counter_storage();
+    let optme = OptME::new(storage, 10);
+
+    let consensus_output = vec![ExecutableEthereumBatch::new(
+        [0, 1, 2, 3, 0, 0]
+            .into_iter()
+            .enumerate()
+            .map(|(i, slot)| increment_transaction(i as u64 + 1, counter, slot))
+            .collect(),
+        BatchDigest::default(),
+    )];
+
+    //when
+    optme.execute(consensus_output).await;
+
+    //then
+    let state = optme.global_state();
+    let slot = |key: u64| {
+        state
+            .get_storage()
+            .storage(counter, H256::from_low_u64_be(key))
+    };
+    assert_eq!(slot(0), H256::from_low_u64_be(3));
+    assert!((1..4).all(|key| slot(key) == H256::from_low_u64_be(1)));
+}
+
+#[tokio::test]
+async fn test_storage_commits_generation_on_top_of_previous_one() {
+    //given (generation 2 reads the slot written by generation 1)
+    let counter = counter_address();
+    let storage = counter_storage();
+    let state = Arc::new(storage);
+
+    //when
+    for (generation, sender) in [(1, 1), (2, 2)] {
+        let tx = increment_transaction(sender, counter, 0);
+        let (outcome, effect, ..) =
+            crate::evm_utils::simulate_tx(&tx, state.as_ref(), state.config()).unwrap();
+        assert!(outcome.is_success());
+        state.apply_local_effect(effect);
+
+        //then
+        assert_eq!(
+            state.get_storage().storage(counter, H256::zero()),
+            H256::from_low_u64_be(generation)
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_simulate_transaction_from_typed() {
+    //given (a transaction built with ethers, without round-tripping through rlp bytes)
+    let counter = counter_address();
+    let storage = counter_storage();
+    let optme = ConcurrencyLevelManager::new(storage, 10);
+
+    let typed: TypedTransaction = TransactionRequest::new()
+        .from(H160::from_low_u64_be(1))
+        .to(counter)
+        .data(H256::zero().as_bytes().to_vec())
+        .value(U256::zero())
+        .nonce(U256::zero())
+        .gas(u64::MAX)
+        .gas_price(U256::zero())
+        .into();
+    let tx = EthereumTransaction::from_typed(typed.clone());
+    assert_eq!(tx.as_typed(), &typed);
+
+    //when
+    let SimulationResult { rw_sets, .. } = optme
+        .simulate(vec![ExecutableEthereumBatch::new(
+            vec![tx],
+            BatchDigest::default(),
+        )])
+        .await
+        .unwrap();
+
+    //then
+    assert_eq!(rw_sets.len(), 1);
+    assert!(rw_sets[0].outcome().is_success());
+    assert_eq!(rw_sets[0].raw_tx().tx.clone().into_typed(), typed);
+    assert!(rw_sets[0].write_set().contains(&H256::zero()));
+}
+
+#[tokio::test]
+async fn test_account_level_conflict_granularity() {
+    //given (transactions touching distinct slots of the same contract)
+    let counter = counter_address();
+    let consensus_output = || {
+        vec![ExecutableEthereumBatch::new(
+            (0..4)
+                .map(|slot| increment_transaction(slot + 1, counter, slot))
+                .collect(),
+            BatchDigest::default(),
+        )]
+    };
+    let schedule = |granularity| async move {
+        let storage = counter_storage();
+        let optme =
+            ConcurrencyLevelManager::new(storage, 10).with_conflict_granularity(granularity);
+
+        let SimulationResult { rw_sets, .. } = optme.simulate(consensus_output()).await.unwrap();
+        AddressBasedConflictGraph::par_construct(rw_sets)
+            .await
+            .hierarchcial_sort()
+            .reorder()
+            .par_extract_schedule()
+            .await
+    };
+
+    //when
+    let slot_level = schedule(ConflictGranularity::Slot).await;
+    let account_level = schedule(ConflictGranularity::Account).await;
+
+    //then (slot-level runs them all at once, while account-level serializes them)
+    assert_eq!(slot_level.scheduled_txs.len(), 1);
+    assert_eq!(slot_level.scheduled_txs_len(), 4);
+    assert_eq!(account_level.scheduled_txs_len(), 1);
+    assert_eq!(account_level.aborted_txs_len(), 3);
+}
+
+#[tokio::test]
+async fn test_slow_simulation() {
+    //given (a contract counting down from 1,000,000 before storing to its slot 0)
+    let looping_contract = H160::from_low_u64_be(0x112);
+    let counter = counter_address();
+    let storage = counter_storage();
+    storage.get_storage().state().pin().insert(
+        looping_contract,
+        CAccount {
+            nonce: U256::one(),
+            balance: U256::zero(),
+            storage: ConcurrentHashMap::default(),
+            // PUSH3 1000000; JUMPDEST; PUSH1 1; SWAP1; SUB; DUP1; PUSH1 4; JUMPI; PUSH1 1; PUSH1 0; SSTORE; STOP
+            code: vec![
+                0x62, 0x0f, 0x42, 0x40, 0x5b, 0x60, 0x01, 0x90, 0x03, 0x80, 0x60, 0x04, 0x57, 0x60,
+                0x01, 0x60, 0x00, 0x55, 0x00,
+            ],
+        },
+    );
+    let optme = ConcurrencyLevelManager::new(storage, 10)
+        .with_slow_simulation_threshold(Duration::from_millis(20));
+
+    let slow_tx = TransactionRequest::new()
+        .from(H160::from_low_u64_be(2))
+        .to(looping_contract)
+        .data(vec![])
+        .value(U256::zero())
+        .nonce(U256::zero())
+        .gas(30_000_000u64)
+        .gas_price(U256::zero());
+    let consensus_output = vec![ExecutableEthereumBatch::new(
+        vec![
+            increment_transaction(1, counter, 0),
+            EthereumTransaction(TypedTransaction::Legacy(slow_tx)),
+        ],
+        BatchDigest::default(),
+    )];
+
+    //when
+    let SimulationResult { rw_sets, .. } = optme.simulate(consensus_output).await.unwrap();
+
+    //then
+    assert_eq!(rw_sets.len(), 2);
+    assert!(rw_sets[0].outcome().is_success());
+    assert!(matches!(
+        rw_sets[1].outcome(),
+        SimulationOutcome::Slow(elapsed) if *elapsed > Duration::from_millis(20)
+    ));
+    // (the slow transaction is interrupted before it stores to its slot)
+    assert!(rw_sets[1].write_set().is_empty());
+    assert!(rw_sets[1].effects().is_empty());
+}
+
+#[tokio::test]
+async fn test_managers_share_the_global_state() {
+    //given
+    let counter = counter_address();
+    let storage = counter_storage();
+    let shared = Arc::new(storage);
+    let optme = ConcurrencyLevelManager::new_with_state(shared.clone(), 10);
+    let shadow = ConcurrencyLevelManager::new_with_state(shared.clone(), 10);
+
+    //when
+    optme
+        ._concurrent_commit(vec![vec![finalized_write(0, counter, 0, 1)]])
+        .await
+        .unwrap();
+
+    //then
+    assert!(Arc::ptr_eq(&optme.global_state(), &shadow.global_state()));
+    assert_eq!(
+        shadow
+            .global_state()
+            .get_storage()
+            .storage(counter, H256::from_low_u64_be(0)),
+        H256::from_low_u64_be(1)
+    );
+}
+
+#[tokio::test]
+async fn test_chunked_commit_matches_per_tx_commit() {
+    let counter = counter_address();
+    let storage = || {
+        let storage = counter_storage();
+        storage
+    };
+    let generations = || {
+        (0..4)
+            .map(|g| {
+                (0..1_000)
+                    .map(|slot| finalized_write(g * 1_000 + slot, counter, slot, g + 1))
+                    .collect_vec()
+            })
+            .collect_vec()
+    };
+
+    //given
+    let per_tx = ConcurrencyLevelManager::new(storage(), 10);
+    let chunked = ConcurrencyLevelManager::new(storage(), 10).with_commit_chunk_len(64);
+
+    //when
+    per_tx._concurrent_commit(generations()).await.unwrap();
+    chunked._concurrent_commit(generations()).await.unwrap();
+
+    //then
+    assert_eq!(
+        state_root(&chunked.global_state()),
+        state_root(&per_tx.global_state())
+    );
+    assert_eq!(
+        chunked
+            .global_state()
+            .get_storage()
+            .storage(counter, H256::from_low_u64_be(999)),
+        H256::from_low_u64_be(4)
+    );
+}
+
+#[tokio::test]
+async fn test_commit_merges_disjoint_generations() {
+    let counter = counter_address();
+    let storage = counter_storage();
+    let optme = ConcurrencyLevelManager::new(storage, 10);
+
+    let generations = || {
+        vec![
+            vec![finalized_write(0, counter, 0, 1)],
+            vec![finalized_write(1, counter, 1, 1)], // disjoint with the 1st generation
+            vec![finalized_write(2, counter, 0, 2)], // overwrites the 1st generation
+        ]
+    };
+
+    //when
+    let merged = ConcurrencyLevelManager::_merge_disjoint_generations(generations());
+    optme._concurrent_commit(generations()).await.unwrap();
+
+    //then
+    assert_eq!(
+        merged
+            .iter()
+            .map(|txs| txs.iter().map(|tx| tx.id()).collect::<Vec<_>>())
+            .collect::<Vec<_>>(),
+        vec![vec![0, 1], vec![2]]
+    );
+
+    let state = optme.global_state();
+    let slot = |key: u64| {
+        state
+            .get_storage()
+            .storage(counter, H256::from_low_u64_be(key))
+    };
+    assert_eq!(slot(0), H256::from_low_u64_be(2));
+    assert_eq!(slot(1), H256::from_low_u64_be(1));
+}
+
+#[tokio::test]
+async fn test_commit_merged_generations_rewriting_and_writing_an_account_in_place() {
+    const SLOTS: u64 = 100;
+    let counter = counter_address();
+    let optme = ConcurrencyLevelManager::new(counter_storage(), 10);
+
+    //given (the writes of the contract carry a new balance: the first applied rewrites the account, the others write it in place)
+    let funded_write = |id: u64| {
+        let effect = Apply::Modify {
+            address: counter,
+            basic: Basic {
+                balance: U256::one(),
+                nonce: U256::one(),
+            },
+            code: None,
+            storage: BTreeMap::from([(H256::from_low_u64_be(id), H256::from_low_u64_be(1))]),
+            reset_storage: false,
+        };
+        FinalizedTransaction::from(ReExecutedTransaction::build_from(
+            IndexedEthereumTransaction::new(EthereumTransaction::default(), id),
+            vec![effect],
+            vec![],
+            RwSet::new(),
+        ))
+    };
+    let generations = || {
+        vec![
+            (0..SLOTS).map(funded_write).collect_vec(),
+            (SLOTS..2 * SLOTS).map(funded_write).collect_vec(),
+        ]
+    };
+
+    //when
+    let merged = ConcurrencyLevelManager::_merge_disjoint_generations(generations());
+    optme._concurrent_commit(generations()).await.unwrap();
+
+    //then (committed in a single phase, without losing any write)
+    assert_eq!(merged.len(), 1);
+    let state = optme.global_state();
+    assert_eq!(state.get_storage().basic(counter).balance, U256::one());
+    assert!((0..2 * SLOTS).all(|key| {
+        state
+            .get_storage()
+            .storage(counter, H256::from_low_u64_be(key))
+            == H256::from_low_u64_be(1)
+    }));
+}
+
+#[tokio::test]
+async fn test_commit_does_not_merge_generations_of_a_shared_sender() {
+    let counter = counter_address();
+    let sender = H160::from_low_u64_be(0x1130);
+    let storage = counter_storage();
+    let optme = ConcurrencyLevelManager::new(storage, 10);
+
+    //given (disjoint slots, but the same sender, whose nonce each generation bumps)
+    let sent = |id: u64, slot: u64| {
+        let mut effect = finalized_write(id, counter, slot, 1).extract();
+        effect.push(Apply::Modify {
+            address: sender,
+            basic: Basic {
+                balance: U256::zero(),
+                nonce: U256::from(id + 1),
+            },
+            code: None,
+            storage: BTreeMap::new(),
+            reset_storage: false,
+        });
+        FinalizedTransaction::from(ReExecutedTransaction::build_from(
+            IndexedEthereumTransaction::new(EthereumTransaction::default(), id),
+            effect,
+            vec![],
+            RwSet::new(),
+        ))
+    };
+    let generations = || vec![vec![sent(0, 0)], vec![sent(1, 1)]];
+
+    //when
+    let merged = ConcurrencyLevelManager::_merge_disjoint_generations(generations());
+    optme._concurrent_commit(generations()).await.unwrap();
+
+    //then
+    assert_eq!(merged.len(), 2);
+    assert_eq!(
+        optme.global_state().get_storage().basic(sender).nonce,
+        U256::from(2)
+    );
+}
+
+#[tokio::test]
+async fn test_commit_scheduled_and_finalized_transactions() {
+    let counter = counter_address();
+    let storage = counter_storage();
+    let optme = ConcurrencyLevelManager::new(storage, 10);
+
+    //given (the same write, as scheduled by the conflict graph and as finalized after re-execution)
+    let finalized = |slot: u64| finalized_write(slot, counter, slot, 7);
+    let scheduled = |slot: u64| {
+        let tx = finalized(slot);
+        ScheduledTransaction {
+            seq: 1,
+            tx_id: Committable::id(&tx),
+            effect: Committable::extract(tx),
+            log: vec![],
+        }
+    };
+
+    //when
+    optme
+        ._concurrent_commit(vec![vec![scheduled(0)]])
+        .await
+        .unwrap();
+    optme
+        ._concurrent_commit(vec![vec![finalized(1)]])
+        .await
+        .unwrap();
+
+    //then
+    let state = optme.global_state();
+    let slot = |key: u64| {
+        state
+            .get_storage()
+            .storage(counter, H256::from_low_u64_be(key))
+    };
+    assert_eq!(slot(0), H256::from_low_u64_be(7));
+    assert_eq!(slot(1), H256::from_low_u64_be(7));
+}
+
+#[tokio::test]
+async fn test_re_execution_histogram() {
+    let counter = counter_address();
+    let storage = counter_storage();
+    let optme = ConcurrencyLevelManager::new(storage, 10);
+
+    let block = |slots: Vec<u64>| {
+        vec![ExecutableEthereumBatch::new(
+            slots
+                .into_iter()
+                .enumerate()
+                .map(|(i, slot)| increment_transaction(i as u64 + 1, counter, slot))
+                .collect(),
+            BatchDigest::default(),
+        )]
+    };
+
+    //when (no conflicts)
+    optme._execute(block(vec![0, 1, 2, 3])).await.unwrap();
+
+    //then
+    assert_eq!(optme.re_execution_histogram(), BTreeMap::from([(0, 1)]));
+
+    //when (every transaction conflicts on the same slot)
+    optme._execute(block(vec![4, 4, 4])).await.unwrap();
+
+    //then
+    let histogram = optme.re_execution_histogram();
+    assert_eq!(histogram.get(&0), Some(&1));
+    assert_eq!(histogram.values().sum::<usize>(), 2);
+    assert!(histogram.keys().any(|rounds| *rounds >= 1));
+}
+
+#[tokio::test]
+async fn test_simulation_effects_of_smallbank_transfer() {
+    let optme = OptME::new(concurrent_evm_storage(), 10);
+    let contract = H160::from_str(DEFAULT_CONTRACT_ADDRESS).unwrap();
+    let smallbank = SmallBank::new(
+        contract,
+        Arc::new(Provider::<MockProvider>::new(MockProvider::default())),
+    );
+    let with_default_params = |mut tx: TypedTransaction| {
+        tx.set_from(H160::from_str(ADMIN_ADDRESS).unwrap())
+            .set_nonce(U256::zero())
+            .set_gas(u64::MAX)
+            .set_gas_price(U256::zero());
+        EthereumTransaction::from_typed(tx)
+    };
+
+    //given
+    optme
+        .execute(vec![ExecutableEthereumBatch::new(
+            vec![
+                with_default_params(
+                    smallbank
+                        .create_account("alice".into(), U256::from(1_000), U256::from(1_000))
+                        .tx,
+                ),
+                with_default_params(
+                    smallbank
+                        .create_account("bob".into(), U256::from(1_000), U256::from(1_000))
+                        .tx,
+                ),
+            ],
+            BatchDigest::default(),
+        )])
+        .await;
+
+    //when
+    let transfer = with_default_params(
+        smallbank
+            .send_payment("bob".into(), "alice".into(), U256::from(10))
+            .tx,
+    );
+    let result = ConcurrencyLevelManager::new(optme.global_state().snapshot(), 10)
+        .simulate(vec![ExecutableEthereumBatch::new(
+            vec![transfer],
+            BatchDigest::default(),
+        )])
+        .await
+        .unwrap();
+
+    //then (the result is inspected without being consumed)
+    assert_eq!(result.rw_sets.len(), 1);
+    assert!(result.rw_sets[0].outcome().is_success());
+    assert!(!result.rw_sets[0].effects().is_empty());
+    assert_eq!(
+        result.all_effects().count(),
+        result.rw_sets[0].effects().len()
+    );
+    assert!(result.all_effects().any(|apply| matches!(
+        apply,
+        Apply::Modify { address, storage, .. } if *address == contract && storage.len() >= 2
+    )));
+}
+
+#[cfg(feature = "deterministic-commit")]
+#[tokio::test]
+async fn test_deterministic_commit_within_generation() {
+    let counter = counter_address();
+    let storage = counter_storage();
+    let optme = ConcurrencyLevelManager::new(storage, 10);
+
+    //given (two writers of the same key in a generation, which must not happen unless a bug)
+    let generation = vec![
+        finalized_write(1, counter, 0, 2),
+        finalized_write(0, counter, 0, 1),
+    ];
+
+    //when
+    optme._concurrent_commit(vec![generation]).await.unwrap();
+
+    //then (the last writer in the order of tx ids wins)
+    assert_eq!(
+        optme
+            .global_state()
+            .get_storage()
+            .storage(counter, H256::zero()),
+        H256::from_low_u64_be(2)
+    );
+}
+
+#[tokio::test]
+async fn test_simulation_with_access_list() {
+    let counter = counter_address();
+    let storage = counter_storage();
+    let optme = ConcurrencyLevelManager::new(storage, 10);
+
+    //given (increments slot 0, while declaring slot 7 as well)
+    let tx = TransactionRequest::new()
+        .from(H160::from_low_u64_be(1))
+        .to(counter)
+        .data(H256::zero().as_bytes().to_vec())
+        .value(U256::zero())
+        .nonce(U256::zero())
+        .gas(u64::MAX)
+        .gas_price(U256::zero());
+    let access_list = AccessList(vec![AccessListItem {
+        address: counter,
+        storage_keys: vec![H256::from_low_u64_be(7)],
+    }]);
+    let tx = EthereumTransaction::from_typed(TypedTransaction::Eip2930(
+        Eip2930TransactionRequest::new(tx, access_list),
+    ));
+    let block = || {
+        vec![ExecutableEthereumBatch::new(
+            vec![tx.clone()],
+            BatchDigest::default(),
+        )]
+    };
+
+    //when
+    let plain = optme.simulate(block()).await.unwrap();
+    let seeded = optme.simulate_with_access_list(block()).await.unwrap();
+
+    //then (the declared slot is only read in the seeded result)
+    assert!(!plain.rw_sets[0]
+        .read_set()
+        .contains(&H256::from_low_u64_be(7)));
+    assert!(seeded.rw_sets[0]
+        .read_set()
+        .contains(&H256::from_low_u64_be(7)));
+    assert!(seeded.rw_sets[0].read_set().contains(&H256::zero()));
+    assert_eq!(seeded.rw_sets[0].write_set(), plain.rw_sets[0].write_set());
+}
+
+#[tokio::test]
+async fn test_blockstm_and_optme_equivalence() {
+    let smallbank = SmallBank::new(
+        H160::from_str(DEFAULT_CONTRACT_ADDRESS).unwrap(),
+        Arc::new(Provider::<MockProvider>::new(MockProvider::default())),
+    );
+    let with_default_params = |mut tx: TypedTransaction| {
+        tx.set_from(H160::from_str(ADMIN_ADDRESS).unwrap())
+            .set_nonce(U256::zero())
+            .set_gas(u64::MAX)
+            .set_gas_price(U256::zero());
+        EthereumTransaction::from_typed(tx)
+    };
+
+    //given (conflicting, but commutative operations over a few accounts,
+    //       so that the final balances do not depend on the serialization order)
+    let accounts = (0..5).map(|i| i.to_string()).collect::<Vec<_>>();
+    let workload = vec![
+        ExecutableEthereumBatch::new(
+            accounts
+                .iter()
+                .map(|acc| {
+                    with_default_params(
+                        smallbank
+                            .create_account(acc.clone(), U256::from(10_000), U256::from(10_000))
+                            .tx,
+                    )
+                })
+                .collect(),
+            BatchDigest::default(),
+        ),
+        ExecutableEthereumBatch::new(
+            (0..20)
+                .map(|i| {
+                    let (acc1, acc2) = (&accounts[i % 5], &accounts[(i * 3 + 1) % 5]);
+                    let tx = match i % 2 {
+                        0 => {
+                            smallbank
+                                .deposit_checking(acc1.clone(), U256::from(i + 1))
+                                .tx
+                        }
+                        _ => {
+                            smallbank
+                                .send_payment(acc2.clone(), acc1.clone(), U256::from(i))
+                                .tx
+                        }
+                    };
+                    with_default_params(tx)
+                })
+                .collect(),
+            BatchDigest::default(),
+        ),
+    ];
+
+    let blockstm_state = Arc::new(
+        sslab_execution_blockstm::utils::smallbank_contract_benchmark::concurrent_evm_storage(),
+    );
+    let blockstm = BlockSTM::new(blockstm_state.clone(), num_cpus::get());
+    let optme = OptME::new(concurrent_evm_storage(), 10);
+
+    //when
+    for batch in workload {
+        blockstm.execute(vec![batch.clone()]).await;
+        optme.execute(vec![batch]).await;
+    }
+
+    //then
+    let expected = smallbank_storage(blockstm_state.get_storage());
+    assert!(!expected.is_empty());
+    assert_eq!(
+        smallbank_storage(optme.global_state().get_storage()),
+        expected
+    );
+}
+
+#[tokio::test]
+async fn test_smallbank_workload_with_initial_balance() {
+    let initial_balance = U256::from(5_000);
+    let handler = get_smallbank_handler().with_initial_balance(initial_balance);
+    let optme = OptME::new(concurrent_evm_storage(), 10);
+
+    //given
+    optme.execute(vec![handler.create_account_batch(2)]).await;
+
+    //when (the whole checking balance is transferred)
+    let result = ConcurrencyLevelManager::new(optme.global_state().snapshot(), 10)
+        .simulate(vec![ExecutableEthereumBatch::new(
+            vec![handler.send_payment_transaction(1, 2, initial_balance)],
+            BatchDigest::default(),
+        )])
+        .await
+        .unwrap();
+
+    //then
+    assert_eq!(result.rw_sets.len(), 1);
+    assert!(result.rw_sets[0].outcome().is_success());
+}
+
+#[tokio::test]
+async fn test_simulation_reports_source_batch() {
+    let counter = counter_address();
+    let storage = counter_storage();
+    let optme = ConcurrencyLevelManager::new(storage, 10);
+
+    let (first, second) = (BatchDigest::new([1; 32]), BatchDigest::new([2; 32]));
+    let consensus_output = vec![
+        ExecutableEthereumBatch::new(
+            vec![
+                increment_transaction(1, counter, 0),
+                increment_transaction(2, counter, 1),
+            ],
+            first,
+        ),
+        ExecutableEthereumBatch::new(vec![increment_transaction(3, counter, 2)], second),
+    ];
+
+    //when
+    let result = optme.simulate(consensus_output).await.unwrap();
+
+    //then
+    assert_eq!(
+        result
+            .rw_sets
+            .iter()
+            .map(|tx| (tx.id(), result.digests[tx.batch_id()]))
+            .collect::<Vec<_>>(),
+        vec![(0, first), (1, first), (2, second)]
+    );
+}
+
+#[cfg(feature = "deadlock-detection")]
+#[test]
+fn test_deadlock_detector_starts() {
+    let _ = get_optme_executor();
+    let _ = get_optme_executor(); // spawned only once
+
+    assert!(crate::optme_core::DEADLOCK_DETECTOR.is_completed());
+}
+
+#[tokio::test]
+async fn test_batches_are_routed_by_conflict_density() {
+    let counter = counter_address();
+    let storage = counter_storage();
+    let optme = ConcurrencyLevelManager::new(storage, 10).with_conflict_threshold(0.5);
+
+    let batch = |sender: u64, slots: Vec<u64>| {
+        ExecutableEthereumBatch::new(
+            slots
+                .into_iter()
+                .enumerate()
+                .map(|(i, slot)| increment_transaction(sender + i as u64, counter, slot))
+                .collect(),
+            BatchDigest::default(),
+        )
+    };
+    let block = || {
+        vec![
+            batch(1, vec![0, 1, 2]),  // every transaction writes a distinct slot
+            batch(11, vec![9, 9, 9]), // every transaction writes the same slot
+        ]
+    };
+
+    //when
+    let simulated = optme.simulate(block()).await.unwrap();
+    let routes = ConcurrencyLevelManager::_route_batches(&simulated.rw_sets, 0.5);
+
+    //then
+    assert_eq!(
+        routes,
+        BTreeMap::from([(0, BatchRoute::SinglePass), (1, BatchRoute::ReExecution)])
+    );
+
+    //when
+    optme._execute(block()).await.unwrap();
+
+    //then (either route commits every transaction)
+    let state = optme.global_state();
+    let slot = |key: u64| {
+        state
+            .get_storage()
+            .storage(counter, H256::from_low_u64_be(key))
+    };
+    assert!([0, 1, 2]
+        .iter()
+        .all(|key| slot(*key) == H256::from_low_u64_be(1)));
+    assert_eq!(slot(9), H256::from_low_u64_be(3));
+}
+
+#[tokio::test]
+async fn test_sharded_commit_matches_unsharded_commit() {
+    let counters = (0..4)
+        .map(|i| H160::from_low_u64_be(0x132 + i))
+        .collect::<Vec<_>>();
+    let service = |num_shards: Option<usize>| {
+        let storage = concurrent_evm_storage();
+        counters
+            .iter()
+            .for_each(|counter| deploy_counter_contract(&storage, *counter));
+        let optme = ConcurrencyLevelManager::new(storage, 10);
+        ExecutionService::new(match num_shards {
+            Some(num_shards) => optme.with_commit_shards(num_shards),
+            None => optme,
+        })
+    };
+    let block = || {
+        vec![ExecutableEthereumBatch::new(
+            counters
+                .iter()
+                .enumerate()
+                .flat_map(|(i, counter)| {
+                    // distinct slots and a hot slot for each counter
+                    [0, 1, 9, 9].into_iter().enumerate().map(move |(j, slot)| {
+                        increment_transaction((i * 4 + j + 1) as u64, *counter, slot)
+                    })
+                })
+                .collect(),
+            BatchDigest::default(),
+        )]
+    };
+
+    let unsharded = service(None);
+    let sharded = service(Some(3));
+
+    //when
+    let _ = unsharded.submit(block()).await;
+    let _ = sharded.submit(block()).await;
+
+    //then
+    for counter in &counters {
+        assert_eq!(
+            sharded.get_state(*counter, H256::from_low_u64_be(9)).await,
+            H256::from_low_u64_be(2)
+        );
+    }
+    assert_eq!(sharded.state_root().await, unsharded.state_root().await);
+}
+
+#[cfg(feature = "verify-digests")]
+#[tokio::test]
+async fn test_tampered_batch_digest_is_detected() {
+    let handler = get_smallbank_handler();
+    let raw_batch = || {
+        (0..2)
+            .map(|_| handler.random_operation_raw(0.0, 1_000).to_vec())
+            .collect::<Vec<_>>()
+    };
+    let (raw_txs, other_raw_txs) = (raw_batch(), raw_batch());
+    let executable =
+        ExecutableEthereumBatch::try_from_batch(&Batch::V1(BatchV1::new(raw_txs.clone()))).unwrap();
+    let other =
+        ExecutableEthereumBatch::try_from_batch(&Batch::V1(BatchV1::new(other_raw_txs))).unwrap();
+    let (digest, other_digest) = (*executable.digest(), *other.digest());
+
+    //given
+    let tampered = ExecutableEthereumBatch::new(executable.data().clone(), other_digest)
+        .with_raw_transactions(raw_txs);
+
+    //then
+    assert!(executable.verify_digest().is_ok());
+    let mismatch = tampered.verify_digest().unwrap_err();
+    assert_eq!(mismatch.expected, other_digest);
+    assert_eq!(mismatch.computed, digest);
+
+    //when
+    let optme = get_optme_executor();
+    let simulated = optme.simulate(vec![tampered.clone()]).await;
+    let executed = optme._execute(vec![executable.clone(), tampered]).await;
+
+    //then (nothing of the corrupted consensus output is propagated)
+    assert!(matches!(simulated, Err(OptmeError::DigestMismatch(_))));
+    assert!(matches!(executed, Err(OptmeError::DigestMismatch(_))));
+    assert_eq!(
+        optme._execute(vec![executable]).await.unwrap(),
+        vec![digest]
+    );
+}
+
+#[cfg(feature = "verify-digests")]
+#[tokio::test]
+async fn test_try_execute_fails_on_tampered_batch() {
+    let handler = get_smallbank_handler();
+    let raw_batch = || {
+        (0..2)
+            .map(|_| handler.random_operation_raw(0.0, 1_000).to_vec())
+            .collect::<Vec<_>>()
+    };
+    let (raw_txs, other_raw_txs) = (raw_batch(), raw_batch());
+    let executable =
+        ExecutableEthereumBatch::try_from_batch(&Batch::V1(BatchV1::new(raw_txs.clone()))).unwrap();
+    let other =
+        ExecutableEthereumBatch::try_from_batch(&Batch::V1(BatchV1::new(other_raw_txs))).unwrap();
+
+    //given
+    let tampered = ExecutableEthereumBatch::new(executable.data().clone(), *other.digest())
+        .with_raw_transactions(raw_txs);
+
+    //when
+    let optme = get_optme_executor();
+    let result = optme.try_execute(vec![executable, tampered]).await;
+
+    //then (the failure is surfaced, instead of skipping the output)
+    assert!(matches!(result, Err(OptmeError::DigestMismatch(_))));
+}
+
+#[cfg(feature = "verify-digests")]
+#[tokio::test]
+async fn test_tampered_batch_is_detected_before_dedup() {
+    let handler = get_smallbank_handler();
+    let raw_batch = || {
+        (0..2)
+            .map(|_| handler.random_operation_raw(0.0, 1_000).to_vec())
+            .collect::<Vec<_>>()
+    };
+    let (raw_txs, other_raw_txs) = (raw_batch(), raw_batch());
+    let executable =
+        ExecutableEthereumBatch::try_from_batch(&Batch::V1(BatchV1::new(raw_txs.clone()))).unwrap();
+    let other =
+        ExecutableEthereumBatch::try_from_batch(&Batch::V1(BatchV1::new(other_raw_txs))).unwrap();
+
+    //given (the tampered batch duplicates the transactions of the first one, so dedup rebuilds it)
+    let tampered = ExecutableEthereumBatch::new(executable.data().clone(), *other.digest())
+        .with_raw_transactions(raw_txs);
+
+    //when
+    let optme = get_optme_executor().with_tx_dedup(true);
+    let result = optme.try_execute(vec![executable, tampered]).await;
+
+    //then
+    assert!(matches!(result, Err(OptmeError::DigestMismatch(_))));
+}
+
+#[tokio::test]
+async fn test_simulate_contract_creation() {
+    let deployer = H160::from_low_u64_be(0x138);
+    // returns 42 whenever called.
+    let runtime_code = vec![0x60, 0x2a, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3];
+    // PUSH10 <runtime code>; PUSH1 0; MSTORE; PUSH1 10; PUSH1 22; RETURN
+    let init_code = [
+        vec![0x69],
+        runtime_code.clone(),
+        vec![0x60, 0x00, 0x52, 0x60, 0x0a, 0x60, 0x16, 0xf3],
+    ]
+    .concat();
+
+    let tx = EthereumTransaction(TypedTransaction::Legacy(
+        TransactionRequest::new()
+            .from(deployer)
+            .data(init_code)
+            .value(U256::zero())
+            .nonce(U256::zero())
+            .gas(u64::MAX)
+            .gas_price(U256::zero()),
+    ));
+    assert!(tx.is_contract_creation());
+    assert!(!increment_transaction(1, deployer, 0).is_contract_creation());
+    let created = tx.created_address().unwrap();
+    assert_eq!(
+        created,
+        ethers_core::utils::get_contract_address(deployer, 0)
+    );
+
+    //when
+    let optme = get_optme_executor();
+    let SimulationResult { rw_sets, .. } = optme
+        .simulate(vec![ExecutableEthereumBatch::new(
+            vec![tx.clone()],
+            BatchDigest::default(),
+        )])
+        .await
+        .unwrap();
+
+    //then
+    assert_eq!(rw_sets.len(), 1);
+    assert!(rw_sets[0].outcome().is_success());
+    assert!(rw_sets[0].effects().iter().any(|apply| matches!(
+        apply,
+        Apply::Modify { address, code: Some(code), .. } if *address == created && *code == runtime_code
+    )));
+
+    //when
+    let _ = optme
+        ._execute(vec![ExecutableEthereumBatch::new(
+            vec![tx],
+            BatchDigest::default(),
+        )])
+        .await
+        .unwrap();
+
+    //then
+    assert_eq!(
+        optme.global_state().get_storage().code(created),
+        runtime_code
+    );
+}
+
+#[tokio::test]
+async fn test_contract_creation_collides_with_existing_code() {
+    let deployer = H160::from_low_u64_be(0x179);
+    // PUSH1 0; PUSH1 0; RETURN (deploys an empty runtime code)
+    let tx = EthereumTransaction(TypedTransaction::Legacy(
+        TransactionRequest::new()
+            .from(deployer)
+            .data(vec![0x60, 0x00, 0x60, 0x00, 0xf3])
+            .value(U256::zero())
+            .nonce(U256::zero())
+            .gas(u64::MAX)
+            .gas_price(U256::zero()),
+    ));
+    let occupied = tx.created_address().unwrap();
+
+    //given (the address to deploy at already has code)
+    let storage = concurrent_evm_storage();
+    assert!(!storage.has_code(occupied));
+    assert_eq!(storage.code(occupied), None);
+    deploy_counter_contract(&storage, occupied);
+    assert!(storage.has_code(occupied));
+    let existing_code = storage.code(occupied).unwrap();
+    let optme = ConcurrencyLevelManager::new(storage, 1);
+
+    //when
+    let SimulationResult { rw_sets, .. } = optme
+        .simulate(vec![ExecutableEthereumBatch::new(
+            vec![tx.clone()],
+            BatchDigest::default(),
+        )])
+        .await
+        .unwrap();
+
+    //then (the creation fails, but still consumes gas and bumps the nonce of the deployer)
+    assert_eq!(rw_sets.len(), 1);
+    assert_eq!(
+        rw_sets[0].outcome(),
+        &SimulationOutcome::Failed(evm::ExitError::CreateCollision)
+    );
+    assert!(rw_sets[0].gas_used() > 0);
+
+    //when
+    let _ = optme
+        ._execute(vec![ExecutableEthereumBatch::new(
+            vec![tx],
+            BatchDigest::default(),
+        )])
+        .await
+        .unwrap();
+
+    //then (the existing code is left untouched)
+    assert_eq!(optme.global_state().code(occupied), Some(existing_code));
+    assert_eq!(
+        optme.global_state().get_storage().basic(deployer).nonce,
+        U256::one()
+    );
+}
+
+#[tokio::test]
+async fn test_sender_chains_execute_the_transactions_of_a_sender_serially() {
+    //given (50 signed transactions of the same sender on distinct slots, i.e., only bumping the same nonce)
+    let optme = ConcurrencyLevelManager::new(counter_storage(), 1).with_sender_chains(true);
+    let txs = (0..50)
+        .map(|nonce| signed_increment_transaction(counter_address(), nonce, nonce))
+        .collect_vec();
+    let sender = txs[0].caller();
+    assert!(txs.iter().all(|tx| tx.caller() == sender));
+
+    //when
+    let _ = optme
+        ._execute(vec![ExecutableEthereumBatch::new(
+            txs,
+            BatchDigest::default(),
+        )])
+        .await
+        .unwrap();
+
+    //then (the first one enters the graph, and the others follow it serially, without any re-execution round)
+    let state = optme.global_state();
+    assert_eq!(state.get_storage().basic(sender).nonce, U256::from(50));
+    for slot in 0..50 {
+        assert_eq!(
+            state
+                .get_storage()
+                .storage(counter_address(), H256::from_low_u64_be(slot)),
+            H256::from_low_u64_be(1)
+        );
+    }
+    assert_eq!(optme.re_execution_histogram(), BTreeMap::from([(0, 1)]));
+}
+
+#[tokio::test]
+async fn test_value_transfers_to_the_same_recipient_conflict() {
+    let recipient = H160::from_low_u64_be(0x139);
+    let senders = [H160::from_low_u64_be(0x1391), H160::from_low_u64_be(0x1392)];
+    let storage = || {
+        let storage = concurrent_evm_storage();
+        senders.iter().for_each(|sender| {
+            storage.get_storage().state().pin().insert(
+                *sender,
+                CAccount {
+                    nonce: U256::zero(),
+                    balance: U256::from(1_000),
+                    storage: ConcurrentHashMap::default(),
+                    code: vec![],
+                },
+            );
+        });
+        storage
+    };
+    // plain transfers, without data.
+    let block = || {
+        vec![ExecutableEthereumBatch::new(
+            senders
+                .iter()
+                .map(|sender| {
+                    EthereumTransaction(TypedTransaction::Legacy(
+                        TransactionRequest::new()
+                            .from(*sender)
+                            .to(recipient)
+                            .value(U256::from(100))
+                            .nonce(U256::zero())
+                            .gas(u64::MAX)
+                            .gas_price(U256::zero()),
+                    ))
+                })
+                .collect(),
+            BatchDigest::default(),
+        )]
+    };
+    let aborted = |rw_sets: Vec<SimulatedTransaction>| {
+        AddressBasedConflictGraph::construct(rw_sets)
+            .hierarchcial_sort()
+            .reorder()
+            .extract_schedule()
+            .aborted_txs_len()
+    };
+
+    //given
+    let optme = ConcurrencyLevelManager::new(storage(), 10);
+    let unaware = ConcurrencyLevelManager::new(storage(), 10).with_value_transfer_conflicts(false);
+
+    //when
+    let simulated = optme.simulate(block()).await.unwrap();
+
+    //then
+    assert!(simulated
+        .rw_sets
+        .iter()
+        .all(|tx| tx.write_set().contains(&balance_key(recipient))));
+    assert_eq!(aborted(simulated.rw_sets), 1);
+    assert_eq!(aborted(unaware.simulate(block()).await.unwrap().rw_sets), 0);
+
+    //when
+    optme._execute(block()).await.unwrap();
+
+    //then (no transfer is lost)
+    assert_eq!(
+        optme.global_state().get_storage().basic(recipient).balance,
+        U256::from(200)
+    );
+}
+
+#[tokio::test]
+async fn test_execute_batches_isolated() {
+    let counter = counter_address();
+    let storage = || {
+        let storage = counter_storage();
+        storage
+    };
+    let block = || {
+        vec![ExecutableEthereumBatch::new(
+            vec![
+                increment_transaction(1, counter, 0),
+                increment_transaction(2, counter, 0),
+                increment_transaction(3, counter, 1),
+            ],
+            BatchDigest::default(),
+        )]
+    };
+    let optme = ConcurrencyLevelManager::new(storage(), 10);
+    let root_before = state_root(&optme.global_state());
+
+    //when
+    let (effects, root) = optme.execute_batches_isolated(block()).await.unwrap();
+
+    //then (the live state is untouched)
+    assert_eq!(state_root(&optme.global_state()), root_before);
+    assert_eq!(
+        optme
+            .global_state()
+            .get_storage()
+            .storage(counter, H256::zero()),
+        H256::zero()
+    );
+    assert_ne!(root, root_before);
+    assert!(effects.iter().any(|apply| matches!(
+        apply,
+        Apply::Modify { address, storage, .. } if *address == counter
+            && storage.get(&H256::from_low_u64_be(0)) == Some(&H256::from_low_u64_be(2))
+            && storage.get(&H256::from_low_u64_be(1)) == Some(&H256::from_low_u64_be(1))
+    )));
+
+    //then (the same as executing over the live state)
+    let service = ExecutionService::new(ConcurrencyLevelManager::new(storage(), 10));
+    let _ = service.submit(block()).await;
+    assert_eq!(service.state_root().await, root);
+}
+
+/* collects the events of `with_trace_tx_id`, emitted on the current thread */
+#[derive(Clone, Default)]
+struct TraceCapture(Arc<std::sync::Mutex<Vec<String>>>);
+
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for TraceCapture {
+    fn on_event(&self, event: &tracing::Event<'_>, _: tracing_subscriber::layer::Context<'_, S>) {
+        struct Message(String);
+        impl tracing::field::Visit for Message {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                if field.name() == "message" {
+                    self.0 = format!("{value:?}");
+                }
+            }
+        }
+
+        let mut message = Message(String::new());
+        event.record(&mut message);
+        if let Some(traced) = message.0.strip_prefix("traced transaction ") {
+            self.0.lock().unwrap().push(traced.to_string());
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_trace_single_transaction() {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let counter = counter_address();
+    let block = || {
+        vec![ExecutableEthereumBatch::new(
+            vec![
+                increment_transaction(1, counter, 0),
+                increment_transaction(2, counter, 9),
+                increment_transaction(3, counter, 9),
+            ],
+            BatchDigest::default(),
+        )]
+    };
+    let trace = |tx_id: u64| async move {
+        let storage = counter_storage();
+        let optme = ConcurrencyLevelManager::new(storage, 10).with_trace_tx_id(tx_id);
+
+        let capture = TraceCapture::default();
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(capture.clone()));
+        optme._execute(block()).await.unwrap();
+
+        let events = capture.0.lock().unwrap().clone();
+        events
+    };
+
+    //when (a transaction without conflicts)
+    let events = trace(0).await;
+
+    //then
+    assert_eq!(events.len(), 3);
+    assert_eq!(events[0], "0: simulated (Success)");
+    assert!(events[1].starts_with("0: scheduled into generation "));
+    assert_eq!(events[2], "0: committed");
+
+    //when (conflicting transactions, one of which is aborted)
+    let (first, second) = (trace(1).await, trace(2).await);
+
+    //then
+    let (aborted, id) = if first.len() > second.len() {
+        (first, 1)
+    } else {
+        (second, 2)
+    };
+    assert_eq!(
+        aborted,
+        vec![
+            format!("{id}: simulated (Success)"),
+            format!("{id}: aborted into epoch 0"),
+            format!("{id}: re-executed in round 1"),
+            format!("{id}: validated and committed"),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_construction_deadline_falls_back_to_serial_execution() {
+    //given (an oversized block, whose conflict graph cannot be constructed within the deadline)
+    let counter = counter_address();
+    let storage = counter_storage();
+    let optme = ConcurrencyLevelManager::new(storage, 10)
+        .with_construction_deadline(Duration::from_nanos(1));
+
+    let consensus_output = vec![ExecutableEthereumBatch::new(
+        (0..2_000)
+            .map(|sender| increment_transaction(sender, counter, sender % 10))
+            .collect(),
+        BatchDigest::default(),
+    )];
+
+    //when
+    optme._execute(consensus_output).await.unwrap();
+
+    //then (every transaction is still executed, in the total order)
+    assert_eq!(optme.construction_deadline_hits(), 1);
+    assert_eq!(optme.re_execution_histogram(), BTreeMap::new());
+    let state = optme.global_state();
+    for key in 0..10 {
+        assert_eq!(
+            state
+                .get_storage()
+                .storage(counter, H256::from_low_u64_be(key)),
+            H256::from_low_u64_be(200)
+        );
+    }
+}
+
+#[cfg(feature = "disable-early-detection")]
+#[tokio::test]
+async fn test_execute_vanilla_commits_every_transaction() {
+    //given (every transaction conflicts on slot 0, so the first-committer-wins rule commits one of them per round)
+    let counter = counter_address();
+    let storage = counter_storage();
+    let optme = ConcurrencyLevelManager::new(storage, 10);
+
+    let consensus_output = vec![ExecutableEthereumBatch::new(
+        (1..=20)
+            .map(|sender| increment_transaction(sender, counter, 0))
+            .chain((21..=30).map(|sender| increment_transaction(sender, counter, sender)))
+            .collect(),
+        BatchDigest::default(),
+    )];
+
+    //when
+    optme.execute_vanilla(consensus_output).await.unwrap();
+
+    //then
+    let state = optme.global_state();
+    let slot = |key: u64| {
+        state
+            .get_storage()
+            .storage(counter, H256::from_low_u64_be(key))
+    };
+    assert_eq!(slot(0), H256::from_low_u64_be(20));
+    for key in 21..=30 {
+        assert_eq!(slot(key), H256::from_low_u64_be(1));
+    }
+}
+
+#[tokio::test]
+async fn test_spec_id_decides_the_gas_rules() {
+    // SLOAD costs 800 gas in Istanbul, and 2100 for a cold slot since Berlin (EIP-2929).
+    let reader = H160::from_low_u64_be(0x147);
+    let consensus_output = || {
+        let tx = TransactionRequest::new()
+            .from(H160::from_low_u64_be(1))
+            .to(reader)
+            .data(vec![])
+            .value(U256::zero())
+            .nonce(U256::zero())
+            .gas(21_000u64 + 1_000)
+            .gas_price(U256::zero());
+        vec![ExecutableEthereumBatch::new(
+            vec![EthereumTransaction(TypedTransaction::Legacy(tx))],
+            BatchDigest::default(),
+        )]
+    };
+    let simulate = |spec_id: SpecId| async move {
+        let storage = concurrent_evm_storage();
+        storage.get_storage().state().pin().insert(
+            reader,
+            CAccount {
+                nonce: U256::one(),
+                balance: U256::zero(),
+                storage: ConcurrentHashMap::default(),
+                code: vec![0x60, 0x00, 0x54, 0x00], // PUSH1 0; SLOAD; STOP
+            },
+        );
+        let optme = ConcurrencyLevelManager::new(storage, 10).with_spec_id(spec_id);
+        let SimulationResult { rw_sets, .. } = optme.simulate(consensus_output()).await.unwrap();
+        rw_sets[0].outcome().to_owned()
+    };
+
+    //when
+    let istanbul = simulate(SpecId::ISTANBUL).await;
+    let london = simulate(SpecId::LONDON).await;
+
+    //then
+    assert!(istanbul.is_success());
+    assert!(matches!(london, SimulationOutcome::Failed(_)));
+}
+
+#[tokio::test]
+async fn test_inline_validation_commit_matches_two_phase() {
+    let counter = counter_address();
+    let re_executed = |storage: &ConcurrentEVMStorage| {
+        deploy_counter_contract(storage, counter);
+        // tx 1 writes the same slot as tx 0, and is invalidated.
+        [0, 0, 1, 2]
+            .into_iter()
+            .enumerate()
+            .map(|(id, slot)| {
+                IndexedEthereumTransaction::new(
+                    increment_transaction(id as u64 + 1, counter, slot),
+                    id as u64,
+                )
+            })
+            .collect_vec()
+    };
+
+    //given
+    let inline = ConcurrencyLevelManager::new(concurrent_evm_storage(), 10);
+    let two_phase = ConcurrencyLevelManager::new(concurrent_evm_storage(), 10);
+    let inline_txs = inline
+        ._re_execute(re_executed(&inline.global_state()), None)
+        .await
+        .unwrap();
+    let two_phase_txs = two_phase
+        ._re_execute(re_executed(&two_phase.global_state()), None)
+        .await
+        .unwrap();
+
+    //when
+    let ids = |txs: Option<Vec<ReExecutedTransaction>>| {
+        txs.unwrap_or_default()
+            .iter()
+            .map(|tx| tx.id())
+            .collect_vec()
+    };
+    let inline_invalid = ids(inline
+        ._validate_optimistic_assumption(inline_txs)
+        .await
+        .unwrap());
+    let two_phase_invalid = ids(two_phase
+        ._validate_optimistic_assumption_two_phase(two_phase_txs)
+        .await
+        .unwrap());
+
+    //then
+    assert_eq!(inline_invalid, vec![1]);
+    assert_eq!(inline_invalid, two_phase_invalid);
+    for key in 0..3 {
+        let slot = |optme: &ConcurrencyLevelManager| {
+            optme
+                .global_state()
+                .get_storage()
+                .storage(counter, H256::from_low_u64_be(key))
+        };
+        assert_eq!(slot(&inline), H256::from_low_u64_be(1));
+        assert_eq!(slot(&inline), slot(&two_phase));
+    }
+    assert_eq!(
+        state_root(&inline.global_state()),
+        state_root(&two_phase.global_state())
+    );
+}
+
+#[tokio::test]
+async fn test_invalidated_re_execution_has_no_receipt() {
+    let counter = counter_address();
+    let optme = ConcurrencyLevelManager::new(concurrent_evm_storage(), 10);
+    deploy_counter_contract(&optme.global_state(), counter);
+
+    //given (tx 1 writes the same slot as tx 0, and is invalidated)
+    let staged = ReceiptCollector::default();
+    let re_executed = optme
+        ._re_execute(
+            [0, 0, 1]
+                .into_iter()
+                .enumerate()
+                .map(|(id, slot)| {
+                    IndexedEthereumTransaction::new(
+                        increment_transaction(id as u64 + 1, counter, slot),
+                        id as u64,
+                    )
+                })
+                .collect_vec(),
+            Some(&staged),
+        )
+        .await
+        .unwrap();
+    let validated_ids = ConcurrencyLevelManager::_ids(&re_executed);
+
+    //when
+    let invalid_txs = optme
+        ._validate_optimistic_assumption(re_executed)
+        .await
+        .unwrap();
+    let receipts = ReceiptCollector::default();
+    ConcurrencyLevelManager::_commit_receipts(
+        Some(&receipts),
+        Some(staged),
+        Some(validated_ids),
+        invalid_txs.as_deref(),
+    );
+
+    //then (only the committed transactions have a receipt)
+    assert_eq!(
+        invalid_txs
+            .unwrap_or_default()
+            .iter()
+            .map(|tx| tx.id())
+            .collect_vec(),
+        vec![1]
+    );
+    assert_eq!(
+        receipts
+            .into_records()
+            .map(|record| record.id)
+            .collect_vec(),
+        vec![0, 2]
+    );
+}
+
+#[tokio::test]
+async fn test_validated_re_execution_commits_captured_effects() {
+    let counter = H160::from_low_u64_be(0x14a);
+    let optme = get_optme_executor();
+    deploy_counter_contract(&optme.global_state(), counter);
+
+    //given (the effects of a re-execution, detached from the transaction which produced them)
+    let re_executed = optme
+        ._re_execute(
+            vec![IndexedEthereumTransaction::new(
+                increment_transaction(1, counter, 3),
+                0,
+            )],
+            None,
+        )
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    assert!(re_executed.gas_used() > 0);
+    assert!(!re_executed.effects().is_empty());
+    let (tx, effects, logs, rw_set) = re_executed.into_parts();
+    assert_eq!(tx.id, 0);
+    let detached = ReExecutedTransaction::build_from(
+        IndexedEthereumTransaction::new(EthereumTransaction::default(), 0),
+        effects,
+        logs,
+        rw_set,
+    );
+
+    //when
+    let invalid_txs = optme
+        ._validate_optimistic_assumption(vec![detached])
+        .await
+        .unwrap();
+
+    //then (the captured effects are committed as they are, since the placeholder transaction would write nothing)
+    assert!(invalid_txs.is_none());
+    assert_eq!(
+        optme
+            .global_state()
+            .get_storage()
+            .storage(counter, H256::from_low_u64_be(3)),
+        H256::from_low_u64_be(1)
+    );
+}
+
+#[tokio::test]
+async fn test_pipelined_windows_match_serial_windows() {
+    let counter = counter_address();
+    let manager = |pipelined: bool| {
+        let storage = counter_storage();
+        ConcurrencyLevelManager::new(storage, 2).with_pipelined_windows(pipelined)
+    };
+    // 3 windows of 2 batches; every batch increments the hot slot 0, and a slot of its own.
+    let consensus_output = (0..5u8)
+        .map(|i| {
+            ExecutableEthereumBatch::new(
+                vec![
+                    increment_transaction(i as u64 * 2 + 1, counter, 0),
+                    increment_transaction(i as u64 * 2 + 2, counter, i as u64 + 1),
+                ],
+                BatchDigest::new([i + 1; 32]),
+            )
+        })
+        .collect_vec();
+
+    //given
+    let serial = manager(false);
+    let pipelined = manager(true);
+
+    //when
+    let serial_result = serial
+        .prepare_execution(consensus_output.clone())
+        .await
+        .unwrap();
+    let pipelined_result = pipelined.prepare_execution(consensus_output).await.unwrap();
+
+    //then
+    assert_eq!(pipelined_result.digests, serial_result.digests);
+    assert_eq!(pipelined_result.digests.len(), 5);
+    for key in 0..6 {
+        let slot = |optme: &ConcurrencyLevelManager| {
+            optme
+                .global_state()
+                .get_storage()
+                .storage(counter, H256::from_low_u64_be(key))
+        };
+        assert_eq!(slot(&pipelined), slot(&serial));
+    }
+    assert_eq!(
+        state_root(&pipelined.global_state()),
+        state_root(&serial.global_state())
+    );
+}
+
+#[tokio::test]
+async fn test_independent_windows_execute_concurrently() {
+    let (left, right) = (H160::from_low_u64_be(0x176), H160::from_low_u64_be(0x177));
+    let manager = |independent: bool| {
+        let storage = concurrent_evm_storage();
+        deploy_counter_contract(&storage, left);
+        deploy_counter_contract(&storage, right);
+        ConcurrencyLevelManager::new(storage, 2).with_independent_windows(independent)
+    };
+    // 3 windows of 2 batches: the 1st increments the slots of `left`, the 2nd the ones of `right`,
+    // and the 3rd the ones of `left` again, so only the first two are independent.
+    let consensus_output = [left, left, right, right, left, left]
+        .into_iter()
+        .enumerate()
+        .map(|(i, counter)| {
+            ExecutableEthereumBatch::new(
+                vec![
+                    increment_transaction(i as u64 * 2 + 1, counter, 0),
+                    increment_transaction(i as u64 * 2 + 2, counter, i as u64 + 1),
+                ],
+                BatchDigest::new([i as u8 + 1; 32]),
+            )
+        })
+        .collect_vec();
+
+    //given
+    let in_order = manager(false);
+    let out_of_order = manager(true);
+
+    //when
+    let in_order_result = in_order
+        .prepare_execution(consensus_output.clone())
+        .await
+        .unwrap();
+    let out_of_order_result = out_of_order
+        .prepare_execution(consensus_output)
+        .await
+        .unwrap();
+
+    //then
+    assert_eq!(out_of_order.concurrent_windows(), 1);
+    assert_eq!(in_order.concurrent_windows(), 0);
+    // (the first two windows are executed concurrently, and recorded as a single block)
+    let blocks =
+        |optme: &ConcurrencyLevelManager| optme.re_execution_histogram().values().sum::<usize>();
+    assert_eq!(blocks(&out_of_order), 2);
+    assert_eq!(blocks(&in_order), 3);
+    assert_eq!(out_of_order_result.digests, in_order_result.digests);
+    assert_eq!(out_of_order_result.digests.len(), 6);
+    let slot = |optme: &ConcurrencyLevelManager, counter: H160, key: u64| {
+        optme
+            .global_state()
+            .get_storage()
+            .storage(counter, H256::from_low_u64_be(key))
+    };
+    assert_eq!(slot(&out_of_order, left, 0), H256::from_low_u64_be(4));
+    assert_eq!(slot(&out_of_order, right, 0), H256::from_low_u64_be(2));
+    for key in 0..7 {
+        for counter in [left, right] {
+            assert_eq!(
+                slot(&out_of_order, counter, key),
+                slot(&in_order, counter, key)
+            );
+        }
+    }
+    assert_eq!(
+        state_root(&out_of_order.global_state()),
+        state_root(&in_order.global_state())
+    );
+}
+
+#[tokio::test]
+async fn test_windows_sharing_a_sender_are_not_executed_concurrently() {
+    let (left, right) = (H160::from_low_u64_be(0x176), H160::from_low_u64_be(0x177));
+    let sender = 0x51;
+    let manager = |independent: bool| {
+        let storage = concurrent_evm_storage();
+        deploy_counter_contract(&storage, left);
+        deploy_counter_contract(&storage, right);
+        ConcurrencyLevelManager::new(storage, 2).with_independent_windows(independent)
+    };
+    // 2 windows of 2 batches, on distinct counters, but both windows bump the nonce of the same sender.
+    let consensus_output = [(left, sender), (left, 2), (right, sender), (right, 4)]
+        .into_iter()
+        .enumerate()
+        .map(|(i, (counter, sender))| {
+            ExecutableEthereumBatch::new(
+                vec![increment_transaction(sender, counter, i as u64)],
+                BatchDigest::new([i as u8 + 1; 32]),
+            )
+        })
+        .collect_vec();
+
+    //given
+    let in_order = manager(false);
+    let out_of_order = manager(true);
+
+    //when
+    let in_order_result = in_order
+        .prepare_execution(consensus_output.clone())
+        .await
+        .unwrap();
+    let out_of_order_result = out_of_order
+        .prepare_execution(consensus_output)
+        .await
+        .unwrap();
+
+    //then
+    assert_eq!(out_of_order.concurrent_windows(), 0);
+    assert_eq!(out_of_order_result.digests, in_order_result.digests);
+    let nonce = |optme: &ConcurrencyLevelManager| {
+        optme
+            .global_state()
+            .get_storage()
+            .basic(H160::from_low_u64_be(sender))
+            .nonce
+    };
+    assert_eq!(nonce(&out_of_order), U256::from(2));
+    assert_eq!(nonce(&out_of_order), nonce(&in_order));
+    assert_eq!(
+        state_root(&out_of_order.global_state()),
+        state_root(&in_order.global_state())
+    );
+}
+
+#[tokio::test]
+async fn test_try_execute_matches_prepare_execution() {
+    let handler = get_smallbank_handler();
+    let consensus_output = handler.create_batches(50, 3, 0.5, 100);
+
+    //given
+    let lenient = ConcurrencyLevelManager::new(concurrent_evm_storage(), 2);
+    let strict = ConcurrencyLevelManager::new(concurrent_evm_storage(), 2);
+
+    //when
+    let expected = lenient
+        .prepare_execution(consensus_output.clone())
+        .await
+        .unwrap();
+    let result = strict.try_execute(consensus_output).await.unwrap();
+
+    //then
+    assert_eq!(result.digests, expected.digests);
+    assert_eq!(
+        state_root(&strict.global_state()),
+        state_root(&lenient.global_state())
+    );
+}
+
+#[tokio::test]
+async fn test_roll_back_restores_the_pre_images() {
+    let counter = counter_address();
+    let created = H160::from_low_u64_be(0x194);
+    let optme = ConcurrencyLevelManager::new(counter_storage(), 2);
+    let generations = vec![
+        vec![
+            finalized_write(0, counter, 0, 1),
+            FinalizedTransaction::new(
+                1,
+                vec![Apply::Modify {
+                    address: created,
+                    basic: Basic {
+                        balance: U256::one(),
+                        nonce: U256::zero(),
+                    },
+                    code: None,
+                    storage: BTreeMap::new(),
+                    reset_storage: false,
+                }],
+            ),
+        ],
+        vec![
+            finalized_write(2, counter, 0, 2),
+            finalized_write(3, counter, 1, 2),
+        ],
+    ];
+
+    //given (an output which has committed its generations, after recording their pre-images)
+    let before = state_root(&optme.global_state());
+    let pre_images = PreImages::default();
+    pre_images.start();
+    for generation in generations {
+        pre_images.record(
+            &optme.global_state(),
+            generation.iter().flat_map(FinalizedTransaction::effects),
+        );
+        optme._concurrent_commit(vec![generation]).await.unwrap();
+    }
+    assert_ne!(state_root(&optme.global_state()), before);
+
+    //when
+    optme._roll_back(pre_images.revert()).await.unwrap();
+
+    //then (the slots are restored to their first pre-image, and the created account is deleted)
+    assert_eq!(state_root(&optme.global_state()), before);
+}
+
+#[tokio::test]
+async fn test_try_execute_rolls_back_the_output_on_worker_panic() {
+    //given (a rayon worker panics while the second window commits)
+    let optme =
+        ConcurrencyLevelManager::new(counter_storage(), 1).with_fault(Fault::WorkerPanic, 1);
+    let before = state_root(&optme.global_state());
+
+    //when
+    let result = optme.try_execute(two_windows_of_increments()).await;
+
+    //then (the first window is rolled back as well)
+    assert!(matches!(
+        result,
+        Err(OptmeError::Worker(RayonError::Panicked(_)))
+    ));
+    assert_eq!(state_root(&optme.global_state()), before);
+
+    //then (the manager keeps executing)
+    let result = optme.try_execute(two_windows_of_increments()).await;
+    assert_eq!(result.unwrap().digests.len(), 2);
+}
+
+#[tokio::test]
+async fn test_try_execute_rolls_back_the_output_on_panic_inside_execution() {
+    //given (the execution panics before the second window commits)
+    let optme = ConcurrencyLevelManager::new(counter_storage(), 1).with_fault(Fault::Panic, 1);
+    let before = state_root(&optme.global_state());
+
+    //when
+    let result = optme.try_execute(two_windows_of_increments()).await;
+
+    //then
+    assert!(matches!(result, Err(OptmeError::Panicked(_))));
+    assert_eq!(state_root(&optme.global_state()), before);
+}
+
+#[tokio::test]
+async fn test_pipelined_execution_rolls_back_the_output_on_worker_panic() {
+    //given (a rayon worker panics while the second window commits, on the path the node executes)
+    let optme = ConcurrencyLevelManager::new(counter_storage(), 1)
+        .with_pipelined_windows(true)
+        .with_fault(Fault::WorkerPanic, 1);
+    let before = state_root(&optme.global_state());
+
+    //when
+    let result = optme.prepare_execution(two_windows_of_increments()).await;
+
+    //then
+    assert!(matches!(
+        result,
+        Err(OptmeError::Worker(RayonError::Panicked(_)))
+    ));
+    assert_eq!(state_root(&optme.global_state()), before);
+}
+
+#[tokio::test]
+async fn test_streaming_ends_with_the_window_which_fails() {
+    use futures::StreamExt as _;
+
+    let counter = counter_address();
+
+    //given (a rayon worker panics while the second window commits)
+    let optme =
+        ConcurrencyLevelManager::new(counter_storage(), 1).with_fault(Fault::WorkerPanic, 1);
+
+    //when
+    let windows = optme
+        .prepare_execution_streaming(two_windows_of_increments())
+        .collect::<Vec<_>>()
+        .await;
+
+    //then (only the failed window is rolled back, since the first one is already yielded)
+    assert_eq!(windows.len(), 2);
+    assert_eq!(
+        windows[0].as_ref().unwrap().digests,
+        vec![BatchDigest::new([1; 32])]
+    );
+    assert!(matches!(windows[1], Err(OptmeError::Worker(_))));
+    let state = optme.global_state();
+    let slot = |key: u64| {
+        state
+            .get_storage()
+            .storage(counter, H256::from_low_u64_be(key))
+    };
+    assert!((0..3).all(|key| slot(key) == H256::from_low_u64_be(1)));
+    assert!((3..6).all(|key| slot(key) == H256::zero()));
+}
+
+#[tokio::test]
+async fn test_streaming_windows_match_prepare_execution() {
+    use futures::TryStreamExt as _;
+
+    let handler = get_smallbank_handler();
+    let consensus_output = handler.create_batches(20, 5, 0.5, 100);
+
+    //given
+    let batched = ConcurrencyLevelManager::new(concurrent_evm_storage(), 2);
+    let streamed = ConcurrencyLevelManager::new(concurrent_evm_storage(), 2);
+
+    //when
+    let expected = batched
+        .prepare_execution(consensus_output.clone())
+        .await
+        .unwrap();
+    let windows = streamed
+        .prepare_execution_streaming(consensus_output)
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+
+    //then (a result per window, whose union is the result of the whole output)
+    assert_eq!(
+        windows
+            .iter()
+            .map(|window| window.digests.len())
+            .collect_vec(),
+        vec![2, 2, 1]
+    );
+    assert_eq!(
+        windows
+            .into_iter()
+            .flat_map(|window| window.digests)
+            .collect_vec(),
+        expected.digests
+    );
+    assert_eq!(
+        state_root(&streamed.global_state()),
+        state_root(&batched.global_state())
+    );
+}
+
+#[tokio::test]
+async fn test_empty_consensus_output() {
+    //given
+    let optme = ConcurrencyLevelManager::new(concurrent_evm_storage(), 10);
+    let root_before = state_root(&optme.global_state());
+
+    //when
+    let result = optme.prepare_execution(vec![]).await.unwrap();
+
+    //then
+    assert!(result.digests.is_empty());
+    assert!(optme.re_execution_histogram().is_empty());
+    assert_eq!(state_root(&optme.global_state()), root_before);
+}
+
+#[tokio::test]
+async fn test_batch_of_failing_transactions() {
+    //given
+    let optme = ConcurrencyLevelManager::new(concurrent_evm_storage(), 10);
+    let root_before = state_root(&optme.global_state());
+    let digest = BatchDigest::new([1; 32]);
+    let consensus_output = vec![ExecutableEthereumBatch::new(
+        (0..4).map(|_| reverting_transaction()).collect(),
+        digest,
+    )];
+
+    //when
+    let result = optme.prepare_execution(consensus_output).await.unwrap();
+
+    //then
+    assert_eq!(result.digests, vec![digest]);
+    assert_eq!(state_root(&optme.global_state()), root_before);
+}
+
+#[tokio::test]
+async fn test_empty_and_failing_batches_mixed_with_valid_ones() {
+    //given
+    let storage = concurrent_evm_storage();
+    let counter = counter_address();
+    deploy_counter_contract(&storage, counter);
+    let optme = ConcurrencyLevelManager::new(storage, 10);
+    let consensus_output = vec![
+        ExecutableEthereumBatch::new(vec![], BatchDigest::new([1; 32])),
+        ExecutableEthereumBatch::new(
+            vec![reverting_transaction(), reverting_transaction()],
+            BatchDigest::new([2; 32]),
+        ),
+        ExecutableEthereumBatch::new(
+            vec![
+                increment_transaction(1, counter, 0),
+                reverting_transaction(),
+                increment_transaction(2, counter, 1),
+            ],
+            BatchDigest::new([3; 32]),
+        ),
+    ];
+
+    //when
+    let result = optme.prepare_execution(consensus_output).await.unwrap();
+
+    //then
+    assert_eq!(
+        result.digests,
+        (1..=3).map(|i| BatchDigest::new([i; 32])).collect_vec()
+    );
+    for key in 0..2 {
+        assert_eq!(
+            optme
+                .global_state()
+                .get_storage()
+                .storage(counter, H256::from_low_u64_be(key)),
+            H256::from_low_u64_be(1)
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_wasted_gas_ratio_of_high_conflict_block() {
+    //given (every transaction increments the same slot, so all but one are aborted)
+    let storage = concurrent_evm_storage();
+    let counter = counter_address();
+    deploy_counter_contract(&storage, counter);
+    let optme = ConcurrencyLevelManager::new(storage, 10);
+    let consensus_output = vec![ExecutableEthereumBatch::new(
+        (1..=8)
+            .map(|sender| increment_transaction(sender, counter, 0))
+            .collect(),
+        BatchDigest::default(),
+    )];
+
+    //when
+    optme.prepare_execution(consensus_output).await.unwrap();
+
+    //then
+    let metrics = optme.gas_metrics();
+    assert!(metrics.simulated_gas > 0);
+    assert!(metrics.aborted_gas > 0 && metrics.aborted_gas < metrics.simulated_gas);
+    assert!(metrics.wasted_gas_ratio() > 0.0 && metrics.wasted_gas_ratio() < 1.0);
+}
+
+#[tokio::test]
+async fn test_identical_transactions_are_scheduled_by_sequential_id() {
+    //given (the same transaction twice, i.e., with the same digest)
+    let storage = concurrent_evm_storage();
+    let counter = counter_address();
+    deploy_counter_contract(&storage, counter);
+    let optme = ConcurrencyLevelManager::new(storage, 10);
+    let tx = increment_transaction(1, counter, 0);
+    let consensus_output = vec![ExecutableEthereumBatch::new(
+        vec![tx.clone(), tx],
+        BatchDigest::default(),
+    )];
+
+    //when
+    let simulated = optme.simulate(consensus_output.clone()).await.unwrap();
+    optme.prepare_execution(consensus_output).await.unwrap();
+
+    //then (both are keyed apart, and both are committed)
+    assert_eq!(
+        simulated.rw_sets.iter().map(|tx| tx.id()).collect_vec(),
+        vec![0, 1]
+    );
+    assert_eq!(
+        optme
+            .global_state()
+            .get_storage()
+            .storage(counter, H256::zero()),
+        H256::from_low_u64_be(2)
+    );
+}
+
+#[tokio::test]
+async fn test_serial_equivalence() {
+    let counters = [0x156a, 0x156b].map(H160::from_low_u64_be);
+
+    // (100 distinct senders, or a few sending many transactions each, whose nonces then conflict)
+    for senders in [100, 4, 1] {
+        for seed in [1, 7, 42] {
+            for skewness in [0.0, 0.5, 0.9] {
+                let storage = concurrent_evm_storage();
+                counters
+                    .iter()
+                    .for_each(|counter| deploy_counter_contract(&storage, *counter));
+                let optme = ConcurrencyLevelManager::new(storage, 10).with_sender_chains(true);
+
+                assert_serial_equivalence(
+                    optme,
+                    skewed_counter_workload(seed, skewness, &counters, senders),
+                )
+                .await;
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_serializability_of_non_commuting_workloads() {
+    let setter = H160::from_low_u64_be(0x156c);
+    // sets and increments of the same slots, which end up in a different state in most of the orders.
+    let workloads = [
+        vec![
+            vec![
+                set_transaction(1, setter, 0, 5),
+                set_transaction(2, setter, 0, 9),
+            ],
+            vec![
+                set_transaction(3, setter, 1, 3),
+                set_transaction(4, setter, 0, 7),
+            ],
+        ],
+        vec![
+            vec![
+                increment_transaction(1, setter, 0),
+                set_transaction(2, setter, 0, 5),
+            ],
+            vec![
+                increment_transaction(3, setter, 0),
+                set_transaction(4, setter, 1, 3),
+                increment_transaction(5, setter, 1),
+            ],
+        ],
+    ];
+
+    for workload in workloads {
+        for concurrency_level in [1, 2] {
+            //given
+            let storage = concurrent_evm_storage();
+            deploy_setter_contract(&storage, setter);
+            let optme = ConcurrencyLevelManager::new(storage, concurrency_level);
+            let workload = workload
+                .iter()
+                .map(|txs| ExecutableEthereumBatch::new(txs.clone(), BatchDigest::default()))
+                .collect_vec();
+
+            //when, then
+            assert_serializable(optme, workload).await;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_optimistic_first_pass_matches_schedule_then_commit() {
+    let counters = [0x157a, 0x157b].map(H160::from_low_u64_be);
+    let manager = |mode: FirstPassMode| {
+        let storage = concurrent_evm_storage();
+        counters
+            .iter()
+            .for_each(|counter| deploy_counter_contract(&storage, *counter));
+        ConcurrencyLevelManager::new(storage, 10).with_first_pass_mode(mode)
+    };
+
+    //given (a moderate-conflict workload)
+    let workload = skewed_counter_workload(7, 0.5, &counters, 100);
+    let scheduled = manager(FirstPassMode::ScheduleThenCommit);
+    let optimistic = manager(FirstPassMode::OptimisticCommitThenValidate);
+
+    //when
+    scheduled._execute(workload.clone()).await.unwrap();
+    optimistic._execute(workload).await.unwrap();
+
+    //then (the conflicting increments are re-executed, and none of them is lost)
+    assert!(optimistic
+        .re_execution_histogram()
+        .keys()
+        .all(|rounds| *rounds > 0));
+    assert_eq!(
+        state_root(&optimistic.global_state()),
+        state_root(&scheduled.global_state())
+    );
+}
+
+#[tokio::test]
+async fn test_optimistic_first_pass_re_executes_stale_reads() {
+    let copier = H160::from_low_u64_be(0x157c);
+    let storage = concurrent_evm_storage();
+    deploy_copier_contract(&storage, copier);
+    let optme = ConcurrencyLevelManager::new(storage, 1)
+        .with_first_pass_mode(FirstPassMode::OptimisticCommitThenValidate);
+
+    //given (the copy reads the slot the set writes, without writing it)
+    let consensus_output = vec![ExecutableEthereumBatch::new(
+        vec![
+            set_transaction(1, copier, 0, 5),
+            increment_transaction(2, copier, 0),
+        ],
+        BatchDigest::default(),
+    )];
+    let slot = |key: u64| {
+        optme
+            .global_state()
+            .get_storage()
+            .storage(copier, H256::from_low_u64_be(key))
+    };
+
+    //when
+    let receipts = optme.execute_to_receipts(consensus_output).await.unwrap();
+
+    //then (the copy is re-executed over the set)
+    assert_eq!(slot(0), H256::from_low_u64_be(5));
+    assert_eq!(slot(1), H256::from_low_u64_be(5));
+    assert_eq!(optme.re_execution_histogram().get(&1), Some(&1));
+    assert_eq!(receipts.len(), 2);
+    assert!(optme.gas_metrics().aborted_gas > 0);
+}
+
+/* guards the quality of the schedule against regressions: on a fixed seeded workload, the generations must stay
+as wide (and as few) as they are now. The counters touch at most 16 distinct keys, each updated by one scheduled transaction. */
+#[tokio::test]
+async fn test_scheduling_quality_regression() {
+    const SEED: u64 = 42;
+    const MIN_AVERAGE_WIDTH: f64 = 12.0;
+    const MAX_DEPTH: usize = 2;
+    let counters = [0x178a, 0x178b].map(H160::from_low_u64_be);
+
+    for skewness in [0.0, 0.5] {
+        //given
+        let storage = concurrent_evm_storage();
+        counters
+            .iter()
+            .for_each(|counter| deploy_counter_contract(&storage, *counter));
+        let optme = ConcurrencyLevelManager::new(storage, 4);
+        let SimulationResult { rw_sets, .. } = optme
+            .simulate(skewed_counter_workload(SEED, skewness, &counters, 100))
+            .await
+            .unwrap();
+
+        //when
+        let (total_tx, average_width, _, max_width, depth) =
+            AddressBasedConflictGraph::par_construct(rw_sets)
+                .await
+                .hierarchcial_sort()
+                .reorder()
+                .par_extract_schedule()
+                .await
+                .parallism_metric();
+
+        //then
+        assert_eq!(total_tx, 100);
+        assert!(
+            average_width >= MIN_AVERAGE_WIDTH,
+            "average width {average_width:.2} < {MIN_AVERAGE_WIDTH} (skewness {skewness})"
+        );
+        assert!(
+            (1..=MAX_DEPTH).contains(&depth),
+            "depth {depth} > {MAX_DEPTH} (skewness {skewness})"
+        );
+        assert!(max_width as f64 >= average_width);
+    }
+}
+
+#[tokio::test]
+async fn test_duplicate_transactions_are_dropped() {
+    let counter = counter_address();
+    let storage = counter_storage();
+    let optme = ConcurrencyLevelManager::new(storage, 2).with_tx_dedup(true);
+
+    //given (tx 1 is submitted twice, through different batches; tx 2 only differs in its sender)
+    let duplicated = increment_transaction(1, counter, 0);
+    let consensus_output = vec![
+        ExecutableEthereumBatch::new(
+            vec![duplicated.clone(), increment_transaction(2, counter, 0)],
+            BatchDigest::new([1; 32]),
+        ),
+        ExecutableEthereumBatch::new(vec![duplicated], BatchDigest::new([2; 32])),
+    ];
+
+    //when
+    let simulated = optme.simulate(consensus_output.clone()).await.unwrap();
+
+    //then (ids stay contiguous, and only the execution counts the duplicates)
+    assert_eq!(optme.removed_duplicates(), 0);
+    assert_eq!(
+        simulated.rw_sets.iter().map(|tx| tx.id()).collect_vec(),
+        vec![0, 1]
+    );
+
+    //when
+    let result = optme.prepare_execution(consensus_output).await.unwrap();
+
+    //then
+    assert_eq!(result.digests.len(), 2);
+    assert_eq!(optme.removed_duplicates(), 1);
+    assert_eq!(
+        optme
+            .global_state()
+            .get_storage()
+            .storage(counter, H256::zero()),
+        H256::from_low_u64_be(2)
+    );
+}
+
+#[tokio::test]
+async fn test_duplicate_transactions_in_different_windows_are_dropped() {
+    let counter = counter_address();
+    let storage = counter_storage();
+    let optme = ConcurrencyLevelManager::new(storage, 1).with_tx_dedup(true);
+
+    //given (one window per batch, and tx 1 is submitted in both)
+    let duplicated = increment_transaction(1, counter, 0);
+    let consensus_output = vec![
+        ExecutableEthereumBatch::new(vec![duplicated.clone()], BatchDigest::new([1; 32])),
+        ExecutableEthereumBatch::new(
+            vec![duplicated, increment_transaction(2, counter, 0)],
+            BatchDigest::new([2; 32]),
+        ),
+    ];
+
+    //when
+    let result = optme.prepare_execution(consensus_output).await.unwrap();
+
+    //then
+    assert_eq!(result.digests.len(), 2);
+    assert_eq!(optme.removed_duplicates(), 1);
+    assert_eq!(
+        optme
+            .global_state()
+            .get_storage()
+            .storage(counter, H256::zero()),
+        H256::from_low_u64_be(2)
+    );
+}
+
+#[tokio::test]
+async fn test_receipts_are_in_input_order() {
+    let counter = counter_address();
+    let storage = counter_storage();
+    let optme = ConcurrencyLevelManager::new(storage, 1);
+
+    //given (2 windows; in the 1st one, tx 2 conflicts with tx 1 on slot 0, and is committed after re-execution)
+    let consensus_output = vec![
+        ExecutableEthereumBatch::new(
+            vec![
+                increment_transaction(1, counter, 0),
+                increment_transaction(2, counter, 0),
+                increment_transaction(3, counter, 1),
+            ],
+            BatchDigest::new([1; 32]),
+        ),
+        ExecutableEthereumBatch::new(
+            vec![increment_transaction(4, counter, 0)],
+            BatchDigest::new([2; 32]),
+        ),
+    ];
+
+    //when
+    let receipts = optme.execute_to_receipts(consensus_output).await.unwrap();
+
+    //then
+    assert_eq!(
+        receipts.iter().map(|receipt| receipt.from).collect_vec(),
+        (1..=4).map(H160::from_low_u64_be).collect_vec()
+    );
+    assert_eq!(
+        receipts
+            .iter()
+            .map(|receipt| receipt.transaction_index.as_u64())
+            .collect_vec(),
+        vec![0, 1, 2, 3]
+    );
+    assert!(receipts
+        .iter()
+        .all(|receipt| receipt.status == Some(1.into()) && receipt.gas_used > Some(U256::zero())));
+    assert!(receipts
+        .windows(2)
+        .all(|pair| pair[0].cumulative_gas_used < pair[1].cumulative_gas_used));
+    assert_eq!(
+        receipts.last().unwrap().cumulative_gas_used,
+        receipts
+            .iter()
+            .map(|receipt| receipt.gas_used.unwrap())
+            .fold(U256::zero(), |sum, gas| sum + gas)
+    );
+    assert_eq!(
+        optme
+            .global_state()
+            .get_storage()
+            .storage(counter, H256::zero()),
+        H256::from_low_u64_be(3)
+    );
+}
+
+#[tokio::test]
+async fn test_concurrent_calls_collect_their_own_receipts() {
+    let counter = counter_address();
+    let storage = counter_storage();
+    let optme = ConcurrencyLevelManager::new(storage, 1);
+
+    //given (two outputs over different slots, whose window-local ids overlap)
+    let output = |senders: std::ops::RangeInclusive<u64>, slot: u64| {
+        vec![ExecutableEthereumBatch::new(
+            senders
+                .map(|sender| increment_transaction(sender, counter, slot))
+                .collect(),
+            BatchDigest::new([slot as u8; 32]),
+        )]
+    };
+
+    //when
+    let (first, second) = tokio::join!(
+        optme.execute_to_receipts(output(1..=3, 0)),
+        optme.execute_to_receipts(output(4..=5, 1))
+    );
+    let (first, second) = (first.unwrap(), second.unwrap());
+
+    //then
+    assert_eq!(
+        first.iter().map(|receipt| receipt.from).collect_vec(),
+        (1..=3).map(H160::from_low_u64_be).collect_vec()
+    );
+    assert_eq!(
+        second.iter().map(|receipt| receipt.from).collect_vec(),
+        (4..=5).map(H160::from_low_u64_be).collect_vec()
+    );
+}
+
+#[tokio::test]
+async fn test_small_block_is_executed_serially() {
+    let counter = counter_address();
+    let manager = |min_txs: usize| {
+        let storage = counter_storage();
+        ConcurrencyLevelManager::new(storage, 10).with_min_parallel_txs(min_txs)
+    };
+    let block = || {
+        vec![ExecutableEthereumBatch::new(
+            vec![
+                increment_transaction(1, counter, 0),
+                increment_transaction(2, counter, 0),
+                increment_transaction(3, counter, 1),
+            ],
+            BatchDigest::new([1; 32]),
+        )]
+    };
+
+    //given
+    let serial = manager(4);
+    let parallel = manager(3);
+
+    //when
+    let serial_digests = serial._execute(block()).await.unwrap();
+    let parallel_digests = parallel._execute(block()).await.unwrap();
+
+    //then (only the parallel path goes through the rounds of the histogram)
+    assert!(serial.re_execution_histogram().is_empty());
+    assert_eq!(parallel.re_execution_histogram().values().sum::<usize>(), 1);
+    assert_eq!(serial_digests, parallel_digests);
+    for (key, value) in [(0, 2), (1, 1)] {
+        assert_eq!(
+            serial
+                .global_state()
+                .get_storage()
+                .storage(counter, H256::from_low_u64_be(key)),
+            H256::from_low_u64_be(value)
+        );
+    }
+    assert_eq!(
+        state_root(&serial.global_state()),
+        state_root(&parallel.global_state())
+    );
+}
+
+#[tokio::test]
+async fn test_contention_patterns() {
+    let handler = get_smallbank_handler();
+    let n = 8;
+    let optme = OptME::new(concurrent_evm_storage(), 10);
+    optme
+        .execute(vec![ExecutableEthereumBatch::new(
+            (0..=n as u64)
+                .map(|acc| handler.create_account_transaction(acc))
+                .collect(),
+            BatchDigest::default(),
+        )])
+        .await;
+
+    let schedule = |pattern| {
+        let consensus_output = vec![handler.create_contention_pattern(pattern, n)];
+        let state = optme.global_state().snapshot();
+        async move {
+            let SimulationResult { rw_sets, .. } = ConcurrencyLevelManager::new(state, 10)
+                .simulate(consensus_output)
+                .await
+                .unwrap();
+            assert!(rw_sets.iter().all(|tx| tx.outcome().is_success()));
+
+            AddressBasedConflictGraph::construct(rw_sets)
+                .hierarchcial_sort()
+                .reorder()
+                .extract_schedule()
+        }
+    };
+
+    //when
+    let chain = schedule(ContentionPattern::Chain).await;
+    let star = schedule(ContentionPattern::Star).await;
+
+    //then (a star is fully serial: a single transaction commits at a time)
+    assert_eq!(star.scheduled_txs_len(), 1);
+    assert_eq!(star.aborted_txs_len(), n - 1);
+    assert_eq!(star.aborted_txs.len(), n - 1);
+    assert!(star.aborted_txs.iter().all(|epoch| epoch.len() == 1));
+
+    //then (a chain conflicts only between neighbors, so at least every other transaction commits at once)
+    assert_eq!(chain.scheduled_txs_len() + chain.aborted_txs_len(), n);
+    assert!(chain.scheduled_txs_len() >= n / 2);
+}
+
+#[tokio::test]
+async fn test_buffer_pooling_matches_fresh_buffers() {
+    let counter = counter_address();
+    let manager = |pooled: bool| {
+        let storage = counter_storage();
+        ConcurrencyLevelManager::new(storage, 2).with_buffer_pooling(pooled)
+    };
+    // every block increments the hot slot 0 (so that some transactions are re-executed), and slots of their own.
+    let block = |b: u64| {
+        (0..2)
+            .map(|batch| {
+                ExecutableEthereumBatch::new(
+                    (0..4)
+                        .map(|i| {
+                            let sender = b * 8 + batch * 4 + i + 1;
+                            increment_transaction(sender, counter, if i == 0 { 0 } else { sender })
+                        })
+                        .collect(),
+                    BatchDigest::default(),
+                )
+            })
+            .collect_vec()
+    };
+
+    //given
+    let fresh = manager(false);
+    let pooled = manager(true);
+
+    //when (the blocks are executed one after another, so the pooled vectors are recycled across them)
+    for b in 0..5 {
+        fresh._execute(block(b)).await.unwrap();
+        pooled._execute(block(b)).await.unwrap();
+    }
+
+    //then
+    assert_eq!(
+        pooled.re_execution_histogram(),
+        fresh.re_execution_histogram()
+    );
+    assert_eq!(
+        state_root(&pooled.global_state()),
+        state_root(&fresh.global_state())
+    );
+}
+
+#[tokio::test]
+async fn test_timed_execution_matches_untimed_execution() {
+    let handler = get_smallbank_handler();
+    let consensus_output = handler.create_batches(20, 2, 0.8, 100);
+    let num_txs = consensus_output
+        .iter()
+        .map(|batch| batch.data().len())
+        .sum::<usize>();
+
+    //given
+    let untimed = ConcurrencyLevelManager::new(concurrent_evm_storage(), 2);
+    let timed = ConcurrencyLevelManager::new(concurrent_evm_storage(), 2);
+    let mut timings = PhaseTimings::default();
+
+    //when
+    let untimed_digests = untimed._execute(consensus_output.clone()).await.unwrap();
+    let timed_digests = timed
+        ._execute_with(consensus_output, Some(&mut timings))
+        .await
+        .unwrap();
+
+    //then (the same execution, along with the timings of its phases)
+    assert_eq!(timed_digests, untimed_digests);
+    assert_eq!(
+        state_root(&timed.global_state()),
+        state_root(&untimed.global_state())
+    );
+    assert!(timings.committed_txs > 0 && timings.committed_txs <= num_txs as u128);
+    assert!(
+        timings.total >= timings.simulation + timings.scheduling + timings.commit + timings.serial
+    );
+}
+
+#[tokio::test]
+async fn test_cancel_simulation() {
+    let handler = get_smallbank_handler();
+    let optme = ConcurrencyLevelManager::new(concurrent_evm_storage(), 10);
+
+    //given
+    let consensus_output = handler.create_batches(1_000, 10, 0.0, 1_000);
+    let num_txs = consensus_output
+        .iter()
+        .map(|batch| batch.data().len())
+        .sum::<usize>();
+    let cancelled = tokio_util::sync::CancellationToken::new();
+    cancelled.cancel();
+
+    //when (cancelled before the simulation, and never cancelled)
+    let nothing = optme
+        .simulate_cancellable(consensus_output.clone(), cancelled)
+        .await
+        .unwrap();
+    let everything = optme
+        .simulate_cancellable(consensus_output, tokio_util::sync::CancellationToken::new())
+        .await
+        .unwrap();
+
+    //then (the digests are kept either way, and the transactions simulated are in the order of ids)
+    assert!(nothing.rw_sets.is_empty());
+    assert_eq!(nothing.digests.len(), 10);
+    assert_eq!(everything.rw_sets.len(), num_txs);
+    assert!(everything
+        .rw_sets
+        .windows(2)
+        .all(|pair| pair[0].id() < pair[1].id()));
+}
+
+#[tokio::test]
+async fn test_simulation_summary() {
+    let handler = get_smallbank_handler();
+    let optme = ConcurrencyLevelManager::new(concurrent_evm_storage(), 1);
+
+    //given
+    let consensus_output = handler.create_batches(50, 1, 0.0, 1_000);
+    let num_txs = consensus_output[0].data().len();
+    let result = optme.simulate(consensus_output).await.unwrap();
+
+    //when
+    let summary = result.summary();
+
+    //then
+    assert_eq!(summary.tx_count, result.rw_sets.len());
+    assert!(summary.tx_count > 0 && summary.tx_count <= num_txs);
+    assert_eq!(
+        summary.total_reads,
+        result
+            .rw_sets
+            .iter()
+            .map(|tx| tx.read_set().len())
+            .sum::<usize>()
+    );
+    assert_eq!(
+        summary.total_writes,
+        result
+            .rw_sets
+            .iter()
+            .map(|tx| tx.write_set().len())
+            .sum::<usize>()
+    );
+    assert_eq!(summary.total_effects, result.all_effects().count());
+    assert_eq!(
+        summary.avg_rw_set_size,
+        (summary.total_reads + summary.total_writes) as f64 / summary.tx_count as f64
+    );
+    assert!(summary.max_rw_set_size as f64 >= summary.avg_rw_set_size);
+    assert_eq!(SimulationResult::default().summary(), SimSummary::default());
+}
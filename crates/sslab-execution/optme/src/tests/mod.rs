@@ -1,5 +1,17 @@
+#[cfg(test)]
+mod test_utils;
+
 #[cfg(test)]
 mod integration_tests;
 
 #[cfg(test)]
 mod optme_tests;
+
+#[cfg(all(test, feature = "sim-cache"))]
+mod codec_tests;
+
+#[cfg(test)]
+mod service_tests;
+
+#[cfg(all(test, feature = "wal"))]
+mod wal_tests;
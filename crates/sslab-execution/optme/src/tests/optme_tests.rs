@@ -1,14 +1,25 @@
-use std::str::FromStr;
+use std::{collections::BTreeMap, str::FromStr};
 
-use ethers_core::types::{H160, H256};
-use evm::executor::stack::{RwSet, Simulatable};
+use ethers_core::types::{BloomInput, H160, H256, U256};
+use evm::{
+    backend::{Apply, Basic, Log},
+    executor::stack::{RwSet, Simulatable},
+};
 use hashbrown::HashSet;
 use itertools::Itertools;
 use sslab_execution::types::{EthereumTransaction, IndexedEthereumTransaction};
 
 use crate::{
-    address_based_conflict_graph::AddressBasedConflictGraph, optme_core::ScheduledInfo,
-    types::SimulatedTransaction,
+    address_based_conflict_graph::{AddressBasedConflictGraph, Transaction},
+    optme_core::ScheduledInfo,
+    scheduling::EpochMap,
+    types::{
+        block_logs_bloom, collapse_effects, merge_rw_sets, AbortClearing, AbortReason,
+        AbortedTransaction, AutoTuner, OptmeError, ReExecutedTransaction, ScheduledTransaction,
+        SimulatedTransaction,
+    },
+    utils::{run_on_rayon, RayonError},
+    SimulationResult,
 };
 
 const CONTRACT_ADDR: u64 = 0x1;
@@ -149,7 +160,7 @@ async fn optme_par_test(
     let ScheduledInfo {
         scheduled_txs,
         aborted_txs,
-    } = AddressBasedConflictGraph::par_construct(input_txs.clone())
+    } = AddressBasedConflictGraph::par_construct(input_txs)
         .await
         .hierarchcial_sort()
         .reorder()
@@ -377,3 +388,911 @@ async fn test_scenario_6() {
     );
     optme_par_test(txs.clone(), (first_scheduled, second_scheduled), false).await;
 }
+
+#[test]
+fn test_adjacency_of_scenario_6() {
+    //given (txs 1 and 2 are disjoint, and tx 3 updates a key of each)
+    let txs = vec![
+        transaction_with_multiple_rw_str(
+            1,
+            vec![
+                "0x48c8d13a49dbf1c93484ba997be20d9cae319d82960232db3544bb8bf65d4ac0",
+                "0xe3ea58be4f1efa6db4e24abc274fb1bccd82dfcd49c8f508a08c911f0357c19d",
+            ],
+            vec![
+                "0x48c8d13a49dbf1c93484ba997be20d9cae319d82960232db3544bb8bf65d4ac0",
+                "0xe3ea58be4f1efa6db4e24abc274fb1bccd82dfcd49c8f508a08c911f0357c19d",
+            ],
+        ),
+        transaction_with_multiple_rw_str(
+            2,
+            vec![
+                "0x7b6a909101d770fd973075a9dbcef6c7ae894d77f3f89dcacb997ab3178cd44e",
+                "0xb955ea50cf68e45358af8183015c9694f0e9401fee45e367d90c462108f102bd",
+            ],
+            vec![
+                "0x7b6a909101d770fd973075a9dbcef6c7ae894d77f3f89dcacb997ab3178cd44e",
+                "0xb955ea50cf68e45358af8183015c9694f0e9401fee45e367d90c462108f102bd",
+            ],
+        ),
+        transaction_with_multiple_rw_str(
+            3,
+            vec![
+                "0x7b6a909101d770fd973075a9dbcef6c7ae894d77f3f89dcacb997ab3178cd44e",
+                "0xe3ea58be4f1efa6db4e24abc274fb1bccd82dfcd49c8f508a08c911f0357c19d",
+            ],
+            vec![
+                "0x7b6a909101d770fd973075a9dbcef6c7ae894d77f3f89dcacb997ab3178cd44e",
+                "0xe3ea58be4f1efa6db4e24abc274fb1bccd82dfcd49c8f508a08c911f0357c19d",
+            ],
+        ),
+    ];
+
+    //when
+    let acg = AddressBasedConflictGraph::construct(txs);
+
+    //then (tx 3 shares a single key with each of the others, even though it is aborted while inserted)
+    assert_eq!(acg.to_adjacency(), vec![(1, 3, 1), (2, 3, 1)]);
+}
+
+#[test]
+fn test_adjacency_counts_shared_keys() {
+    //given (tx 2 writes the key tx 1 reads, and tx 3 writes both keys tx 1 and tx 2 write)
+    let txs = vec![
+        transaction_with_multiple_rw(1, vec![1], vec![2]),
+        transaction_with_multiple_rw(2, vec![3], vec![1]),
+        transaction_with_multiple_rw(3, vec![], vec![1, 2]),
+        transaction_with_multiple_rw(4, vec![5], vec![6]),
+    ];
+
+    //when
+    let acg = AddressBasedConflictGraph::construct(txs);
+
+    //then (tx 4 conflicts with none)
+    assert_eq!(acg.to_adjacency(), vec![(1, 2, 1), (1, 3, 2), (2, 3, 1)]);
+}
+
+#[tokio::test]
+async fn test_incremental_update() {
+    let txs = vec![
+        transaction_with_rw(1, 1, 2),
+        transaction_with_rw(2, 2, 3),
+        transaction_with_rw(3, 3, 4),
+        transaction_with_rw(4, 4, 5),
+        transaction_with_rw(5, 6, 7),
+    ];
+
+    // tx 3 reads a different key at re-execution.
+    let re_executed = transaction_with_rw(3, 5, 1);
+    let (_, rw_set, effects, logs, raw_tx) = re_executed.clone().deconstruct();
+
+    let mut reconstructed = txs.clone();
+    reconstructed[2] = re_executed;
+
+    let ScheduledInfo {
+        scheduled_txs,
+        aborted_txs,
+    } = AddressBasedConflictGraph::construct(txs)
+        .update(vec![ReExecutedTransaction::build_from(
+            raw_tx, effects, logs, rw_set,
+        )])
+        .hierarchcial_sort()
+        .reorder()
+        .extract_schedule();
+
+    let ScheduledInfo {
+        scheduled_txs: expected_scheduled_txs,
+        aborted_txs: expected_aborted_txs,
+    } = AddressBasedConflictGraph::construct(reconstructed)
+        .hierarchcial_sort()
+        .reorder()
+        .extract_schedule();
+
+    let ids = |txs: Vec<Vec<u64>>| {
+        txs.into_iter()
+            .map(|txs| txs.into_iter().sorted().collect_vec())
+            .collect_vec()
+    };
+
+    assert_eq!(
+        ids(scheduled_txs
+            .iter()
+            .map(|txs| txs.iter().map(|tx| tx.id()).collect())
+            .collect()),
+        ids(expected_scheduled_txs
+            .iter()
+            .map(|txs| txs.iter().map(|tx| tx.id()).collect())
+            .collect())
+    );
+    assert_eq!(
+        ids(aborted_txs
+            .iter()
+            .map(|txs| txs.iter().map(|tx| tx.id()).collect())
+            .collect()),
+        ids(expected_aborted_txs
+            .iter()
+            .map(|txs| txs.iter().map(|tx| tx.id()).collect())
+            .collect())
+    );
+}
+
+#[tokio::test]
+async fn test_retain_removes_the_other_txs() {
+    let txs = vec![
+        transaction_with_rw(1, 1, 2),
+        transaction_with_rw(2, 2, 3),
+        transaction_with_rw(3, 3, 4),
+        transaction_with_rw(4, 4, 5),
+    ];
+    let kept = txs.iter().filter(|tx| tx.id() != 3).cloned().collect_vec();
+
+    //given
+    let mut acg = AddressBasedConflictGraph::construct(txs);
+
+    //when
+    let ScheduledInfo {
+        scheduled_txs,
+        aborted_txs,
+    } = acg
+        .retain(&[1, 2, 4].into_iter().collect())
+        .hierarchcial_sort()
+        .reorder()
+        .extract_schedule();
+
+    //then
+    let ScheduledInfo {
+        scheduled_txs: expected_scheduled_txs,
+        aborted_txs: expected_aborted_txs,
+    } = AddressBasedConflictGraph::construct(kept)
+        .hierarchcial_sort()
+        .reorder()
+        .extract_schedule();
+
+    let ids = |txs: Vec<Vec<u64>>| {
+        txs.into_iter()
+            .map(|txs| txs.into_iter().sorted().collect_vec())
+            .collect_vec()
+    };
+    assert_eq!(
+        ids(scheduled_txs
+            .iter()
+            .map(|txs| txs.iter().map(|tx| tx.id()).collect())
+            .collect()),
+        ids(expected_scheduled_txs
+            .iter()
+            .map(|txs| txs.iter().map(|tx| tx.id()).collect())
+            .collect())
+    );
+    assert_eq!(
+        ids(aborted_txs
+            .iter()
+            .map(|txs| txs.iter().map(|tx| tx.id()).collect())
+            .collect()),
+        ids(expected_aborted_txs
+            .iter()
+            .map(|txs| txs.iter().map(|tx| tx.id()).collect())
+            .collect())
+    );
+}
+
+#[tokio::test]
+async fn test_total_order() {
+    let txs = vec![
+        transaction_with_rw(1, 2, 1),
+        transaction_with_rw(2, 3, 2),
+        transaction_with_rw(3, 4, 2),
+        transaction_with_rw(4, 4, 3),
+        transaction_with_rw(5, 4, 4),
+        transaction_with_rw(6, 1, 3),
+    ];
+
+    // scheduled: [[2], [3, 4], [5, 6]], aborted: [[1]] (see `test_scenario_1`)
+    let schedule = AddressBasedConflictGraph::par_construct(txs)
+        .await
+        .hierarchcial_sort()
+        .reorder()
+        .par_extract_schedule()
+        .await;
+
+    assert_eq!(schedule.total_order(), vec![2, 3, 4, 5, 6, 1]);
+}
+
+#[test]
+fn test_abort_reasons_are_recorded() {
+    //given (as in scenario 5: txs 4, 5 and 7 update key 4, and tx 6 closes a cycle through keys 1, 2 and 3)
+    let txs = vec![
+        transaction_with_rw(1, 2, 1),
+        transaction_with_rw(2, 3, 2),
+        transaction_with_rw(3, 4, 2),
+        transaction_with_rw(4, 4, 4),
+        transaction_with_rw(5, 4, 4),
+        transaction_with_rw(6, 1, 3),
+        transaction_with_rw(7, 4, 4),
+    ];
+
+    //when
+    let ScheduledInfo { aborted_txs, .. } = AddressBasedConflictGraph::construct(txs)
+        .hierarchcial_sort()
+        .reorder()
+        .extract_schedule();
+
+    //then
+    let reasons = aborted_txs
+        .iter()
+        .flatten()
+        .map(|tx| (tx.id(), tx.abort_reason()))
+        .sorted_by_key(|(id, _)| *id)
+        .collect_vec();
+    assert_eq!(
+        reasons,
+        vec![
+            (5, Some(AbortReason::WriteWrite)),
+            (6, Some(AbortReason::Rescheduled)),
+            (7, Some(AbortReason::WriteWrite)),
+        ]
+    );
+}
+
+#[test]
+fn test_aborted_transaction_from_shared_arc() {
+    let (tx, _) = Transaction::from(transaction_with_rw(7, 1, 2));
+    let tx = std::sync::Arc::new(tx);
+
+    //given (a clone still holds the transaction)
+    let holder = tx.clone();
+
+    //when
+    let err = AbortedTransaction::try_from(tx).unwrap_err();
+
+    //then (a clean error which gives the transaction back)
+    assert!(err
+        .to_string()
+        .contains("transaction 7 is still shared (strong:2"));
+
+    drop(holder);
+    let aborted = AbortedTransaction::try_from(err.0).unwrap();
+    assert_eq!(aborted.id(), 7);
+    assert!(aborted.read_keys().contains(&H256::from_low_u64_be(1)));
+    assert!(aborted.write_keys().contains(&H256::from_low_u64_be(2)));
+}
+
+#[test]
+fn test_scheduled_transaction_from_shared_arc() {
+    let (tx, _) = Transaction::from(transaction_with_rw(7, 1, 2));
+    let tx = std::sync::Arc::new(tx);
+
+    //given (a clone still holds the transaction)
+    let holder = tx.clone();
+
+    //when
+    let scheduled = ScheduledTransaction::from(tx);
+
+    //then (the transaction is copied, instead of panicking)
+    assert_eq!(scheduled.id(), 7);
+    assert_eq!(scheduled.seq(), holder.sequence());
+    assert_eq!(std::sync::Arc::strong_count(&holder), 1);
+}
+
+#[test]
+fn test_aborted_transaction_into_indexed_keeps_id() {
+    //given
+    let simulated = transaction_with_rw(7, 1, 2);
+    let raw_tx = simulated.raw_tx().clone();
+    let (tx, _) = Transaction::from(simulated);
+    let aborted = AbortedTransaction::try_from(std::sync::Arc::new(tx)).unwrap();
+
+    //when
+    let indexed = IndexedEthereumTransaction::from(aborted);
+
+    //then
+    assert_eq!(indexed.id, 7);
+    assert_eq!(indexed, raw_tx);
+}
+
+#[test]
+fn test_streaming_abort_clearing_assigns_same_epochs() {
+    //given (aborted transactions with overlapping keys, out of the order of ids)
+    let aborted_txs = || {
+        [
+            (1, 1, 2),
+            (2, 2, 3),
+            (3, 3, 3),
+            (4, 5, 1),
+            (5, 6, 7),
+            (6, 7, 7),
+        ]
+        .into_iter()
+        .rev()
+        .map(|(id, read, write)| {
+            let (tx, _) = Transaction::from(transaction_with_rw(id, read, write));
+            std::sync::Arc::new(tx)
+        })
+        .collect_vec()
+    };
+    let epochs = |info: ScheduledInfo| {
+        info.aborted_txs
+            .iter()
+            .map(|txs| txs.iter().map(|tx| tx.id()).collect_vec())
+            .collect_vec()
+    };
+
+    //when
+    let batched = epochs(ScheduledInfo::par_from(Default::default(), aborted_txs()));
+    let streaming = epochs(ScheduledInfo::par_from_with(
+        Default::default(),
+        aborted_txs(),
+        AbortClearing::Streaming,
+    ));
+
+    //then
+    assert!(batched.len() > 1);
+    assert_eq!(batched, streaming);
+}
+
+#[test]
+fn test_clear_write_units_and_init_are_idempotent() {
+    //given (a transaction sorted in a graph, whose units refer back to it)
+    let mut acg = AddressBasedConflictGraph::construct(vec![
+        transaction_with_rw(1, 1, 2),
+        transaction_with_rw(2, 2, 3),
+    ]);
+    acg.hierarchcial_sort();
+    let tx = acg.transaction(2).unwrap().clone();
+    let rw_set = tx.rw_set();
+    drop(acg);
+    assert_ne!(tx.sequence(), 0);
+
+    //when
+    for _ in 0..2 {
+        tx.clear_write_units();
+        tx.init();
+    }
+
+    //then (no unit is left referring to the transaction, and only its rw-set is kept)
+    assert_eq!(std::sync::Arc::strong_count(&tx), 1);
+    assert_eq!(tx.sequence(), 0);
+    assert_eq!(tx.rw_set(), rw_set);
+    assert!(AbortedTransaction::try_from(tx).is_ok());
+}
+
+#[test]
+fn test_merge_rw_sets() {
+    let round = |read_addr: u64, write_addr: u64| {
+        let mut set = RwSet::new();
+        set.record_read_key(
+            H160::from_low_u64_be(CONTRACT_ADDR),
+            H256::from_low_u64_be(read_addr),
+            H256::from_low_u64_be(1),
+        );
+        set.record_write_key(
+            H160::from_low_u64_be(CONTRACT_ADDR),
+            H256::from_low_u64_be(write_addr),
+            H256::from_low_u64_be(1),
+        );
+        set
+    };
+
+    //given (the second round reads another key)
+    let rounds = vec![round(1, 2), round(3, 2)];
+
+    //when
+    let (reads, writes) = merge_rw_sets(&rounds);
+
+    //then
+    assert_eq!(
+        reads,
+        HashSet::from_iter([H256::from_low_u64_be(1), H256::from_low_u64_be(3)])
+    );
+    assert_eq!(writes, HashSet::from_iter([H256::from_low_u64_be(2)]));
+}
+
+#[test]
+fn test_init_resets_sequence() {
+    //given (a transaction ordered in a previous round)
+    let mut acg = AddressBasedConflictGraph::construct(vec![transaction_with_rw(1, 1, 2)]);
+    acg.hierarchcial_sort();
+    let tx = acg.transaction(1).unwrap().clone();
+    assert_ne!(tx.sequence(), 0);
+
+    //when
+    tx.init();
+
+    //then
+    assert_eq!(tx.sequence(), 0);
+    assert!(!tx.abort_info.read().aborted());
+}
+
+#[test]
+fn test_filter_committed() {
+    let result = SimulationResult {
+        digests: vec![],
+        rw_sets: (0..4)
+            .map(|id| transaction_with_rw(id, id + 10, id + 20))
+            .collect(),
+    };
+    let committed = std::collections::HashSet::from([1, 3, 7]);
+
+    //when
+    let filtered = result.filter_committed(&committed);
+
+    //then
+    assert_eq!(
+        filtered.rw_sets.iter().map(|tx| tx.id()).collect_vec(),
+        vec![1, 3]
+    );
+    filtered.rw_sets.iter().for_each(|tx| {
+        let original = &result.rw_sets[tx.id() as usize];
+        assert_eq!(tx.read_set(), original.read_set());
+        assert_eq!(tx.write_set(), original.write_set());
+    });
+    assert!(result
+        .filter_committed(&Default::default())
+        .rw_sets
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_run_on_rayon() {
+    assert_eq!(run_on_rayon(|| (0..4u64).sum::<u64>()).await, Ok(6));
+
+    //when
+    let result = run_on_rayon(|| -> u64 { panic!("boom") }).await;
+
+    //then
+    assert_eq!(result, Err(RayonError::Panicked("boom".to_string())));
+}
+
+#[tokio::test]
+async fn test_worker_panic_into_optme_error() {
+    //when
+    let err = run_on_rayon(|| -> u64 { panic!("boom") })
+        .await
+        .unwrap_err();
+
+    //then
+    let err = OptmeError::from(err);
+    assert!(matches!(&err, OptmeError::Worker(RayonError::Panicked(msg)) if msg == "boom"));
+}
+
+#[test]
+fn test_append_scheduled_info() {
+    let schedule = |txs: Vec<SimulatedTransaction>| {
+        AddressBasedConflictGraph::construct(txs)
+            .hierarchcial_sort()
+            .reorder()
+            .extract_schedule()
+    };
+    let ids = |info: &ScheduledInfo| {
+        info.scheduled_txs
+            .iter()
+            .flat_map(|txs| txs.iter().map(|tx| tx.id()))
+            .chain(
+                info.aborted_txs
+                    .iter()
+                    .flat_map(|txs| txs.iter().map(|tx| tx.id())),
+            )
+            .sorted()
+            .collect_vec()
+    };
+
+    //given (two windows with conflicts in each)
+    let mut first = schedule(vec![
+        transaction_with_rw(1, 1, 2),
+        transaction_with_rw(2, 2, 3),
+        transaction_with_rw(3, 3, 1),
+    ]);
+    let second = schedule(vec![
+        transaction_with_rw(4, 4, 5),
+        transaction_with_rw(5, 5, 4),
+        transaction_with_rw(6, 6, 6),
+    ]);
+    let generations = first.scheduled_txs.len() + second.scheduled_txs.len();
+    let epochs = first.aborted_txs.len() + second.aborted_txs.len();
+    let (first_ids, second_ids) = (ids(&first), ids(&second));
+
+    //when
+    first.append(second);
+
+    //then (each transaction keeps its generation in the whole-block view)
+    assert_eq!(first.scheduled_txs.len(), generations);
+    for (generation, txs) in first.scheduled_txs.iter().enumerate() {
+        assert!(txs.iter().all(|tx| tx.seq() == generation as u32));
+    }
+    assert_eq!(first.aborted_txs.len(), epochs);
+    assert_eq!(
+        ids(&first),
+        first_ids
+            .into_iter()
+            .chain(second_ids)
+            .sorted()
+            .collect_vec()
+    );
+    assert_eq!(ids(&first), (1..=6).collect_vec());
+}
+
+#[test]
+fn test_reorder_preserve_order() {
+    // the number of pairs of transactions scheduled in the opposite order to their ids.
+    let reordering_distance = |scheduled: &Vec<Vec<u64>>| {
+        scheduled
+            .iter()
+            .enumerate()
+            .flat_map(|(i, txs)| {
+                scheduled[i + 1..]
+                    .iter()
+                    .flatten()
+                    .flat_map(move |later| txs.iter().filter(move |tx| *tx > later))
+            })
+            .count()
+    };
+    let schedule = |preserve_order: bool| {
+        // tx1 blindly writes 1 and 2, and is aborted at 2 by the reader tx2 (scheduled after it at 1).
+        let txs = vec![
+            transaction_with_multiple_rw(1, vec![], vec![1, 2]),
+            transaction_with_rw(2, 2, 1),
+            transaction_with_multiple_rw(3, vec![], vec![1]),
+            transaction_with_multiple_rw(4, vec![], vec![1]),
+            transaction_with_multiple_rw(5, vec![], vec![1]),
+        ];
+        let mut graph = AddressBasedConflictGraph::construct(txs);
+        let graph = graph.hierarchcial_sort();
+        let graph = match preserve_order {
+            true => graph.reorder_preserve_order(),
+            false => graph.reorder(),
+        };
+        let ScheduledInfo {
+            scheduled_txs,
+            aborted_txs,
+        } = graph.extract_schedule();
+        assert!(aborted_txs.is_empty());
+
+        scheduled_txs
+            .iter()
+            .map(|txs| txs.iter().map(|tx| tx.id()).sorted().collect_vec())
+            .collect_vec()
+    };
+
+    //when
+    let parallel = schedule(false);
+    let preserving = schedule(true);
+
+    //then (tx1 is revived after all the writers of 1, or only after its reader)
+    assert_eq!(parallel, vec![vec![2], vec![3], vec![4], vec![1, 5]]);
+    assert_eq!(preserving, vec![vec![2], vec![1, 3], vec![4], vec![5]]);
+    assert_eq!(reordering_distance(&parallel), 3);
+    assert_eq!(reordering_distance(&preserving), 1);
+}
+
+#[test]
+fn test_parallism_metric_of_all_aborted_schedule() {
+    //given (a schedule with no generations)
+    let aborted = [1, 2, 3]
+        .into_iter()
+        .map(|id| {
+            let (tx, _) = Transaction::from(transaction_with_rw(id, id, id));
+            AbortedTransaction::try_from(std::sync::Arc::new(tx)).unwrap()
+        })
+        .collect_vec();
+    let info = ScheduledInfo {
+        scheduled_txs: vec![],
+        aborted_txs: vec![aborted],
+    };
+
+    //when
+    let (total_tx, average_width, std_width, max_width, depth) = info.parallism_metric();
+
+    //then
+    assert_eq!((total_tx, max_width, depth), (3, 0, 0));
+    assert!(average_width.is_finite() && std_width.is_finite());
+    assert_eq!((average_width, std_width), (0.0, 0.0));
+}
+
+#[test]
+fn test_read_only_transactions_in_first_generation() {
+    //given (tx 2 and tx 4 only read, and read-read on key 1 and 2 is not a conflict)
+    let txs = vec![
+        transaction_with_rw(1, 1, 2),
+        transaction_with_multiple_rw(2, vec![1, 2], vec![]),
+        transaction_with_rw(3, 2, 3),
+        transaction_with_multiple_rw(4, vec![1, 2, 3], vec![]),
+        transaction_with_rw(5, 3, 4),
+    ];
+
+    //when
+    let ScheduledInfo {
+        scheduled_txs,
+        aborted_txs,
+    } = AddressBasedConflictGraph::construct(txs)
+        .hierarchcial_sort()
+        .reorder()
+        .extract_schedule();
+
+    //then
+    let first_generation = scheduled_txs[0].iter().map(|tx| tx.id()).collect_vec();
+    assert!(first_generation.contains(&2) && first_generation.contains(&4));
+    assert!(aborted_txs
+        .iter()
+        .flatten()
+        .all(|tx| tx.id() != 2 && tx.id() != 4));
+    assert_eq!(
+        scheduled_txs.iter().map(|txs| txs.len()).sum::<usize>()
+            + aborted_txs.iter().map(|txs| txs.len()).sum::<usize>(),
+        5
+    );
+}
+
+#[test]
+fn test_block_logs_bloom() {
+    //given (a transaction emitting a log with two topics)
+    let emitter = H160::from_low_u64_be(0x157);
+    let topics = vec![H256::from_low_u64_be(1), H256::from_low_u64_be(2)];
+    let tx = SimulatedTransaction::new(
+        RwSet::new(),
+        Vec::new(),
+        vec![Log {
+            address: emitter,
+            topics: topics.clone(),
+            data: vec![0xff],
+        }],
+        IndexedEthereumTransaction::new(EthereumTransaction::default(), 0),
+    );
+    let result = SimulationResult {
+        digests: vec![],
+        rw_sets: vec![tx, transaction_with_rw(1, 1, 2)],
+    };
+
+    //when
+    let bloom = block_logs_bloom(&result);
+
+    //then
+    assert!(bloom.contains_input(BloomInput::Raw(emitter.as_bytes())));
+    topics
+        .iter()
+        .for_each(|topic| assert!(bloom.contains_input(BloomInput::Raw(topic.as_bytes()))));
+    assert!(!bloom.contains_input(BloomInput::Raw(H160::from_low_u64_be(0xdead).as_bytes())));
+    assert!(block_logs_bloom(&SimulationResult::default()).is_zero());
+}
+
+#[test]
+fn test_generation_of() {
+    //given (scenario 1: [[2], [3, 4], [5, 6]] scheduled, and 1 aborted)
+    let txs = vec![
+        transaction_with_rw(1, 2, 1),
+        transaction_with_rw(2, 3, 2),
+        transaction_with_rw(3, 4, 2),
+        transaction_with_rw(4, 4, 3),
+        transaction_with_rw(5, 4, 4),
+        transaction_with_rw(6, 1, 3),
+    ];
+    let mut acg = AddressBasedConflictGraph::construct(txs);
+
+    //when
+    let ScheduledInfo { scheduled_txs, .. } = acg.hierarchcial_sort().reorder().extract_schedule();
+
+    //then
+    assert_eq!(acg.generation_of(2), Some(0));
+    assert_eq!(acg.generation_of(4), Some(1));
+    assert_eq!(acg.generation_of(6), Some(2));
+    assert_eq!(acg.generation_of(1), None);
+    assert_eq!(acg.generation_of(7), None);
+    scheduled_txs
+        .iter()
+        .enumerate()
+        .for_each(|(generation, txs)| {
+            txs.iter()
+                .for_each(|tx| assert_eq!(acg.generation_of(tx.id()), Some(generation as u32)))
+        });
+}
+
+#[test]
+fn test_epoch_map_assigns_minimum_epoch_without_conflicts() {
+    //given (the (read, write) keys of scenario 1, in the order of tx ids)
+    let keys: Vec<(u64, u64)> = vec![(2, 1), (3, 2), (4, 2), (4, 3), (4, 4), (1, 3)];
+    let mut epoch_map = EpochMap::default();
+
+    //when
+    let epochs = keys
+        .into_iter()
+        .map(|(read, write)| epoch_map.assign(&HashSet::from([read]), HashSet::from([write])))
+        .collect_vec();
+
+    //then
+    assert_eq!(epochs, vec![0, 0, 1, 0, 0, 1]);
+    assert_eq!(epoch_map.len(), 2);
+}
+
+#[cfg(feature = "schedule-dump")]
+#[test]
+fn test_write_schedule_as_json() {
+    use crate::optme_core::ScheduleDump;
+
+    //given (scenario 1: [[2], [3, 4], [5, 6]] scheduled, and 1 aborted)
+    let txs = vec![
+        transaction_with_rw(1, 2, 1),
+        transaction_with_rw(2, 3, 2),
+        transaction_with_rw(3, 4, 2),
+        transaction_with_rw(4, 4, 3),
+        transaction_with_rw(5, 4, 4),
+        transaction_with_rw(6, 1, 3),
+    ];
+    let schedule = AddressBasedConflictGraph::construct(txs)
+        .hierarchcial_sort()
+        .reorder()
+        .extract_schedule();
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("schedule.json");
+
+    //when
+    schedule.write_json(&path).unwrap();
+
+    //then
+    let dump: ScheduleDump = serde_json::from_reader(std::fs::File::open(&path).unwrap()).unwrap();
+    assert_eq!(
+        dump,
+        ScheduleDump {
+            scheduled_txs: vec![vec![2], vec![3, 4], vec![5, 6]],
+            aborted_txs: vec![vec![1]],
+        }
+    );
+}
+
+#[test]
+fn test_autotuner_converges_toward_the_optimum() {
+    //given (a synthetic throughput peaking at the level of 7)
+    let score = |level: usize| 1000.0 - (level as f64 - 7.0).powi(2);
+    let mut tuner = AutoTuner::new(2, 16);
+    let mut level = 2;
+
+    //when
+    let levels = (0..30)
+        .map(|_| {
+            level = tuner.next_level(level, score(level));
+            level
+        })
+        .collect_vec();
+
+    //then (climbs to the optimum, and oscillates around it from then on)
+    assert!(levels.iter().all(|level| (2..=16).contains(level)));
+    assert!(levels[10..].iter().all(|level| (6..=8).contains(level)));
+
+    //given (the optimum out of the bounds)
+    let mut tuner = AutoTuner::new(2, 5);
+    let mut level = 2;
+
+    //when
+    (0..30).for_each(|_| level = tuner.next_level(level, score(level)));
+
+    //then (stays at the bound closest to the optimum)
+    assert!((4..=5).contains(&level));
+}
+
+fn modify(
+    address: H160,
+    balance: u64,
+    code: Option<Vec<u8>>,
+    storage: &[(u64, u64)],
+    reset_storage: bool,
+) -> Apply {
+    Apply::Modify {
+        address,
+        basic: Basic {
+            balance: U256::from(balance),
+            nonce: U256::one(),
+        },
+        code,
+        storage: storage
+            .iter()
+            .map(|(key, value)| (H256::from_low_u64_be(*key), H256::from_low_u64_be(*value)))
+            .collect::<BTreeMap<_, _>>(),
+        reset_storage,
+    }
+}
+
+// (balance, code, storage, reset_storage) of the collapsed `Modify`, or `None` for a `Delete`.
+fn collapsed_account(apply: &Apply) -> Option<(U256, Option<Vec<u8>>, Vec<(u64, u64)>, bool)> {
+    match apply {
+        Apply::Modify {
+            basic,
+            code,
+            storage,
+            reset_storage,
+            ..
+        } => Some((
+            basic.balance,
+            code.clone(),
+            storage
+                .iter()
+                .map(|(key, value)| (key.to_low_u64_be(), value.to_low_u64_be()))
+                .collect(),
+            *reset_storage,
+        )),
+        Apply::Delete { .. } => None,
+    }
+}
+
+#[test]
+fn test_collapse_modify_then_delete() {
+    //given
+    let account = H160::from_low_u64_be(0x190);
+    let effects = vec![
+        modify(account, 1, Some(vec![0x00]), &[(1, 1)], false),
+        modify(account, 2, None, &[(2, 2)], false),
+        Apply::Delete { address: account },
+    ];
+
+    //when
+    let collapsed = collapse_effects(effects);
+
+    //then (the delete supersedes the modifications)
+    assert_eq!(collapsed.len(), 1);
+    assert!(matches!(collapsed[0], Apply::Delete { address } if address == account));
+}
+
+#[test]
+fn test_collapse_delete_then_modify() {
+    //given
+    let account = H160::from_low_u64_be(0x190);
+    let effects = vec![
+        modify(account, 1, Some(vec![0x00]), &[(1, 1)], false),
+        Apply::Delete { address: account },
+        modify(account, 3, None, &[(3, 3)], false),
+    ];
+
+    //when
+    let collapsed = collapse_effects(effects);
+
+    //then (the account is recreated, without the code and the storage before the delete)
+    assert_eq!(collapsed.len(), 1);
+    assert_eq!(
+        collapsed_account(&collapsed[0]),
+        Some((U256::from(3), Some(vec![]), vec![(3, 3)], true))
+    );
+}
+
+#[test]
+fn test_collapse_interleaved_storage_and_account_effects() {
+    //given
+    let contract = H160::from_low_u64_be(0x190);
+    let other = H160::from_low_u64_be(0x191);
+    let effects = vec![
+        modify(other, 7, None, &[], false),
+        modify(contract, 1, Some(vec![0x60]), &[(1, 1), (2, 1)], false),
+        Apply::Delete { address: other },
+        modify(contract, 1, None, &[(2, 2), (3, 0)], false),
+        modify(other, 8, None, &[(1, 1)], false),
+        modify(contract, 5, None, &[(4, 4)], false),
+    ];
+
+    //when
+    let collapsed = collapse_effects(effects);
+
+    //then (in the order of addresses: the contract merges its writes, and the other account is recreated)
+    assert_eq!(
+        collapsed.iter().map(collapsed_account).collect_vec(),
+        vec![
+            Some((
+                U256::from(5),
+                Some(vec![0x60]),
+                vec![(1, 1), (2, 2), (3, 0), (4, 4)],
+                false
+            )),
+            Some((U256::from(8), Some(vec![]), vec![(1, 1)], true)),
+        ]
+    );
+
+    //given (a reset of the storage drops the writes before it)
+    let effects = vec![
+        modify(contract, 1, None, &[(1, 1)], false),
+        modify(contract, 1, None, &[(2, 2)], true),
+        modify(contract, 1, None, &[(3, 3)], false),
+    ];
+
+    //when
+    let collapsed = collapse_effects(effects);
+
+    //then
+    assert_eq!(
+        collapsed_account(&collapsed[0]),
+        Some((U256::from(1), None, vec![(2, 2), (3, 3)], true))
+    );
+}
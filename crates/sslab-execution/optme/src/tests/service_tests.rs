@@ -0,0 +1,47 @@
+use ethers_core::types::H256;
+use narwhal_types::BatchDigest;
+use sslab_execution::types::ExecutableEthereumBatch;
+
+use super::test_utils::{counter_address, counter_storage, increment_transaction};
+use crate::{optme_core::ConcurrencyLevelManager, ExecutionService};
+
+#[tokio::test]
+async fn test_execution_service_round_trip() {
+    let counter = counter_address();
+    let storage = counter_storage();
+    let service = ExecutionService::new(ConcurrencyLevelManager::new(storage, 10));
+
+    let digest = BatchDigest::new([1; 32]);
+    let batch = vec![ExecutableEthereumBatch::new(
+        vec![
+            increment_transaction(1, counter, 0),
+            increment_transaction(2, counter, 1),
+        ],
+        digest,
+    )];
+    let root_before = service.state_root().await;
+
+    //when (simulation does not commit)
+    let simulated = service.simulate(batch.clone()).await.unwrap();
+
+    //then
+    assert_eq!(simulated.digests, vec![digest]);
+    assert_eq!(service.state_root().await, root_before);
+
+    //when
+    let result = service.submit(batch).await.unwrap();
+
+    //then
+    assert_eq!(result.iter().collect::<Vec<_>>(), vec![&digest]);
+    for slot in [0, 1] {
+        assert_eq!(
+            service
+                .get_state(counter, H256::from_low_u64_be(slot))
+                .await,
+            H256::from_low_u64_be(1)
+        );
+    }
+    let root_after = service.state_root().await;
+    assert_ne!(root_after, root_before);
+    assert_eq!(service.state_root().await, root_after);
+}
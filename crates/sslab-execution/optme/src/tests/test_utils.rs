@@ -0,0 +1,342 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use ethers_core::types::{
+    transaction::eip2718::TypedTransaction, TransactionRequest, H160, H256, U256,
+};
+use ethers_providers::{MockProvider, Provider};
+use ethers_signers::{LocalWallet, Signer as _};
+use evm::{
+    backend::{Apply, Basic},
+    executor::stack::RwSet,
+};
+use itertools::Itertools;
+use narwhal_types::BatchDigest;
+use sslab_execution::{
+    evm_storage::{
+        backend::{CAccount, CMemoryBackend, ConcurrentHashMap, ExecutionBackend},
+        ConcurrentEVMStorage,
+    },
+    types::{EthereumTransaction, ExecutableEthereumBatch, IndexedEthereumTransaction},
+    utils::{
+        smallbank_contract_benchmark::{
+            concurrent_evm_storage, ADMIN_ADDRESS, DEFAULT_CONTRACT_ADDRESS,
+        },
+        test_utils::{SmallBankTransactionHandler, ADMIN_SECRET_KEY, DEFAULT_CHAIN_ID},
+    },
+};
+
+use crate::{
+    optme_core::ConcurrencyLevelManager,
+    service::state_root,
+    types::{FinalizedTransaction, ReExecutedTransaction},
+};
+
+pub(crate) fn get_smallbank_handler() -> SmallBankTransactionHandler {
+    let provider = Provider::<MockProvider>::new(MockProvider::default());
+    SmallBankTransactionHandler::new(provider, DEFAULT_CHAIN_ID)
+}
+
+pub(crate) fn get_optme_executor() -> ConcurrencyLevelManager {
+    ConcurrencyLevelManager::new(concurrent_evm_storage(), 10)
+}
+
+/* calls the smallbank contract with an unknown function selector, so the simulation always reverts. */
+pub(crate) fn reverting_transaction() -> EthereumTransaction {
+    let tx = TransactionRequest::new()
+        .from(H160::from_str(ADMIN_ADDRESS).unwrap())
+        .to(H160::from_str(DEFAULT_CONTRACT_ADDRESS).unwrap())
+        .data(vec![0xde, 0xad, 0xbe, 0xef])
+        .value(U256::zero())
+        .nonce(U256::zero())
+        .gas(u64::MAX)
+        .gas_price(U256::zero());
+
+    EthereumTransaction(TypedTransaction::Legacy(tx))
+}
+
+/* a contract which increments the storage slot given as calldata: SSTORE(slot, SLOAD(slot) + 1) */
+pub(crate) fn deploy_counter_contract(storage: &ConcurrentEVMStorage, contract: H160) {
+    deploy_code(
+        storage,
+        contract,
+        vec![
+            0x60, 0x00, 0x35, // CALLDATALOAD(0)
+            0x80, 0x54, // DUP1; SLOAD
+            0x60, 0x01, 0x01, // PUSH1 1; ADD
+            0x90, 0x55, 0x00, // SWAP1; SSTORE; STOP
+        ],
+    );
+}
+
+/* a counter (see `deploy_counter_contract`) which sets the slot to the value following it in the calldata, if any:
+SSTORE(slot, value). Unlike increments, sets commute neither with each other nor with increments of the same slot. */
+pub(crate) fn deploy_setter_contract(storage: &ConcurrentEVMStorage, contract: H160) {
+    deploy_code(
+        storage,
+        contract,
+        vec![
+            0x60, 0x20, 0x36, 0x11, // GT(CALLDATASIZE, 32)
+            0x60, 0x12, 0x57, // PUSH1 18; JUMPI
+            0x60, 0x00, 0x35, // CALLDATALOAD(0)
+            0x80, 0x54, // DUP1; SLOAD
+            0x60, 0x01, 0x01, // PUSH1 1; ADD
+            0x90, 0x55, 0x00, // SWAP1; SSTORE; STOP
+            0x5b, // JUMPDEST (18)
+            0x60, 0x20, 0x35, // CALLDATALOAD(32)
+            0x60, 0x00, 0x35, // CALLDATALOAD(0)
+            0x55, 0x00, // SSTORE; STOP
+        ],
+    );
+}
+
+/* copies slot 0 into slot 1 (SSTORE(1, SLOAD(0))), or sets slot 0 to the value following the slot in the calldata,
+if any, as `deploy_setter_contract` does. A copy reads slot 0 without writing it, so it conflicts with a set only
+through its read set. */
+pub(crate) fn deploy_copier_contract(storage: &ConcurrentEVMStorage, contract: H160) {
+    deploy_code(
+        storage,
+        contract,
+        vec![
+            0x60, 0x20, 0x36, 0x11, // GT(CALLDATASIZE, 32)
+            0x60, 0x0e, 0x57, // PUSH1 14; JUMPI
+            0x60, 0x00, 0x54, // PUSH1 0; SLOAD
+            0x60, 0x01, 0x55, 0x00, // PUSH1 1; SSTORE; STOP
+            0x5b, // JUMPDEST (14)
+            0x60, 0x20, 0x35, // CALLDATALOAD(32)
+            0x60, 0x00, 0x55, 0x00, // PUSH1 0; SSTORE; STOP
+        ],
+    );
+}
+
+fn deploy_code(storage: &ConcurrentEVMStorage, contract: H160, code: Vec<u8>) {
+    storage.get_storage().state().pin().insert(
+        contract,
+        CAccount {
+            nonce: U256::one(),
+            balance: U256::zero(),
+            storage: ConcurrentHashMap::default(),
+            code,
+        },
+    );
+}
+
+/* the address of the counter contract deployed by `counter_storage` */
+pub(crate) fn counter_address() -> H160 {
+    H160::from_low_u64_be(0xc0de)
+}
+
+/* a fresh state with the counter contract deployed at `counter_address()` */
+pub(crate) fn counter_storage() -> ConcurrentEVMStorage {
+    let storage = concurrent_evm_storage();
+    deploy_counter_contract(&storage, counter_address());
+    storage
+}
+
+/* every sender is distinct, so that transactions only conflict on the counter slots. */
+pub(crate) fn increment_transaction(sender: u64, contract: H160, slot: u64) -> EthereumTransaction {
+    let tx = TransactionRequest::new()
+        .from(H160::from_low_u64_be(sender))
+        .to(contract)
+        .data(H256::from_low_u64_be(slot).as_bytes().to_vec())
+        .value(U256::zero())
+        .nonce(U256::zero())
+        .gas(u64::MAX)
+        .gas_price(U256::zero());
+
+    EthereumTransaction(TypedTransaction::Legacy(tx))
+}
+
+/* sets the `slot` of the contract deployed by `deploy_setter_contract` to `value` */
+pub(crate) fn set_transaction(
+    sender: u64,
+    contract: H160,
+    slot: u64,
+    value: u64,
+) -> EthereumTransaction {
+    let data = [H256::from_low_u64_be(slot), H256::from_low_u64_be(value)]
+        .iter()
+        .flat_map(|word| word.as_bytes().to_vec())
+        .collect_vec();
+    let tx = TransactionRequest::new()
+        .from(H160::from_low_u64_be(sender))
+        .to(contract)
+        .data(data)
+        .value(U256::zero())
+        .nonce(U256::zero())
+        .gas(u64::MAX)
+        .gas_price(U256::zero());
+
+    EthereumTransaction(TypedTransaction::Legacy(tx))
+}
+
+/* an increment signed by the admin, i.e., whose caller is recovered from the signature as in production */
+pub(crate) fn signed_increment_transaction(
+    contract: H160,
+    slot: u64,
+    nonce: u64,
+) -> EthereumTransaction {
+    let wallet = LocalWallet::from_bytes(ADMIN_SECRET_KEY)
+        .unwrap()
+        .with_chain_id(DEFAULT_CHAIN_ID);
+    let tx = TypedTransaction::Legacy(
+        TransactionRequest::new()
+            .from(wallet.address())
+            .to(contract)
+            .data(H256::from_low_u64_be(slot).as_bytes().to_vec())
+            .value(U256::zero())
+            .nonce(U256::from(nonce))
+            .gas(u64::MAX)
+            .gas_price(U256::zero())
+            .chain_id(DEFAULT_CHAIN_ID),
+    );
+    let signature = wallet.sign_transaction_sync(&tx).expect("signature failed");
+    EthereumTransaction::from_rlp(&tx.rlp_signed(&signature)).unwrap()
+}
+
+/* a committed transaction which only writes `value` to the `slot` of the contract */
+pub(crate) fn finalized_write(
+    id: u64,
+    contract: H160,
+    slot: u64,
+    value: u64,
+) -> FinalizedTransaction {
+    let effect = Apply::Modify {
+        address: contract,
+        basic: Basic {
+            balance: U256::zero(),
+            nonce: U256::one(),
+        },
+        code: None,
+        storage: BTreeMap::from([(H256::from_low_u64_be(slot), H256::from_low_u64_be(value))]),
+        reset_storage: false,
+    };
+    FinalizedTransaction::from(ReExecutedTransaction::build_from(
+        IndexedEthereumTransaction::new(EthereumTransaction::default(), id),
+        vec![effect],
+        vec![],
+        RwSet::new(),
+    ))
+}
+
+/* reads the smallbank contract storage, whichever engine's storage wraps the backend */
+pub(crate) fn smallbank_storage(backend: &CMemoryBackend) -> BTreeMap<H256, H256> {
+    let state = backend.state().pin();
+    let contract = state
+        .get(&H160::from_str(DEFAULT_CONTRACT_ADDRESS).unwrap())
+        .unwrap();
+    let storage = contract.storage.pin();
+    storage.iter().map(|(key, value)| (*key, *value)).collect()
+}
+
+/* executes the workload through OptME, and one transaction after another (in the order of ids) over a copy of
+the same initial state, then asserts that both end up in the identical state. OptME only guarantees *a* serial order,
+so the workload must commute (e.g., increments) for the states to be comparable (see `assert_serializable` otherwise). */
+pub(crate) async fn assert_serial_equivalence(
+    optme: ConcurrencyLevelManager,
+    workload: Vec<ExecutableEthereumBatch>,
+) {
+    let serial = optme.global_state().snapshot();
+    let accounts = workload
+        .iter()
+        .flat_map(|batch| batch.data())
+        .flat_map(|tx| std::iter::once(tx.caller()).chain(tx.to_addr().copied()))
+        .unique()
+        .collect_vec();
+
+    optme._execute(workload.clone()).await.unwrap();
+    for tx in workload.iter().flat_map(|batch| batch.data()) {
+        let (_, effect, ..) = crate::evm_utils::simulate_tx(tx, &serial, serial.config()).unwrap();
+        serial.apply_local_effect(effect);
+    }
+
+    let optme_state = optme.global_state();
+    for address in accounts {
+        assert_eq!(
+            optme_state.get_account(address),
+            serial.get_account(address),
+            "account {address:?}"
+        );
+    }
+    assert_eq!(state_root(&optme_state), state_root(&serial));
+}
+
+/* executes the workload through OptME, and asserts that it ends up in the state of executing its transactions
+one after another in *some* order over a copy of the same initial state. Every order is tried, so the workload must be tiny. */
+pub(crate) async fn assert_serializable(
+    optme: ConcurrencyLevelManager,
+    workload: Vec<ExecutableEthereumBatch>,
+) {
+    let initial_state = optme.global_state().snapshot();
+    let txs = workload
+        .iter()
+        .flat_map(|batch| batch.data())
+        .cloned()
+        .collect_vec();
+
+    optme._execute(workload).await.unwrap();
+
+    let optme_root = state_root(&optme.global_state());
+    let serializable = txs.iter().permutations(txs.len()).any(|order| {
+        let serial = initial_state.snapshot();
+        for tx in order {
+            let (_, effect, ..) =
+                crate::evm_utils::simulate_tx(tx, &serial, serial.config()).unwrap();
+            serial.apply_local_effect(effect);
+        }
+        state_root(&serial) == optme_root
+    });
+    assert!(
+        serializable,
+        "no serial order of the transactions ends up in the state of OptME"
+    );
+}
+
+/* increments of the counters by `senders` distinct senders taking turns, where a `skewness` fraction of them
+hits slot 0 of the first counter. Deterministic for the seed (xorshift64). */
+pub(crate) fn skewed_counter_workload(
+    seed: u64,
+    skewness: f64,
+    counters: &[H160],
+    senders: u64,
+) -> Vec<ExecutableEthereumBatch> {
+    let mut state = seed.max(1);
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+
+    (0..4)
+        .map(|batch| {
+            let txs = (0..25)
+                .map(|i| {
+                    let sender = (batch * 25 + i) % senders + 1;
+                    if (next() % 1000) as f64 / 1000.0 < skewness {
+                        increment_transaction(sender, counters[0], 0)
+                    } else {
+                        let counter = counters[next() as usize % counters.len()];
+                        increment_transaction(sender, counter, next() % 16)
+                    }
+                })
+                .collect();
+            ExecutableEthereumBatch::new(txs, BatchDigest::default())
+        })
+        .collect()
+}
+
+/* 2 windows (with a concurrency level of 1) of a batch each, incrementing distinct slots of the counter deployed by
+`counter_storage`, so that each window commits a single generation. */
+pub(crate) fn two_windows_of_increments() -> Vec<ExecutableEthereumBatch> {
+    let counter = counter_address();
+    (0..2)
+        .map(|window| {
+            ExecutableEthereumBatch::new(
+                (0..3)
+                    .map(|i| increment_transaction(window * 3 + i + 1, counter, window * 3 + i))
+                    .collect(),
+                BatchDigest::new([window as u8 + 1; 32]),
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,131 @@
+use ethers_core::types::H256;
+use evm::backend::Backend as _;
+
+use super::test_utils::{
+    counter_address, counter_storage, finalized_write, two_windows_of_increments,
+};
+use crate::{
+    optme_core::{ConcurrencyLevelManager, Fault},
+    service::state_root,
+    types::OptmeError,
+    wal::CommitWal,
+};
+
+#[tokio::test]
+async fn test_commit_wal_recovers_fresh_state() {
+    let contract = counter_address();
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("wal");
+
+    //given (the first generation is committed through the WAL)
+    let optme = ConcurrencyLevelManager::new(counter_storage(), 10)
+        .with_commit_wal(CommitWal::open(&path))
+        .unwrap();
+    optme
+        ._concurrent_commit(vec![vec![
+            finalized_write(0, contract, 0, 1),
+            finalized_write(1, contract, 1, 1),
+        ]])
+        .await
+        .unwrap();
+    drop(optme);
+
+    //given (the node goes down after logging the second generation, before acking it)
+    let wal = CommitWal::open(&path);
+    assert!(wal.pending().is_empty());
+    let second = vec![
+        finalized_write(2, contract, 2, 1),
+        finalized_write(3, contract, 3, 1),
+    ];
+    assert_eq!(wal.log_generation(&second).unwrap(), 0);
+    drop(wal);
+
+    //when (restart over the genesis state)
+    let optme = ConcurrencyLevelManager::new(counter_storage(), 10)
+        .with_commit_wal(CommitWal::open(&path))
+        .unwrap();
+
+    //then
+    let state = optme.global_state();
+    (0..4).for_each(|slot| {
+        assert_eq!(
+            state
+                .get_storage()
+                .storage(contract, H256::from_low_u64_be(slot)),
+            H256::from_low_u64_be(1)
+        );
+    });
+    drop(optme);
+
+    //then (the replayed generation is persisted as well)
+    let wal = CommitWal::open(&path);
+    assert!(wal.pending().is_empty());
+    let genesis = counter_storage();
+    assert_eq!(wal.recover(&genesis).unwrap(), 0);
+    assert_eq!(
+        genesis
+            .get_storage()
+            .storage(contract, H256::from_low_u64_be(3)),
+        H256::from_low_u64_be(1)
+    );
+}
+
+#[tokio::test]
+async fn test_commit_wal_persists_slots_and_deletes_zeroed_ones() {
+    let contract = counter_address();
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("wal");
+
+    //given
+    let optme = ConcurrencyLevelManager::new(counter_storage(), 10)
+        .with_commit_wal(CommitWal::open(&path))
+        .unwrap();
+
+    //when (the second generation writes zero to a slot the first one wrote)
+    optme
+        ._concurrent_commit(vec![
+            vec![
+                finalized_write(0, contract, 0, 1),
+                finalized_write(1, contract, 1, 1),
+            ],
+            vec![finalized_write(2, contract, 0, 0)],
+        ])
+        .await
+        .unwrap();
+    drop(optme);
+
+    //then
+    let wal = CommitWal::open(&path);
+    assert!(wal.pending().is_empty());
+    assert_eq!(
+        wal.slots_of(contract),
+        vec![(H256::from_low_u64_be(1), H256::from_low_u64_be(1))]
+    );
+}
+
+#[tokio::test]
+async fn test_try_execute_rolls_back_the_output_on_wal_failure() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("wal");
+
+    //given (the WAL fails to log the generation of the second window)
+    let optme = ConcurrencyLevelManager::new(counter_storage(), 1)
+        .with_commit_wal(CommitWal::open(&path))
+        .unwrap()
+        .with_fault(Fault::Wal, 1);
+    let before = state_root(&optme.global_state());
+
+    //when
+    let result = optme.try_execute(two_windows_of_increments()).await;
+
+    //then (the first window is rolled back as well)
+    assert!(matches!(result, Err(OptmeError::Wal(_))));
+    assert_eq!(state_root(&optme.global_state()), before);
+    drop(optme);
+
+    //then (the rollback is logged as well, so the first window is not recovered on restart either)
+    let recovered = ConcurrencyLevelManager::new(counter_storage(), 1)
+        .with_commit_wal(CommitWal::open(&path))
+        .unwrap();
+    assert_eq!(state_root(&recovered.global_state()), before);
+}
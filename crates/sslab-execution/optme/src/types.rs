@@ -1,15 +1,29 @@
-use core::panic;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    time::{Duration, Instant},
+};
 
-use ethers_core::types::H256;
+use ethers_core::{
+    types::{Bloom, BloomInput, Bytes, TransactionReceipt, H160, H256, U256},
+    utils::keccak256,
+};
 use evm::{
-    backend::{Apply, Log},
-    executor::stack::RwSet,
+    backend::{Apply, Basic, Log},
+    executor::stack::{RwSet, Simulatable},
+    ExitError,
 };
 
 use narwhal_types::BatchDigest;
-use sslab_execution::types::{EthereumTransaction, IndexedEthereumTransaction};
+use parking_lot::Mutex;
+use sslab_execution::{
+    evm_storage::ConcurrentEVMStorage,
+    types::{DigestMismatch, EthereumTransaction, IndexedEthereumTransaction},
+};
+use tracing::warn;
 
 use crate::address_based_conflict_graph::Transaction;
+pub(crate) use crate::scheduling::is_disjoint;
+use crate::utils::RayonError;
 
 // SimulcationResult includes the batch digests and rw sets of each transctions in a ConsensusOutput.
 #[derive(Clone, Debug, Default)]
@@ -18,6 +32,470 @@ pub struct SimulationResult {
     pub rw_sets: Vec<SimulatedTransaction>,
 }
 
+impl SimulationResult {
+    /// Groups the logs emitted during simulation by the emitting contract, in the order of transactions.
+    pub fn logs_by_address(&self) -> HashMap<H160, Vec<Log>> {
+        let mut logs: HashMap<H160, Vec<Log>> = HashMap::new();
+        self.rw_sets
+            .iter()
+            .flat_map(|tx| tx.logs().iter())
+            .for_each(|log| logs.entry(log.address).or_default().push(log.clone()));
+        logs
+    }
+
+    /// Iterates over the effects of all the simulated transactions, in the order of transactions.
+    pub fn all_effects(&self) -> impl Iterator<Item = &Apply> + '_ {
+        self.rw_sets.iter().flat_map(|tx| tx.effects().iter())
+    }
+
+    /// The keys read and written by the simulated transactions over `state` (see [`AccessKeys::of`]),
+    /// or `None` if any of them is slow, since it is executed again serially over a later state, accessing what it may.
+    pub fn access_keys(&self, state: &ConcurrentEVMStorage) -> Option<AccessKeys> {
+        self.rw_sets
+            .iter()
+            .try_fold(AccessKeys::default(), |mut keys, tx| {
+                if matches!(tx.outcome(), SimulationOutcome::Slow(_)) {
+                    return None;
+                }
+                keys.extend(tx.access_keys(state)?);
+                Some(keys)
+            })
+    }
+
+    /// Splits a window simulated ahead of time (i.e., while the previous window was still being committed)
+    /// into the transactions which touched none of the accounts the previous window committed to,
+    /// and the stale ones to simulate again.
+    pub fn split_stale(
+        self,
+        prev_committed: &HashSet<H160>,
+    ) -> (SimulationResult, Vec<IndexedEthereumTransaction>) {
+        let (fresh, stale): (Vec<_>, Vec<_>) = self.rw_sets.into_iter().partition(|tx| {
+            tx.touched_addresses()
+                .all(|address| !prev_committed.contains(&address))
+        });
+
+        (
+            SimulationResult {
+                digests: self.digests,
+                rw_sets: fresh,
+            },
+            stale.into_iter().map(|tx| tx.raw_tx).collect(),
+        )
+    }
+
+    /// Summarizes the state-access footprint of the simulated transactions, e.g., for capacity planning.
+    /// The size of a rw-set is the number of keys it reads plus the number of keys it writes.
+    pub fn summary(&self) -> SimSummary {
+        let rw_set_sizes = self
+            .rw_sets
+            .iter()
+            .map(|tx| tx.read_set().len() + tx.write_set().len());
+        let total_rw_set_size = rw_set_sizes.clone().sum::<usize>();
+        let tx_count = self.rw_sets.len();
+
+        SimSummary {
+            tx_count,
+            total_reads: self.rw_sets.iter().map(|tx| tx.read_set().len()).sum(),
+            total_writes: self.rw_sets.iter().map(|tx| tx.write_set().len()).sum(),
+            total_effects: self.rw_sets.iter().map(|tx| tx.effects().len()).sum(),
+            avg_rw_set_size: if tx_count == 0 {
+                0.0
+            } else {
+                total_rw_set_size as f64 / tx_count as f64
+            },
+            max_rw_set_size: rw_set_sizes.max().unwrap_or_default(),
+        }
+    }
+
+    /// Restricts the result to the transactions which ultimately committed (e.g., to build the canonical receipts
+    /// of a partially committed block). The digests are kept as they are, so `batch_id`s still index into them.
+    pub fn filter_committed(&self, committed_ids: &HashSet<u64>) -> SimulationResult {
+        SimulationResult {
+            digests: self.digests.clone(),
+            rw_sets: self
+                .rw_sets
+                .iter()
+                .filter(|tx| committed_ids.contains(&tx.id()))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+/// The keys read and written by the transactions of a window (see [`SimulationResult::access_keys`]).
+/// A storage slot is keyed along with its account, and the basic (balance and nonce) of an account by [`basic_key`].
+#[derive(Clone, Debug, Default)]
+pub struct AccessKeys {
+    pub reads: hashbrown::HashSet<(H160, H256)>,
+    pub writes: hashbrown::HashSet<(H160, H256)>,
+}
+
+impl AccessKeys {
+    /// The keys of a transaction executed over `state`: the storage slots of its rw-set, and the basic of every account
+    /// it touches (e.g., its caller), read, and written only if its effects change it. So an account only called
+    /// (e.g., a contract shared by two windows) is rewritten with the same basic by both, which commutes.
+    /// `None` if the transaction deletes an account or resets its storage, i.e., writes slots it does not list.
+    fn of(
+        tx: &EthereumTransaction,
+        rw_set: &RwSet,
+        effects: &[Apply],
+        state: &ConcurrentEVMStorage,
+    ) -> Option<AccessKeys> {
+        let mut keys = AccessKeys::default();
+        keys.reads.extend(
+            rw_set
+                .reads()
+                .into_iter()
+                .flat_map(|(address, slots)| slots.keys().map(move |key| (*address, *key))),
+        );
+        keys.writes.extend(
+            rw_set
+                .writes()
+                .into_iter()
+                .flat_map(|(address, slots)| slots.keys().map(move |key| (*address, *key))),
+        );
+        keys.reads.extend(
+            accessed_addresses(tx, rw_set, effects).map(|address| (address, basic_key(address))),
+        );
+        for apply in effects {
+            match apply {
+                Apply::Modify {
+                    address,
+                    basic,
+                    reset_storage: false,
+                    ..
+                } => {
+                    if *basic != state.get_account(*address) {
+                        keys.writes.insert((*address, basic_key(*address)));
+                    }
+                }
+                _ => return None,
+            }
+        }
+        Some(keys)
+    }
+
+    /// Whether the window neither reads nor writes a key the other writes, nor writes a key the other reads;
+    /// i.e., the two windows give the same state in whichever order they are executed.
+    pub fn is_independent_of(&self, other: &AccessKeys) -> bool {
+        is_disjoint(&self.writes, &other.writes)
+            && is_disjoint(&self.writes, &other.reads)
+            && is_disjoint(&self.reads, &other.writes)
+    }
+
+    /// Whether the keys stay within the given ones, i.e., every key written is written by the other,
+    /// and every key read is accessed by the other.
+    pub fn is_within(&self, other: &AccessKeys) -> bool {
+        self.writes.is_subset(&other.writes)
+            && self
+                .reads
+                .iter()
+                .all(|key| other.reads.contains(key) || other.writes.contains(key))
+    }
+
+    pub fn extend(&mut self, other: AccessKeys) {
+        self.reads.extend(other.reads);
+        self.writes.extend(other.writes);
+    }
+}
+
+/// The storage slots and account basics (balance and nonce) written by committed effects.
+/// Every `Apply::Modify` carries the whole basic of its account, whether the transaction changed it or not.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CommittedWrites {
+    pub slots: hashbrown::HashSet<(H160, H256)>,
+    pub basics: hashbrown::HashMap<H160, Basic>,
+}
+
+impl CommittedWrites {
+    /// Whether applying both writes in any order gives the same state, i.e., they write no common slot,
+    /// and set the same basic for every account they both modify.
+    pub fn commutes_with(&self, other: &CommittedWrites) -> bool {
+        is_disjoint(&self.slots, &other.slots)
+            && self.basics.iter().all(|(address, basic)| {
+                other
+                    .basics
+                    .get(address)
+                    .map_or(true, |other_basic| other_basic == basic)
+            })
+    }
+
+    pub fn extend(&mut self, other: CommittedWrites) {
+        self.slots.extend(other.slots);
+        self.basics.extend(other.basics);
+    }
+}
+
+/// The accounts modified or deleted by the effects.
+pub(crate) fn modified_accounts<'a>(
+    effects: impl IntoIterator<Item = &'a Apply> + 'a,
+) -> impl Iterator<Item = H160> + 'a {
+    effects.into_iter().map(|apply| match apply {
+        Apply::Modify { address, .. } | Apply::Delete { address } => *address,
+    })
+}
+
+/// The summary of the state accesses of a simulated block (see [`SimulationResult::summary`]).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SimSummary {
+    pub tx_count: usize,
+    /// the number of keys read, summed over the transactions.
+    pub total_reads: usize,
+    /// the number of keys written, summed over the transactions.
+    pub total_writes: usize,
+    /// the number of account effects, summed over the transactions.
+    pub total_effects: usize,
+    pub avg_rw_set_size: f64,
+    pub max_rw_set_size: usize,
+}
+
+/// The gas used by the simulation of a block, and the part of it wasted on the transactions which did not commit
+/// their simulated effects (i.e., aborted by the schedule, or slow), and are executed again.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GasMetrics {
+    pub simulated_gas: u64,
+    pub aborted_gas: u64,
+}
+
+impl GasMetrics {
+    /// The ratio of the simulated gas wasted on the aborted transactions, i.e., the cost of the optimistic execution.
+    pub fn wasted_gas_ratio(&self) -> f64 {
+        if self.simulated_gas == 0 {
+            return 0.0;
+        }
+        self.aborted_gas as f64 / self.simulated_gas as f64
+    }
+}
+
+/// What the execution of a window reports, accumulated by the window itself (instead of the manager-wide metrics),
+/// so that the windows executed concurrently do not overwrite each other's.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct WindowMetrics {
+    pub gas_metrics: GasMetrics,
+    /// `None` if the window fell back to serial execution before any re-execution round.
+    pub re_execution_rounds: Option<usize>,
+}
+
+impl WindowMetrics {
+    /// Merges the metrics of the windows executed concurrently as a single block: the gas adds up, while the rounds
+    /// overlap, so that the block takes as many rounds as its window which took the most.
+    pub(crate) fn merge(self, other: Self) -> Self {
+        Self {
+            gas_metrics: GasMetrics {
+                simulated_gas: self.gas_metrics.simulated_gas + other.gas_metrics.simulated_gas,
+                aborted_gas: self.gas_metrics.aborted_gas + other.gas_metrics.aborted_gas,
+            },
+            re_execution_rounds: self.re_execution_rounds.max(other.re_execution_rounds),
+        }
+    }
+}
+
+/// The time (in microseconds) each phase of executing a block took, recorded when an accumulator is injected
+/// into the execution (see [`crate::optme_core::LatencyBenchmark`]). Each phase adds up over the re-execution rounds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PhaseTimings {
+    pub total: u128,
+    pub simulation: u128,
+    pub scheduling: u128,
+    pub re_execution: u128,
+    /// the validation of the re-executed transactions, including the commit of the valid ones.
+    pub validation: u128,
+    /// the commit of the scheduled generations.
+    pub commit: u128,
+    /// the serial execution of the transactions left out of the concurrent passes (e.g., the slow ones).
+    pub serial: u128,
+    /// the sum, over the committed transactions, of the time from the start of the block until their commit.
+    pub commit_latency_sum: u128,
+    pub committed_txs: u128,
+    pub(crate) started: Option<Instant>,
+}
+
+impl PhaseTimings {
+    /// The average time from the start of the block until a transaction is committed.
+    pub fn avg_tx_latency(&self) -> f64 {
+        if self.committed_txs == 0 {
+            return 0.0;
+        }
+        self.commit_latency_sum as f64 / self.committed_txs as f64
+    }
+
+    pub(crate) fn start(&mut self) {
+        self.started = Some(Instant::now());
+    }
+
+    pub(crate) fn finish(&mut self) {
+        self.total = self.elapsed();
+    }
+
+    pub(crate) fn record_commit(&mut self, num_txs: usize) {
+        self.commit_latency_sum += self.elapsed() * num_txs as u128;
+        self.committed_txs += num_txs as u128;
+    }
+
+    fn elapsed(&self) -> u128 {
+        self.started
+            .map_or(0, |started| started.elapsed().as_micros())
+    }
+}
+
+// SimulationOutcome tells why a transaction has (not) produced effects during simulation.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum SimulationOutcome {
+    #[default]
+    Success,
+    /// explicit `REVERT`, along with the output bytes (e.g., an ABI-encoded reason string).
+    Reverted(Bytes),
+    /// normal EVM errors, such as out-of-gas or invalid opcode.
+    Failed(ExitError),
+    /// the simulation was interrupted after the slow-simulation threshold (along with the time it ran),
+    /// so it has no rw-set nor effects, and is executed serially later.
+    Slow(Duration),
+}
+
+impl SimulationOutcome {
+    #[inline]
+    pub fn is_success(&self) -> bool {
+        matches!(self, SimulationOutcome::Success)
+    }
+}
+
+// ConflictGranularity decides the keys on which transactions conflict with each other.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConflictGranularity {
+    /// any two accesses to the same storage slot conflict.
+    #[default]
+    Slot,
+    /// any write to an account conflicts with any access to the account, regardless of slots.
+    Account,
+}
+
+// AbortReason tells why a transaction was left out of the schedule of its block (or out of a re-execution round).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AbortReason {
+    /// another transaction updating (i.e., reading and writing) the same key won as the first updater.
+    WriteWrite,
+    /// its anti-rw dependency would make a cycle in the conflict graph, so it is rescheduled into a later epoch.
+    Rescheduled,
+    /// its re-execution wrote a key already written by an earlier transaction of the same round, failing the validation.
+    Invalidated,
+    /// its batch is routed to the serial execution (see [`BatchRoute::SinglePass`]).
+    SerialFallback,
+}
+
+// AbortClearing decides when the aborted transactions are cleared while their epochs are assigned.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AbortClearing {
+    /// every aborted transaction is cleared first, and keeps its keys until the end of the scheduling.
+    #[default]
+    Batched,
+    /// each aborted transaction is cleared right before its epoch is assigned, and its keys are dropped right after,
+    /// so that only the write keys of the epochs are held at a time.
+    Streaming,
+}
+
+// FirstPassMode decides how the simulated transactions of a window are first committed (experimental).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FirstPassMode {
+    /// the conflict graph of the simulated transactions is scheduled first, and its schedule is committed.
+    #[default]
+    ScheduleThenCommit,
+    /// the simulated transactions are committed right away unless they conflict with an earlier committed one
+    /// (i.e., read or write a key it writes), as BlockSTM does. The others are re-executed over the updated state
+    /// and validated again, until every transaction is committed.
+    OptimisticCommitThenValidate,
+}
+
+// AutoTuner hill-climbs the concurrency level (i.e., the number of batches in a window) toward the one maximizing
+// the score fed back after each window, e.g., its effective throughput.
+#[derive(Clone, Debug)]
+pub struct AutoTuner {
+    min: usize,
+    max: usize,
+    ascending: bool,
+    last_score: Option<f64>,
+}
+
+impl AutoTuner {
+    pub fn new(min: usize, max: usize) -> Self {
+        assert!(
+            0 < min && min <= max,
+            "the bounds of the concurrency level must be positive and ordered"
+        );
+        Self {
+            min,
+            max,
+            ascending: true,
+            last_score: None,
+        }
+    }
+
+    /// The next concurrency level, given the score of the window executed at `level`.
+    /// Keeps moving by a single step in the same direction while the score improves, and turns back once it drops
+    /// (or a bound is reached), so that the level ends up oscillating around the optimum.
+    pub fn next_level(&mut self, level: usize, score: f64) -> usize {
+        if self.last_score.map_or(false, |last| score < last) {
+            self.ascending = !self.ascending;
+        }
+        self.last_score = Some(score);
+
+        if (self.ascending && level >= self.max) || (!self.ascending && level <= self.min) {
+            self.ascending = !self.ascending;
+        }
+        let next = if self.ascending {
+            level + 1
+        } else {
+            level.saturating_sub(1)
+        };
+        next.clamp(self.min, self.max)
+    }
+}
+
+impl ConflictGranularity {
+    /// Rewrites the rw-set so that it is keyed on the conflict granularity.
+    /// With `Account`, every contract touched is represented by a single key derived from its address,
+    /// thus `extract_read_set` and `extract_write_set` (and the conflict graph) key on the address instead of the slot.
+    pub fn coarsen(&self, rw_set: RwSet) -> RwSet {
+        match self {
+            ConflictGranularity::Slot => rw_set,
+            ConflictGranularity::Account => {
+                let mut coarsened = RwSet::new();
+                rw_set.reads().keys().for_each(|contract| {
+                    coarsened.record_read_key(*contract, H256::from(*contract), H256::zero())
+                });
+                rw_set.writes().keys().for_each(|contract| {
+                    coarsened.record_write_key(*contract, H256::from(*contract), H256::zero())
+                });
+                coarsened
+            }
+        }
+    }
+}
+
+// BatchRoute decides how the transactions of a batch, which are aborted by the conflict graph, are executed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BatchRoute {
+    /// a few conflicts: the aborted transactions are executed serially after the others, in a single pass.
+    SinglePass,
+    /// many conflicts: the aborted transactions go through the re-execution rounds.
+    ReExecution,
+}
+
+/// Estimates how much the given transactions conflict with each other, as `1 - #unique write keys / #writes`.
+/// 0 means that no key is written twice, and it approaches 1 as the writes concentrate on a few keys.
+pub fn conflict_density<'a>(txs: impl IntoIterator<Item = &'a SimulatedTransaction>) -> f64 {
+    let mut unique = hashbrown::HashSet::new();
+    let mut total = 0;
+    txs.into_iter().for_each(|tx| {
+        total += tx.write_set().len();
+        unique.extend(tx.write_set().iter().cloned());
+    });
+
+    if total == 0 {
+        return 0.0;
+    }
+    1.0 - unique.len() as f64 / total as f64
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct SimulatedTransaction {
     tx_id: u64,
@@ -27,6 +505,8 @@ pub struct SimulatedTransaction {
     effects: Vec<Apply>,
     logs: Vec<Log>,
     raw_tx: IndexedEthereumTransaction,
+    outcome: SimulationOutcome,
+    gas_used: u64,
 }
 
 impl SimulatedTransaction {
@@ -52,14 +532,81 @@ impl SimulatedTransaction {
             effects,
             logs,
             raw_tx,
+            outcome: SimulationOutcome::Success,
+            gas_used: 0,
         }
     }
 
+    #[inline]
+    pub fn with_outcome(mut self, outcome: SimulationOutcome) -> Self {
+        self.outcome = outcome;
+        self
+    }
+
+    #[inline]
+    pub fn with_gas_used(mut self, gas_used: u64) -> Self {
+        self.gas_used = gas_used;
+        self
+    }
+
+    /// Records the given (contract, slot, value) reads, keyed on the conflict granularity.
+    pub(crate) fn seed_reads(
+        mut self,
+        reads: Vec<(H160, H256, H256)>,
+        granularity: ConflictGranularity,
+    ) -> Self {
+        reads.into_iter().for_each(|(contract, key, value)| {
+            if self
+                .rw_set
+                .reads()
+                .get(&contract)
+                .map_or(true, |states| !states.contains_key(&key))
+            {
+                self.rw_set.record_read_key(contract, key, value);
+            }
+        });
+        self.rw_set = granularity.coarsen(std::mem::take(&mut self.rw_set));
+        self.read_set = extract_read_set(&self.rw_set);
+        self
+    }
+
     #[inline]
     pub fn id(&self) -> u64 {
         self.tx_id
     }
 
+    /// The position of the originating batch, i.e., its digest is `SimulationResult::digests[batch_id]`.
+    #[inline]
+    pub fn batch_id(&self) -> usize {
+        self.raw_tx.batch_id
+    }
+
+    #[inline]
+    pub fn outcome(&self) -> &SimulationOutcome {
+        &self.outcome
+    }
+
+    #[inline]
+    pub fn logs(&self) -> &Vec<Log> {
+        &self.logs
+    }
+
+    /// The gas used by the simulation.
+    #[inline]
+    pub fn gas_used(&self) -> u64 {
+        self.gas_used
+    }
+
+    #[inline]
+    pub fn effects(&self) -> &[Apply] {
+        &self.effects
+    }
+
+    #[inline]
+    pub fn rw_set(&self) -> &RwSet {
+        &self.rw_set
+    }
+
     #[inline]
     pub fn deconstruct(self) -> (u64, RwSet, Vec<Apply>, Vec<Log>, IndexedEthereumTransaction) {
         (
@@ -85,6 +632,31 @@ impl SimulatedTransaction {
     pub fn raw_tx(&self) -> &IndexedEthereumTransaction {
         &self.raw_tx
     }
+
+    /// The accounts the transaction read or wrote during simulation, including its caller and recipient.
+    pub fn touched_addresses(&self) -> impl Iterator<Item = H160> + '_ {
+        accessed_addresses(self.raw_tx.data(), &self.rw_set, &self.effects)
+    }
+
+    /// The keys the transaction accessed during simulation over `state` (see [`AccessKeys::of`]).
+    pub(crate) fn access_keys(&self, state: &ConcurrentEVMStorage) -> Option<AccessKeys> {
+        AccessKeys::of(self.raw_tx.data(), &self.rw_set, &self.effects, state)
+    }
+}
+
+fn accessed_addresses<'a>(
+    tx: &'a EthereumTransaction,
+    rw_set: &'a RwSet,
+    effects: &'a [Apply],
+) -> impl Iterator<Item = H160> + 'a {
+    rw_set
+        .reads()
+        .into_iter()
+        .chain(rw_set.writes().into_iter())
+        .map(|(address, _)| *address)
+        .chain(modified_accounts(effects))
+        .chain(std::iter::once(tx.caller()))
+        .chain(tx.to_addr().copied())
 }
 
 #[derive(Clone, Debug)]
@@ -92,6 +664,7 @@ pub struct AbortedTransaction {
     raw_tx: IndexedEthereumTransaction,
     prev_write_keys: hashbrown::HashSet<H256>,
     prev_read_keys: hashbrown::HashSet<H256>,
+    reason: Option<AbortReason>,
 }
 
 impl AbortedTransaction {
@@ -110,10 +683,47 @@ impl AbortedTransaction {
         &self.prev_read_keys
     }
 
+    // takes the (read, write) keys out, e.g., to drop them once the epoch of the transaction is assigned.
+    #[inline]
+    pub(crate) fn take_keys(&mut self) -> (hashbrown::HashSet<H256>, hashbrown::HashSet<H256>) {
+        (
+            std::mem::take(&mut self.prev_read_keys),
+            std::mem::take(&mut self.prev_write_keys),
+        )
+    }
+
     #[inline]
     pub fn into_raw_tx(self) -> IndexedEthereumTransaction {
         self.raw_tx
     }
+
+    #[inline]
+    pub fn abort_reason(&self) -> Option<AbortReason> {
+        self.reason
+    }
+
+    #[inline]
+    pub(crate) fn with_abort_reason(mut self, reason: AbortReason) -> Self {
+        self.reason = Some(reason);
+        self
+    }
+
+    #[inline]
+    pub(crate) fn batch_id(&self) -> usize {
+        self.raw_tx.batch_id
+    }
+
+    // copies the transaction, instead of taking it out of the (shared) Arc.
+    pub(crate) fn from_shared(tx: &Transaction) -> Self {
+        let ainfo = tx.abort_info.read();
+
+        Self {
+            raw_tx: tx.raw_tx.clone(),
+            prev_write_keys: ainfo.write_keys(),
+            prev_read_keys: ainfo.read_keys(),
+            reason: ainfo.reason(),
+        }
+    }
 }
 
 // #[derive(Clone, Debug)]
@@ -122,33 +732,101 @@ impl AbortedTransaction {
 //     raw_tx: IndexedEthereumTransaction,
 // }
 
-impl From<std::sync::Arc<Transaction>> for AbortedTransaction {
-    fn from(value: std::sync::Arc<Transaction>) -> Self {
+impl TryFrom<std::sync::Arc<Transaction>> for AbortedTransaction {
+    type Error = SharedTransactionError;
+
+    fn try_from(value: std::sync::Arc<Transaction>) -> Result<Self, Self::Error> {
         let Transaction {
             raw_tx, abort_info, ..
-        } = _unwrap_arc(value);
+        } = std::sync::Arc::try_unwrap(value).map_err(SharedTransactionError)?;
         let ainfo = abort_info.read();
         let prev_write_keys = ainfo.write_keys();
         let prev_read_keys = ainfo.read_keys();
+        let reason = ainfo.reason();
 
-        Self {
+        Ok(Self {
             raw_tx,
             prev_write_keys,
             prev_read_keys,
-        }
+            reason,
+        })
     }
 }
 
-#[inline]
-fn _unwrap_arc<T>(data: std::sync::Arc<T>) -> T {
-    match std::sync::Arc::into_inner(data) {
-        Some(inner) => inner,
-        None => {
-            panic!("fail to unwrap Arc! please check if the data has only one strong reference!")
+// keeps the id (and the batch id) of the transaction, by which its re-execution is keyed.
+impl From<AbortedTransaction> for IndexedEthereumTransaction {
+    fn from(value: AbortedTransaction) -> Self {
+        value.into_raw_tx()
+    }
+}
+
+// SharedTransactionError is returned when a transaction of the conflict graph is still referenced elsewhere
+// (e.g., by the units of an address), so it cannot be taken out of its Arc.
+#[derive(Debug)]
+pub struct SharedTransactionError(pub std::sync::Arc<Transaction>);
+
+impl std::fmt::Display for SharedTransactionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "transaction {} is still shared (strong:{}, weak:{})",
+            self.0.id(),
+            std::sync::Arc::strong_count(&self.0),
+            std::sync::Arc::weak_count(&self.0)
+        )
+    }
+}
+
+impl std::error::Error for SharedTransactionError {}
+
+// OptmeError is what fails the execution of a consensus output (see `ConcurrencyLevelManager::prepare_execution`),
+// instead of taking the node down.
+#[derive(Debug)]
+pub enum OptmeError {
+    /// a batch does not match its digest (with `verify-digests`).
+    DigestMismatch(DigestMismatch),
+    /// a job on the rayon workers panicked, or dropped its result.
+    Worker(RayonError),
+    /// the execution panicked elsewhere, along with the panic message.
+    Panicked(String),
+    /// the commit WAL cannot be accessed (with `wal`).
+    #[cfg(feature = "wal")]
+    Wal(typed_store::rocks::TypedStoreError),
+}
+
+impl std::fmt::Display for OptmeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OptmeError::DigestMismatch(e) => write!(f, "{e}"),
+            OptmeError::Worker(e) => write!(f, "{e}"),
+            OptmeError::Panicked(msg) => write!(f, "the execution panicked: {msg}"),
+            #[cfg(feature = "wal")]
+            OptmeError::Wal(e) => write!(f, "fail to access the commit WAL: {e}"),
         }
     }
 }
 
+impl std::error::Error for OptmeError {}
+
+impl From<DigestMismatch> for OptmeError {
+    fn from(e: DigestMismatch) -> Self {
+        OptmeError::DigestMismatch(e)
+    }
+}
+
+impl From<RayonError> for OptmeError {
+    fn from(e: RayonError) -> Self {
+        OptmeError::Worker(e)
+    }
+}
+
+#[cfg(feature = "wal")]
+impl From<typed_store::rocks::TypedStoreError> for OptmeError {
+    fn from(e: typed_store::rocks::TypedStoreError) -> Self {
+        OptmeError::Wal(e)
+    }
+}
+
 #[derive(Debug)]
 pub struct ScheduledTransaction {
     pub seq: u32,
@@ -203,15 +881,23 @@ impl ScheduledTransaction {
     }
 }
 
+// all the references to the scheduled transactions should have been dropped by `clear_write_units`
+// and clearing the addresses, but fall back to copying the transaction rather than panicking.
 impl From<std::sync::Arc<Transaction>> for ScheduledTransaction {
     fn from(tx: std::sync::Arc<Transaction>) -> Self {
-        let (tx_id, seq, effect, log) = _unwrap_arc(tx).deconstruct();
+        match std::sync::Arc::try_unwrap(tx) {
+            Ok(tx) => Self::from(tx),
+            Err(tx) => {
+                warn!("{}", SharedTransactionError(tx.clone()));
+                let (effect, log) = tx.simulation_result();
 
-        Self {
-            seq,
-            tx_id,
-            effect,
-            log,
+                Self {
+                    seq: tx.sequence(),
+                    tx_id: tx.id(),
+                    effect,
+                    log,
+                }
+            }
         }
     }
 }
@@ -235,6 +921,8 @@ pub struct ReExecutedTransaction {
     effect: Vec<Apply>,
     log: Vec<Log>,
     rw_set: RwSet,
+    gas_used: u64,
+    reason: Option<AbortReason>,
 }
 
 impl ReExecutedTransaction {
@@ -250,22 +938,106 @@ impl ReExecutedTransaction {
             effect,
             log,
             rw_set,
+            gas_used: 0,
+            reason: None,
         }
     }
 
+    #[inline]
+    pub fn with_gas_used(mut self, gas_used: u64) -> Self {
+        self.gas_used = gas_used;
+        self
+    }
+
+    /// Why the transaction was aborted (i.e., why it had to be re-executed, or why its re-execution was invalidated).
+    #[inline]
+    pub fn abort_reason(&self) -> Option<AbortReason> {
+        self.reason
+    }
+
+    #[inline]
+    pub(crate) fn set_abort_reason(&mut self, reason: Option<AbortReason>) {
+        self.reason = reason;
+    }
+
+    #[inline]
+    pub fn id(&self) -> u64 {
+        self.tx.id
+    }
+
     #[inline]
     pub fn write_set(&self) -> hashbrown::HashSet<H256> {
         extract_write_set(&self.rw_set)
     }
 
+    #[inline]
+    pub fn read_set(&self) -> hashbrown::HashSet<H256> {
+        extract_read_set(&self.rw_set)
+    }
+
+    /// The keys the transaction accessed during re-execution over `state` (see [`AccessKeys::of`]).
+    pub(crate) fn access_keys(&self, state: &ConcurrentEVMStorage) -> Option<AccessKeys> {
+        AccessKeys::of(self.tx.data(), &self.rw_set, &self.effect, state)
+    }
+
     #[inline]
     pub fn raw_tx(&self) -> &EthereumTransaction {
         &self.tx.tx
     }
+
+    #[inline]
+    pub fn into_raw_tx(self) -> IndexedEthereumTransaction {
+        self.tx
+    }
+
+    /// The effects captured by the re-execution, e.g., to commit them once validated, without re-running the EVM.
+    #[inline]
+    pub fn effects(&self) -> &[Apply] {
+        &self.effect
+    }
+
+    #[inline]
+    pub fn logs(&self) -> &[Log] {
+        &self.log
+    }
+
+    #[inline]
+    pub fn gas_used(&self) -> u64 {
+        self.gas_used
+    }
+
+    /// The inverse of [`Self::build_from`].
+    #[inline]
+    pub fn into_parts(self) -> (IndexedEthereumTransaction, Vec<Apply>, Vec<Log>, RwSet) {
+        (self.tx, self.effect, self.log, self.rw_set)
+    }
+}
+
+impl From<SimulatedTransaction> for ReExecutedTransaction {
+    fn from(tx: SimulatedTransaction) -> Self {
+        let gas_used = tx.gas_used();
+        let (_, rw_set, effect, log, tx) = tx.deconstruct();
+        ReExecutedTransaction::build_from(tx, effect, log, rw_set).with_gas_used(gas_used)
+    }
+}
+
+impl From<ReExecutedTransaction> for SimulatedTransaction {
+    fn from(tx: ReExecutedTransaction) -> Self {
+        let ReExecutedTransaction {
+            tx,
+            effect,
+            log,
+            rw_set,
+            gas_used,
+            ..
+        } = tx;
+        SimulatedTransaction::new(rw_set, effect, log, tx).with_gas_used(gas_used)
+    }
 }
 
 pub struct FinalizedTransaction {
     id: u64,
+    seq: u32, // the generation of the schedule, if scheduled
     effect: Vec<Apply>,
     // log: Vec<Log>,
 }
@@ -276,10 +1048,44 @@ impl FinalizedTransaction {
         self.effect
     }
 
+    #[inline]
+    pub fn effects(&self) -> &[Apply] {
+        &self.effect
+    }
+
     #[inline]
     pub fn id(&self) -> u64 {
         self.id
     }
+
+    #[inline]
+    pub fn seq(&self) -> u32 {
+        self.seq
+    }
+
+    /// The storage slots and account basics written by the effect,
+    /// or `None` if it also replaces or deletes a whole account storage.
+    pub(crate) fn writes(&self) -> Option<CommittedWrites> {
+        let mut writes = CommittedWrites::default();
+        for apply in &self.effect {
+            match apply {
+                Apply::Modify {
+                    address,
+                    basic,
+                    storage,
+                    code: None,
+                    reset_storage: false,
+                } => {
+                    writes
+                        .slots
+                        .extend(storage.keys().map(|key| (*address, *key)));
+                    writes.basics.insert(*address, basic.clone());
+                }
+                _ => return None,
+            }
+        }
+        Some(writes)
+    }
 }
 
 impl From<ReExecutedTransaction> for FinalizedTransaction {
@@ -290,7 +1096,11 @@ impl From<ReExecutedTransaction> for FinalizedTransaction {
             tx,
             ..
         } = value;
-        Self { effect, id: tx.id }
+        Self {
+            effect,
+            id: tx.id,
+            seq: 0,
+        }
     }
 }
 
@@ -300,13 +1110,401 @@ impl From<ScheduledTransaction> for FinalizedTransaction {
             effect: effects,
             log: _log,
             tx_id,
-            ..
+            seq,
         } = value;
         Self {
             effect: effects,
             id: tx_id,
+            seq,
+        }
+    }
+}
+
+/// A transaction whose effects are ready to be applied to the global state, whichever path it went through
+/// (e.g., scheduled by the conflict graph, or validated after re-execution).
+pub trait Committable {
+    fn id(&self) -> u64;
+
+    fn extract(self) -> Vec<Apply>;
+}
+
+impl Committable for FinalizedTransaction {
+    #[inline]
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    #[inline]
+    fn extract(self) -> Vec<Apply> {
+        self.effect
+    }
+}
+
+impl Committable for ScheduledTransaction {
+    #[inline]
+    fn id(&self) -> u64 {
+        self.tx_id
+    }
+
+    #[inline]
+    fn extract(self) -> Vec<Apply> {
+        self.effect
+    }
+}
+
+impl FinalizedTransaction {
+    /// Effects committed on behalf of no transaction, e.g., the ones rolling a failed output back.
+    #[inline]
+    pub(crate) fn new(id: u64, effect: Vec<Apply>) -> Self {
+        Self { id, seq: 0, effect }
+    }
+
+    #[inline]
+    pub(crate) fn from_committable(tx: impl Committable) -> Self {
+        let id = tx.id();
+        Self {
+            effect: tx.extract(),
+            id,
+            seq: 0,
+        }
+    }
+
+    // shifts the generation, e.g., when the schedule of a window is appended to the ones before it.
+    #[inline]
+    pub(crate) fn offset_seq(&mut self, offset: u32) {
+        self.seq += offset;
+    }
+}
+
+/// What the last execution of a transaction produced, kept to build its receipt
+/// (see [`crate::ConcurrencyLevelManager::execute_to_receipts`]).
+#[derive(Clone, Debug)]
+pub(crate) struct ReceiptRecord {
+    pub id: u64,
+    pub tx: EthereumTransaction,
+    pub success: bool,
+    pub gas_used: u64,
+    pub logs: Vec<Log>,
+}
+
+impl ReceiptRecord {
+    pub(crate) fn new(
+        tx: &IndexedEthereumTransaction,
+        success: bool,
+        gas_used: u64,
+        logs: &[Log],
+    ) -> Self {
+        Self {
+            id: tx.id,
+            tx: tx.tx.clone(),
+            success,
+            gas_used,
+            logs: logs.to_vec(),
+        }
+    }
+
+    pub(crate) fn into_receipt(
+        self,
+        index: usize,
+        cumulative_gas_used: U256,
+    ) -> TransactionReceipt {
+        let transaction_hash = self.tx.digest();
+        let logs_bloom = logs_bloom(&self.logs);
+        let logs = self
+            .logs
+            .into_iter()
+            .map(|log| ethers_core::types::Log {
+                address: log.address,
+                topics: log.topics,
+                data: log.data.into(),
+                transaction_hash: Some(transaction_hash),
+                transaction_index: Some((index as u64).into()),
+                ..Default::default()
+            })
+            .collect();
+
+        TransactionReceipt {
+            transaction_hash,
+            transaction_index: (index as u64).into(),
+            from: self.tx.caller(),
+            to: self.tx.to_addr().copied(),
+            contract_address: self.success.then(|| self.tx.created_address()).flatten(),
+            cumulative_gas_used,
+            gas_used: Some(self.gas_used.into()),
+            logs,
+            logs_bloom,
+            status: Some(u64::from(self.success).into()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Collects the receipt records of the transactions of a window for a single call, keyed by their ids, so that
+/// the record of a later execution of a transaction (e.g., a re-execution) overwrites the one of an earlier one.
+/// The records are added in bulk, once per execution phase, and only for the executions whose effects are committed:
+/// the ones of a speculative phase are staged in a separate collector, and [`Self::commit`]ted once it is validated.
+#[derive(Debug, Default)]
+pub(crate) struct ReceiptCollector(Mutex<BTreeMap<u64, ReceiptRecord>>);
+
+impl ReceiptCollector {
+    pub(crate) fn extend(&self, records: impl IntoIterator<Item = ReceiptRecord>) {
+        self.0
+            .lock()
+            .extend(records.into_iter().map(|record| (record.id, record)));
+    }
+
+    /// Moves the staged records of the committed transactions over, dropping the others (e.g., of the aborted
+    /// or invalidated transactions), which would otherwise report the outcome of a discarded execution.
+    pub(crate) fn commit(&self, staged: ReceiptCollector, is_committed: impl Fn(u64) -> bool) {
+        self.extend(
+            staged
+                .into_records()
+                .filter(|record| is_committed(record.id)),
+        );
+    }
+
+    /// The records in the order of ids, i.e., the order of the transactions in the window.
+    pub(crate) fn into_records(self) -> impl Iterator<Item = ReceiptRecord> {
+        self.0.into_inner().into_values()
+    }
+}
+
+/// The pre-images of what a consensus output commits, i.e., each account (and each slot) it writes as it was
+/// before the first write of the output, recorded while the output is executed (see `ConcurrencyLevelManager::prepare_execution`).
+/// A failed output is rolled back by writing them back, which costs only what the output has touched,
+/// instead of copying the whole state beforehand.
+#[derive(Debug, Default)]
+pub(crate) struct PreImages(Mutex<Option<BTreeMap<H160, PreImage>>>);
+
+#[derive(Debug)]
+struct PreImage {
+    // the basic and the code of the account, or `None` if it did not exist.
+    account: Option<(Basic, Vec<u8>)>,
+    // the slots the output has written, to their values before (zero if absent).
+    storage: BTreeMap<H256, H256>,
+    // whether `storage` holds every slot of the account, i.e., the output has reset its storage (or deleted it).
+    whole_storage: bool,
+}
+
+impl PreImages {
+    /// Starts recording, discarding what was recorded before.
+    pub(crate) fn start(&self) {
+        *self.0.lock() = Some(BTreeMap::new());
+    }
+
+    /// Records the pre-images of what the effects are about to write, unless an earlier write of the output
+    /// has already recorded them. Does nothing unless recording.
+    pub(crate) fn record<'a>(
+        &self,
+        storage: &ConcurrentEVMStorage,
+        effects: impl IntoIterator<Item = &'a Apply>,
+    ) {
+        let mut pre_images = self.0.lock();
+        let Some(pre_images) = pre_images.as_mut() else {
+            return;
+        };
+
+        let state = storage.get_storage().state().pin();
+        for effect in effects {
+            let (address, written, whole_storage) = match effect {
+                Apply::Modify {
+                    address,
+                    storage,
+                    reset_storage,
+                    ..
+                } => (*address, Some(storage), *reset_storage),
+                Apply::Delete { address } => (*address, None, true),
+            };
+            let account = state.get(&address);
+            let pre_image = pre_images.entry(address).or_insert_with(|| PreImage {
+                account: account.map(|account| {
+                    let basic = Basic {
+                        balance: account.balance,
+                        nonce: account.nonce,
+                    };
+                    (basic, account.code.clone())
+                }),
+                storage: BTreeMap::new(),
+                whole_storage: false,
+            });
+            if pre_image.whole_storage {
+                continue;
+            }
+
+            let slots = account.map(|account| account.storage.pin());
+            if whole_storage {
+                // (the slots the output has written before keep their older values)
+                for (key, value) in slots.iter().flat_map(|slots| slots.iter()) {
+                    pre_image.storage.entry(*key).or_insert(*value);
+                }
+                pre_image.whole_storage = true;
+            } else {
+                for key in written.into_iter().flat_map(BTreeMap::keys) {
+                    pre_image.storage.entry(*key).or_insert_with(|| {
+                        slots
+                            .as_ref()
+                            .and_then(|slots| slots.get(key).copied())
+                            .unwrap_or_default()
+                    });
+                }
+            }
         }
     }
+
+    /// Stops recording, and discards what was recorded (i.e., the output is committed).
+    pub(crate) fn commit(&self) {
+        self.0.lock().take();
+    }
+
+    /// Stops recording, and returns the effects which write the pre-images back, i.e., roll back what was recorded.
+    pub(crate) fn revert(&self) -> Vec<Apply> {
+        self.0
+            .lock()
+            .take()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(address, pre_image)| match pre_image.account {
+                None => Apply::Delete { address },
+                Some((basic, code)) => Apply::Modify {
+                    address,
+                    basic,
+                    code: Some(code),
+                    storage: pre_image.storage,
+                    reset_storage: pre_image.whole_storage,
+                },
+            })
+            .collect()
+    }
+}
+
+/// Collapses the effects (in the order they are applied) into the last write of each account, in the order of addresses,
+/// so that applying the result leaves the same state as applying the effects one by one:
+/// 1) a `Modify` after a `Modify` overwrites the basic (and the code, if any) of the former, and its storage is merged
+///    into the one of the former, unless it resets the storage.
+/// 2) a `Delete` supersedes every effect before it.
+/// 3) a `Modify` after a `Delete` recreates the account, i.e., it resets the storage and the code (to empty, if it has none).
+pub fn collapse_effects(effects: impl IntoIterator<Item = Apply>) -> Vec<Apply> {
+    let mut collapsed: BTreeMap<H160, Apply> = BTreeMap::new();
+
+    for effect in effects {
+        let address = match &effect {
+            Apply::Modify { address, .. } | Apply::Delete { address } => *address,
+        };
+
+        let effect = match (collapsed.remove(&address), effect) {
+            (
+                Some(Apply::Modify {
+                    code: prev_code,
+                    storage: mut prev_storage,
+                    reset_storage: prev_reset_storage,
+                    ..
+                }),
+                Apply::Modify {
+                    address,
+                    basic,
+                    code,
+                    storage,
+                    reset_storage,
+                },
+            ) => {
+                let (storage, reset_storage) = if reset_storage {
+                    (storage, true)
+                } else {
+                    prev_storage.extend(storage);
+                    (prev_storage, prev_reset_storage)
+                };
+                Apply::Modify {
+                    address,
+                    basic,
+                    code: code.or(prev_code),
+                    storage,
+                    reset_storage,
+                }
+            }
+            (
+                Some(Apply::Delete { .. }),
+                Apply::Modify {
+                    address,
+                    basic,
+                    code,
+                    storage,
+                    ..
+                },
+            ) => Apply::Modify {
+                address,
+                basic,
+                code: Some(code.unwrap_or_default()),
+                storage,
+                reset_storage: true,
+            },
+            (_, effect) => effect,
+        };
+        collapsed.insert(address, effect);
+    }
+
+    collapsed.into_values().collect()
+}
+
+/// Unions the read and write keys of the rw-sets a transaction recorded across re-execution rounds,
+/// i.e., its cumulative footprint.
+pub fn merge_rw_sets(sets: &[RwSet]) -> (hashbrown::HashSet<H256>, hashbrown::HashSet<H256>) {
+    sets.iter().fold(
+        (hashbrown::HashSet::new(), hashbrown::HashSet::new()),
+        |(mut reads, mut writes), rw_set| {
+            reads.extend(extract_read_set(rw_set));
+            writes.extend(extract_write_set(rw_set));
+            (reads, writes)
+        },
+    )
+}
+
+/// The logs bloom of the block (as in the Ethereum block header), i.e., the union of the bloom of every log,
+/// which accrues the emitter address and each of the topics. Restrict the result to the committed transactions
+/// beforehand (see [`SimulationResult::filter_committed`]), as the logs of the others are void.
+pub fn block_logs_bloom(result: &SimulationResult) -> Bloom {
+    logs_bloom(result.rw_sets.iter().flat_map(|tx| tx.logs().iter()))
+}
+
+// the union of the bloom of every log, which accrues the emitter address and each of the topics.
+fn logs_bloom<'a>(logs: impl IntoIterator<Item = &'a Log>) -> Bloom {
+    let mut bloom = Bloom::zero();
+    logs.into_iter().for_each(|log| {
+        bloom.accrue(BloomInput::Raw(log.address.as_bytes()));
+        log.topics
+            .iter()
+            .for_each(|topic| bloom.accrue(BloomInput::Raw(topic.as_bytes())));
+    });
+    bloom
+}
+
+/// The pseudo-key standing for the balance of an account in rw-sets, i.e., keccak256(address ++ "balance"),
+/// so that it never collides with a storage slot.
+pub fn balance_key(address: H160) -> H256 {
+    H256::from(keccak256([address.as_bytes(), b"balance"].concat()))
+}
+
+/// The pseudo-key standing for the basic (balance and nonce) of an account in [`AccessKeys`],
+/// i.e., keccak256(address ++ "basic").
+pub fn basic_key(address: H160) -> H256 {
+    H256::from(keccak256([address.as_bytes(), b"basic"].concat()))
+}
+
+/// Records a read and a write of the balances of the caller and the recipient, if the transaction transfers value.
+/// Otherwise, transfers to the same account (e.g., plain ones, which touch no storage) would never conflict,
+/// although every one of them overwrites the balance computed on the same snapshot.
+pub(crate) fn record_value_transfer(mut rw_set: RwSet, tx: &EthereumTransaction) -> RwSet {
+    if tx.as_typed().value().map_or(true, |value| value.is_zero()) {
+        return rw_set;
+    }
+
+    std::iter::once(tx.caller())
+        .chain(tx.to_addr().copied())
+        .for_each(|address| {
+            let key = balance_key(address);
+            rw_set.record_read_key(address, key, H256::zero());
+            rw_set.record_write_key(address, key, H256::zero());
+        });
+    rw_set
 }
 
 #[inline]
@@ -326,13 +1524,3 @@ fn extract_write_set(rw_set: &RwSet) -> hashbrown::HashSet<H256> {
         .flat_map(|(_, state)| state.keys().cloned())
         .collect()
 }
-
-#[inline]
-pub(crate) fn is_disjoint<K>(left: &hashbrown::HashSet<K>, right: &hashbrown::HashSet<K>) -> bool
-where
-    K: std::cmp::Eq,
-    K: std::hash::Hash,
-{
-    (left.len() <= right.len() && left.is_disjoint(right))
-        || (left.len() > right.len() && right.is_disjoint(left))
-}
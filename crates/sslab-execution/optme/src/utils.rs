@@ -0,0 +1,96 @@
+use std::{any::Any, fmt, panic::AssertUnwindSafe};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RayonError {
+    /// the closure panicked on the rayon worker, along with the panic message.
+    Panicked(String),
+    /// the result was never sent back from the rayon worker.
+    Canceled,
+}
+
+impl fmt::Display for RayonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RayonError::Panicked(msg) => write!(f, "a rayon worker panicked: {msg}"),
+            RayonError::Canceled => write!(f, "a rayon worker dropped the result"),
+        }
+    }
+}
+
+impl std::error::Error for RayonError {}
+
+/// Runs the CPU-bound closure on the rayon thread pool, and awaits its result without blocking the tokio threads.
+/// (CPU-bound jobs would make the I/O-bound tokio threads starve.)
+/// A panic in the closure is caught and returned as an error, instead of aborting the process as `rayon::spawn` does.
+pub async fn run_on_rayon<T, F>(f: F) -> Result<T, RayonError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let (send, recv) = tokio::sync::oneshot::channel();
+    rayon::spawn(move || {
+        let _ = send.send(std::panic::catch_unwind(AssertUnwindSafe(f)));
+    });
+
+    match recv.await {
+        Ok(Ok(result)) => Ok(result),
+        Ok(Err(payload)) => Err(RayonError::Panicked(panic_message(payload))),
+        Err(_) => Err(RayonError::Canceled),
+    }
+}
+
+pub(crate) fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// The number of vectors a [`VecPool`] keeps at most; the ones given back beyond it are dropped.
+pub const MAX_POOLED_BUFFERS: usize = 8;
+
+/// A pool of vectors recycled across blocks, so that a steady-state node stops reallocating the per-block vectors
+/// once they have grown to the usual size of a block. A vector is cleared when given back, but keeps its capacity.
+pub struct VecPool<T> {
+    buffers: parking_lot::Mutex<Vec<Vec<T>>>,
+}
+
+impl<T> Default for VecPool<T> {
+    fn default() -> Self {
+        Self {
+            buffers: parking_lot::Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl<T> VecPool<T> {
+    /// Takes an empty vector out of the pool, or a new one if the pool is empty.
+    pub fn take(&self) -> Vec<T> {
+        self.buffers.lock().pop().unwrap_or_default()
+    }
+
+    /// Gives the vector back to the pool, unless it has never allocated or the pool is full.
+    pub fn recycle(&self, mut buffer: Vec<T>) {
+        buffer.clear();
+        if buffer.capacity() == 0 {
+            return;
+        }
+
+        let mut buffers = self.buffers.lock();
+        if buffers.len() < MAX_POOLED_BUFFERS {
+            buffers.push(buffer);
+        }
+    }
+
+    /// The number of vectors in the pool.
+    pub fn len(&self) -> usize {
+        self.buffers.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,246 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use ethers_core::types::{H160, H256, U256};
+use evm::backend::{Apply, Basic};
+use serde::{Deserialize, Serialize};
+use sslab_execution::evm_storage::{backend::ExecutionBackend, ConcurrentEVMStorage};
+use typed_store::{
+    rocks::{DBMap, MetricConf, TypedStoreError},
+    traits::{TableSummary, TypedStoreDebug},
+    Map,
+};
+use typed_store_derive::DBMapUtils;
+
+pub use crate::codec::SerdeApply as WalEffect;
+use crate::types::FinalizedTransaction;
+
+#[derive(DBMapUtils)]
+struct CommitWalTables {
+    /// the effects of each generation not yet acked, keyed by the order in which it was logged.
+    log: DBMap<u64, Vec<WalEffect>>,
+    /// the balance and nonce of each account which the acked generations have modified.
+    basics: DBMap<H160, WalBasic>,
+    /// the code of each account which the acked generations have deployed.
+    codes: DBMap<H160, Vec<u8>>,
+    /// the non-zero slots of each account which the acked generations have written, keyed by (address, slot).
+    slots: DBMap<(H160, H256), H256>,
+}
+
+/// The serializable form of [`Basic`].
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+struct WalBasic {
+    balance: U256,
+    nonce: U256,
+}
+
+/// Persistent log of the generations being committed. Each generation is written to the log before it is applied
+/// to the global state. Once it is fully applied, its effects are persisted row by row (the basic and the code of
+/// an account, and each of its slots) and it is removed from the log in a single write. So if the node goes down
+/// in the middle of a commit, the global state is recovered on restart by restoring the persisted accounts over
+/// the genesis state and replaying the generations left in the log, which are exactly the ones which may be
+/// partially applied.
+///
+/// Replaying is idempotent, since an effect overwrites the account (or the slots) as a whole.
+pub struct CommitWal {
+    tables: CommitWalTables,
+    next_seq: AtomicU64,
+}
+
+// the changes of an acked generation to an account, i.e., its effects folded together.
+#[derive(Default)]
+struct AccountChanges {
+    // the account is deleted before the rest is applied.
+    deleted: bool,
+    // the storage of the account is reset before the slots are written.
+    reset_storage: bool,
+    basic: Option<WalBasic>,
+    code: Option<Vec<u8>>,
+    storage: BTreeMap<H256, H256>,
+}
+
+impl AccountChanges {
+    fn fold(&mut self, effect: WalEffect) {
+        match effect {
+            WalEffect::Delete { .. } => {
+                *self = AccountChanges {
+                    deleted: true,
+                    reset_storage: true,
+                    ..Default::default()
+                };
+            }
+            WalEffect::Modify {
+                balance,
+                nonce,
+                code,
+                storage,
+                reset_storage,
+                ..
+            } => {
+                if reset_storage {
+                    self.reset_storage = true;
+                    self.storage.clear();
+                }
+                self.basic = Some(WalBasic { balance, nonce });
+                if code.is_some() {
+                    self.code = code;
+                }
+                self.storage.extend(storage);
+            }
+        }
+    }
+}
+
+impl CommitWal {
+    pub fn open(path: &Path) -> Self {
+        let tables = CommitWalTables::open_tables_read_write(
+            path.to_path_buf(),
+            MetricConf::default(),
+            None,
+            None,
+        );
+        let next_seq = tables
+            .log
+            .unbounded_iter()
+            .map(|(seq, _)| seq + 1)
+            .max()
+            .unwrap_or_default();
+
+        Self {
+            tables,
+            next_seq: AtomicU64::new(next_seq),
+        }
+    }
+
+    /// Logs the effects of the generation, and returns the sequence to ack it with.
+    pub fn log_generation(&self, txs: &[FinalizedTransaction]) -> Result<u64, TypedStoreError> {
+        let seq = self.next_seq.fetch_add(1, Ordering::SeqCst);
+        let effects = txs
+            .iter()
+            .flat_map(|tx| tx.effects().iter().map(WalEffect::from))
+            .collect::<Vec<_>>();
+
+        self.tables.log.insert(&seq, &effects)?;
+        Ok(seq)
+    }
+
+    /// Indicates that the generation has been fully applied, so that its effects are persisted and its entry removed.
+    /// Only the rows the generation has changed are written: the basic (and the code, if deployed) of each account
+    /// it modifies, and the slots it writes, where a slot written to zero is deleted.
+    pub fn ack(&self, seq: u64) -> Result<(), TypedStoreError> {
+        let Some(effects) = self.tables.log.get(&seq)? else {
+            return Ok(());
+        };
+
+        let mut changes = HashMap::<H160, AccountChanges>::new();
+        for effect in effects {
+            let address = match &effect {
+                WalEffect::Modify { address, .. } | WalEffect::Delete { address } => *address,
+            };
+            changes.entry(address).or_default().fold(effect);
+        }
+
+        let mut batch = self.tables.log.batch();
+        for (address, changes) in changes {
+            if changes.reset_storage {
+                let persisted = self
+                    .slots_of(address)
+                    .into_iter()
+                    .map(|(slot, _)| (address, slot));
+                batch.delete_batch(&self.tables.slots, persisted)?;
+            }
+            if changes.deleted {
+                batch.delete_batch(&self.tables.basics, std::iter::once(address))?;
+                batch.delete_batch(&self.tables.codes, std::iter::once(address))?;
+            }
+            if let Some(basic) = changes.basic {
+                batch.insert_batch(&self.tables.basics, std::iter::once((address, basic)))?;
+            }
+            if let Some(code) = changes.code {
+                batch.insert_batch(&self.tables.codes, std::iter::once((address, code)))?;
+            }
+
+            let (zeroed, written): (Vec<_>, Vec<_>) = changes
+                .storage
+                .into_iter()
+                .partition(|(_, value)| value.is_zero());
+            batch.delete_batch(
+                &self.tables.slots,
+                zeroed.into_iter().map(|(slot, _)| (address, slot)),
+            )?;
+            batch.insert_batch(
+                &self.tables.slots,
+                written
+                    .into_iter()
+                    .map(|(slot, value)| ((address, slot), value)),
+            )?;
+        }
+        batch.delete_batch(&self.tables.log, std::iter::once(seq))?;
+        batch.write()
+    }
+
+    /// The non-zero slots persisted for the account, in the order of slots.
+    pub fn slots_of(&self, address: H160) -> Vec<(H256, H256)> {
+        self.tables
+            .slots
+            .range_iter((address, H256::zero())..=(address, H256::repeat_byte(0xff)))
+            .map(|((_, slot), value)| (slot, value))
+            .collect()
+    }
+
+    /// Logs the generation, applies it with `apply`, and acks it.
+    pub(crate) fn commit(
+        &self,
+        txs: Vec<FinalizedTransaction>,
+        apply: impl FnOnce(Vec<FinalizedTransaction>),
+    ) -> Result<(), TypedStoreError> {
+        let seq = self.log_generation(&txs)?;
+        apply(txs);
+        self.ack(seq)
+    }
+
+    /// The sequences of the generations not yet acked, in the order of logging.
+    pub fn pending(&self) -> Vec<u64> {
+        self.tables
+            .log
+            .unbounded_iter()
+            .map(|(seq, _)| seq)
+            .collect()
+    }
+
+    /// Recovers the committed state over the given (genesis) state: restores the account images, and replays the
+    /// generations not yet acked. Returns the number of replayed generations.
+    pub fn recover(&self, storage: &ConcurrentEVMStorage) -> Result<usize, TypedStoreError> {
+        let mut accounts = vec![];
+        for (address, WalBasic { balance, nonce }) in self.tables.basics.unbounded_iter() {
+            accounts.push(Apply::Modify {
+                address,
+                basic: Basic { balance, nonce },
+                code: self.tables.codes.get(&address)?,
+                storage: self.slots_of(address).into_iter().collect(),
+                // (the slots not persisted are zero)
+                reset_storage: true,
+            });
+        }
+        storage.apply_local_effect(accounts);
+
+        self.replay(storage)
+    }
+
+    /// Re-applies the generations not yet acked to the given state in the order of logging, and acks them.
+    /// Returns the number of replayed generations.
+    pub fn replay(&self, storage: &ConcurrentEVMStorage) -> Result<usize, TypedStoreError> {
+        let entries = self.tables.log.unbounded_iter().collect::<Vec<_>>();
+        let replayed = entries.len();
+
+        for (seq, effects) in entries {
+            storage.apply_local_effect(effects.into_iter().map(Apply::from).collect());
+            self.ack(seq)?;
+        }
+
+        Ok(replayed)
+    }
+}
@@ -157,6 +157,10 @@ pub struct Parameters {
         default = "Parameters::default_max_batch_delay"
     )]
     pub max_batch_delay: Duration,
+    /// The maximum size of a single transaction accepted into a batch. Larger transactions are
+    /// rejected by the workers. Denominated in bytes.
+    #[serde(default = "Parameters::default_max_transaction_size")]
+    pub max_transaction_size: usize,
     /// The parameters for the block synchronizer
     #[serde(default = "BlockSynchronizerParameters::default")]
     pub block_synchronizer: BlockSynchronizerParameters,
@@ -214,6 +218,10 @@ impl Parameters {
         Duration::from_millis(100)
     }
 
+    fn default_max_transaction_size() -> usize {
+        128 * 1024
+    }
+
     fn default_max_concurrent_requests() -> usize {
         500_000
     }
@@ -423,6 +431,7 @@ impl Default for Parameters {
             sync_retry_nodes: Parameters::default_sync_retry_nodes(),
             batch_size: Parameters::default_batch_size(),
             max_batch_delay: Parameters::default_max_batch_delay(),
+            max_transaction_size: Parameters::default_max_transaction_size(),
             block_synchronizer: BlockSynchronizerParameters::default(),
             consensus_api_grpc: ConsensusAPIGrpcParameters::default(),
             max_concurrent_requests: Parameters::default_max_concurrent_requests(),
@@ -470,6 +479,10 @@ impl Parameters {
             "Max batch delay set to {} ms",
             self.max_batch_delay.as_millis()
         );
+        info!(
+            "Max transaction size set to {} B",
+            self.max_transaction_size
+        );
         info!(
             "Synchronize range timeout set to {} s",
             self.block_synchronizer.range_synchronize_timeout.as_secs()
@@ -41,6 +41,19 @@ pub struct RequestBatchesResponse {
     pub is_size_limit_reached: bool,
 }
 
+/// The encoding of the transactions in a sealed batch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxWireFormat {
+    /// The transactions are kept rlp-encoded, as signed by the clients.
+    Rlp,
+    /// The transactions are decoded, and serialized to json.
+    Json,
+}
+
+/// The encoding in which the batch maker seals the transactions, and in which the executors decode them.
+/// Both sides must refer to this, so that they cannot drift apart.
+pub const TX_WIRE_FORMAT: TxWireFormat = TxWireFormat::Json;
+
 // TODO: support propagating errors from the worker to the primary.
 pub type TxResponse = tokio::sync::oneshot::Sender<BatchDigest>;
 
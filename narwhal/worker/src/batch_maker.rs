@@ -2,7 +2,7 @@
 // Copyright (c) Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::metrics::WorkerMetrics;
+use crate::metrics::{tx_count_range, WorkerMetrics};
 use config::WorkerId;
 use ethers_core::{
     types::transaction::eip2718::TypedTransaction,
@@ -10,6 +10,7 @@ use ethers_core::{
 };
 use fastcrypto::hash::Hash;
 use futures::future::BoxFuture;
+use futures::Future;
 use futures::stream::FuturesUnordered;
 use futures::StreamExt;
 use mysten_metrics::metered_channel::{Receiver, Sender};
@@ -25,8 +26,10 @@ use tokio::{
 };
 use tracing::{error, warn};
 use types::{
-    error::DagError, now, Batch, BatchAPI, BatchDigest, ConditionalBroadcastReceiver, MetadataAPI,
-    Transaction, TxResponse, WorkerOurBatchMessage, WorkerOwnBatchMessage,
+    error::{DagError, LocalClientError},
+    now, Batch, BatchAPI, BatchDigest, ConditionalBroadcastReceiver, MetadataAPI,
+    Transaction, TxResponse, TxWireFormat, WorkerOurBatchMessage, WorkerOwnBatchMessage,
+    TX_WIRE_FORMAT,
 };
 
 #[cfg(feature = "trace_transaction")]
@@ -37,16 +40,36 @@ use std::convert::TryInto;
 // The number of batches to store / transmit in parallel.
 pub const MAX_PARALLEL_BATCH: usize = 100;
 
+// The number of attempts to report a sealed batch to the primary before giving up.
+pub const MAX_REPORT_ATTEMPTS: usize = 4;
+
+// The delay before the first retry to report a batch, doubled after each failed attempt.
+const INITIAL_REPORT_BACKOFF: Duration = Duration::from_millis(100);
+
 #[cfg(test)]
 #[path = "tests/batch_maker_tests.rs"]
 pub mod batch_maker_tests;
 
+/// Re-encodes a transaction received from a client (rlp-encoded) in `TX_WIRE_FORMAT`, as sealed in a batch.
+pub fn encode_for_wire(tx: &[u8]) -> Transaction {
+    match TX_WIRE_FORMAT {
+        TxWireFormat::Rlp => tx.to_vec(),
+        TxWireFormat::Json => {
+            let (rlp_decoded_tx, _) = TypedTransaction::decode_signed(&Rlp::new(tx))
+                .expect("validation for rlp decoding must be done once receiving the tx from clients at TxServer");
+            serde_json::to_vec(&rlp_decoded_tx).unwrap()
+        }
+    }
+}
+
 /// Assemble clients transactions into batches.
 pub struct BatchMaker {
     // Our worker's id.
     id: WorkerId,
     /// The preferred batch size (in bytes).
     batch_size_limit: usize,
+    /// The maximum size of a single transaction (in bytes); larger ones are rejected.
+    max_transaction_size: usize,
     /// The maximum delay after which to seal the batch.
     max_batch_delay: Duration,
     /// Receiver for shutdown.
@@ -72,6 +95,7 @@ impl BatchMaker {
     pub fn spawn(
         id: WorkerId,
         batch_size_limit: usize,
+        max_transaction_size: usize,
         max_batch_delay: Duration,
         rx_shutdown: ConditionalBroadcastReceiver,
         rx_batch_maker: Receiver<(Transaction, TxResponse)>,
@@ -86,6 +110,7 @@ impl BatchMaker {
                 Self {
                     id,
                     batch_size_limit,
+                    max_transaction_size,
                     max_batch_delay,
                     rx_shutdown,
                     rx_batch_maker,
@@ -122,6 +147,16 @@ impl BatchMaker {
                 // condition will be met eventually if the store and network are functioning.
                 Some((transaction, response_sender)) = self.rx_batch_maker.recv(), if batch_pipeline.len() < MAX_PARALLEL_BATCH => {
                     let _scope = monitored_scope("BatchMaker::recv");
+                    if transaction.len() > self.max_transaction_size {
+                        // Dropping the response sender notifies the submitter that the transaction is rejected.
+                        warn!(
+                            "Rejected a transaction of {} B exceeding the maximum of {} B",
+                            transaction.len(),
+                            self.max_transaction_size
+                        );
+                        drop(response_sender);
+                        continue;
+                    }
                     current_batch_size += transaction.len();
                     current_batch.transactions_mut().push(transaction);
                     current_responses.push(response_sender);
@@ -160,7 +195,17 @@ impl BatchMaker {
                     self.batch_start_timestamp = Instant::now();
                 }
 
+                // On shutdown, seal the pending transactions and wait for the batches in flight,
+                // so that the transactions already accepted are not lost.
                 _ = self.rx_shutdown.receiver.recv() => {
+                    let _scope = monitored_scope("BatchMaker::drain");
+                    if !current_batch.transactions().is_empty() {
+                        if let Some(seal) = self.seal(true, current_batch, current_batch_size, current_responses).await {
+                            batch_pipeline.push(seal);
+                        }
+                    }
+                    while batch_pipeline.next().await.is_some() {}
+                    self.node_metrics.parallel_worker_batches.set(0);
                     return
                 }
 
@@ -192,22 +237,19 @@ impl BatchMaker {
             .filter_map(|tx| tx[2..10].try_into().ok())
             .collect::<Vec<[u8; 8]>>();
 
+        let tx_count = batch.transactions().len();
+        let transform_start = Instant::now();
         let mut batch = tokio::task::spawn_blocking(move || {
             batch
                 .transactions_mut()
                 .into_par_iter() 
-                .for_each(|tx| {
-                    let _tx: Vec<u8> = tx.clone();
-                    let rlp = Rlp::new(&_tx);
-                    tx.clear();
-
-                    let (rlp_decoded_tx, _) = TypedTransaction::decode_signed(&rlp)
-                        .expect("validation for rlp decoding must be done once receiving the tx from clients at TxServer");
-                    let se = serde_json::to_vec(&rlp_decoded_tx).unwrap();
-                    tx.extend(se);
-                });
+                .for_each(|tx| *tx = encode_for_wire(tx));
             batch
         }).await.expect("Failed to spawn a thread for decoding transactions.");
+        self.node_metrics
+            .batch_transform_latency
+            .with_label_values(&[tx_count_range(tx_count)])
+            .observe(transform_start.elapsed().as_secs_f64());
         
 
         #[cfg(feature = "benchmark")]
@@ -322,7 +364,9 @@ impl BatchMaker {
                     worker_id,
                     metadata,
                 };
-                if let Err(e) = client.report_own_batch(message).await {
+                if let Err(e) =
+                    report_with_backoff(|| client.report_own_batch(message.clone())).await
+                {
                     warn!("Failed to report our batch: {}", e);
                     // Drop all response handers to signal error, since we
                     // cannot ensure the primary has actually signaled the
@@ -366,7 +410,9 @@ impl BatchMaker {
                     worker_id,
                     metadata,
                 };
-                if let Err(e) = client.report_our_batch(message).await {
+                if let Err(e) =
+                    report_with_backoff(|| client.report_our_batch(message.clone())).await
+                {
                     warn!("Failed to report our batch: {}", e);
                     // Drop all response handers to signal error, since we
                     // cannot ensure the primary has actually signaled the
@@ -384,3 +430,28 @@ impl BatchMaker {
         }
     }
 }
+
+/// Reports a sealed batch to the primary, retrying with exponential backoff up to
+/// `MAX_REPORT_ATTEMPTS` times. The batch is already persisted to the store by then, so a
+/// transient failure should not force the transaction submitters to retry from scratch.
+async fn report_with_backoff<F, Fut>(mut report: F) -> Result<(), LocalClientError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<(), LocalClientError>>,
+{
+    let mut backoff = INITIAL_REPORT_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match report().await {
+            Ok(()) => return Ok(()),
+            Err(LocalClientError::ShuttingDown) => return Err(LocalClientError::ShuttingDown),
+            Err(e) if attempt < MAX_REPORT_ATTEMPTS => {
+                warn!("Failed to report our batch (attempt {attempt}), retry in {backoff:?}: {e}");
+                sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
@@ -17,6 +17,17 @@ const LATENCY_SEC_BUCKETS: &[f64] = &[
     12.5, 15., 17.5, 20., 25., 30., 60., 90., 120., 180., 300.,
 ];
 
+/// The range of the number of transactions of a batch which `batch_transform_latency` is labelled by,
+/// so that the metric has a bounded number of series.
+pub fn tx_count_range(tx_count: usize) -> &'static str {
+    match tx_count {
+        0..=99 => "0-99",
+        100..=999 => "100-999",
+        1_000..=9_999 => "1000-9999",
+        _ => "10000+",
+    }
+}
+
 #[derive(Clone)]
 pub struct Metrics {
     pub worker_metrics: Option<WorkerMetrics>,
@@ -61,6 +72,8 @@ pub struct WorkerMetrics {
     pub created_batch_size: HistogramVec,
     /// Time taken to create a batch
     pub created_batch_latency: HistogramVec,
+    /// Time taken to decode and re-serialize the transactions of a batch, by the range of its number of transactions
+    pub batch_transform_latency: HistogramVec,
     /// The number of parallel worker batches currently processed by the worker
     pub parallel_worker_batches: IntGauge,
     /// Latency of broadcasting batches to a quorum in seconds.
@@ -108,6 +121,15 @@ impl WorkerMetrics {
                 registry
             )
             .unwrap(),
+            batch_transform_latency: register_histogram_vec_with_registry!(
+                "batch_transform_latency",
+                "The latency of decoding and re-serializing the transactions of a batch when sealing it",
+                &["tx_count_range"],
+                // buckets in seconds
+                LATENCY_SEC_BUCKETS.to_vec(),
+                registry
+            )
+            .unwrap(),
             parallel_worker_batches: register_int_gauge_with_registry!(
                 "parallel_worker_batches",
                 "The number of parallel worker batches currently processed by the worker",
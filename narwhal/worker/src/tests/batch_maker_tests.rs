@@ -36,6 +36,7 @@ async fn make_batch() {
     let _batch_maker_handle = BatchMaker::spawn(
         id,
         /* max_batch_size */ 200,
+        /* max_transaction_size */ 1_000,
         /* max_batch_delay */
         Duration::from_millis(1_000_000), // Ensure the timer is not triggered.
         tx_shutdown.subscribe(),
@@ -71,6 +72,124 @@ async fn make_batch() {
     assert!(store.get(&expected_batch.digest()).unwrap().is_some());
 }
 
+#[tokio::test]
+async fn batch_transform_latency_is_recorded() {
+    let client = create_network_client();
+    let store = create_batch_store();
+    let mut tx_shutdown = PreSubscribedBroadcastSender::new(NUM_SHUTDOWN_RECEIVERS);
+    let (tx_batch_maker, rx_batch_maker) = test_utils::test_channel!(1);
+    let (tx_quorum_waiter, mut rx_quorum_waiter) = test_utils::test_channel!(1);
+    let node_metrics = Arc::new(WorkerMetrics::new(&Registry::new()));
+
+    // Mock the primary client to always succeed.
+    let mut mock_server = MockWorkerToPrimary::new();
+    mock_server
+        .expect_report_own_batch()
+        .returning(|_| Ok(anemo::Response::new(())));
+    client.set_worker_to_primary_local_handler(Arc::new(mock_server));
+
+    // Spawn a `BatchMaker` instance.
+    let id = 0;
+    let _batch_maker_handle = BatchMaker::spawn(
+        id,
+        /* max_batch_size */ 200,
+        /* max_transaction_size */ 1_000,
+        /* max_batch_delay */
+        Duration::from_millis(1_000_000), // Ensure the timer is not triggered.
+        tx_shutdown.subscribe(),
+        rx_batch_maker,
+        tx_quorum_waiter,
+        node_metrics.clone(),
+        client,
+        store.clone(),
+        latest_protocol_version(),
+    );
+
+    // Send enough transactions to seal a batch.
+    let tx = transaction();
+    let (s0, r0) = tokio::sync::oneshot::channel();
+    let (s1, r1) = tokio::sync::oneshot::channel();
+    tx_batch_maker.send((tx.clone(), s0)).await.unwrap();
+    tx_batch_maker.send((tx.clone(), s1)).await.unwrap();
+
+    // Eventually deliver message
+    let (_batch, resp) = rx_quorum_waiter.recv().await.unwrap();
+    assert!(resp.send(()).is_ok());
+    assert!(r0.await.is_ok());
+    assert!(r1.await.is_ok());
+
+    // The transformation of the sealed batch is recorded under the range of its number of transactions.
+    assert_eq!(
+        node_metrics
+            .batch_transform_latency
+            .with_label_values(&["0-99"])
+            .get_sample_count(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn reject_oversized_transaction() {
+    let client = create_network_client();
+    let store = create_batch_store();
+    let mut tx_shutdown = PreSubscribedBroadcastSender::new(NUM_SHUTDOWN_RECEIVERS);
+    let (tx_batch_maker, rx_batch_maker) = test_utils::test_channel!(1);
+    let (tx_quorum_waiter, mut rx_quorum_waiter) = test_utils::test_channel!(1);
+    let node_metrics = WorkerMetrics::new(&Registry::new());
+
+    // Mock the primary client to always succeed.
+    let mut mock_server = MockWorkerToPrimary::new();
+    mock_server
+        .expect_report_own_batch()
+        .returning(|_| Ok(anemo::Response::new(())));
+    client.set_worker_to_primary_local_handler(Arc::new(mock_server));
+
+    // Spawn a `BatchMaker` instance.
+    let id = 0;
+    let _batch_maker_handle = BatchMaker::spawn(
+        id,
+        /* max_batch_size */ 200,
+        /* max_transaction_size */ 150,
+        /* max_batch_delay */
+        Duration::from_millis(1_000_000), // Ensure the timer is not triggered.
+        tx_shutdown.subscribe(),
+        rx_batch_maker,
+        tx_quorum_waiter,
+        Arc::new(node_metrics),
+        client,
+        store.clone(),
+        latest_protocol_version(),
+    );
+
+    // Send a transaction larger than the maximum, which alone would seal a batch.
+    let oversized_tx: Transaction = vec![0u8; 300];
+    let (s0, r0) = tokio::sync::oneshot::channel();
+    tx_batch_maker.send((oversized_tx, s0)).await.unwrap();
+
+    // The transaction is rejected.
+    assert!(r0.await.is_err());
+
+    // Send enough transactions to seal a batch.
+    let tx = transaction();
+    let (s1, r1) = tokio::sync::oneshot::channel();
+    let (s2, r2) = tokio::sync::oneshot::channel();
+    tx_batch_maker.send((tx.clone(), s1)).await.unwrap();
+    tx_batch_maker.send((tx.clone(), s2)).await.unwrap();
+
+    // Ensure the batch does not contain the rejected transaction.
+    let expected_batch = Batch::new(vec![tx.clone(), tx.clone()], &latest_protocol_version());
+    let (batch, resp) = rx_quorum_waiter.recv().await.unwrap();
+
+    assert_eq!(batch.transactions(), expected_batch.transactions());
+
+    // Eventually deliver message
+    assert!(resp.send(()).is_ok());
+
+    // Batch maker should finish creating the batch.
+    assert!(r1.await.is_ok());
+    assert!(r2.await.is_ok());
+}
+
 #[tokio::test]
 async fn batch_timeout() {
     let client = create_network_client();
@@ -92,6 +211,7 @@ async fn batch_timeout() {
     let _batch_maker_handle = BatchMaker::spawn(
         id,
         /* max_batch_size */ 200,
+        /* max_transaction_size */ 1_000,
         /* max_batch_delay */
         Duration::from_millis(50), // Ensure the timer is triggered.
         tx_shutdown.subscribe(),
@@ -122,3 +242,119 @@ async fn batch_timeout() {
     // Ensure the batch is stored
     assert!(store.get(&batch.digest()).unwrap().is_some());
 }
+
+#[tokio::test]
+async fn report_batch_with_retries() {
+    let client = create_network_client();
+    let store = create_batch_store();
+    let mut tx_shutdown = PreSubscribedBroadcastSender::new(NUM_SHUTDOWN_RECEIVERS);
+    let (tx_batch_maker, rx_batch_maker) = test_utils::test_channel!(1);
+    let (tx_quorum_waiter, mut rx_quorum_waiter) = test_utils::test_channel!(1);
+    let node_metrics = WorkerMetrics::new(&Registry::new());
+
+    // Mock the primary client to fail once, then succeed.
+    let attempts = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let mut mock_server = MockWorkerToPrimary::new();
+    let counter = attempts.clone();
+    mock_server.expect_report_own_batch().returning(move |_| {
+        if counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 {
+            Err(anemo::rpc::Status::internal("Flaky primary"))
+        } else {
+            Ok(anemo::Response::new(()))
+        }
+    });
+    client.set_worker_to_primary_local_handler(Arc::new(mock_server));
+
+    // Spawn a `BatchMaker` instance.
+    let id = 0;
+    let _batch_maker_handle = BatchMaker::spawn(
+        id,
+        /* max_batch_size */ 200,
+        /* max_transaction_size */ 1_000,
+        /* max_batch_delay */
+        Duration::from_millis(1_000_000), // Ensure the timer is not triggered.
+        tx_shutdown.subscribe(),
+        rx_batch_maker,
+        tx_quorum_waiter,
+        Arc::new(node_metrics),
+        client,
+        store.clone(),
+        latest_protocol_version(),
+    );
+
+    // Send enough transactions to seal a batch.
+    let tx = transaction();
+    let (s0, r0) = tokio::sync::oneshot::channel();
+    let (s1, r1) = tokio::sync::oneshot::channel();
+    tx_batch_maker.send((tx.clone(), s0)).await.unwrap();
+    tx_batch_maker.send((tx.clone(), s1)).await.unwrap();
+
+    // Eventually deliver message
+    let (_batch, resp) = rx_quorum_waiter.recv().await.unwrap();
+    assert!(resp.send(()).is_ok());
+
+    // The responses are notified once the retried report succeeds.
+    assert!(r0.await.is_ok());
+    assert!(r1.await.is_ok());
+    assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn drain_on_shutdown() {
+    let client = create_network_client();
+    let store = create_batch_store();
+    let mut tx_shutdown = PreSubscribedBroadcastSender::new(NUM_SHUTDOWN_RECEIVERS);
+    let (tx_batch_maker, rx_batch_maker) = test_utils::test_channel!(1);
+    let (tx_quorum_waiter, mut rx_quorum_waiter) = test_utils::test_channel!(1);
+    let node_metrics = WorkerMetrics::new(&Registry::new());
+
+    // Mock the primary client to expect the pending batch to be reported.
+    let mut mock_server = MockWorkerToPrimary::new();
+    mock_server
+        .expect_report_own_batch()
+        .times(1)
+        .returning(|_| Ok(anemo::Response::new(())));
+    client.set_worker_to_primary_local_handler(Arc::new(mock_server));
+
+    // Spawn a `BatchMaker` instance.
+    let id = 0;
+    let batch_maker_handle = BatchMaker::spawn(
+        id,
+        /* max_batch_size */ 200,
+        /* max_transaction_size */ 1_000,
+        /* max_batch_delay */
+        Duration::from_millis(1_000_000), // Ensure the timer is not triggered.
+        tx_shutdown.subscribe(),
+        rx_batch_maker,
+        tx_quorum_waiter,
+        Arc::new(node_metrics),
+        client,
+        store.clone(),
+        latest_protocol_version(),
+    );
+
+    // Do not send enough transactions to seal a batch. The channel holds a single message, so the
+    // slot is free again only once the batch maker has accepted the transaction into its pending batch.
+    let tx = transaction();
+    let (s0, r0) = tokio::sync::oneshot::channel();
+    tx_batch_maker.send((tx.clone(), s0)).await.unwrap();
+    drop(tx_batch_maker.reserve().await.unwrap());
+
+    // Shutdown the batch maker.
+    tx_shutdown.send().unwrap();
+
+    // Ensure the pending batch is sealed rather than dropped.
+    let (batch, resp) = rx_quorum_waiter.recv().await.unwrap();
+    let expected_batch = Batch::new(vec![tx.clone()], &latest_protocol_version());
+    assert_eq!(batch.transactions(), expected_batch.transactions());
+
+    // Eventually deliver message
+    assert!(resp.send(()).is_ok());
+
+    // The batch is reported before the batch maker returns.
+    assert!(r0.await.is_ok());
+    batch_maker_handle.await.unwrap();
+
+    // Ensure the batch is stored
+    assert!(store.get(&batch.digest()).unwrap().is_some());
+}
@@ -488,6 +488,7 @@ impl Worker {
         let batch_maker_handle = BatchMaker::spawn(
             self.id,
             self.parameters.batch_size,
+            self.parameters.max_transaction_size,
             self.parameters.max_batch_delay,
             shutdown_receivers.pop().unwrap(),
             rx_batch_maker,